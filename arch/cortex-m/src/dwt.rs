@@ -483,6 +483,10 @@ impl hil::hw_debug::CycleCounter for Dwt {
         self.registers.cyccnt.read(CycleCount::CYCCNT) as u64
     }
 
+    fn width(&self) -> u32 {
+        32
+    }
+
     fn reset(&self) {
         // disable the counter
         self.registers.ctrl.modify(Control::CYCNTENA::CLEAR);
@@ -50,6 +50,10 @@ pub struct CortexMStoredState {
     yield_pc: usize,
     psr: usize,
     psp: usize,
+    /// Address the last memory access of the process faulted on, if the
+    /// process last returned to the kernel because of a MemManage or BusFault
+    /// with a valid fault address. This is not part of the stored context.
+    fault_address: Option<usize>,
 }
 
 // Space for 8 u32s: r0-r3, r12, lr, pc, and xPSR
@@ -57,7 +61,9 @@ const SVC_FRAME_SIZE: usize = 32;
 
 /// Values for encoding the stored state buffer in a binary slice.
 const VERSION: usize = 1;
-const STORED_STATE_SIZE: usize = size_of::<CortexMStoredState>();
+/// Size of the registers in the stored state buffer: r0-r3, r12, lr, pc,
+/// xPSR, yield_pc, psr and psp.
+const STORED_STATE_SIZE: usize = 11 * USIZE_SZ;
 const TAG: [u8; 4] = [b'c', b't', b'x', b'm'];
 const METADATA_LEN: usize = 3;
 
@@ -95,7 +101,7 @@ fn write_usize_to_u8_slice(val: usize, slice: &mut [u8], index: usize) {
 impl core::convert::TryFrom<&[u8]> for CortexMStoredState {
     type Error = ErrorCode;
     fn try_from(ss: &[u8]) -> Result<CortexMStoredState, Self::Error> {
-        if ss.len() == size_of::<CortexMStoredState>() + METADATA_LEN * USIZE_SZ
+        if ss.len() == STORED_STATE_SIZE + METADATA_LEN * USIZE_SZ
             && usize_from_u8_slice(ss, VERSION_IDX)? == VERSION
             && usize_from_u8_slice(ss, SIZE_IDX)? == STORED_STATE_SIZE
             && usize_from_u8_slice(ss, TAG_IDX)? == u32::from_le_bytes(TAG) as usize
@@ -105,6 +111,7 @@ impl core::convert::TryFrom<&[u8]> for CortexMStoredState {
                 yield_pc: usize_from_u8_slice(ss, YIELDPC_IDX)?,
                 psr: usize_from_u8_slice(ss, PSR_IDX)?,
                 psp: usize_from_u8_slice(ss, PSP_IDX)?,
+                fault_address: None,
             };
             for (i, v) in (REGS_RANGE).enumerate() {
                 res.regs[i] = usize_from_u8_slice(ss, v)?;
@@ -286,6 +293,20 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
         let syscall_fired = read_volatile(&*addr_of!(SYSCALL_FIRED));
         write_volatile(&mut *addr_of_mut!(SYSCALL_FIRED), 0);
 
+        // Record the fault address of this process now, as the fault status
+        // registers saved by the fault handler are shared by all processes and
+        // the process can also be faulted by the kernel without a memory
+        // access.
+        state.fault_address = if app_fault == 1 {
+            memory_fault_address(
+                read_volatile(&*addr_of!(SCB_REGISTERS[1])),
+                read_volatile(&*addr_of!(SCB_REGISTERS[3])),
+                read_volatile(&*addr_of!(SCB_REGISTERS[4])),
+            )
+        } else {
+            None
+        };
+
         // Now decide the reason based on which flags were set.
         let switch_reason = if app_fault == 1 || invalid_stack_pointer {
             // APP_HARD_FAULT takes priority. This means we hit the hardfault
@@ -436,7 +457,7 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
         state: &CortexMStoredState,
         out: &mut [u8],
     ) -> Result<usize, ErrorCode> {
-        if out.len() >= size_of::<CortexMStoredState>() + 3 * USIZE_SZ {
+        if out.len() >= STORED_STATE_SIZE + 3 * USIZE_SZ {
             write_usize_to_u8_slice(VERSION, out, VERSION_IDX);
             write_usize_to_u8_slice(STORED_STATE_SIZE, out, SIZE_IDX);
             write_usize_to_u8_slice(u32::from_le_bytes(TAG) as usize, out, TAG_IDX);
@@ -452,4 +473,32 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut CortexMStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = CortexMStoredState::try_from(input)?;
+        Ok(())
+    }
+
+    fn stack_pointer(&self, state: &CortexMStoredState) -> Option<usize> {
+        Some(state.psp)
+    }
+
+    fn fault_address(&self, state: &CortexMStoredState) -> Option<usize> {
+        state.fault_address
+    }
+}
+
+/// Returns the address of a faulting memory access from the fault status
+/// register `cfsr` and the fault address registers `mmfar` and `bfar`, if
+/// either of them holds a valid address.
+fn memory_fault_address(cfsr: u32, mmfar: u32, bfar: u32) -> Option<usize> {
+    let mmfarvalid = (cfsr & 0x80) == 0x80;
+    let bfarvalid = ((cfsr >> 8) & 0x80) == 0x80;
+    if mmfarvalid {
+        Some(mmfar as usize)
+    } else if bfarvalid {
+        Some(bfar as usize)
+    } else {
+        None
+    }
 }
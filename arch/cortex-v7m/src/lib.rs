@@ -496,6 +496,8 @@ pub unsafe extern "C" fn hard_fault_handler_arm_v7m() {
     str r2, [r0, #12]
     ldr r2, [r1, #36]         // BFAR
     str r2, [r0, #16]
+    ldr r2, [r0, #4]          // Clear the CFSR bits that were set, as
+    str r2, [r1, #20]         // they are write-one-to-clear and sticky
 
     ldr r0, =APP_HARD_FAULT  // Global variable address
     mov r1, #1               // r1 = 1
@@ -749,4 +749,20 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut Riscv32iStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = Riscv32iStoredState::try_from(input)?;
+        Ok(())
+    }
+
+    fn stack_pointer(&self, state: &Riscv32iStoredState) -> Option<usize> {
+        Some(state.regs[R_SP] as usize)
+    }
+
+    fn fault_address(&self, state: &Riscv32iStoredState) -> Option<usize> {
+        // `mtval` holds the faulting address for access faults and page
+        // faults, which are exceptions (the top bit of `mcause` is clear).
+        let access_fault = matches!(state.mcause, 1 | 4 | 5 | 6 | 7 | 12 | 13 | 15);
+        access_fault.then_some(state.mtval as usize)
+    }
 }
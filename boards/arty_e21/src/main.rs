@@ -237,8 +237,8 @@ unsafe fn start() -> (
 
     chip.enable_all_interrupts();
 
-    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel)
-        .finalize(components::priority_component_static!());
+    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel, &[])
+        .finalize(components::priority_component_static!(NUM_PROCS));
 
     let artye21 = ArtyE21 {
         console,
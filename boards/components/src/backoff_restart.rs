@@ -0,0 +1,111 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the fault policy that restarts processes with exponential
+//! backoff.
+//!
+//! The second argument of the static macro is the number of processes whose
+//! backoff is tracked, usually the number of processes of the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fault_policy = components::backoff_restart::BackoffRestartComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     BackoffConfig {
+//!         initial_delay_ms: 100,
+//!         max_delay_ms: 60_000,
+//!         healthy_ms: 600_000,
+//!         max_restarts: 10,
+//!         escalation: BackoffEscalation::Stop,
+//!     },
+//! )
+//! .finalize(components::backoff_restart_component_static!(
+//!     sam4l::ast::Ast,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::backoff_restart::{BackoffConfig, BackoffRestartFaultPolicy};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! backoff_restart_component_static {
+    ($A:ty, $NUM_PROCS:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let policy = kernel::static_buf!(
+            capsules_system::backoff_restart::BackoffRestartFaultPolicy<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                components::backoff_restart::Capability,
+                $NUM_PROCS,
+            >
+        );
+
+        (alarm, policy)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct BackoffRestartComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    config: BackoffConfig,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize>
+    BackoffRestartComponent<A, NUM_PROCS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        config: BackoffConfig,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+            config,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for BackoffRestartComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            BackoffRestartFaultPolicy<'static, VirtualMuxAlarm<'static, A>, Capability, NUM_PROCS>,
+        >,
+    );
+    type Output = &'static BackoffRestartFaultPolicy<
+        'static,
+        VirtualMuxAlarm<'static, A>,
+        Capability,
+        NUM_PROCS,
+    >;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let policy = static_buffer.1.write(BackoffRestartFaultPolicy::new(
+            self.board_kernel,
+            alarm,
+            self.config,
+            Capability,
+        ));
+        alarm.set_alarm_client(policy);
+        policy
+    }
+}
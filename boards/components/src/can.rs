@@ -7,8 +7,15 @@
 
 //! Component for CAN syscall interface.
 //!
-//! This provides one Component, `CanComponent`, which implements a
-//! userspace syscall interface to the Can peripheral.
+//! This provides four Components:
+//! - `CanComponent`, which implements a userspace syscall interface to the
+//!   Can peripheral.
+//! - `CanFdComponent`, which implements the same interface, with the CAN FD
+//!   commands, to a CAN FD peripheral.
+//! - `CanMuxComponent`, which virtualizes the transmit and receive interfaces
+//!   of a Can peripheral.
+//! - `VirtualCanDeviceComponent`, which provides one user of a `MuxCan` with
+//!   its own transmit, receive and filter interfaces.
 //!
 //! Usage
 //! -----
@@ -22,10 +29,49 @@
 //! ));
 //! ```
 //!
+//! ```rust
+//! let can = components::can::CanFdComponent::new(
+//!     board_kernel,
+//!     capsules_extra::can::DRIVER_NUM,
+//!     &peripherals.can_fd,
+//! ).finalize(components::can_fd_component_static!(
+//!     chip::can::CanFd<'static>
+//! ));
+//! ```
+//!
+//! ```rust
+//! let mux_can = components::can::CanMuxComponent::new(&peripherals.can1)
+//!     .finalize(components::can_mux_component_static!(
+//!         stm32f429zi::can::Can<'static>
+//!     ));
+//! let can_device = components::can::VirtualCanDeviceComponent::new(mux_can)
+//!     .finalize(components::virtual_can_device_component_static!(
+//!         stm32f429zi::can::Can<'static>
+//!     ));
+//! ```
+//!
+//! The syscall interface can use a virtual device, so that other kernel
+//! users can share the peripheral:
+//!
+//! ```rust
+//! let can = components::can::CanComponent::new(
+//!     board_kernel,
+//!     capsules_extra::can::DRIVER_NUM,
+//!     can_device,
+//! ).finalize(components::can_component_static!(
+//!     capsules_core::virtualizers::virtual_can::VirtualCanDevice<
+//!         'static,
+//!         stm32f429zi::can::Can<'static>,
+//!     >
+//! ));
+//! ```
+//!
 
+use capsules_core::virtualizers::virtual_can::{MuxCan, VirtualCanDevice};
 use capsules_extra::can::CanCapsule;
 use core::mem::MaybeUninit;
 use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
 use kernel::hil::can;
 use kernel::{capabilities, create_capability};
 
@@ -44,6 +90,39 @@ macro_rules! can_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! can_fd_component_static {
+    ($C:ty $(,)?) => {{
+        use kernel::hil::can;
+        use kernel::static_buf;
+
+        let CAN_TX_BUF = static_buf!([u8; can::FD_CAN_PACKET_SIZE]);
+        let CAN_RX_BUF = static_buf!([u8; can::FD_CAN_PACKET_SIZE]);
+        let can =
+            static_buf!(capsules_extra::can::CanCapsule<'static, $C, { can::FD_CAN_PACKET_SIZE }>);
+        (can, CAN_TX_BUF, CAN_RX_BUF)
+    };};
+}
+
+#[macro_export]
+macro_rules! can_mux_component_static {
+    ($C:ty $(,)?) => {{
+        use kernel::hil::can;
+        use kernel::static_buf;
+
+        let rx_buffer = static_buf!([u8; can::STANDARD_CAN_PACKET_SIZE]);
+        let mux = static_buf!(capsules_core::virtualizers::virtual_can::MuxCan<'static, $C>);
+        (mux, rx_buffer)
+    };};
+}
+
+#[macro_export]
+macro_rules! virtual_can_device_component_static {
+    ($C:ty $(,)?) => {{
+        kernel::static_buf!(capsules_core::virtualizers::virtual_can::VirtualCanDevice<'static, $C>)
+    };};
+}
+
 pub struct CanComponent<A: 'static + can::Can> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
@@ -89,3 +168,129 @@ impl<A: 'static + can::Can> Component for CanComponent<A> {
         can
     }
 }
+
+pub struct CanFdComponent<A: 'static + can::CanFd> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    can: &'static A,
+}
+
+impl<A: 'static + can::CanFd> CanFdComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        can: &'static A,
+    ) -> CanFdComponent<A> {
+        CanFdComponent {
+            board_kernel,
+            driver_num,
+            can,
+        }
+    }
+}
+
+impl<A: 'static + can::CanFd> Component for CanFdComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<CanCapsule<'static, A, { can::FD_CAN_PACKET_SIZE }>>,
+        &'static mut MaybeUninit<[u8; can::FD_CAN_PACKET_SIZE]>,
+        &'static mut MaybeUninit<[u8; can::FD_CAN_PACKET_SIZE]>,
+    );
+    type Output = &'static CanCapsule<'static, A, { can::FD_CAN_PACKET_SIZE }>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let grant_can = self.board_kernel.create_grant(self.driver_num, &grant_cap);
+
+        let can = static_buffer.0.write(capsules_extra::can::CanCapsule::new(
+            self.can,
+            grant_can,
+            static_buffer.1.write([0; can::FD_CAN_PACKET_SIZE]),
+            static_buffer.2.write([0; can::FD_CAN_PACKET_SIZE]),
+        ));
+        can::Controller::set_client(self.can, Some(can));
+        can::Transmit::set_client(self.can, Some(can));
+        can::Receive::set_client(self.can, Some(can));
+
+        can
+    }
+}
+
+pub struct CanMuxComponent<
+    A: 'static
+        + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+        + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+> {
+    can: &'static A,
+}
+
+impl<
+        A: 'static
+            + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+            + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+    > CanMuxComponent<A>
+{
+    pub fn new(can: &'static A) -> CanMuxComponent<A> {
+        CanMuxComponent { can }
+    }
+}
+
+impl<
+        A: 'static
+            + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+            + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+    > Component for CanMuxComponent<A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<MuxCan<'static, A>>,
+        &'static mut MaybeUninit<[u8; can::STANDARD_CAN_PACKET_SIZE]>,
+    );
+    type Output = &'static MuxCan<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let mux = static_buffer.0.write(MuxCan::new(
+            self.can,
+            static_buffer.1.write([0; can::STANDARD_CAN_PACKET_SIZE]),
+        ));
+        mux.register();
+        can::Transmit::set_client(self.can, Some(mux));
+        can::Receive::set_client(self.can, Some(mux));
+
+        mux
+    }
+}
+
+pub struct VirtualCanDeviceComponent<
+    A: 'static
+        + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+        + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+> {
+    mux: &'static MuxCan<'static, A>,
+}
+
+impl<
+        A: 'static
+            + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+            + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+    > VirtualCanDeviceComponent<A>
+{
+    pub fn new(mux: &'static MuxCan<'static, A>) -> VirtualCanDeviceComponent<A> {
+        VirtualCanDeviceComponent { mux }
+    }
+}
+
+impl<
+        A: 'static
+            + can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }>
+            + can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }>,
+    > Component for VirtualCanDeviceComponent<A>
+{
+    type StaticInput = &'static mut MaybeUninit<VirtualCanDevice<'static, A>>;
+    type Output = &'static VirtualCanDevice<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let device = static_buffer.write(VirtualCanDevice::new(self.mux));
+        device.setup();
+
+        device
+    }
+}
@@ -0,0 +1,147 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the ISO-TP (ISO 15765-2) syscall interface.
+//!
+//! This provides one Component, `CanIsoTpComponent`, which implements a
+//! userspace syscall interface for sending and receiving segmented messages
+//! over a classic CAN or a CAN FD peripheral.
+//!
+//! The component takes over the transmit and receive clients of the CAN
+//! peripheral, so it cannot be used together with `CanComponent` on the same
+//! peripheral. The peripheral must be configured and enabled by the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let isotp = components::can_isotp::CanIsoTpComponent::new(
+//!     board_kernel,
+//!     capsules_extra::can_isotp::DRIVER_NUM,
+//!     &peripherals.can1,
+//!     mux_alarm,
+//! )
+//! .finalize(components::can_isotp_component_static!(
+//!     stm32f429zi::can::Can<'static>,
+//!     stm32f429zi::tim2::Tim2<'static>
+//! ));
+//! ```
+//!
+//! For a CAN FD peripheral, pass the FD packet size to the static macro. The
+//! capsule then sends and receives frames of up to 64 bytes:
+//!
+//! ```rust
+//! let isotp = components::can_isotp::CanIsoTpComponent::new(
+//!     board_kernel,
+//!     capsules_extra::can_isotp::DRIVER_NUM,
+//!     &peripherals.fdcan,
+//!     mux_alarm,
+//! )
+//! .finalize(components::can_isotp_component_static!(
+//!     chip::fdcan::FdCan<'static>,
+//!     chip::timer::Timer<'static>,
+//!     kernel::hil::can::FD_CAN_PACKET_SIZE
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::can_isotp::CanIsoTp;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::can;
+use kernel::hil::time::{self, Alarm};
+use kernel::{capabilities, create_capability};
+
+#[macro_export]
+macro_rules! can_isotp_component_static {
+    ($C:ty, $A:ty $(,)?) => {{
+        $crate::can_isotp_component_static!($C, $A, kernel::hil::can::STANDARD_CAN_PACKET_SIZE)
+    };};
+    ($C:ty, $A:ty, $PACKET_SIZE:expr $(,)?) => {{
+        use kernel::static_buf;
+
+        let alarm =
+            static_buf!(capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>);
+        let tx_frame = static_buf!([u8; $PACKET_SIZE]);
+        let rx_frame = static_buf!([u8; $PACKET_SIZE]);
+        let isotp = static_buf!(
+            capsules_extra::can_isotp::CanIsoTp<
+                'static,
+                $C,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                { $PACKET_SIZE },
+            >
+        );
+        (alarm, tx_frame, rx_frame, isotp)
+    };};
+}
+
+pub type CanIsoTpComponentType<C, A, const PACKET_SIZE: usize = { can::STANDARD_CAN_PACKET_SIZE }> =
+    CanIsoTp<'static, C, VirtualMuxAlarm<'static, A>, PACKET_SIZE>;
+
+pub struct CanIsoTpComponent<
+    C: 'static + can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+    A: 'static + time::Alarm<'static>,
+    const PACKET_SIZE: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    can: &'static C,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<
+        C: 'static + can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: 'static + time::Alarm<'static>,
+        const PACKET_SIZE: usize,
+    > CanIsoTpComponent<C, A, PACKET_SIZE>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        can: &'static C,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        CanIsoTpComponent {
+            board_kernel,
+            driver_num,
+            can,
+            alarm_mux,
+        }
+    }
+}
+
+impl<
+        C: 'static + can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: 'static + time::Alarm<'static>,
+        const PACKET_SIZE: usize,
+    > Component for CanIsoTpComponent<C, A, PACKET_SIZE>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; PACKET_SIZE]>,
+        &'static mut MaybeUninit<[u8; PACKET_SIZE]>,
+        &'static mut MaybeUninit<CanIsoTp<'static, C, VirtualMuxAlarm<'static, A>, PACKET_SIZE>>,
+    );
+    type Output = &'static CanIsoTp<'static, C, VirtualMuxAlarm<'static, A>, PACKET_SIZE>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let isotp = static_buffer.3.write(CanIsoTp::new(
+            self.can,
+            alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            static_buffer.1.write([0; PACKET_SIZE]),
+            static_buffer.2.write([0; PACKET_SIZE]),
+        ));
+        alarm.set_alarm_client(isotp);
+        can::Transmit::set_client(self.can, Some(isotp));
+        can::Receive::set_client(self.can, Some(isotp));
+
+        isotp
+    }
+}
@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the fault policy that saves crash dumps to a log.
+//!
+//! The log must be dedicated to the crash records, as the capsule is set as
+//! its read and append client. `policy` decides what happens to a process
+//! after its crash record is saved.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let crash_dump = components::crash_dump::CrashDumpComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crash_dump::DRIVER_NUM,
+//!     log,
+//!     &FAULT_RESPONSE,
+//! )
+//! .finalize(components::crash_dump_component_static!(
+//!     capsules_extra::log::Log<'static, sam4l::flashcalw::FLASHCALW>
+//! ));
+//! crash_dump.set_syscall_tracer(syscall_tracer);
+//! ```
+//!
+//! Setting the syscall tracer is optional: with it, the crash records also
+//! hold the last system calls and upcalls of the faulted process.
+
+use capsules_extra::crash_dump::{CrashDump, RECORD_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::log::{LogRead, LogWrite};
+use kernel::process::ProcessFaultPolicy;
+
+#[macro_export]
+macro_rules! crash_dump_component_static {
+    ($L:ty $(,)?) => {{
+        let write_buffer = kernel::static_buf!([u8; capsules_extra::crash_dump::RECORD_LEN]);
+        let read_buffer = kernel::static_buf!([u8; capsules_extra::crash_dump::RECORD_LEN]);
+        let crash_dump = kernel::static_buf!(capsules_extra::crash_dump::CrashDump<'static, $L>);
+
+        (write_buffer, read_buffer, crash_dump)
+    };};
+}
+
+pub struct CrashDumpComponent<L: LogRead<'static, EntryID = usize> + LogWrite<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    log: &'static L,
+    policy: &'static dyn ProcessFaultPolicy,
+}
+
+impl<L: LogRead<'static, EntryID = usize> + LogWrite<'static>> CrashDumpComponent<L> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        log: &'static L,
+        policy: &'static dyn ProcessFaultPolicy,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            log,
+            policy,
+        }
+    }
+}
+
+impl<L: LogRead<'static, EntryID = usize> + LogWrite<'static>> Component for CrashDumpComponent<L> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; RECORD_LEN]>,
+        &'static mut MaybeUninit<[u8; RECORD_LEN]>,
+        &'static mut MaybeUninit<CrashDump<'static, L>>,
+    );
+    type Output = &'static CrashDump<'static, L>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let write_buffer = static_buffer.0.write([0; RECORD_LEN]);
+        let read_buffer = static_buffer.1.write([0; RECORD_LEN]);
+        let crash_dump = static_buffer.2.write(CrashDump::new(
+            self.log,
+            self.policy,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            write_buffer,
+            read_buffer,
+        ));
+        self.log.set_read_client(crash_dump);
+        self.log.set_append_client(crash_dump);
+        crash_dump
+    }
+}
@@ -0,0 +1,88 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the FAT filesystem driver.
+//!
+//! This provides one component, FatFilesystemComponent, which provides a
+//! system call interface to the files of a FAT16 or FAT32 volume stored on a
+//! `NonvolatileStorage`, such as an SD card.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sd_storage = static_init!(
+//!     capsules_extra::sdcard::SDCardNonvolatileStorage<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::sdcard::SDCardNonvolatileStorage::new(sdcard)
+//! );
+//! sdcard.set_client(sd_storage);
+//!
+//! let filesystem = components::fat_filesystem::FatFilesystemComponent::new(
+//!     board_kernel,
+//!     capsules_extra::fat_filesystem::DRIVER_NUM,
+//!     sd_storage,
+//! )
+//! .finalize(components::fat_filesystem_component_static!());
+//! ```
+
+use capsules_extra::fat_filesystem::{FatFilesystem, SECTOR_SIZE};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! fat_filesystem_component_static {
+    () => {{
+        let filesystem =
+            kernel::static_buf!(capsules_extra::fat_filesystem::FatFilesystem<'static>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::fat_filesystem::SECTOR_SIZE]);
+
+        (filesystem, buffer)
+    };};
+}
+
+pub struct FatFilesystemComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+}
+
+impl FatFilesystemComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            storage,
+        }
+    }
+}
+
+impl Component for FatFilesystemComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<FatFilesystem<'static>>,
+        &'static mut MaybeUninit<[u8; SECTOR_SIZE]>,
+    );
+    type Output = &'static FatFilesystem<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = static_buffer.1.write([0; SECTOR_SIZE]);
+
+        let filesystem = static_buffer.0.write(FatFilesystem::new(
+            self.storage,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+        ));
+        self.storage.set_client(filesystem);
+
+        filesystem
+    }
+}
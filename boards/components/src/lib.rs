@@ -15,6 +15,7 @@ pub mod app_flash_driver;
 pub mod app_loader;
 pub mod appid;
 pub mod atecc508a;
+pub mod backoff_restart;
 pub mod ble;
 pub mod bme280;
 pub mod bmm150;
@@ -22,10 +23,12 @@ pub mod bmp280;
 pub mod bus;
 pub mod button;
 pub mod can;
+pub mod can_isotp;
 pub mod ccs811;
 pub mod cdc;
 pub mod chirp_i2c_moisture;
 pub mod console;
+pub mod crash_dump;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
@@ -34,6 +37,7 @@ pub mod debug_writer;
 pub mod dfrobot_rainfall_sensor;
 pub mod dynamic_binary_storage;
 pub mod eui64;
+pub mod fat_filesystem;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
@@ -62,19 +66,25 @@ pub mod lsm303agr;
 pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
+pub mod memory_pool;
 pub mod mlx90614;
 pub mod moisture;
 pub mod mx25r6435f;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
+pub mod ota_udp;
 pub mod panic_button;
+pub mod power_manager;
 pub mod pressure;
 pub mod process_array;
+pub mod process_checkpoint;
 pub mod process_console;
 pub mod process_info_driver;
 pub mod process_printer;
+pub mod process_watchdog;
 pub mod proximity;
+pub mod pubsub;
 pub mod pwm;
 pub mod rainfall;
 pub mod rf233;
@@ -95,6 +105,9 @@ pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod storage_permissions;
+pub mod sync_ipc;
+pub mod syscall_rate_limit;
+pub mod syscall_trace;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
@@ -106,3 +119,4 @@ pub mod touch;
 pub mod udp_driver;
 pub mod udp_mux;
 pub mod usb;
+pub mod version_rollback;
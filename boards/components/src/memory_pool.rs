@@ -0,0 +1,76 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the shared memory pool processes can grow their grant
+//! region into.
+//!
+//! The component sets the pool as the memory pool of the kernel, with a
+//! policy that lets each process take up to `max_growth` bytes from it. The
+//! argument of the static macro is the number of blocks of
+//! `kernel::memory_pool::BLOCK_SIZE` bytes in the pool.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! components::memory_pool::MemoryPoolComponent::new(board_kernel, 512)
+//!     .finalize(components::memory_pool_component_static!(32));
+//! ```
+
+use capsules_system::process_policies::ThresholdMemoryGrowthPolicy;
+use core::cell::Cell;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::memory_pool::{MemoryBlock, MemoryPool};
+
+#[macro_export]
+macro_rules! memory_pool_component_static {
+    ($N:expr $(,)?) => {{
+        let blocks = kernel::static_buf!([kernel::memory_pool::MemoryBlock; $N]);
+        let owners = kernel::static_buf!([core::cell::Cell<Option<usize>>; $N]);
+        let policy =
+            kernel::static_buf!(capsules_system::process_policies::ThresholdMemoryGrowthPolicy);
+        let pool = kernel::static_buf!(kernel::memory_pool::MemoryPool);
+
+        (blocks, owners, policy, pool)
+    };};
+}
+
+pub struct MemoryPoolComponent<const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    max_growth: usize,
+}
+
+impl<const N: usize> MemoryPoolComponent<N> {
+    pub fn new(board_kernel: &'static kernel::Kernel, max_growth: usize) -> Self {
+        Self {
+            board_kernel,
+            max_growth,
+        }
+    }
+}
+
+impl<const N: usize> Component for MemoryPoolComponent<N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[MemoryBlock; N]>,
+        &'static mut MaybeUninit<[Cell<Option<usize>>; N]>,
+        &'static mut MaybeUninit<ThresholdMemoryGrowthPolicy>,
+        &'static mut MaybeUninit<MemoryPool>,
+    );
+    type Output = &'static MemoryPool;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let blocks = static_buffer
+            .0
+            .write(core::array::from_fn(|_| MemoryBlock::new()));
+        let owners = static_buffer
+            .1
+            .write(core::array::from_fn(|_| Cell::new(None)));
+        let policy = static_buffer
+            .2
+            .write(ThresholdMemoryGrowthPolicy::new(self.max_growth));
+        let pool = static_buffer.3.write(MemoryPool::new(blocks, owners));
+        self.board_kernel.set_memory_pool(pool, policy);
+        pool
+    }
+}
@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the over-the-air application update service over UDP.
+//!
+//! This provides one component, OtaUdpComponent, which binds the update
+//! service to a UDP port and makes it the client of the dynamic binary
+//! storage.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ota = components::ota_udp::OtaUdpComponent::new(
+//!     board_kernel,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     dynamic_binary_storage,
+//!     dynamic_binary_storage,
+//!     OTA_PORT,
+//! )
+//! .finalize(components::ota_udp_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     DynamicBinaryStorage<'static>,
+//!     DynamicBinaryStorage<'static>,
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use capsules_extra::ota_udp::{OtaUdp, REPLY_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::dynamic_binary_storage::{DynamicBinaryStore, DynamicProcessLoad};
+use kernel::hil::time::Alarm;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ota_udp_component_static {
+    ($A:ty, $S:ty, $L:ty $(,)?) => {{
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::ota_udp::REPLY_LEN]);
+        let write_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let ota = kernel::static_buf!(
+            capsules_extra::ota_udp::OtaUdp<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $S,
+                $L,
+                components::ota_udp::Capability,
+            >
+        );
+
+        (
+            udp_send,
+            udp_recv,
+            udp_vis_cap,
+            net_cap,
+            tx_buffer,
+            write_buffer,
+            alarm,
+            ota,
+        )
+    };};
+}
+
+pub struct OtaUdpComponent<
+    A: Alarm<'static> + 'static,
+    S: DynamicBinaryStore + 'static,
+    L: DynamicProcessLoad + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    mux_alarm: &'static MuxAlarm<'static, A>,
+    storage_driver: &'static S,
+    load_driver: &'static L,
+    port: u16,
+}
+
+impl<A: Alarm<'static>, S: DynamicBinaryStore + 'static, L: DynamicProcessLoad + 'static>
+    OtaUdpComponent<A, S, L>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        mux_alarm: &'static MuxAlarm<'static, A>,
+        storage_driver: &'static S,
+        load_driver: &'static L,
+        port: u16,
+    ) -> Self {
+        Self {
+            board_kernel,
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            mux_alarm,
+            storage_driver,
+            load_driver,
+            port,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, S: DynamicBinaryStore + 'static, L: DynamicProcessLoad + 'static> Component
+    for OtaUdpComponent<A, S, L>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<[u8; REPLY_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<OtaUdp<'static, VirtualMuxAlarm<'static, A>, S, L, Capability>>,
+    );
+    type Output = &'static OtaUdp<'static, VirtualMuxAlarm<'static, A>, S, L, Capability>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.2.write(UdpVisibilityCapability::new(&create_cap));
+        let net_cap = s.3.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Port(self.port),
+            &create_cap,
+        ));
+
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        let udp_recv = s.1.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let socket = self
+            .port_table
+            .create_socket()
+            .expect("No UDP socket left for the OTA service");
+        let Ok((send_binding, recv_binding)) = self.port_table.bind(socket, self.port, net_cap)
+        else {
+            panic!("Unable to bind the OTA service to UDP port {}", self.port);
+        };
+        udp_send.set_binding(send_binding);
+        udp_recv.set_binding(recv_binding);
+
+        let tx_buffer = s.4.write([0; REPLY_LEN]);
+        let write_buffer = s.5.write([0; MAX_PAYLOAD_LEN]);
+        let alarm = s.6.write(VirtualMuxAlarm::new(self.mux_alarm));
+        alarm.setup();
+        let ota = s.7.write(OtaUdp::new(
+            self.board_kernel,
+            alarm,
+            self.storage_driver,
+            self.load_driver,
+            udp_send,
+            net_cap,
+            SubSliceMut::new(tx_buffer),
+            write_buffer,
+            Capability,
+        ));
+        alarm.set_alarm_client(ota);
+        udp_send.set_client(ota);
+        udp_recv.set_client(ota);
+        self.storage_driver.set_storage_client(ota);
+        self.load_driver.set_load_client(ota);
+        ota
+    }
+}
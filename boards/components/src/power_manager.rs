@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for a power manager that selects sleep states from registered
+//! constraints.
+//!
+//! The component sets the power manager as the power manager of the kernel,
+//! and gives it a virtual alarm. The second argument of the static macro is
+//! the maximum number of constraints.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let power_manager =
+//!     components::power_manager::PowerManagerComponent::new(board_kernel, mux_alarm)
+//!         .finalize(components::power_manager_component_static!(
+//!             sam4l::ast::Ast,
+//!             4
+//!         ));
+//! power_manager.register(mux_alarm).unwrap();
+//! power_manager.register(uart_mux).unwrap();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::power_manager::ConstraintPowerManager;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::platform::power::PowerConstraint;
+use kernel::utilities::cells::OptionalCell;
+
+#[macro_export]
+macro_rules! power_manager_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let constraints = kernel::static_buf!(
+            [kernel::utilities::cells::OptionalCell<
+                &'static dyn kernel::platform::power::PowerConstraint,
+            >; $N]
+        );
+        let power_manager = kernel::static_buf!(
+            capsules_system::power_manager::ConstraintPowerManager<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, constraints, power_manager)
+    };};
+}
+
+pub struct PowerManagerComponent<A: 'static + time::Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> PowerManagerComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> Component for PowerManagerComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[OptionalCell<&'static dyn PowerConstraint>; N]>,
+        &'static mut MaybeUninit<ConstraintPowerManager<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static ConstraintPowerManager<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let constraints = static_buffer
+            .1
+            .write(core::array::from_fn(|_| OptionalCell::empty()));
+        let power_manager = static_buffer
+            .2
+            .write(ConstraintPowerManager::new(alarm, constraints));
+        alarm.set_alarm_client(power_manager);
+        power_manager.start();
+        self.board_kernel.set_power_manager(power_manager);
+        power_manager
+    }
+}
@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for checkpointing processes to nonvolatile storage.
+//!
+//! The storage must be dedicated to the checkpoints, as the capsule is set as
+//! its client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_checkpoint = components::process_checkpoint::ProcessCheckpointComponent::new(
+//!     board_kernel,
+//!     nv_to_page,
+//!     0x60000,
+//!     0x10000,
+//!     4,
+//!     ProcessMgmtCap,
+//! )
+//! .finalize(components::process_checkpoint_component_static!(
+//!     capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, sam4l::flashcalw::FLASHCALW>,
+//!     ProcessMgmtCap,
+//! ));
+//! ```
+
+use capsules_extra::process_checkpoint::{ProcessCheckpoint, BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::component::Component;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+
+#[macro_export]
+macro_rules! process_checkpoint_component_static {
+    ($S:ty, $C:ty $(,)?) => {{
+        let buffer = kernel::static_buf!([u8; capsules_extra::process_checkpoint::BUF_LEN]);
+        let process_checkpoint = kernel::static_buf!(
+            capsules_extra::process_checkpoint::ProcessCheckpoint<'static, $S, $C>
+        );
+
+        (buffer, process_checkpoint)
+    };};
+}
+
+pub struct ProcessCheckpointComponent<
+    S: NonvolatileStorage<'static> + 'static,
+    C: ProcessManagementCapability,
+> {
+    board_kernel: &'static kernel::Kernel,
+    storage: &'static S,
+    region_start: usize,
+    slot_len: usize,
+    num_slots: usize,
+    capability: C,
+}
+
+impl<S: NonvolatileStorage<'static>, C: ProcessManagementCapability>
+    ProcessCheckpointComponent<S, C>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        storage: &'static S,
+        region_start: usize,
+        slot_len: usize,
+        num_slots: usize,
+        capability: C,
+    ) -> Self {
+        Self {
+            board_kernel,
+            storage,
+            region_start,
+            slot_len,
+            num_slots,
+            capability,
+        }
+    }
+}
+
+impl<S: NonvolatileStorage<'static>, C: ProcessManagementCapability + 'static> Component
+    for ProcessCheckpointComponent<S, C>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<ProcessCheckpoint<'static, S, C>>,
+    );
+    type Output = &'static ProcessCheckpoint<'static, S, C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.0.write([0; BUF_LEN]);
+        let process_checkpoint = static_buffer.1.write(ProcessCheckpoint::new(
+            self.board_kernel,
+            self.storage,
+            buffer,
+            self.region_start,
+            self.slot_len,
+            self.num_slots,
+            self.capability,
+        ));
+        self.storage.set_client(process_checkpoint);
+        process_checkpoint
+    }
+}
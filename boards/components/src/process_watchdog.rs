@@ -0,0 +1,108 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the software watchdog that monitors the liveness of
+//! processes.
+//!
+//! The board must return the watchdog from `KernelResources::watchdog()`, so
+//! that the kernel tickles the hardware watchdog through it. Boards without a
+//! hardware watchdog can pass `&()`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_watchdog = components::process_watchdog::ProcessWatchdogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::process_watchdog::DRIVER_NUM,
+//!     mux_alarm,
+//!     &peripherals.wdt,
+//! )
+//! .finalize(components::process_watchdog_component_static!(
+//!     sam4l::ast::Ast,
+//!     sam4l::wdt::Wdt
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::process_watchdog::ProcessWatchdog;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+use kernel::platform::watchdog::WatchDog;
+
+#[macro_export]
+macro_rules! process_watchdog_component_static {
+    ($A:ty, $W:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let process_watchdog = kernel::static_buf!(
+            capsules_extra::process_watchdog::ProcessWatchdog<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $W,
+                components::process_watchdog::Capability,
+            >
+        );
+
+        (alarm, process_watchdog)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct ProcessWatchdogComponent<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    watchdog: &'static W,
+}
+
+impl<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> ProcessWatchdogComponent<A, W> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        watchdog: &'static W,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            watchdog,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> Component
+    for ProcessWatchdogComponent<A, W>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            ProcessWatchdog<'static, VirtualMuxAlarm<'static, A>, W, Capability>,
+        >,
+    );
+    type Output = &'static ProcessWatchdog<'static, VirtualMuxAlarm<'static, A>, W, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let process_watchdog = static_buffer.1.write(ProcessWatchdog::new(
+            self.board_kernel,
+            alarm,
+            self.watchdog,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            Capability,
+        ));
+        alarm.set_alarm_client(process_watchdog);
+        process_watchdog
+    }
+}
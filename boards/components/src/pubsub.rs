@@ -0,0 +1,55 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the publish/subscribe event bus.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pubsub = components::pubsub::PubSubComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pubsub::DRIVER_NUM,
+//! )
+//! .finalize(components::pubsub_component_static!());
+//! ```
+
+use capsules_extra::pubsub::PubSub;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! pubsub_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::pubsub::PubSub)
+    };};
+}
+
+pub struct PubSubComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl PubSubComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl Component for PubSubComponent {
+    type StaticInput = &'static mut MaybeUninit<PubSub>;
+    type Output = &'static PubSub;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(PubSub::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
@@ -0,0 +1,97 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for an earliest deadline first real-time scheduler.
+//!
+//! This provides one Component, EdfComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler = components::sched::edf::EdfComponent::new(mux_alarm, processes)
+//!     .finalize(components::edf_component_static!(
+//!         sam4l::ast::Ast,
+//!         NUM_PROCS
+//!     ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time;
+use kernel::process::ProcessArray;
+use kernel::scheduler::edf::{EdfProcessNode, EdfSched};
+
+#[macro_export]
+macro_rules! edf_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let edf_sched = kernel::static_buf!(
+            kernel::scheduler::edf::EdfSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let edf_node = kernel::static_buf!(
+            [core::mem::MaybeUninit<
+                kernel::scheduler::edf::EdfProcessNode<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >; $N]
+        );
+
+        (alarm, edf_sched, edf_node)
+    };};
+}
+
+pub struct EdfComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static ProcessArray<NUM_PROCS>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> EdfComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static ProcessArray<NUM_PROCS>,
+    ) -> EdfComponent<A, NUM_PROCS> {
+        EdfComponent {
+            alarm_mux,
+            processes,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for EdfComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EdfSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<
+            [MaybeUninit<EdfProcessNode<'static, VirtualMuxAlarm<'static, A>>>; NUM_PROCS],
+        >,
+    );
+    type Output = &'static mut EdfSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler = static_buffer.1.write(EdfSched::new(scheduler_alarm));
+
+        let nodes = static_buffer
+            .2
+            .write([const { MaybeUninit::uninit() }; NUM_PROCS]);
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(EdfProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_tail(init_node);
+        }
+        scheduler
+    }
+}
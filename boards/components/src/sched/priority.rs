@@ -4,42 +4,71 @@
 
 //! Component for a priority scheduler.
 //!
-//! This provides one Component, PriorityComponent.
+//! This provides one Component, PriorityComponent. The board can assign
+//! priorities to processes by name; other processes use the priority from
+//! their TBF header or their index in the process array.
 //!
 //! Usage
 //! -----
 //! ```rust
 //! let scheduler =
-//!     components::priority::PriorityComponent::new(board_kernel)
-//!         .finalize(components::priority_component_static!());
+//!     components::sched::priority::PriorityComponent::new(board_kernel, &[("control", 0)])
+//!         .finalize(components::priority_component_static!(NUM_PROCS));
+//! ```
+//!
+//! To let IPC services inherit the priority of their clients:
+//!
+//! ```rust
+//! ipc.set_notify_client(scheduler);
 //! ```
 
 use core::mem::MaybeUninit;
 use kernel::component::Component;
-use kernel::scheduler::priority::PrioritySched;
+use kernel::scheduler::priority::{PrioritySched, ProcessPriority};
 
 #[macro_export]
 macro_rules! priority_component_static {
-    () => {{
-        kernel::static_buf!(kernel::scheduler::priority::PrioritySched)
+    ($N:expr $(,)?) => {{
+        let scheduler = kernel::static_buf!(kernel::scheduler::priority::PrioritySched);
+        let priorities = kernel::static_buf!([kernel::scheduler::priority::ProcessPriority; $N]);
+
+        (scheduler, priorities)
     };};
 }
 
-pub struct PriorityComponent {
+pub struct PriorityComponent<const NUM_PROCS: usize> {
     board_kernel: &'static kernel::Kernel,
+    board_priorities: &'static [(&'static str, u32)],
 }
 
-impl PriorityComponent {
-    pub fn new(board_kernel: &'static kernel::Kernel) -> PriorityComponent {
-        PriorityComponent { board_kernel }
+impl<const NUM_PROCS: usize> PriorityComponent<NUM_PROCS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        board_priorities: &'static [(&'static str, u32)],
+    ) -> PriorityComponent<NUM_PROCS> {
+        PriorityComponent {
+            board_kernel,
+            board_priorities,
+        }
     }
 }
 
-impl Component for PriorityComponent {
-    type StaticInput = &'static mut MaybeUninit<PrioritySched>;
+impl<const NUM_PROCS: usize> Component for PriorityComponent<NUM_PROCS> {
+    type StaticInput = (
+        &'static mut MaybeUninit<PrioritySched>,
+        &'static mut MaybeUninit<[ProcessPriority; NUM_PROCS]>,
+    );
     type Output = &'static mut PrioritySched;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
-        static_buffer.write(PrioritySched::new(self.board_kernel))
+        let priorities = static_buffer
+            .1
+            .write([const { ProcessPriority::new() }; NUM_PROCS]);
+
+        static_buffer.0.write(PrioritySched::new(
+            self.board_kernel,
+            priorities,
+            self.board_priorities,
+        ))
     }
 }
@@ -0,0 +1,55 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the request/response IPC driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sync_ipc = components::sync_ipc::SyncIpcComponent::new(
+//!     board_kernel,
+//!     capsules_extra::sync_ipc::DRIVER_NUM,
+//! )
+//! .finalize(components::sync_ipc_component_static!());
+//! ```
+
+use capsules_extra::sync_ipc::SyncIpc;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! sync_ipc_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::sync_ipc::SyncIpc)
+    };};
+}
+
+pub struct SyncIpcComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl SyncIpcComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl Component for SyncIpcComponent {
+    type StaticInput = &'static mut MaybeUninit<SyncIpc>;
+    type Output = &'static SyncIpc;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(SyncIpc::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
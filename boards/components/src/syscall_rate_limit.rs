@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for a system call filter that limits how often each process can
+//! call a driver.
+//!
+//! The second argument of the static macro is the number of processes that
+//! can be limited at the same time, and the third the number of rate limits.
+//! The board returns the filter from `KernelResources::syscall_filter()`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let rate_limiter = components::syscall_rate_limit::SyscallRateLimitComponent::new(
+//!     mux_alarm,
+//!     &SYSCALL_RATE_LIMITS,
+//!     100,
+//!     RateLimitResponse::Busy,
+//! )
+//! .finalize(components::syscall_rate_limit_component_static!(
+//!     sam4l::ast::Ast,
+//!     NUM_PROCS,
+//!     1
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::syscall_rate_limit::{
+    RateLimitResponse, SyscallRateLimit, SyscallRateLimiter,
+};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! syscall_rate_limit_component_static {
+    ($A:ty, $NUM_PROCS:expr, $NUM_LIMITS:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let rate_limiter = kernel::static_buf!(
+            capsules_system::syscall_rate_limit::SyscallRateLimiter<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $NUM_PROCS,
+                $NUM_LIMITS,
+            >
+        );
+
+        (alarm, rate_limiter)
+    };};
+}
+
+pub struct SyscallRateLimitComponent<
+    A: 'static + time::Alarm<'static>,
+    const NUM_PROCS: usize,
+    const NUM_LIMITS: usize,
+> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    limits: &'static [SyscallRateLimit; NUM_LIMITS],
+    period_ms: u32,
+    response: RateLimitResponse,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_LIMITS: usize>
+    SyscallRateLimitComponent<A, NUM_PROCS, NUM_LIMITS>
+{
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        limits: &'static [SyscallRateLimit; NUM_LIMITS],
+        period_ms: u32,
+        response: RateLimitResponse,
+    ) -> Self {
+        Self {
+            alarm_mux,
+            limits,
+            period_ms,
+            response,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_LIMITS: usize> Component
+    for SyscallRateLimitComponent<A, NUM_PROCS, NUM_LIMITS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            SyscallRateLimiter<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS, NUM_LIMITS>,
+        >,
+    );
+    type Output =
+        &'static SyscallRateLimiter<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS, NUM_LIMITS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let rate_limiter = static_buffer.1.write(SyscallRateLimiter::new(
+            alarm,
+            self.limits,
+            self.period_ms,
+            self.response,
+        ));
+        alarm.set_alarm_client(rate_limiter);
+        rate_limiter
+    }
+}
@@ -0,0 +1,65 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for a system call tracer that stores records in a ring buffer.
+//!
+//! The component sets the tracer as the system call tracer of the kernel. The
+//! second argument of the static macro is the size of the ring buffer, which
+//! holds one record less than its size.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let syscall_tracer =
+//!     components::syscall_trace::SyscallTraceComponent::new(board_kernel, &peripherals.ast)
+//!         .finalize(components::syscall_trace_component_static!(
+//!             sam4l::ast::Ast,
+//!             64
+//!         ));
+//! ```
+
+use capsules_system::syscall_trace::SyscallTraceBuffer;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::Time;
+use kernel::syscall_trace::TraceRecord;
+
+#[macro_export]
+macro_rules! syscall_trace_component_static {
+    ($T:ty, $N:expr $(,)?) => {{
+        let buffer = kernel::static_buf!([kernel::syscall_trace::TraceRecord; $N]);
+        let tracer =
+            kernel::static_buf!(capsules_system::syscall_trace::SyscallTraceBuffer<'static, $T>);
+
+        (buffer, tracer)
+    };};
+}
+
+pub struct SyscallTraceComponent<T: 'static + Time, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    time: &'static T,
+}
+
+impl<T: 'static + Time, const N: usize> SyscallTraceComponent<T, N> {
+    pub fn new(board_kernel: &'static kernel::Kernel, time: &'static T) -> Self {
+        Self { board_kernel, time }
+    }
+}
+
+impl<T: 'static + Time, const N: usize> Component for SyscallTraceComponent<T, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[TraceRecord; N]>,
+        &'static mut MaybeUninit<SyscallTraceBuffer<'static, T>>,
+    );
+    type Output = &'static SyscallTraceBuffer<'static, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.0.write([TraceRecord::default(); N]);
+        let tracer = static_buffer
+            .1
+            .write(SyscallTraceBuffer::new(self.time, buffer));
+        self.board_kernel.set_syscall_tracer(tracer);
+        tracer
+    }
+}
@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Component for the fault policy that rolls apps back to their previous
+//! version when a new version keeps faulting.
+//!
+//! The last argument of the static macro is the number of apps whose known
+//! good version is recorded, usually the number of processes of the board.
+//! The storage region starting at the given address must be at least
+//! `capsules_system::version_rollback::storage_len(NUM_PROCS)` bytes long.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fault_policy = components::version_rollback::VersionRollbackComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     dynamic_binary_storage,
+//!     rollback_pages,
+//!     ROLLBACK_TABLE_ADDRESS,
+//!     &DEFAULT_FAULT_POLICY,
+//!     RollbackConfig {
+//!         window_ms: 60_000,
+//!         max_faults: 3,
+//!     },
+//! )
+//! .finalize(components::version_rollback_component_static!(
+//!     sam4l::ast::Ast,
+//!     DynamicBinaryStorage<'static>,
+//!     NonVolatilePages,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::version_rollback::{RollbackConfig, VersionRollbackFaultPolicy};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::dynamic_binary_storage::DynamicProcessLoad;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::ProcessFaultPolicy;
+
+#[macro_export]
+macro_rules! version_rollback_component_static {
+    ($A:ty, $L:ty, $S:ty, $NUM_PROCS:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer =
+            kernel::static_buf!([u8; capsules_system::version_rollback::storage_len($NUM_PROCS)]);
+        let policy = kernel::static_buf!(
+            capsules_system::version_rollback::VersionRollbackFaultPolicy<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $L,
+                $S,
+                components::version_rollback::Capability,
+                $NUM_PROCS,
+            >
+        );
+
+        (alarm, buffer, policy)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct VersionRollbackComponent<
+    A: 'static + time::Alarm<'static>,
+    L: DynamicProcessLoad + 'static,
+    S: NonvolatileStorage<'static> + 'static,
+    const NUM_PROCS: usize,
+    const BUF_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    load_driver: &'static L,
+    storage: &'static S,
+    storage_address: usize,
+    inner: &'static dyn ProcessFaultPolicy,
+    config: RollbackConfig,
+}
+
+impl<
+        A: 'static + time::Alarm<'static>,
+        L: DynamicProcessLoad + 'static,
+        S: NonvolatileStorage<'static> + 'static,
+        const NUM_PROCS: usize,
+        const BUF_LEN: usize,
+    > VersionRollbackComponent<A, L, S, NUM_PROCS, BUF_LEN>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        load_driver: &'static L,
+        storage: &'static S,
+        storage_address: usize,
+        inner: &'static dyn ProcessFaultPolicy,
+        config: RollbackConfig,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+            load_driver,
+            storage,
+            storage_address,
+            inner,
+            config,
+        }
+    }
+}
+
+impl<
+        A: 'static + time::Alarm<'static>,
+        L: DynamicProcessLoad + 'static,
+        S: NonvolatileStorage<'static> + 'static,
+        const NUM_PROCS: usize,
+        const BUF_LEN: usize,
+    > Component for VersionRollbackComponent<A, L, S, NUM_PROCS, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<
+            VersionRollbackFaultPolicy<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                L,
+                S,
+                Capability,
+                NUM_PROCS,
+            >,
+        >,
+    );
+    type Output = &'static VersionRollbackFaultPolicy<
+        'static,
+        VirtualMuxAlarm<'static, A>,
+        L,
+        S,
+        Capability,
+        NUM_PROCS,
+    >;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let buffer = static_buffer.1.write([0; BUF_LEN]);
+        let policy = static_buffer.2.write(VersionRollbackFaultPolicy::new(
+            self.board_kernel,
+            alarm,
+            self.load_driver,
+            self.storage,
+            self.storage_address,
+            buffer,
+            self.inner,
+            self.config,
+            Capability,
+        ));
+        alarm.set_alarm_client(policy);
+        self.storage.set_client(policy);
+        let _ = policy.init();
+        policy
+    }
+}
@@ -91,6 +91,8 @@ impl TestLauncher {
             4 => unsafe { test::aes_test::run_aes128_cbc(&self.peripherals.ecb, self) },
             5 => unsafe { test::aes_test::run_aes128_ecb(&self.peripherals.ecb, self) },
             6 => unsafe { test::ecdsa_p256_test::run_ecdsa_p256(self) },
+            7 => unsafe { test::ecdsa_p384_test::run_ecdsa_p384(self) },
+            8 => unsafe { test::ed25519ph_test::run_ed25519ph(self) },
             _ => kernel::debug!("All tests finished."),
         }
     }
@@ -0,0 +1,71 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! This tests a software ECDSA P384 verifier. To run this test,
+//! add this line to the boot sequence:
+//! ```
+//! test::ecdsa_p384_test::run_ecdsa_p384(client);
+//! ```
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use core::ptr::addr_of_mut;
+use ecdsa_sw::p384_verifier::EcdsaP384SignatureVerifier;
+use ecdsa_sw::test::p384::TestEcdsaP384Verify;
+use kernel::static_init;
+
+// The test vector is the first P-384, SHA-384 vector of `SigGen.txt` in the
+// NIST CAVP FIPS 186-4 ECDSA test vectors (`186-4ecdsatestvectors.zip`).
+
+// HHASH is the signed hash: the SHA-384 hash of `Msg`.
+pub static mut HHASH: [u8; 48] = [
+    0x31, 0xA4, 0x52, 0xD6, 0x16, 0x4D, 0x90, 0x4B, 0xB5, 0x72, 0x4C, 0x87, 0x82, 0x80, 0x23, 0x1E,
+    0xAE, 0x70, 0x5C, 0x29, 0xCE, 0x9D, 0x4B, 0xC7, 0xD5, 0x8E, 0x02, 0x0E, 0x10, 0x85, 0xF1, 0x7E,
+    0xEB, 0xCC, 0x1A, 0x38, 0xF0, 0xED, 0x0B, 0xF2, 0xB3, 0x44, 0xD8, 0x1F, 0xBD, 0x89, 0x68, 0x25,
+];
+
+// PKEY is the public key used for verifying, encoded as the values `Qx` and
+// `Qy` both in big-endian byte order concatenated.
+pub static mut PKEY: [u8; 96] = [
+    0xC2, 0xB4, 0x79, 0x44, 0xFB, 0x5D, 0xE3, 0x42, 0xD0, 0x32, 0x85, 0x88, 0x01, 0x77, 0xCA, 0x5F,
+    0x7D, 0x0F, 0x2F, 0xCA, 0xD7, 0x67, 0x8C, 0xCE, 0x42, 0x29, 0xD6, 0xE1, 0x93, 0x2F, 0xCA, 0xC1,
+    0x1B, 0xFC, 0x3C, 0x3E, 0x97, 0xD9, 0x42, 0xA3, 0xC5, 0x6B, 0xF3, 0x41, 0x23, 0x01, 0x3D, 0xBF,
+    0x37, 0x25, 0x79, 0x06, 0xA8, 0x22, 0x38, 0x66, 0xED, 0xA0, 0x74, 0x3C, 0x51, 0x96, 0x16, 0xA7,
+    0x6A, 0x75, 0x8A, 0xE5, 0x8A, 0xEE, 0x81, 0xC5, 0xFD, 0x35, 0xFB, 0xF3, 0xA8, 0x55, 0xB7, 0x75,
+    0x4A, 0x36, 0xD4, 0xA0, 0x67, 0x2D, 0xF9, 0x5D, 0x6C, 0x44, 0xA8, 0x1C, 0xF7, 0x62, 0x0C, 0x2D,
+];
+
+// HSIG is the signature of the hash in HHASH, encoded as the values `R` and
+// `S` both in big-endian byte order concatenated.
+pub static mut HSIG: [u8; 96] = [
+    0x50, 0x83, 0x5A, 0x92, 0x51, 0xBA, 0xD0, 0x08, 0x10, 0x61, 0x77, 0xEF, 0x00, 0x4B, 0x09, 0x1A,
+    0x1E, 0x42, 0x35, 0xCD, 0x0D, 0xA8, 0x4F, 0xFF, 0x54, 0x54, 0x2B, 0x0E, 0xD7, 0x55, 0xC1, 0xD6,
+    0xF2, 0x51, 0x60, 0x9D, 0x14, 0xEC, 0xF1, 0x8F, 0x9E, 0x1D, 0xDF, 0xE6, 0x9B, 0x94, 0x6E, 0x32,
+    0x04, 0x75, 0xF3, 0xD3, 0x0C, 0x64, 0x63, 0xB6, 0x46, 0xE8, 0xD3, 0xBF, 0x24, 0x55, 0x83, 0x03,
+    0x14, 0x61, 0x1C, 0xBD, 0xE4, 0x04, 0xBE, 0x51, 0x8B, 0x14, 0x46, 0x4F, 0xDB, 0x19, 0x5F, 0xDC,
+    0xC9, 0x2E, 0xB2, 0x22, 0xE6, 0x1F, 0x42, 0x6A, 0x4A, 0x59, 0x2C, 0x00, 0xA6, 0xA8, 0x97, 0x21,
+];
+
+pub unsafe fn run_ecdsa_p384(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_ecdsa_p384(client);
+    t.run();
+}
+
+unsafe fn static_init_test_ecdsa_p384(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestEcdsaP384Verify {
+    let ecdsa = static_init!(
+        EcdsaP384SignatureVerifier<'static>,
+        EcdsaP384SignatureVerifier::new(&mut *addr_of_mut!(PKEY)),
+    );
+    kernel::deferred_call::DeferredCallClient::register(ecdsa);
+
+    let test = static_init!(
+        TestEcdsaP384Verify,
+        TestEcdsaP384Verify::new(ecdsa, &mut *addr_of_mut!(HHASH), &mut *addr_of_mut!(HSIG))
+    );
+
+    test.set_client(client);
+
+    test
+}
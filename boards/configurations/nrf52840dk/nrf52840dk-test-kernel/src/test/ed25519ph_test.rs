@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! This tests a software Ed25519ph verifier. To run this test,
+//! add this line to the boot sequence:
+//! ```
+//! test::ed25519ph_test::run_ed25519ph(client);
+//! ```
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use core::ptr::addr_of_mut;
+use ed25519_sw::ed25519ph_verifier::Ed25519phSignatureVerifier;
+use ed25519_sw::test::ed25519ph::TestEd25519phVerify;
+use kernel::static_init;
+
+// The test vector is the Ed25519ph vector "TEST abc" of RFC 8032, section 7.3.
+
+// HHASH is the signed hash: the SHA-512 hash of the message "abc".
+pub static mut HHASH: [u8; 64] = [
+    0xDD, 0xAF, 0x35, 0xA1, 0x93, 0x61, 0x7A, 0xBA, 0xCC, 0x41, 0x73, 0x49, 0xAE, 0x20, 0x41, 0x31,
+    0x12, 0xE6, 0xFA, 0x4E, 0x89, 0xA9, 0x7E, 0xA2, 0x0A, 0x9E, 0xEE, 0xE6, 0x4B, 0x55, 0xD3, 0x9A,
+    0x21, 0x92, 0x99, 0x2A, 0x27, 0x4F, 0xC1, 0xA8, 0x36, 0xBA, 0x3C, 0x23, 0xA3, 0xFE, 0xEB, 0xBD,
+    0x45, 0x4D, 0x44, 0x23, 0x64, 0x3C, 0xE8, 0x0E, 0x2A, 0x9A, 0xC9, 0x4F, 0xA5, 0x4C, 0xA4, 0x9F,
+];
+
+// PKEY is the public key used for verifying.
+pub static mut PKEY: [u8; 32] = [
+    0xEC, 0x17, 0x2B, 0x93, 0xAD, 0x5E, 0x56, 0x3B, 0xF4, 0x93, 0x2C, 0x70, 0xE1, 0x24, 0x50, 0x34,
+    0xC3, 0x54, 0x67, 0xEF, 0x2E, 0xFD, 0x4D, 0x64, 0xEB, 0xF8, 0x19, 0x68, 0x34, 0x67, 0xE2, 0xBF,
+];
+
+// HSIG is the Ed25519ph signature of the message, with an empty context.
+pub static mut HSIG: [u8; 64] = [
+    0x98, 0xA7, 0x02, 0x22, 0xF0, 0xB8, 0x12, 0x1A, 0xA9, 0xD3, 0x0F, 0x81, 0x3D, 0x68, 0x3F, 0x80,
+    0x9E, 0x46, 0x2B, 0x46, 0x9C, 0x7F, 0xF8, 0x76, 0x39, 0x49, 0x9B, 0xB9, 0x4E, 0x6D, 0xAE, 0x41,
+    0x31, 0xF8, 0x50, 0x42, 0x46, 0x3C, 0x2A, 0x35, 0x5A, 0x20, 0x03, 0xD0, 0x62, 0xAD, 0xF5, 0xAA,
+    0xA1, 0x0B, 0x8C, 0x61, 0xE6, 0x36, 0x06, 0x2A, 0xAA, 0xD1, 0x1C, 0x2A, 0x26, 0x08, 0x34, 0x06,
+];
+
+pub unsafe fn run_ed25519ph(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_ed25519ph(client);
+    t.run();
+}
+
+unsafe fn static_init_test_ed25519ph(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestEd25519phVerify {
+    let ed25519 = static_init!(
+        Ed25519phSignatureVerifier<'static>,
+        Ed25519phSignatureVerifier::new(&mut *addr_of_mut!(PKEY)),
+    );
+    kernel::deferred_call::DeferredCallClient::register(ed25519);
+
+    let test = static_init!(
+        TestEd25519phVerify,
+        TestEd25519phVerify::new(ed25519, &mut *addr_of_mut!(HHASH), &mut *addr_of_mut!(HSIG))
+    );
+
+    test.set_client(client);
+
+    test
+}
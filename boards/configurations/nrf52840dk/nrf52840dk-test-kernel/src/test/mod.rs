@@ -4,6 +4,8 @@
 
 pub(crate) mod aes_test;
 pub(crate) mod ecdsa_p256_test;
+pub(crate) mod ecdsa_p384_test;
+pub(crate) mod ed25519ph_test;
 pub(crate) mod hmac_sha256_test;
 pub(crate) mod sha256_test;
 pub(crate) mod siphash24_test;
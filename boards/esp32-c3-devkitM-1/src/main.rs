@@ -269,8 +269,8 @@ unsafe fn setup() -> (
         static _eappmem: u8;
     }
 
-    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel)
-        .finalize(components::priority_component_static!());
+    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel, &[])
+        .finalize(components::priority_component_static!(NUM_PROCS));
 
     // PROCESS CONSOLE
     let process_console = components::process_console::ProcessConsoleComponent::new(
@@ -364,8 +364,8 @@ fn test_runner(tests: &[&dyn Fn()]) {
         PLATFORM = Some(&esp32_c3_board);
         PERIPHERALS = Some(peripherals);
         SCHEDULER = Some(
-            components::sched::priority::PriorityComponent::new(board_kernel)
-                .finalize(components::priority_component_static!()),
+            components::sched::priority::PriorityComponent::new(board_kernel, &[])
+                .finalize(components::priority_component_static!(NUM_PROCS)),
         );
         MAIN_CAP = Some(&create_capability!(capabilities::MainLoopCapability));
 
@@ -94,6 +94,26 @@ const PAN_ID: u16 = 0xABCD;
 const FAULT_RESPONSE: capsules_system::process_policies::StopFaultPolicy =
     capsules_system::process_policies::StopFaultPolicy {};
 
+// Limit how often each process can use the SPI controller, which is shared by
+// all processes.
+const SYSCALL_RATE_LIMITS: [capsules_system::syscall_rate_limit::SyscallRateLimit; 1] =
+    [capsules_system::syscall_rate_limit::SyscallRateLimit {
+        driver_num: capsules_core::spi_controller::DRIVER_NUM,
+        burst: 20,
+        refill: 10,
+    }];
+
+// Coalesce button upcalls so a button storm cannot fill the upcall queue of a
+// process and make it miss its alarm upcalls.
+const UPCALL_QUEUE_POLICY: capsules_system::process_policies::QuotaUpcallQueuePolicy =
+    capsules_system::process_policies::QuotaUpcallQueuePolicy::new(
+        &[capsules_system::process_policies::DriverUpcallQuota {
+            driver_num: capsules_core::button::DRIVER_NUM,
+            max_pending: 2,
+        }],
+        false,
+    );
+
 /// Static variables used by io.rs.
 static mut PROCESSES: Option<&'static ProcessArray<NUM_PROCS>> = None;
 static mut CHIP: Option<&'static sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> = None;
@@ -164,9 +184,23 @@ struct Imix {
     nonvolatile_storage:
         &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
     scheduler: &'static RoundRobinSched<'static>,
+    syscall_filter: &'static capsules_system::syscall_rate_limit::SyscallRateLimiter<
+        'static,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+        NUM_PROCS,
+        1,
+    >,
+    process_watchdog: &'static ProcessWatchdog,
     systick: cortexm4::systick::SysTick,
 }
 
+type ProcessWatchdog = capsules_extra::process_watchdog::ProcessWatchdog<
+    'static,
+    VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    (),
+    components::process_watchdog::Capability,
+>;
+
 impl SyscallDriverLookup for Imix {
     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
     where
@@ -193,6 +227,7 @@ impl SyscallDriverLookup for Imix {
                 f(Some(self.nonvolatile_storage))
             }
             capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules_extra::process_watchdog::DRIVER_NUM => f(Some(self.process_watchdog)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -201,18 +236,23 @@ impl SyscallDriverLookup for Imix {
 
 impl KernelResources<sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> for Imix {
     type SyscallDriverLookup = Self;
-    type SyscallFilter = ();
+    type SyscallFilter = capsules_system::syscall_rate_limit::SyscallRateLimiter<
+        'static,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+        NUM_PROCS,
+        1,
+    >;
     type ProcessFault = ();
     type Scheduler = RoundRobinSched<'static>;
     type SchedulerTimer = cortexm4::systick::SysTick;
-    type WatchDog = ();
+    type WatchDog = ProcessWatchdog;
     type ContextSwitchCallback = ();
 
     fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
         self
     }
     fn syscall_filter(&self) -> &Self::SyscallFilter {
-        &()
+        self.syscall_filter
     }
     fn process_fault(&self) -> &Self::ProcessFault {
         &()
@@ -224,7 +264,7 @@ impl KernelResources<sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> for Imix {
         &self.systick
     }
     fn watchdog(&self) -> &Self::WatchDog {
-        &()
+        self.process_watchdog
     }
     fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
         &()
@@ -377,6 +417,61 @@ unsafe fn start() -> (
         AlarmDriverComponent::new(board_kernel, capsules_core::alarm::DRIVER_NUM, mux_alarm)
             .finalize(components::alarm_component_static!(sam4l::ast::Ast));
 
+    // # POWER MANAGEMENT
+    // Only deep sleep when no alarm is due soon and no UART transmission is in
+    // progress.
+    let power_manager =
+        components::power_manager::PowerManagerComponent::new(board_kernel, mux_alarm).finalize(
+            components::power_manager_component_static!(sam4l::ast::Ast<'static>, 2),
+        );
+    power_manager.register(mux_alarm).unwrap();
+    power_manager.register(uart_mux).unwrap();
+
+    // # MEMORY POOL
+    // Let processes whose grant region is full grow into a shared pool of 1 kB.
+    components::memory_pool::MemoryPoolComponent::new(board_kernel, 512)
+        .finalize(components::memory_pool_component_static!(16));
+
+    // # SYSCALL RATE LIMIT
+    let syscall_filter = components::syscall_rate_limit::SyscallRateLimitComponent::new(
+        mux_alarm,
+        &SYSCALL_RATE_LIMITS,
+        100,
+        capsules_system::syscall_rate_limit::RateLimitResponse::Busy,
+    )
+    .finalize(components::syscall_rate_limit_component_static!(
+        sam4l::ast::Ast,
+        NUM_PROCS,
+        1
+    ));
+
+    // # PROCESS WATCHDOG
+    // Fault processes that stop checking in. Imix does not use its hardware
+    // watchdog.
+    let process_watchdog = components::process_watchdog::ProcessWatchdogComponent::new(
+        board_kernel,
+        capsules_extra::process_watchdog::DRIVER_NUM,
+        mux_alarm,
+        &(),
+    )
+    .finalize(components::process_watchdog_component_static!(
+        sam4l::ast::Ast,
+        ()
+    ));
+
+    // # UPCALL QUEUE
+    board_kernel.set_upcall_queue_policy(&UPCALL_QUEUE_POLICY);
+
+    // # SYSCALL TRACE
+    // Keep the last 31 system calls and upcalls for the `trace` command of the
+    // process console.
+    let syscall_tracer =
+        components::syscall_trace::SyscallTraceComponent::new(board_kernel, &peripherals.ast)
+            .finalize(components::syscall_trace_component_static!(
+                sam4l::ast::Ast<'static>,
+                32
+            ));
+
     let pconsole = ProcessConsoleComponent::new(
         board_kernel,
         uart_mux,
@@ -387,6 +482,7 @@ unsafe fn start() -> (
     .finalize(components::process_console_component_static!(
         sam4l::ast::Ast
     ));
+    pconsole.set_syscall_tracer(syscall_tracer);
 
     let console = ConsoleOrderedComponent::new(
         board_kernel,
@@ -789,6 +885,8 @@ unsafe fn start() -> (
         nrf51822: nrf_serialization,
         nonvolatile_storage,
         scheduler,
+        syscall_filter,
+        process_watchdog,
         systick: cortexm4::systick::SysTick::new(),
     };
 
@@ -821,6 +919,7 @@ unsafe fn start() -> (
     //    mux_alarm,
     //    &peripherals.flash_controller,
     //);
+    //test::log_recovery_test::run(&peripherals.flash_controller);
     //test::icmp_lowpan_test::run(mux_mac, mux_alarm);
     //let lowpan_frag_test = test::ipv6_lowpan_test::initialize_all(mux_mac, mux_alarm);
     //lowpan_frag_test.start(); // If flashing the transmitting Imix
@@ -0,0 +1,53 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Tests that the log recovers from power loss and corruption, using the
+//! `log_recovery` test of `capsules_extra`.
+//!
+//! To run the test, add the following line to the imix boot sequence:
+//! ```
+//!     test::log_recovery_test::run(&peripherals.flash_controller);
+//! ```
+//! The test prints `LogRecoveryTest: passed` when it succeeds.
+
+use capsules_extra::test::log_recovery::{
+    FaultInjectingFlash, LogRecoveryTest, TestLog, BOOTS, BUFFER_LEN,
+};
+use core::mem::MaybeUninit;
+use kernel::hil::flash;
+use kernel::static_init;
+use kernel::storage_volume;
+use sam4l::flashcalw;
+
+// Allocate 2kiB volume for log storage.
+storage_volume!(RECOVERY_TEST_LOG, 2);
+
+pub unsafe fn run(flash_controller: &'static sam4l::flashcalw::FLASHCALW) {
+    // Set up flash controller.
+    flash_controller.configure();
+
+    let flash = static_init!(
+        FaultInjectingFlash<'static, flashcalw::FLASHCALW>,
+        FaultInjectingFlash::new(
+            flash_controller,
+            static_init!(flashcalw::Sam4lPage, flashcalw::Sam4lPage::default())
+        )
+    );
+    flash::HasClient::set_client(flash_controller, flash);
+
+    let test = static_init!(
+        LogRecoveryTest<flashcalw::FLASHCALW>,
+        LogRecoveryTest::new(
+            flash,
+            &RECOVERY_TEST_LOG,
+            static_init!(
+                [MaybeUninit<TestLog<flashcalw::FLASHCALW>>; BOOTS],
+                [const { MaybeUninit::uninit() }; BOOTS]
+            ),
+            static_init!([flashcalw::Sam4lPage; BOOTS], Default::default()),
+            static_init!([u8; BUFFER_LEN], [0; BUFFER_LEN]),
+        )
+    );
+    test.run();
+}
@@ -8,6 +8,7 @@ pub(crate) mod i2c_dummy;
 pub(crate) mod icmp_lowpan_test;
 pub(crate) mod ipv6_lowpan_test;
 pub(crate) mod linear_log_test;
+pub(crate) mod log_recovery_test;
 pub(crate) mod log_test;
 pub(crate) mod rng_test;
 pub(crate) mod sha256_test;
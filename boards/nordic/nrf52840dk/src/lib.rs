@@ -457,6 +457,11 @@ pub unsafe fn start_no_pconsole() -> (
     // Setup space to store the core kernel data structure.
     let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(processes.as_slice()));
 
+    // Measure the CPU time used by processes and the kernel with the DWT cycle
+    // counter. The core runs at 64 MHz.
+    let dwt = static_init!(cortexm4::dwt::Dwt, cortexm4::dwt::Dwt::new());
+    board_kernel.set_cycle_counter(dwt, 64_000_000);
+
     // Create (and save for panic debugging) a chip object to setup low-level
     // resources (e.g. MPU, systick).
     let chip = static_init!(Chip, nrf52840::chip::NRF52::new(nrf52840_peripherals));
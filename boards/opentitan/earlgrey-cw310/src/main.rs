@@ -839,8 +839,8 @@ unsafe fn setup() -> (
     hil::symmetric_encryption::AES128::set_client(gcm_client, ccm_client);
 
     let syscall_filter = static_init!(TbfHeaderFilterDefaultAllow, TbfHeaderFilterDefaultAllow {});
-    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel)
-        .finalize(components::priority_component_static!());
+    let scheduler = components::sched::priority::PriorityComponent::new(board_kernel, &[])
+        .finalize(components::priority_component_static!(NUM_PROCS));
     let watchdog = &peripherals.watchdog;
 
     let earlgrey = static_init!(
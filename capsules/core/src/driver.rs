@@ -28,6 +28,10 @@ pub enum NUM {
     Ipc                   = 0x10000,
     AppLoader             = 0x10001,
     ProcessInfo           = 0x10002,
+    SyncIpc               = 0x10003,
+    PubSub                = 0x10004,
+    CrashDump             = 0x10005,
+    ProcessWatchdog       = 0x10006,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -36,6 +40,7 @@ pub enum NUM {
     UsbUser               = 0x20005,
     I2cMasterSlave        = 0x20006,
     Can                   = 0x20007,
+    CanIsoTp              = 0x20008,
 
     // Networking
     BleAdvertising        = 0x30000,
@@ -61,6 +66,7 @@ pub enum NUM {
     SdCard                = 0x50002,
     Kv                    = 0x50003,
     IsolatedNvmStorage    = 0x50004,
+    FatFilesystem         = 0x50005,
 
     // Sensors
     Temperature           = 0x60000,
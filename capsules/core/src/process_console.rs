@@ -15,6 +15,7 @@ use kernel::capabilities::ProcessManagementCapability;
 use kernel::capabilities::ProcessStartCapability;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
@@ -23,6 +24,8 @@ use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
 use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::scheduler::priority::PriorityControl;
+use kernel::syscall_trace::SyscallTracer;
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::ErrorCode;
 use kernel::Kernel;
@@ -44,7 +47,13 @@ pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stop start fault boot terminate process priority trace crashes kernel reset panic console-start console-stop\r\n";
+
+/// Prints crash records saved by a fault policy, for the `crashes` command.
+pub trait CrashDumpPrinter {
+    /// Start printing all saved crash records with `debug!()`, oldest first.
+    fn print_crash_dumps(&self) -> Result<(), ErrorCode>;
+}
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -91,6 +100,10 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    Trace {
+        index: isize,
+        total: isize,
+    },
 }
 
 /// Key that can be part from an escape sequence.
@@ -271,6 +284,15 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Scheduler whose process priorities can be shown and changed.
+    priority_control: OptionalCell<&'a dyn PriorityControl>,
+
+    /// Tracer whose system call records are printed by the `trace` command.
+    syscall_tracer: OptionalCell<&'a dyn SyscallTracer>,
+
+    /// Crash records printed by the `crashes` command.
+    crash_dump_printer: OptionalCell<&'a dyn CrashDumpPrinter>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -477,10 +499,31 @@ impl<
             kernel,
             kernel_addresses,
             reset_function,
+            priority_control: OptionalCell::empty(),
+            syscall_tracer: OptionalCell::empty(),
+            crash_dump_printer: OptionalCell::empty(),
             capability,
         }
     }
 
+    /// Enable the `priority` command with the scheduler that manages process
+    /// priorities.
+    pub fn set_priority_control(&self, priority_control: &'a dyn PriorityControl) {
+        self.priority_control.set(priority_control);
+    }
+
+    /// Enable the `trace` command, which prints and removes the records
+    /// stored by `syscall_tracer`.
+    pub fn set_syscall_tracer(&self, syscall_tracer: &'a dyn SyscallTracer) {
+        self.syscall_tracer.set(syscall_tracer);
+    }
+
+    /// Enable the `crashes` command, which prints the crash records saved by
+    /// `crash_dump_printer`.
+    pub fn set_crash_dump_printer(&self, crash_dump_printer: &'a dyn CrashDumpPrinter) {
+        self.crash_dump_printer.set(crash_dump_printer);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -563,6 +606,16 @@ impl<
                     }
                 }
             }
+            WriterState::Trace { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Trace {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -732,9 +785,10 @@ impl<
                             let _ = write(
                                 &mut console_writer,
                                 format_args!(
-                                    "{:<20}{:6}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
+                                    "{:<20}{:6}{:10}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
                                     pname,
                                     process.debug_timeslice_expiration_count(),
+                                    process.debug_cpu_time_us() / 1000,
                                     process.debug_syscall_count(),
                                     process.get_restart_count(),
                                     grants_used,
@@ -747,6 +801,15 @@ impl<
                         }
                     });
             }
+            WriterState::Trace { .. } => {
+                self.syscall_tracer.map(|tracer| {
+                    if let Some(record) = tracer.take() {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(&mut console_writer, format_args!("{}\r\n", record));
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -895,7 +958,7 @@ impl<
                         } else if clean_str.starts_with("list") {
                             let _ = self
                                 .write_bytes(b" PID    ShortID    Name                Quanta  ");
-                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
+                            let _ = self.write_bytes(b" CPU(ms)  Syscalls  Restarts  Grants  State\r\n");
 
                             // Count the number of current processes.
                             let mut count = 0;
@@ -910,6 +973,52 @@ impl<
                                     total: count,
                                 });
                             }
+                        } else if clean_str.starts_with("trace") {
+                            self.syscall_tracer.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"System call tracing is disabled.\r\n");
+                                },
+                                |tracer| {
+                                    let count = tracer.len();
+                                    let mut console_writer = ConsoleWriter::new();
+                                    let _ = write(
+                                        &mut console_writer,
+                                        format_args!(
+                                            "{} records, {} dropped\r\n",
+                                            count,
+                                            tracer.dropped()
+                                        ),
+                                    );
+                                    let _ = self
+                                        .write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                                    if count > 0 {
+                                        // Print the records that are stored
+                                        // now, one per state, so records added
+                                        // while printing do not keep the
+                                        // console busy.
+                                        self.write_state(WriterState::Trace {
+                                            index: -1,
+                                            total: count as isize,
+                                        });
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("crashes") {
+                            let result = self
+                                .crash_dump_printer
+                                .map_or(Err(ErrorCode::NOSUPPORT), |printer| {
+                                    printer.print_crash_dumps()
+                                });
+                            match result {
+                                Ok(()) => {}
+                                Err(ErrorCode::NOSUPPORT) => {
+                                    let _ = self.write_bytes(b"Crash dumps are disabled.\r\n");
+                                }
+                                Err(_) => {
+                                    let _ = self.write_bytes(b"Crash dumps are busy.\r\n");
+                                }
+                            }
                         } else if clean_str.starts_with("status") {
                             let info: KernelInfo = KernelInfo::new(self.kernel);
                             let mut console_writer = ConsoleWriter::new();
@@ -939,6 +1048,24 @@ impl<
                                 ),
                             );
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            console_writer.clear();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    "Process CPU time: {} ms\r\n",
+                                    info.processes_cpu_time_us(&self.capability) / 1000
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            console_writer.clear();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    "Kernel CPU time: {} ms\r\n",
+                                    info.kernel_cpu_time_us(&self.capability) / 1000
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else if clean_str.starts_with("process") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -977,6 +1104,76 @@ impl<
                                         }
                                     });
                             });
+                        } else if clean_str.starts_with("priority") {
+                            let mut arguments = clean_str.split_whitespace().skip(1);
+                            let name = arguments.next();
+                            let priority = arguments.next().map(|p| p.parse::<u32>());
+                            self.priority_control.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(
+                                        b"Priorities are not supported by the scheduler\r\n",
+                                    );
+                                },
+                                |priority_control| {
+                                    name.map(|name| {
+                                        self.kernel.process_each_capability(
+                                            &self.capability,
+                                            |proc| {
+                                                let proc_name = proc.get_process_name();
+                                                if proc_name != name {
+                                                    return;
+                                                }
+                                                let processid = proc.processid();
+                                                let mut console_writer = ConsoleWriter::new();
+                                                let _ = match priority {
+                                                    Some(Ok(priority)) => {
+                                                        match priority_control.set_priority(
+                                                            processid,
+                                                            priority,
+                                                            &self.capability,
+                                                        ) {
+                                                            Ok(()) => write(
+                                                                &mut console_writer,
+                                                                format_args!(
+                                                                    "Process {} priority set to {}\r\n",
+                                                                    proc_name, priority
+                                                                ),
+                                                            ),
+                                                            Err(e) => write(
+                                                                &mut console_writer,
+                                                                format_args!(
+                                                                    "Failed to set priority of {}: {:?}\r\n",
+                                                                    proc_name, e
+                                                                ),
+                                                            ),
+                                                        }
+                                                    }
+                                                    Some(Err(_)) => write(
+                                                        &mut console_writer,
+                                                        format_args!(
+                                                            "Usage: priority <name> [<priority>]\r\n"
+                                                        ),
+                                                    ),
+                                                    None => write(
+                                                        &mut console_writer,
+                                                        format_args!(
+                                                            "Process {} priority: {}\r\n",
+                                                            proc_name,
+                                                            priority_control
+                                                                .get_priority(processid)
+                                                                .unwrap_or(0)
+                                                        ),
+                                                    ),
+                                                };
+
+                                                let _ = self.write_bytes(
+                                                    &(console_writer.buf)[..console_writer.size],
+                                                );
+                                            },
+                                        );
+                                    });
+                                },
+                            );
                         } else if clean_str.starts_with("kernel") {
                             let mut console_writer = ConsoleWriter::new();
                             let _ = write(
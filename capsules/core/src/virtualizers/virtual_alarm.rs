@@ -8,7 +8,8 @@
 use core::cell::Cell;
 
 use kernel::collections::list::{List, ListLink, ListNode};
-use kernel::hil::time::{self, Alarm, Ticks, Time};
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks, Time};
+use kernel::platform::power::{PowerConstraint, PowerRequirement};
 use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
 
@@ -234,6 +235,27 @@ impl<'a, A: Alarm<'a>> MuxAlarm<'a, A> {
     }
 }
 
+/// Sleep states that take longer to wake up from than the time left until the
+/// next alarm would delay it, so the chip only enters them when the next alarm
+/// is far enough away.
+impl<'a, A: Alarm<'a>> PowerConstraint for MuxAlarm<'a, A> {
+    fn power_requirement(&self) -> PowerRequirement {
+        match self.next_tick_vals.get() {
+            Some((reference, dt)) => {
+                let now = self.alarm.now();
+                let expiration = reference.wrapping_add(dt);
+                let remaining = if now.within_range(reference, expiration) {
+                    expiration.wrapping_sub(now)
+                } else {
+                    A::Ticks::from(0u32)
+                };
+                PowerRequirement::WakeupWithinUs(self.alarm.ticks_to_us(remaining))
+            }
+            None => PowerRequirement::Unconstrained,
+        }
+    }
+}
+
 impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarm<'a, A> {
     /// When the underlying alarm has fired, we have to multiplex this event back to the virtual
     /// alarms that should now fire.
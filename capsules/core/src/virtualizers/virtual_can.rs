@@ -0,0 +1,534 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Virtualize a CAN controller.
+//!
+//! `MuxCan` provides shared access to a single CAN controller for multiple
+//! users. `VirtualCanDevice` provides the `Transmit`, `Receive` and `Filter`
+//! interfaces to one user.
+//!
+//! Each virtual device can send one frame at a time. When several devices
+//! have a frame waiting, the mux sends the one with the highest bus priority
+//! (the lowest identifier) first, as the bus arbitration would.
+//!
+//! Filters are applied in software: a receiving virtual device gets all the
+//! frames accepted by at least one of its enabled filters, or all the frames
+//! if it has no filter enabled. The controller itself is configured to
+//! receive every frame as long as at least one virtual device is receiving.
+//!
+//! If the controller implements them, virtual devices also provide the
+//! `Configure`, `ConfigureFd` and `Controller` interfaces, which act on the
+//! shared controller. The `Controller` callbacks are delivered to every
+//! virtual device that has a controller client, as a change of the state of
+//! the controller affects all of them. This lets a capsule that configures
+//! the controller, such as the userspace CAN driver, share it with other
+//! users of the mux. Otherwise, the controller must be configured and
+//! enabled by the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let mux_can = static_init!(
+//!     MuxCan<'static, stm32f429zi::can::Can<'static>>,
+//!     MuxCan::new(&peripherals.can1, rx_buffer)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(mux_can);
+//! kernel::hil::can::Transmit::set_client(&peripherals.can1, Some(mux_can));
+//! kernel::hil::can::Receive::set_client(&peripherals.can1, Some(mux_can));
+//!
+//! let can_device = static_init!(
+//!     VirtualCanDevice<'static, stm32f429zi::can::Can<'static>>,
+//!     VirtualCanDevice::new(mux_can)
+//! );
+//! can_device.setup();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::can;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Copy, Clone, PartialEq)]
+enum TxOp {
+    Idle,
+    Send(can::Id, usize),
+    /// The frame could not be handed to the controller and the client must
+    /// be notified from a deferred call.
+    Complete(Result<(), can::Error>),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RxState {
+    Stopped,
+    Receiving,
+    /// `stop_receive` was called and the `stopped` callback is delivered from
+    /// a deferred call.
+    Stopping,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum HwRxState {
+    Idle,
+    Receiving,
+    Stopping,
+}
+
+pub struct MuxCan<
+    'a,
+    C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+    const PACKET_SIZE: usize = { can::STANDARD_CAN_PACKET_SIZE },
+> {
+    can: &'a C,
+    devices: List<'a, VirtualCanDevice<'a, C, PACKET_SIZE>>,
+    tx_inflight: OptionalCell<&'a VirtualCanDevice<'a, C, PACKET_SIZE>>,
+    rx_buffer: TakeCell<'static, [u8; PACKET_SIZE]>,
+    rx_state: Cell<HwRxState>,
+    /// Whether the mux is the controller client of the controller.
+    controller_client: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    MuxCan<'a, C, PACKET_SIZE>
+{
+    pub fn new(can: &'a C, rx_buffer: &'static mut [u8; PACKET_SIZE]) -> Self {
+        Self {
+            can,
+            devices: List::new(),
+            tx_inflight: OptionalCell::empty(),
+            rx_buffer: TakeCell::new(rx_buffer),
+            rx_state: Cell::new(HwRxState::Idle),
+            controller_client: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Sends the waiting frame with the highest bus priority, if the
+    /// controller is not already sending a frame.
+    fn do_next_op(&self) {
+        if self.tx_inflight.is_some() {
+            return;
+        }
+
+        let next = self
+            .devices
+            .iter()
+            .filter_map(|node| match node.tx_operation.get() {
+                TxOp::Send(id, len) => Some((node, id, len)),
+                _ => None,
+            })
+            .min_by_key(|(_, id, _)| id.arbitration_priority());
+
+        if let Some((node, id, len)) = next {
+            node.tx_buffer.take().map(|buffer| {
+                node.tx_operation.set(TxOp::Idle);
+                match self.can.send(id, buffer, len) {
+                    Ok(()) => self.tx_inflight.set(node),
+                    Err((_, buffer)) => {
+                        node.tx_buffer.replace(buffer);
+                        node.tx_operation
+                            .set(TxOp::Complete(Err(can::Error::Transmission)));
+                        self.deferred_call.set();
+                    }
+                }
+            });
+        }
+    }
+
+    /// Starts receiving frames on the controller, unless it is already
+    /// receiving.
+    fn start_receive(&self) -> Result<(), ErrorCode> {
+        match self.rx_state.get() {
+            HwRxState::Receiving => Ok(()),
+            // The controller is restarted when it returns the buffer.
+            HwRxState::Stopping => Ok(()),
+            HwRxState::Idle => self
+                .rx_buffer
+                .take()
+                .map_or(Err(ErrorCode::BUSY), |buffer| {
+                    match self.can.start_receive_process(buffer) {
+                        Ok(()) => {
+                            self.rx_state.set(HwRxState::Receiving);
+                            Ok(())
+                        }
+                        Err((err, buffer)) => {
+                            self.rx_buffer.replace(buffer);
+                            Err(err)
+                        }
+                    }
+                }),
+        }
+    }
+
+    /// Stops receiving frames on the controller if no virtual device is
+    /// receiving anymore.
+    fn stop_receive_if_unused(&self) {
+        let in_use = self
+            .devices
+            .iter()
+            .any(|node| node.rx_state.get() == RxState::Receiving);
+        if !in_use && self.rx_state.get() == HwRxState::Receiving && self.can.stop_receive().is_ok()
+        {
+            self.rx_state.set(HwRxState::Stopping);
+        }
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    DeferredCallClient for MuxCan<'_, C, PACKET_SIZE>
+{
+    fn handle_deferred_call(&self) {
+        for node in self.devices.iter() {
+            if let TxOp::Complete(status) = node.tx_operation.get() {
+                node.tx_operation.set(TxOp::Idle);
+                node.tx_buffer.take().map(|buffer| {
+                    node.tx_client
+                        .map(move |client| client.transmit_complete(status, buffer));
+                });
+            }
+            if node.rx_state.get() == RxState::Stopping {
+                node.rx_state.set(RxState::Stopped);
+                node.rx_buffer.take().map(|buffer| {
+                    node.rx_client.map(move |client| client.stopped(buffer));
+                });
+            }
+        }
+        self.do_next_op();
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::TransmitClient<PACKET_SIZE> for MuxCan<'_, C, PACKET_SIZE>
+{
+    fn transmit_complete(
+        &self,
+        status: Result<(), can::Error>,
+        buffer: &'static mut [u8; PACKET_SIZE],
+    ) {
+        if let Some(node) = self.tx_inflight.take() {
+            node.tx_client
+                .map(move |client| client.transmit_complete(status, buffer));
+        }
+        self.do_next_op();
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::ReceiveClient<PACKET_SIZE> for MuxCan<'_, C, PACKET_SIZE>
+{
+    fn message_received(
+        &self,
+        id: can::Id,
+        buffer: &mut [u8; PACKET_SIZE],
+        len: usize,
+        status: Result<(), can::Error>,
+    ) {
+        for node in self.devices.iter() {
+            // Errors are reported to all the receiving devices.
+            if node.rx_state.get() != RxState::Receiving || (status.is_ok() && !node.accepts(id)) {
+                continue;
+            }
+            node.rx_buffer.map(|node_buffer| {
+                node_buffer.copy_from_slice(buffer);
+                node.rx_client
+                    .map(|client| client.message_received(id, node_buffer, len, status));
+            });
+        }
+    }
+
+    fn stopped(&self, buffer: &'static mut [u8; PACKET_SIZE]) {
+        self.rx_buffer.replace(buffer);
+        self.rx_state.set(HwRxState::Idle);
+
+        // A device may have started receiving while the controller was
+        // stopping.
+        if self
+            .devices
+            .iter()
+            .any(|node| node.rx_state.get() == RxState::Receiving)
+        {
+            let _ = self.start_receive();
+        }
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::ControllerClient for MuxCan<'_, C, PACKET_SIZE>
+{
+    fn state_changed(&self, state: can::State) {
+        for node in self.devices.iter() {
+            node.controller_client
+                .map(|client| client.state_changed(state));
+        }
+    }
+
+    fn enabled(&self, status: Result<(), ErrorCode>) {
+        for node in self.devices.iter() {
+            node.controller_client.map(|client| client.enabled(status));
+        }
+    }
+
+    fn disabled(&self, status: Result<(), ErrorCode>) {
+        for node in self.devices.iter() {
+            node.controller_client.map(|client| client.disabled(status));
+        }
+    }
+}
+
+pub struct VirtualCanDevice<
+    'a,
+    C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+    const PACKET_SIZE: usize = { can::STANDARD_CAN_PACKET_SIZE },
+> {
+    mux: &'a MuxCan<'a, C, PACKET_SIZE>,
+    tx_buffer: TakeCell<'static, [u8; PACKET_SIZE]>,
+    tx_operation: Cell<TxOp>,
+    rx_buffer: TakeCell<'static, [u8; PACKET_SIZE]>,
+    rx_state: Cell<RxState>,
+    filters: [Cell<Option<can::FilterParameters>>; can::SOFTWARE_FILTER_COUNT],
+    next: ListLink<'a, VirtualCanDevice<'a, C, PACKET_SIZE>>,
+    tx_client: OptionalCell<&'static dyn can::TransmitClient<PACKET_SIZE>>,
+    rx_client: OptionalCell<&'static dyn can::ReceiveClient<PACKET_SIZE>>,
+    controller_client: OptionalCell<&'static dyn can::ControllerClient>,
+}
+
+impl<'a, C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    VirtualCanDevice<'a, C, PACKET_SIZE>
+{
+    pub fn new(mux: &'a MuxCan<'a, C, PACKET_SIZE>) -> Self {
+        Self {
+            mux,
+            tx_buffer: TakeCell::empty(),
+            tx_operation: Cell::new(TxOp::Idle),
+            rx_buffer: TakeCell::empty(),
+            rx_state: Cell::new(RxState::Stopped),
+            filters: Default::default(),
+            next: ListLink::empty(),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            controller_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Must be called right after `static_init!()`.
+    pub fn setup(&'a self) {
+        self.mux.devices.push_head(self);
+    }
+
+    /// Returns whether a frame with the identifier `id` passes the filters
+    /// of this device.
+    fn accepts(&self, id: can::Id) -> bool {
+        can::FilterParameters::accepted_by(self.filters.iter().filter_map(Cell::get), id)
+    }
+}
+
+impl<'a, C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    ListNode<'a, VirtualCanDevice<'a, C, PACKET_SIZE>> for VirtualCanDevice<'a, C, PACKET_SIZE>
+{
+    fn next(&'a self) -> &'a ListLink<'a, VirtualCanDevice<'a, C, PACKET_SIZE>> {
+        &self.next
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::Transmit<PACKET_SIZE> for VirtualCanDevice<'_, C, PACKET_SIZE>
+{
+    fn set_client(&self, client: Option<&'static dyn can::TransmitClient<PACKET_SIZE>>) {
+        self.tx_client.insert(client);
+    }
+
+    fn send(
+        &self,
+        id: can::Id,
+        buffer: &'static mut [u8; PACKET_SIZE],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8; PACKET_SIZE])> {
+        if self.tx_operation.get() != TxOp::Idle || self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if len > PACKET_SIZE {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.tx_buffer.replace(buffer);
+        self.tx_operation.set(TxOp::Send(id, len));
+        self.mux.do_next_op();
+        Ok(())
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::Receive<PACKET_SIZE> for VirtualCanDevice<'_, C, PACKET_SIZE>
+{
+    fn set_client(&self, client: Option<&'static dyn can::ReceiveClient<PACKET_SIZE>>) {
+        self.rx_client.insert(client);
+    }
+
+    fn start_receive_process(
+        &self,
+        buffer: &'static mut [u8; PACKET_SIZE],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; PACKET_SIZE])> {
+        if self.rx_state.get() != RxState::Stopped {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.rx_buffer.replace(buffer);
+        self.rx_state.set(RxState::Receiving);
+        match self.mux.start_receive() {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.rx_state.set(RxState::Stopped);
+                match self.rx_buffer.take() {
+                    Some(buffer) => Err((err, buffer)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn stop_receive(&self) -> Result<(), ErrorCode> {
+        if self.rx_state.get() != RxState::Receiving {
+            return Err(ErrorCode::OFF);
+        }
+        self.rx_state.set(RxState::Stopping);
+        self.mux.deferred_call.set();
+        self.mux.stop_receive_if_unused();
+        Ok(())
+    }
+}
+
+impl<C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>, const PACKET_SIZE: usize>
+    can::Filter for VirtualCanDevice<'_, C, PACKET_SIZE>
+{
+    fn enable_filter(&self, filter: can::FilterParameters) -> Result<(), ErrorCode> {
+        self.filters
+            .get(filter.number as usize)
+            .map_or(Err(ErrorCode::INVAL), |slot| {
+                slot.set(Some(filter));
+                Ok(())
+            })
+    }
+
+    fn disable_filter(&self, number: u32) -> Result<(), ErrorCode> {
+        self.filters
+            .get(number as usize)
+            .map_or(Err(ErrorCode::INVAL), |slot| {
+                slot.set(None);
+                Ok(())
+            })
+    }
+
+    fn filter_count(&self) -> usize {
+        can::SOFTWARE_FILTER_COUNT
+    }
+}
+
+impl<
+        C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure,
+        const PACKET_SIZE: usize,
+    > can::Configure for VirtualCanDevice<'_, C, PACKET_SIZE>
+{
+    const MIN_BIT_TIMINGS: can::BitTiming = C::MIN_BIT_TIMINGS;
+    const MAX_BIT_TIMINGS: can::BitTiming = C::MAX_BIT_TIMINGS;
+    const SYNC_SEG: u8 = C::SYNC_SEG;
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<(), ErrorCode> {
+        self.mux.can.set_bitrate(bitrate)
+    }
+
+    fn set_bit_timing(&self, bit_timing: can::BitTiming) -> Result<(), ErrorCode> {
+        self.mux.can.set_bit_timing(bit_timing)
+    }
+
+    fn set_operation_mode(&self, mode: can::OperationMode) -> Result<(), ErrorCode> {
+        self.mux.can.set_operation_mode(mode)
+    }
+
+    fn get_bit_timing(&self) -> Result<can::BitTiming, ErrorCode> {
+        self.mux.can.get_bit_timing()
+    }
+
+    fn get_operation_mode(&self) -> Result<can::OperationMode, ErrorCode> {
+        self.mux.can.get_operation_mode()
+    }
+
+    fn set_automatic_retransmission(&self, automatic: bool) -> Result<(), ErrorCode> {
+        self.mux.can.set_automatic_retransmission(automatic)
+    }
+
+    fn set_wake_up(&self, wake_up: bool) -> Result<(), ErrorCode> {
+        self.mux.can.set_wake_up(wake_up)
+    }
+
+    fn get_automatic_retransmission(&self) -> Result<bool, ErrorCode> {
+        self.mux.can.get_automatic_retransmission()
+    }
+
+    fn get_wake_up(&self) -> Result<bool, ErrorCode> {
+        self.mux.can.get_wake_up()
+    }
+
+    fn receive_fifo_count(&self) -> usize {
+        self.mux.can.receive_fifo_count()
+    }
+}
+
+impl<
+        C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::ConfigureFd,
+        const PACKET_SIZE: usize,
+    > can::ConfigureFd for VirtualCanDevice<'_, C, PACKET_SIZE>
+{
+    fn set_payload_bit_timing(&self, payload_bit_timing: can::BitTiming) -> Result<(), ErrorCode> {
+        self.mux.can.set_payload_bit_timing(payload_bit_timing)
+    }
+
+    fn get_payload_bit_timing(&self) -> Result<can::BitTiming, ErrorCode> {
+        self.mux.can.get_payload_bit_timing()
+    }
+
+    fn set_bit_rate_switching(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.mux.can.set_bit_rate_switching(enabled)
+    }
+
+    fn get_bit_rate_switching(&self) -> Result<bool, ErrorCode> {
+        self.mux.can.get_bit_rate_switching()
+    }
+
+    fn get_frame_size() -> usize {
+        C::get_frame_size()
+    }
+}
+
+/// The mux registers itself as the controller client of the controller when
+/// the first virtual device sets a controller client.
+impl<
+        C: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Controller,
+        const PACKET_SIZE: usize,
+    > can::Controller for VirtualCanDevice<'static, C, PACKET_SIZE>
+{
+    fn set_client(&self, client: Option<&'static dyn can::ControllerClient>) {
+        if client.is_some() && !self.mux.controller_client.replace(true) {
+            can::Controller::set_client(self.mux.can, Some(self.mux));
+        }
+        self.controller_client.insert(client);
+    }
+
+    fn enable(&self) -> Result<(), ErrorCode> {
+        self.mux.can.enable()
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        self.mux.can.disable()
+    }
+
+    fn get_state(&self) -> Result<can::State, ErrorCode> {
+        self.mux.can.get_state()
+    }
+}
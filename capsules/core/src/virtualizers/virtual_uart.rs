@@ -51,6 +51,7 @@ use core::cmp;
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::uart;
+use kernel::platform::power::{PowerConstraint, PowerRequirement, SleepState};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
@@ -66,6 +67,18 @@ pub struct MuxUart<'a> {
     deferred_call: DeferredCall,
 }
 
+/// Transmissions need the high-frequency clocks, so the chip must not enter
+/// deeper sleep states while one is in progress.
+impl PowerConstraint for MuxUart<'_> {
+    fn power_requirement(&self) -> PowerRequirement {
+        if self.inflight.is_some() {
+            PowerRequirement::MaxSleepState(SleepState::Sleep)
+        } else {
+            PowerRequirement::Unconstrained
+        }
+    }
+}
+
 impl uart::TransmitClient for MuxUart<'_> {
     fn transmitted_buffer(
         &self,
@@ -9,3 +9,5 @@ pub mod test;
 
 pub mod p256_signer;
 pub mod p256_verifier;
+pub mod p384_verifier;
+pub mod sha_software;
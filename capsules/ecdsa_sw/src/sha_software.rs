@@ -0,0 +1,256 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Software implementations of SHA-384 and SHA-512.
+//!
+//! SHA-384 is the hash of P384 signatures, and SHA-512 the prehash of Ed25519ph
+//! signatures, for example to check the `EcdsaNistP384` and `Ed25519ph`
+//! credentials of process binaries on chips without a SHA-384 or SHA-512
+//! engine.
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::digest::{Client, ClientData, ClientHash, ClientVerify};
+use kernel::hil::digest::{ClientDataHash, ClientDataVerify, DigestDataHash, DigestDataVerify};
+use kernel::hil::digest::{Digest, DigestData, DigestHash, DigestVerify};
+use kernel::hil::digest::{Sha384, Sha512};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::leasable_buffer::SubSliceMutImmut;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Data,
+    Hash,
+    Verify,
+    CancelData,
+    CancelHash,
+    CancelVerify,
+}
+
+/// SHA-384 in software.
+pub type Sha384Software<'a> = ShaSoftware<'a, sha2::Sha384, 48>;
+
+/// SHA-512 in software.
+pub type Sha512Software<'a> = ShaSoftware<'a, sha2::Sha512, 64>;
+
+/// A SHA-2 hash `H` in software, with `L` bytes long hashes.
+pub struct ShaSoftware<'a, H, const L: usize> {
+    state: Cell<State>,
+    data_client: OptionalCell<&'a dyn ClientData<L>>,
+    hash_client: OptionalCell<&'a dyn ClientHash<L>>,
+    verify_client: OptionalCell<&'a dyn ClientVerify<L>>,
+    input_data: OptionalCell<SubSliceMutImmut<'static, u8>>,
+    hasher: MapCell<H>,
+    // Used to store the hash or the hash to compare against with verify
+    output_data: TakeCell<'static, [u8; L]>,
+    verified: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<H: sha2::Digest, const L: usize> ShaSoftware<'_, H, L> {
+    pub fn new() -> Self {
+        Self {
+            state: Cell::new(State::Idle),
+            data_client: OptionalCell::empty(),
+            hash_client: OptionalCell::empty(),
+            verify_client: OptionalCell::empty(),
+            input_data: OptionalCell::empty(),
+            hasher: MapCell::new(H::new()),
+            output_data: TakeCell::empty(),
+            verified: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    /// Returns the hash of the data added so far, and starts a new hash.
+    fn finalize(&self) -> [u8; L] {
+        let mut hash = [0; L];
+        self.hasher.map(|hasher| {
+            hash.copy_from_slice(&core::mem::replace(hasher, H::new()).finalize());
+        });
+        hash
+    }
+}
+
+impl<H: sha2::Digest, const L: usize> Default for ShaSoftware<'_, H, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> DigestData<'a, L> for ShaSoftware<'a, H, L> {
+    fn add_data(
+        &self,
+        data: SubSlice<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSlice<'static, u8>)> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, data));
+        }
+        self.hasher.map(|hasher| hasher.update(data.as_slice()));
+        self.input_data.set(SubSliceMutImmut::Immutable(data));
+        self.state.set(State::Data);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn add_mut_data(
+        &self,
+        data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, data));
+        }
+        self.hasher.map(|hasher| hasher.update(data.as_slice()));
+        self.input_data.set(SubSliceMutImmut::Mutable(data));
+        self.state.set(State::Data);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn clear_data(&self) {
+        self.hasher.map(|hasher| *hasher = H::new());
+        let new_state = match self.state.get() {
+            State::Idle => State::Idle,
+            State::Data | State::CancelData => State::CancelData,
+            State::Hash | State::CancelHash => State::CancelHash,
+            State::Verify | State::CancelVerify => State::CancelVerify,
+        };
+        self.state.set(new_state);
+    }
+
+    fn set_data_client(&'a self, client: &'a (dyn ClientData<L> + 'a)) {
+        self.data_client.set(client);
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> DigestHash<'a, L> for ShaSoftware<'a, H, L> {
+    fn run(
+        &'a self,
+        digest: &'static mut [u8; L],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; L])> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, digest));
+        }
+        *digest = self.finalize();
+        self.output_data.replace(digest);
+        self.state.set(State::Hash);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn set_hash_client(&'a self, client: &'a (dyn ClientHash<L> + 'a)) {
+        self.hash_client.set(client);
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> DigestVerify<'a, L> for ShaSoftware<'a, H, L> {
+    fn verify(
+        &'a self,
+        compare: &'static mut [u8; L],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; L])> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, compare));
+        }
+        self.verified.set(self.finalize() == *compare);
+        self.output_data.replace(compare);
+        self.state.set(State::Verify);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn set_verify_client(&'a self, client: &'a (dyn ClientVerify<L> + 'a)) {
+        self.verify_client.set(client);
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> Digest<'a, L> for ShaSoftware<'a, H, L> {
+    fn set_client(&'a self, client: &'a dyn Client<L>) {
+        self.data_client.set(client);
+        self.hash_client.set(client);
+        self.verify_client.set(client);
+    }
+}
+
+impl<H: sha2::Digest, const L: usize> DeferredCallClient for ShaSoftware<'_, H, L> {
+    fn handle_deferred_call(&self) {
+        let prior = self.state.get();
+        self.state.set(State::Idle);
+        let result = match prior {
+            State::Idle => return,
+            State::Data | State::Hash | State::Verify => Ok(()),
+            State::CancelData | State::CancelHash | State::CancelVerify => Err(ErrorCode::CANCEL),
+        };
+        match prior {
+            State::Idle => {}
+            State::Data | State::CancelData => match self.input_data.take() {
+                Some(SubSliceMutImmut::Mutable(buffer)) => {
+                    self.data_client.map(|client| {
+                        client.add_mut_data_done(result, buffer);
+                    });
+                }
+                Some(SubSliceMutImmut::Immutable(buffer)) => {
+                    self.data_client.map(|client| {
+                        client.add_data_done(result, buffer);
+                    });
+                }
+                None => {}
+            },
+            State::Hash | State::CancelHash => {
+                if let Some(output) = self.output_data.take() {
+                    self.hash_client.map(|client| {
+                        client.hash_done(result, output);
+                    });
+                }
+            }
+            State::Verify | State::CancelVerify => {
+                if let Some(output) = self.output_data.take() {
+                    self.verify_client.map(|client| {
+                        client.verification_done(result.map(|()| self.verified.get()), output);
+                    });
+                }
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl Sha384 for Sha384Software<'_> {
+    /// Call before adding data to perform Sha384
+    fn set_mode_sha384(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl Sha512 for Sha512Software<'_> {
+    /// Call before adding data to perform Sha512
+    fn set_mode_sha512(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> DigestDataHash<'a, L> for ShaSoftware<'a, H, L> {
+    fn set_client(&'a self, client: &'a dyn ClientDataHash<L>) {
+        self.data_client.set(client);
+        self.hash_client.set(client);
+    }
+}
+
+impl<'a, H: sha2::Digest, const L: usize> DigestDataVerify<'a, L> for ShaSoftware<'a, H, L> {
+    fn set_client(&'a self, client: &'a dyn ClientDataVerify<L>) {
+        self.data_client.set(client);
+        self.verify_client.set(client);
+    }
+}
@@ -3,3 +3,4 @@
 // Copyright Tock Contributors 2023.
 
 pub mod p256;
+pub mod p384;
@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Test the software implementation of ECDSA verification over the P384 curve
+//! by checking a known correct signature of a hash, which must verify, and
+//! then the same signature with one bit flipped, which must not.
+
+use crate::p384_verifier::EcdsaP384SignatureVerifier;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use core::cell::Cell;
+use kernel::debug;
+use kernel::hil::public_key_crypto::signature;
+use kernel::hil::public_key_crypto::signature::SignatureVerify;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct TestEcdsaP384Verify {
+    ecdsa: &'static EcdsaP384SignatureVerifier<'static>,
+    hash: TakeCell<'static, [u8; 48]>,      // The signed hash
+    signature: TakeCell<'static, [u8; 96]>, // The signature to check
+    corrupted: Cell<bool>,                  // Whether a bit of the signature was flipped
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl TestEcdsaP384Verify {
+    pub fn new(
+        ecdsa: &'static EcdsaP384SignatureVerifier<'static>,
+        hash: &'static mut [u8; 48],
+        signature: &'static mut [u8; 96],
+    ) -> Self {
+        TestEcdsaP384Verify {
+            ecdsa,
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+            corrupted: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        self.ecdsa.set_verify_client(self);
+        let hash = self.hash.take().unwrap();
+        let signature = self.signature.take().unwrap();
+        let r = self.ecdsa.verify(hash, signature);
+        if r.is_err() {
+            panic!("EcdsaP384VerifyTest: failed to verify: {:?}", r);
+        }
+    }
+}
+
+impl signature::ClientVerify<48, 96> for TestEcdsaP384Verify {
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; 48],
+        signature: &'static mut [u8; 96],
+    ) {
+        match result {
+            Ok(true) if !self.corrupted.get() => {
+                // Check that the signature no longer verifies with a bit
+                // flipped.
+                self.corrupted.set(true);
+                signature[0] ^= 1;
+                let r = self.ecdsa.verify(hash, signature);
+                if r.is_err() {
+                    panic!("EcdsaP384VerifyTest: failed to verify: {:?}", r);
+                }
+            }
+            Ok(verified) => {
+                let res = if verified != self.corrupted.get() {
+                    debug!("EcdsaP384VerifyTest passed (signatures checked)");
+                    Ok(())
+                } else {
+                    debug!("EcdsaP384VerifyTest failed (wrong verification result)");
+                    Err(CapsuleTestError::IncorrectResult)
+                };
+                self.client.map(|client| client.done(res));
+            }
+            Err(e) => {
+                panic!("EcdsaP384VerifyTest: verification failed: {:?}", e);
+            }
+        }
+    }
+}
+
+impl CapsuleTest for TestEcdsaP384Verify {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
@@ -0,0 +1,173 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Ed25519ph Signature Verifier.
+//!
+//! Ed25519ph (RFC 8032, section 5.1) is the variant of Ed25519 that signs the
+//! SHA-512 hash of the message. The hash passed to `verify()` is that SHA-512
+//! hash, and signatures are checked with an empty context, as for the
+//! `Ed25519ph` credentials of process binaries.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::digest::consts::U64;
+use sha2::digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+
+use core::cell::Cell;
+use kernel::hil;
+use kernel::hil::public_key_crypto::keys::SetKeyBySliceClient;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+enum State {
+    Verifying,
+    ChangingKey(&'static mut [u8; 32]),
+}
+
+/// The SHA-512 hash of the signed message, as the digest `ed25519-dalek`
+/// finalizes to verify Ed25519ph signatures.
+#[derive(Clone)]
+struct Prehash([u8; 64]);
+
+impl Default for Prehash {
+    fn default() -> Self {
+        Self([0; 64])
+    }
+}
+
+impl HashMarker for Prehash {}
+
+impl OutputSizeUser for Prehash {
+    type OutputSize = U64;
+}
+
+impl Update for Prehash {
+    fn update(&mut self, _data: &[u8]) {
+        // The message is hashed already.
+    }
+}
+
+impl FixedOutput for Prehash {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        out.copy_from_slice(&self.0);
+    }
+}
+
+pub struct Ed25519phSignatureVerifier<'a> {
+    verified: Cell<bool>,
+    client: OptionalCell<&'a dyn hil::public_key_crypto::signature::ClientVerify<64, 64>>,
+    client_key_set: OptionalCell<&'a dyn hil::public_key_crypto::keys::SetKeyBySliceClient<32>>,
+    verifying_key: TakeCell<'static, [u8; 32]>,
+    hash_storage: TakeCell<'static, [u8; 64]>,
+    signature_storage: TakeCell<'static, [u8; 64]>,
+    deferred_call: kernel::deferred_call::DeferredCall,
+    state: OptionalCell<State>,
+}
+
+impl Ed25519phSignatureVerifier<'_> {
+    pub fn new(verifying_key: &'static mut [u8; 32]) -> Self {
+        Self {
+            verified: Cell::new(false),
+            client: OptionalCell::empty(),
+            client_key_set: OptionalCell::empty(),
+            verifying_key: TakeCell::new(verifying_key),
+            hash_storage: TakeCell::empty(),
+            signature_storage: TakeCell::empty(),
+            deferred_call: kernel::deferred_call::DeferredCall::new(),
+            state: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a> hil::public_key_crypto::signature::SignatureVerify<'a, 64, 64>
+    for Ed25519phSignatureVerifier<'a>
+{
+    fn set_verify_client(
+        &self,
+        client: &'a dyn hil::public_key_crypto::signature::ClientVerify<64, 64>,
+    ) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        hash: &'static mut [u8; 64],
+        signature: &'static mut [u8; 64],
+    ) -> Result<
+        (),
+        (
+            kernel::ErrorCode,
+            &'static mut [u8; 64],
+            &'static mut [u8; 64],
+        ),
+    > {
+        let sig = Signature::from_bytes(signature);
+        let key = self
+            .verifying_key
+            .map(|vkey| VerifyingKey::from_bytes(vkey));
+        match key {
+            Some(Ok(key)) => {
+                // Reject malleable signatures and weak keys.
+                self.verified.set(
+                    key.verify_prehashed_strict(Prehash(*hash), None, &sig)
+                        .is_ok(),
+                );
+                self.hash_storage.replace(hash);
+                self.signature_storage.replace(signature);
+                self.state.set(State::Verifying);
+                self.deferred_call.set();
+                Ok(())
+            }
+            Some(Err(_)) => Err((kernel::ErrorCode::INVAL, hash, signature)),
+            None => Err((kernel::ErrorCode::FAIL, hash, signature)),
+        }
+    }
+}
+
+impl<'a> hil::public_key_crypto::keys::SetKeyBySlice<'a, 32> for Ed25519phSignatureVerifier<'a> {
+    fn set_key(
+        &self,
+        key: &'static mut [u8; 32],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 32])> {
+        // Just wait for the deferred call to make the change so we can keep
+        // both the old and the new key in the meantime.
+        self.state.set(State::ChangingKey(key));
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn SetKeyBySliceClient<32>) {
+        self.client_key_set.replace(client);
+    }
+}
+
+impl kernel::deferred_call::DeferredCallClient for Ed25519phSignatureVerifier<'_> {
+    fn handle_deferred_call(&self) {
+        if let Some(s) = self.state.take() {
+            match s {
+                State::Verifying => {
+                    self.client.map(|client| {
+                        if let Some(h) = self.hash_storage.take() {
+                            if let Some(s) = self.signature_storage.take() {
+                                client.verification_done(Ok(self.verified.get()), h, s);
+                            }
+                        }
+                    });
+                }
+                State::ChangingKey(key) => {
+                    self.verifying_key.map(|vkey| {
+                        vkey.copy_from_slice(key);
+                    });
+
+                    self.client_key_set.map(|client| {
+                        client.set_key_done(key, Ok(()));
+                    });
+                }
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
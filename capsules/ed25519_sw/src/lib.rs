@@ -0,0 +1,10 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+#![forbid(unsafe_code)]
+#![no_std]
+
+pub mod test;
+
+pub mod ed25519ph_verifier;
@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Test the software implementation of Ed25519ph verification by checking a
+//! known correct signature of the SHA-512 hash of a message, which must
+//! verify, and then the same signature with one bit flipped, which must not.
+
+use crate::ed25519ph_verifier::Ed25519phSignatureVerifier;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use core::cell::Cell;
+use kernel::debug;
+use kernel::hil::public_key_crypto::signature;
+use kernel::hil::public_key_crypto::signature::SignatureVerify;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct TestEd25519phVerify {
+    ed25519: &'static Ed25519phSignatureVerifier<'static>,
+    hash: TakeCell<'static, [u8; 64]>,      // The signed hash
+    signature: TakeCell<'static, [u8; 64]>, // The signature to check
+    corrupted: Cell<bool>,                  // Whether a bit of the signature was flipped
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl TestEd25519phVerify {
+    pub fn new(
+        ed25519: &'static Ed25519phSignatureVerifier<'static>,
+        hash: &'static mut [u8; 64],
+        signature: &'static mut [u8; 64],
+    ) -> Self {
+        TestEd25519phVerify {
+            ed25519,
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+            corrupted: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        self.ed25519.set_verify_client(self);
+        let hash = self.hash.take().unwrap();
+        let signature = self.signature.take().unwrap();
+        let r = self.ed25519.verify(hash, signature);
+        if r.is_err() {
+            panic!("Ed25519phVerifyTest: failed to verify: {:?}", r);
+        }
+    }
+}
+
+impl signature::ClientVerify<64, 64> for TestEd25519phVerify {
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; 64],
+        signature: &'static mut [u8; 64],
+    ) {
+        match result {
+            Ok(true) if !self.corrupted.get() => {
+                // Check that the signature no longer verifies with a bit
+                // flipped.
+                self.corrupted.set(true);
+                signature[0] ^= 1;
+                let r = self.ed25519.verify(hash, signature);
+                if r.is_err() {
+                    panic!("Ed25519phVerifyTest: failed to verify: {:?}", r);
+                }
+            }
+            Ok(verified) => {
+                let res = if verified != self.corrupted.get() {
+                    debug!("Ed25519phVerifyTest passed (signatures checked)");
+                    Ok(())
+                } else {
+                    debug!("Ed25519phVerifyTest failed (wrong verification result)");
+                    Err(CapsuleTestError::IncorrectResult)
+                };
+                self.client.map(|client| client.done(res));
+            }
+            Err(e) => {
+                panic!("Ed25519phVerifyTest: verification failed: {:?}", e);
+            }
+        }
+    }
+}
+
+impl CapsuleTest for TestEd25519phVerify {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
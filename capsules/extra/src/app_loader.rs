@@ -62,8 +62,25 @@
 //!
 //! NOTE:
 //! 1. This capsule is not virtualized, and can only serve one app at a time.
-//! 2. This implementation currently only loads new apps. It does not update apps.
 //! ```
+//!
+//! Besides loading new apps, the capsule can update and uninstall apps. To
+//! update an app, the new binary is written with the same setup, write and
+//! finalize commands as a new app, and then loaded in place of the running
+//! app. The running app is only terminated, and its binary retired, once the
+//! new binary has passed its credential checks and the new process was
+//! created; otherwise the new binary is retired and the old app keeps
+//! running. An update can also keep the binary of the running app in flash,
+//! so the app can be rolled back to it if the new version misbehaves.
+//! Uninstalling an app terminates it and turns its binary into
+//! padding, so the flash can be reused for new apps.
+//!
+//! Updating and uninstalling are privileged: the app using this capsule can
+//! replace or uninstall any other app, identified by its process id.
+//!
+//! Installing and uninstalling apps leaves gaps in the application flash.
+//! When a new app does not fit, the capsule can compact the flash, which
+//! moves apps into the gaps before them and restarts them.
 
 use core::cell::Cell;
 use core::cmp;
@@ -94,8 +111,12 @@ mod upcall {
     pub const LOAD_DONE: usize = 3;
     /// Abort done callback.
     pub const ABORT_DONE: usize = 4;
+    /// Uninstall done callback.
+    pub const UNINSTALL_DONE: usize = 5;
+    /// Compact done callback.
+    pub const COMPACT_DONE: usize = 6;
     /// Number of upcalls.
-    pub const COUNT: u8 = 5;
+    pub const COUNT: u8 = 7;
 }
 
 // Ids for read-only allow buffers
@@ -343,6 +364,32 @@ impl<
             });
         });
     }
+
+    /// Let the requesting app know we are done uninstalling the process
+    fn uninstall_done(&self, result: Result<(), ErrorCode>) {
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, move |app, kernel_data| {
+                app.pending_command = false;
+                // Signal the app.
+                self.current_process.take();
+                let _ = kernel_data
+                    .schedule_upcall(upcall::UNINSTALL_DONE, (into_statuscode(result), 0, 0));
+            });
+        });
+    }
+
+    /// Let the requesting app know we are done compacting the flash
+    fn compact_done(&self, result: Result<(), ErrorCode>) {
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, move |app, kernel_data| {
+                app.pending_command = false;
+                // Signal the app.
+                self.current_process.take();
+                let _ = kernel_data
+                    .schedule_upcall(upcall::COMPACT_DONE, (into_statuscode(result), 0, 0));
+            });
+        });
+    }
 }
 
 /// Provide an interface for userland.
@@ -387,6 +434,36 @@ impl<
     ///  - Returns ErrorCode::BUSY when the abort fails
     ///  (due to padding app being unable to be written, so try again)
     ///  - Returns ErrorCode::FAIL if the driver is not dedicated to this process
+    /// - `6`: Request kernel to load app in place of the process with
+    ///   identifier `arg1`. The load done upcall is issued once the binary that
+    ///   lost the replacement is retired.
+    ///  - Returns Ok(()) when the new binary is being checked and loaded
+    ///  - Returns ErrorCode::INVAL if there is no process `arg1`, or it is the
+    ///  calling process
+    ///  - Returns ErrorCode::FAIL if the binary cannot be loaded
+    /// - `7`: Request kernel to uninstall the process with identifier `arg1`.
+    ///   The uninstall done upcall is issued once the binary is retired.
+    ///  - Returns Ok(()) when the uninstall has started
+    ///  - Returns ErrorCode::INVAL if there is no process `arg1`, or it is the
+    ///  calling process
+    ///  - Returns ErrorCode::BUSY if an app is being loaded
+    /// - `8`: Request kernel to compact the application flash. Moved apps are
+    ///   restarted; if the calling app is moved, it gets no compact done
+    ///   upcall.
+    ///  - Returns Ok(()) when the first app is being moved
+    ///  - Returns ErrorCode::ALREADY if no app can be moved
+    ///  - Returns ErrorCode::BUSY if an app is being loaded
+    /// - `9`: Request kernel to load app in place of the process with
+    ///   identifier `arg1`, like `6`, but keep the binary of the process in
+    ///   flash so the app can be rolled back to it.
+    ///  - Returns Ok(()) when the new binary is being checked and loaded
+    ///  - Returns ErrorCode::INVAL if there is no process `arg1`, it is the
+    ///  calling process, or the new binary does not have a higher version
+    ///  - Returns ErrorCode::FAIL if the binary cannot be loaded
+    ///
+    /// Commands `6`, `7` and `9` are privileged: any process that can use
+    /// this driver can replace or uninstall any other process. Boards should
+    /// only give this driver to trusted apps.
     ///
     /// The driver returns ErrorCode::INVAL if any operation is called before the
     /// preceeding operation was invoked. For example, `write()` cannot be called before
@@ -510,6 +587,53 @@ impl<
                     }
                 }
             }
+            6 | 9 => {
+                // Request kernel to load the new app in place of an app.
+                let res = if arg1 == processid.id() {
+                    Err(ErrorCode::INVAL)
+                } else if command_num == 6 {
+                    self.load_driver.load_replacing(arg1)
+                } else {
+                    self.load_driver.load_upgrading(arg1)
+                };
+                match res {
+                    Ok(()) => {
+                        self.new_app_length.set(0);
+                        CommandReturn::success()
+                    }
+                    // The new binary is still ready to be loaded or aborted,
+                    // so the driver stays dedicated to this process.
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            7 => {
+                // Request kernel to uninstall an app.
+                let res = if arg1 == processid.id() {
+                    Err(ErrorCode::INVAL)
+                } else {
+                    self.load_driver.uninstall(arg1)
+                };
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => {
+                        self.current_process.take();
+                        CommandReturn::failure(e)
+                    }
+                }
+            }
+
+            8 => {
+                // Request kernel to compact the application flash.
+                match self.load_driver.compact() {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => {
+                        self.current_process.take();
+                        CommandReturn::failure(e)
+                    }
+                }
+            }
+
             // Unsupported command numbers.
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -28,6 +28,27 @@
 //! - if it's greater the 0, the message will be copied to the RW buffer
 //!   but no upcall will be done
 //!
+//! The capsule can drive either a classic CAN peripheral (8 byte frames,
+//! implementing `hil::can::Can`) or a CAN FD peripheral (64 byte frames,
+//! implementing `hil::can::CanFd`). For CAN FD peripherals, two additional
+//! commands configure the payload bit timing and enable bit rate switching,
+//! and the send commands accept messages of up to 64 bytes.
+//!
+//! Several processes can use the capsule at the same time. The process that
+//! first configures the peripheral (bitrate, operation mode, timing, enable or
+//! disable) owns its configuration until the peripheral is disabled. Any
+//! process can send and receive messages:
+//! - each process can have one message queued while another message is being
+//!   transmitted; queued messages are sent in the order of their bus priority
+//!   (lowest identifier first), as the arbitration on the bus would do
+//! - each process can set up to `SOFTWARE_FILTER_COUNT` acceptance filters
+//!   and only receives the messages that match at least one of them; a
+//!   process that has no filters receives all messages
+//!
+//! The capsule can also share the peripheral with other kernel users through
+//! a `VirtualCanDevice` of a `MuxCan`, which forwards the configuration and
+//! controller interfaces to the peripheral.
+//!
 //! Usage
 //! -----
 //!
@@ -48,7 +69,28 @@
 //! kernel::hil::can::Receive::set_client(can_peripheral, Some(can));
 //! ```
 //!
+//! On top of a virtual device, the device is the peripheral of the capsule:
+//! ```rust,ignore
+//! let can = capsules::can::CanCapsule::new(can_device, grant_can, tx_buffer, rx_buffer);
+//!
+//! kernel::hil::can::Controller::set_client(can_device, Some(can));
+//! kernel::hil::can::Transmit::set_client(can_device, Some(can));
+//! kernel::hil::can::Receive::set_client(can_device, Some(can));
+//! ```
+//!
+//! For a CAN FD peripheral, the capsule is instantiated with
+//! `FD_CAN_PACKET_SIZE` buffers:
+//! ```rust,ignore
+//! let can = capsules::can::CanCapsule::<_, { can::FD_CAN_PACKET_SIZE }>::new(
+//!    can_fd_peripheral,
+//!    grant_can,
+//!    tx_buffer,
+//!    rx_buffer,
+//! );
+//! ```
+//!
 
+use core::cell::Cell;
 use core::mem::size_of;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
@@ -92,13 +134,16 @@ mod rw_allow {
     pub const COUNT: u8 = 1;
 }
 
-pub struct CanCapsule<'a, Can: can::Can> {
+pub struct CanCapsule<'a, Can, const PACKET_SIZE: usize = { can::STANDARD_CAN_PACKET_SIZE }>
+where
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure + can::Controller,
+{
     // CAN driver
     can: &'a Can,
 
     // CAN buffers
-    can_tx: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
-    can_rx: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
+    can_tx: TakeCell<'static, [u8; PACKET_SIZE]>,
+    can_rx: TakeCell<'static, [u8; PACKET_SIZE]>,
 
     // Process
     processes: Grant<
@@ -109,6 +154,15 @@ pub struct CanCapsule<'a, Can: can::Can> {
     >,
     processid: OptionalCell<ProcessId>,
 
+    // Process whose message is being transmitted
+    sending: OptionalCell<ProcessId>,
+
+    // Whether the receive process of the peripheral is started
+    receiving: Cell<bool>,
+
+    // Process that requested the receive process of the peripheral to stop
+    stop_requester: OptionalCell<ProcessId>,
+
     // Variable used to store the current state of the CAN peripheral
     // during an `enable` or `disable` command.
     peripheral_state: OptionalCell<can::State>,
@@ -117,9 +171,51 @@ pub struct CanCapsule<'a, Can: can::Can> {
 #[derive(Default)]
 pub struct App {
     lost_messages: u32,
+    pending_send: Option<(can::Id, usize)>,
+    receiving: bool,
+    filters: [Option<can::FilterParameters>; can::SOFTWARE_FILTER_COUNT],
+}
+
+impl App {
+    /// A process without any filter receives all messages.
+    fn accepts(&self, id: can::Id) -> bool {
+        can::FilterParameters::accepted_by(self.filters.iter().flatten().copied(), id)
+    }
+}
+
+/// Decodes the identifier and the mask of the add filter commands.
+///
+/// Returns `ErrorCode::INVAL` if they do not fit in a standard (11 bit) or an
+/// extended (29 bit) identifier.
+fn filter_from_args(extended: bool, arg1: usize, arg2: usize) -> Result<(can::Id, u32), ErrorCode> {
+    let (id, mask) = (
+        u32::try_from(arg1).map_err(|_| ErrorCode::INVAL)?,
+        u32::try_from(arg2).map_err(|_| ErrorCode::INVAL)?,
+    );
+    let id = if extended {
+        can::Id::Extended(id)
+    } else {
+        can::Id::Standard(u16::try_from(id).map_err(|_| ErrorCode::INVAL)?)
+    };
+    Ok((id, mask))
+}
+
+/// Decodes the bit timing parameters packed in the arguments of the
+/// set timing commands.
+fn bit_timing_from_args(arg1: usize, arg2: usize) -> can::BitTiming {
+    can::BitTiming {
+        segment1: ((arg1 & BYTE4_MASK) >> 24) as u8,
+        segment2: ((arg1 & BYTE3_MASK) >> 16) as u8,
+        propagation: arg2 as u8,
+        sync_jump_width: ((arg1 & BYTE2_MASK) >> 8) as u32,
+        baud_rate_prescaler: (arg1 & BYTE1_MASK) as u32,
+    }
 }
 
-impl<'a, Can: can::Can> CanCapsule<'a, Can> {
+impl<'a, Can, const PACKET_SIZE: usize> CanCapsule<'a, Can, PACKET_SIZE>
+where
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure + can::Controller,
+{
     pub fn new(
         can: &'a Can,
         grant: Grant<
@@ -128,9 +224,9 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
-        can_tx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
-        can_rx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
-    ) -> CanCapsule<'a, Can> {
+        can_tx: &'static mut [u8; PACKET_SIZE],
+        can_rx: &'static mut [u8; PACKET_SIZE],
+    ) -> CanCapsule<'a, Can, PACKET_SIZE> {
         CanCapsule {
             can,
             can_tx: TakeCell::new(can_tx),
@@ -138,14 +234,26 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
             processes: grant,
             peripheral_state: OptionalCell::empty(),
             processid: OptionalCell::empty(),
+            sending: OptionalCell::empty(),
+            receiving: Cell::new(false),
+            stop_requester: OptionalCell::empty(),
         }
     }
 
     fn schedule_callback(&self, callback_number: usize, data: (usize, usize, usize)) {
         self.processid.map(|processid| {
-            let _ = self.processes.enter(processid, |_app, kernel_data| {
-                let _ = kernel_data.schedule_upcall(callback_number, (data.0, data.1, data.2));
-            });
+            self.schedule_callback_to(processid, callback_number, data);
+        });
+    }
+
+    fn schedule_callback_to(
+        &self,
+        processid: ProcessId,
+        callback_number: usize,
+        data: (usize, usize, usize),
+    ) {
+        let _ = self.processes.enter(processid, |_app, kernel_data| {
+            let _ = kernel_data.schedule_upcall(callback_number, (data.0, data.1, data.2));
         });
     }
 
@@ -166,6 +274,9 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
                         |buffer_ref| {
                             buffer_ref
                                 .enter(|buffer| {
+                                    if length > PACKET_SIZE || length > buffer.len() {
+                                        return Err(ErrorCode::SIZE);
+                                    }
                                     self.can_tx.take().map_or(
                                         Err(ErrorCode::NOMEM),
                                         |dest_buffer| {
@@ -187,6 +298,168 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
                     )
             })
             .unwrap_or_else(|err| err.into())
+            .inspect(|()| self.sending.set(processid))
+    }
+
+    /// Sends a message from the process, or queues it if another message is
+    /// being transmitted. Each process can have one message queued.
+    fn queue_send_command(
+        &self,
+        processid: ProcessId,
+        id: can::Id,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.sending.is_none() {
+            return self.process_send_command(processid, id, length);
+        }
+        if self.sending.contains(&processid) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.processes
+            .enter(processid, |app, _| {
+                if app.pending_send.is_some() {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.pending_send = Some((id, length));
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| err.into())
+    }
+
+    /// Sends the queued message with the highest bus priority (the lowest
+    /// identifier). Messages that cannot be sent are reported to their
+    /// process with an error upcall.
+    fn send_next_queued(&self) {
+        while self.sending.is_none() {
+            let mut next: Option<(ProcessId, can::Id, usize)> = None;
+            for cntr in self.processes.iter() {
+                let processid = cntr.processid();
+                cntr.enter(|app, _| {
+                    if let Some((id, length)) = app.pending_send {
+                        if next.is_none_or(|(_, next_id, _)| {
+                            id.arbitration_priority() < next_id.arbitration_priority()
+                        }) {
+                            next = Some((processid, id, length));
+                        }
+                    }
+                });
+            }
+
+            let Some((processid, id, length)) = next else {
+                return;
+            };
+            let _ = self.processes.enter(processid, |app, _| {
+                app.pending_send = None;
+            });
+            if let Err(err) = self.process_send_command(processid, id, length) {
+                self.schedule_callback_to(
+                    processid,
+                    up_calls::UPCALL_TRANSMISSION_ERROR,
+                    (error_upcalls::ERROR_TX, err as usize, 0),
+                );
+            }
+        }
+    }
+
+    /// Starts delivering received messages to the process, and starts the
+    /// receive process of the peripheral if no other process is receiving.
+    fn start_receive_command(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.processes
+            .enter(processid, |app, kernel| {
+                kernel
+                    .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
+                    .map_or_else(
+                        |err| err.into(),
+                        |buffer_ref| {
+                            buffer_ref
+                                .enter(|buffer| {
+                                    // make sure that the receiving buffer can have at least
+                                    // 2 messages and 4 another bytes for the counter
+                                    if buffer.len() >= 2 * PACKET_SIZE + size_of::<u32>() {
+                                        Ok(())
+                                    } else {
+                                        Err(ErrorCode::SIZE)
+                                    }
+                                })
+                                .unwrap_or_else(|err| err.into())
+                        },
+                    )
+                    .map(|()| app.receiving = true)
+            })
+            .unwrap_or_else(|err| err.into())?;
+
+        if self.receiving.get() {
+            return Ok(());
+        }
+        let result = self
+            .can_rx
+            .take()
+            .map_or(Err(ErrorCode::NOMEM), |dest_buffer| {
+                match self.can.start_receive_process(dest_buffer) {
+                    Ok(()) => Ok(()),
+                    Err((err, buf)) => {
+                        self.can_rx.replace(buf);
+                        Err(err)
+                    }
+                }
+            });
+        match result {
+            Ok(()) => self.receiving.set(true),
+            Err(_) => {
+                let _ = self.processes.enter(processid, |app, _| {
+                    app.receiving = false;
+                });
+            }
+        }
+        result
+    }
+
+    /// Stops delivering received messages to the process, and stops the
+    /// receive process of the peripheral if no other process is receiving.
+    fn stop_receive_command(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let was_receiving = self
+            .processes
+            .enter(processid, |app, _| {
+                core::mem::replace(&mut app.receiving, false)
+            })
+            .unwrap_or(false);
+        if !was_receiving {
+            return Err(ErrorCode::OFF);
+        }
+
+        let others_receiving = self
+            .processes
+            .iter()
+            .any(|cntr| cntr.enter(|app, _| app.receiving));
+        if others_receiving {
+            self.schedule_callback_to(processid, up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0));
+            Ok(())
+        } else {
+            self.can
+                .stop_receive()
+                .inspect(|()| self.stop_requester.set(processid))
+        }
+    }
+
+    /// Adds an acceptance filter for the process and returns its number.
+    fn enable_filter_command(
+        &self,
+        processid: ProcessId,
+        id: can::Id,
+        mask: u32,
+    ) -> Result<u32, ErrorCode> {
+        self.processes
+            .enter(processid, |app, _| {
+                let number = app
+                    .filters
+                    .iter()
+                    .position(|filter| filter.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                app.filters[number] = Some(can::FilterParameters::mask(number as u32, id, mask)?);
+                Ok(number as u32)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
     }
 
     pub fn is_valid_process(&self, processid: ProcessId) -> bool {
@@ -196,31 +469,41 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
                 .unwrap_or(true)
         })
     }
-}
 
-impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
-    fn command(
+    /// Check to see if the process or no process at all owns the peripheral
+    /// configuration and, if so, mark the process as the owner. Only one
+    /// application can configure, enable and disable the peripheral at a
+    /// time.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if !self.is_valid_process(processid) {
+            Err(ErrorCode::RESERVE)
+        } else {
+            self.processid.set(processid);
+            Ok(())
+        }
+    }
+
+    /// Handles the commands shared by the classic CAN and CAN FD flavours
+    /// of the driver.
+    fn common_command(
         &self,
         command_num: usize,
         arg1: usize,
         arg2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
-        // This driver exists.
-        if command_num == 0 {
-            return CommandReturn::success();
-        }
-
-        // Check to see if the process or no process at all
-        // owns the capsule. Only one application can use the
-        // capsule at a time.
-        if !self.is_valid_process(processid) {
-            return CommandReturn::failure(ErrorCode::RESERVE);
-        } else {
-            self.processid.set(processid);
+        // Commands that change the configuration or the state of the
+        // peripheral are reserved to the owning process.
+        if matches!(command_num, 1..=4 | 9..=11) {
+            if let Err(err) = self.claim(processid) {
+                return CommandReturn::failure(err);
+            }
         }
 
         match command_num {
+            // This driver exists.
+            0 => CommandReturn::success(),
+
             // Set the bitrate
             1 => match self.can.set_bitrate(arg1 as u32) {
                 Ok(()) => CommandReturn::success(),
@@ -255,87 +538,120 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
             // Send a message with a 16-bit identifier
             5 => {
                 let id = can::Id::Standard(arg1 as u16);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
+                match self.queue_send_command(processid, id, arg2) {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err),
+                }
             }
 
             // Send a message with a 32-bit identifier
             6 => {
                 let id = can::Id::Extended(arg1 as u32);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
+                match self.queue_send_command(processid, id, arg2) {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err),
+                }
             }
 
             // Start receiving messages
-            7 => {
-                self.can_rx
-                    .take()
-                    .map_or(CommandReturn::failure(ErrorCode::NOMEM), |dest_buffer| {
-                        self.processes
-                            .enter(processid, |_, kernel| {
-                                match kernel.get_readwrite_processbuffer(0).map_or_else(
-                                    |err| err.into(),
-                                    |buffer_ref| {
-                                        buffer_ref
-                                            .enter(|buffer| {
-                                                // make sure that the receiving buffer can have at least
-                                                // 2 messages of 8 bytes each and 4 another bytes for the counter
-                                                if buffer.len()
-                                                    >= 2 * can::STANDARD_CAN_PACKET_SIZE
-                                                        + size_of::<u32>()
-                                                {
-                                                    Ok(())
-                                                } else {
-                                                    Err(ErrorCode::SIZE)
-                                                }
-                                            })
-                                            .unwrap_or_else(|err| err.into())
-                                    },
-                                ) {
-                                    Ok(()) => match self.can.start_receive_process(dest_buffer) {
-                                        Ok(()) => CommandReturn::success(),
-                                        Err((err, _)) => CommandReturn::failure(err),
-                                    },
-                                    Err(err) => CommandReturn::failure(err),
-                                }
-                            })
-                            .unwrap_or_else(|err| err.into())
-                    })
-            }
+            7 => match self.start_receive_command(processid) {
+                Ok(()) => CommandReturn::success(),
+                Err(err) => CommandReturn::failure(err),
+            },
 
             // Stop receiving messages
-            8 => match self.can.stop_receive() {
+            8 => match self.stop_receive_command(processid) {
                 Ok(()) => CommandReturn::success(),
                 Err(err) => CommandReturn::failure(err),
             },
 
             // Set the timing parameters
-            9 => {
-                match self.can.set_bit_timing(can::BitTiming {
-                    segment1: ((arg1 & BYTE4_MASK) >> 24) as u8,
-                    segment2: ((arg1 & BYTE3_MASK) >> 16) as u8,
-                    propagation: arg2 as u8,
-                    sync_jump_width: ((arg1 & BYTE2_MASK) >> 8) as u32,
-                    baud_rate_prescaler: (arg1 & BYTE1_MASK) as u32,
-                }) {
+            9 => match self.can.set_bit_timing(bit_timing_from_args(arg1, arg2)) {
+                Ok(()) => CommandReturn::success(),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            // Add an acceptance filter for messages with a standard (12) or
+            // an extended (13) identifier. The identifier and the mask must
+            // fit in 11 and 29 bits respectively.
+            12 | 13 => match filter_from_args(command_num == 13, arg1, arg2)
+                .and_then(|(id, mask)| self.enable_filter_command(processid, id, mask))
+            {
+                Ok(number) => CommandReturn::success_u32(number),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            // Remove an acceptance filter
+            14 => self
+                .processes
+                .enter(processid, |app, _| {
+                    match app.filters.get_mut(arg1).and_then(|filter| filter.take()) {
+                        Some(_) => CommandReturn::success(),
+                        None => CommandReturn::failure(ErrorCode::INVAL),
+                    }
+                })
+                .unwrap_or_else(|err| err.into()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+}
+
+impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            // The payload bit timing is only available on CAN FD peripherals
+            10 | 11 => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            _ => self.common_command(command_num, arg1, arg2, processid),
+        }
+    }
+
+    fn allocate_grant(&self, process_id: ProcessId) -> Result<(), kernel::process::Error> {
+        self.processes.enter(process_id, |_, _| {})
+    }
+}
+
+impl<Can: can::CanFd> SyscallDriver for CanCapsule<'_, Can, { can::FD_CAN_PACKET_SIZE }> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            // Set the timing parameters for the data phase of FD frames
+            10 => {
+                if let Err(err) = self.claim(processid) {
+                    return CommandReturn::failure(err);
+                }
+                match self
+                    .can
+                    .set_payload_bit_timing(bit_timing_from_args(arg1, arg2))
+                {
                     Ok(()) => CommandReturn::success(),
                     Err(err) => CommandReturn::failure(err),
                 }
             }
 
-            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            // Enable or disable bit rate switching
+            11 => {
+                if let Err(err) = self.claim(processid) {
+                    return CommandReturn::failure(err);
+                }
+                match self.can.set_bit_rate_switching(arg1 != 0) {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err),
+                }
+            }
+
+            _ => self.common_command(command_num, arg1, arg2, processid),
         }
     }
 
@@ -344,7 +660,10 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
     }
 }
 
-impl<Can: can::Can> can::ControllerClient for CanCapsule<'_, Can> {
+impl<Can, const PACKET_SIZE: usize> can::ControllerClient for CanCapsule<'_, Can, PACKET_SIZE>
+where
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure + can::Controller,
+{
     // This callback must be called after an `enable` or `disable` command was sent.
     // It stores the new state of the peripheral.
     fn state_changed(&self, state: can::State) {
@@ -409,43 +728,65 @@ impl<Can: can::Can> can::ControllerClient for CanCapsule<'_, Can> {
     }
 }
 
-impl<Can: can::Can> can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }> for CanCapsule<'_, Can> {
+impl<Can, const PACKET_SIZE: usize> can::TransmitClient<PACKET_SIZE>
+    for CanCapsule<'_, Can, PACKET_SIZE>
+where
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure + can::Controller,
+{
     // This callback is called when the hardware acknowledges that a message
     // was sent. This callback also makes an upcall to the userspace.
     fn transmit_complete(
         &self,
         status: Result<(), can::Error>,
-        buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        buffer: &'static mut [u8; PACKET_SIZE],
     ) {
         self.can_tx.replace(buffer);
-        match status {
-            Ok(()) => self.schedule_callback(up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0)),
-            Err(err) => {
-                self.schedule_callback(
-                    up_calls::UPCALL_TRANSMISSION_ERROR,
-                    (error_upcalls::ERROR_TX, err as usize, 0),
-                );
+        if let Some(processid) = self.sending.take() {
+            match status {
+                Ok(()) => {
+                    self.schedule_callback_to(processid, up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0))
+                }
+                Err(err) => {
+                    self.schedule_callback_to(
+                        processid,
+                        up_calls::UPCALL_TRANSMISSION_ERROR,
+                        (error_upcalls::ERROR_TX, err as usize, 0),
+                    );
+                }
             }
         }
+        self.send_next_queued();
     }
 }
 
-impl<Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }> for CanCapsule<'_, Can> {
+impl<Can, const PACKET_SIZE: usize> can::ReceiveClient<PACKET_SIZE>
+    for CanCapsule<'_, Can, PACKET_SIZE>
+where
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE> + can::Configure + can::Controller,
+{
     // This callback is called when a new message is received on any receiving
     // fifo.
     fn message_received(
         &self,
         id: can::Id,
-        buffer: &mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        buffer: &mut [u8; PACKET_SIZE],
         _len: usize,
         status: Result<(), can::Error>,
     ) {
         match status {
             Ok(()) => {
-                let res: Result<(bool, u32), ErrorCode> =
-                    self.processid.map_or(Err(ErrorCode::NOMEM), |processid| {
-                        self.processes
-                            .enter(processid, |app_data, kernel_data| {
+                let raw_id = match id {
+                    can::Id::Standard(u16) => u16 as usize,
+                    can::Id::Extended(u32) => u32 as usize,
+                };
+                for cntr in self.processes.iter() {
+                    let processid = cntr.processid();
+                    let res: Option<Result<(bool, u32), ErrorCode>> =
+                        cntr.enter(|app_data, kernel_data| {
+                            if !app_data.receiving || !app_data.accepts(id) {
+                                return None;
+                            }
+                            Some(
                                 kernel_data
                                     .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
                                     .map_or_else(
@@ -461,41 +802,46 @@ impl<Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }> for Ca
                                                 })
                                                 .unwrap_or_else(|err| Err(err.into()))
                                         },
-                                    )
-                            })
-                            .unwrap_or_else(|err| Err(err.into()))
-                    });
-
-                match res {
-                    Err(err) => self.schedule_callback(
-                        up_calls::UPCALL_TRANSMISSION_ERROR,
-                        (error_upcalls::ERROR_RX, err as usize, 0),
-                    ),
-                    Ok((_first_chunk, new_offset)) => self.schedule_callback(
-                        up_calls::UPCALL_MESSAGE_RECEIVED,
-                        (
-                            0,
-                            new_offset as usize,
-                            match id {
-                                can::Id::Standard(u16) => u16 as usize,
-                                can::Id::Extended(u32) => u32 as usize,
-                            },
+                                    ),
+                            )
+                        });
+
+                    match res {
+                        None => {}
+                        Some(Err(err)) => self.schedule_callback_to(
+                            processid,
+                            up_calls::UPCALL_TRANSMISSION_ERROR,
+                            (error_upcalls::ERROR_RX, err as usize, 0),
                         ),
-                    ),
+                        Some(Ok((_first_chunk, new_offset))) => self.schedule_callback_to(
+                            processid,
+                            up_calls::UPCALL_MESSAGE_RECEIVED,
+                            (0, new_offset as usize, raw_id),
+                        ),
+                    }
                 }
             }
             Err(err) => {
                 let kernel_err: ErrorCode = err.into();
-                self.schedule_callback(
-                    up_calls::UPCALL_TRANSMISSION_ERROR,
-                    (error_upcalls::ERROR_RX, kernel_err.into(), 0),
-                )
+                for cntr in self.processes.iter() {
+                    cntr.enter(|app_data, kernel_data| {
+                        if app_data.receiving {
+                            let _ = kernel_data.schedule_upcall(
+                                up_calls::UPCALL_TRANSMISSION_ERROR,
+                                (error_upcalls::ERROR_RX, kernel_err.into(), 0),
+                            );
+                        }
+                    });
+                }
             }
         }
     }
 
-    fn stopped(&self, buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE]) {
+    fn stopped(&self, buffer: &'static mut [u8; PACKET_SIZE]) {
         self.can_rx.replace(buffer);
-        self.schedule_callback(up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0));
+        self.receiving.set(false);
+        self.stop_requester.take().map(|processid| {
+            self.schedule_callback_to(processid, up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0));
+        });
     }
 }
@@ -0,0 +1,1173 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! ISO-TP (ISO 15765-2) transport over CAN.
+//!
+//! This capsule implements the ISO-TP segmentation and reassembly layer on
+//! top of a CAN peripheral and exposes it to userspace, so applications can
+//! exchange messages larger than a single CAN frame (for example UDS
+//! diagnostic requests and responses).
+//!
+//! The capsule uses normal addressing: frames are sent with a configurable
+//! transmit identifier and only frames with the configured receive
+//! identifier are processed. It works with classic CAN peripherals (8 byte
+//! frames) as well as CAN FD peripherals (64 byte frames), in which case
+//! the escape sequences for single frames longer than 7 bytes and first
+//! frames longer than 4095 bytes are used.
+//!
+//! Messages are copied directly between the CAN frames and the process
+//! buffers, so the maximum message length is only limited by the size of
+//! the buffers the application shares with the capsule.
+//!
+//! Only one application can use the capsule at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let isotp_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! isotp_alarm.setup();
+//! let isotp = static_init!(
+//!     capsules_extra::can_isotp::CanIsoTp<'static, Can, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::can_isotp::CanIsoTp::new(
+//!         can_peripheral,
+//!         isotp_alarm,
+//!         board_kernel.create_grant(capsules_extra::can_isotp::DRIVER_NUM, &grant_cap),
+//!         tx_frame,
+//!         rx_frame,
+//!     )
+//! );
+//! isotp_alarm.set_alarm_client(isotp);
+//! kernel::hil::can::Transmit::set_client(can_peripheral, Some(isotp));
+//! kernel::hil::can::Receive::set_client(can_peripheral, Some(isotp));
+//! ```
+//!
+//! The CAN peripheral must be configured and enabled before messages can be
+//! exchanged.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::can;
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+use kernel::ProcessId;
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::CanIsoTp as usize;
+
+/// Time to wait for a flow control frame from the receiver (N_Bs) and for
+/// the next consecutive frame from the sender (N_Cr), in milliseconds.
+pub const TIMEOUT_MS: u32 = 1000;
+
+/// Value used to pad frames up to a length that can be encoded in the DLC.
+const PADDING_BYTE: u8 = 0xCC;
+
+/// Largest message a first frame with a 12 bit length can announce.
+const MAX_SHORT_MESSAGE_LENGTH: usize = 0xFFF;
+
+/// Protocol control information types, stored in the high nibble of the
+/// first byte of each frame.
+mod pci {
+    pub const SINGLE_FRAME: u8 = 0x0;
+    pub const FIRST_FRAME: u8 = 0x1;
+    pub const CONSECUTIVE_FRAME: u8 = 0x2;
+    pub const FLOW_CONTROL: u8 = 0x3;
+}
+
+/// Flow status values sent in flow control frames.
+mod flow_status {
+    pub const CONTINUE_TO_SEND: u8 = 0x0;
+    pub const WAIT: u8 = 0x1;
+    pub const OVERFLOW: u8 = 0x2;
+}
+
+mod upcall {
+    pub const SEND_DONE: usize = 0;
+    pub const MESSAGE_RECEIVED: usize = 1;
+    pub const COUNT: u8 = 2;
+}
+
+mod ro_allow {
+    pub const MESSAGE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+mod rw_allow {
+    pub const MESSAGE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TxState {
+    Idle,
+    /// The next data frame is being transmitted or waits for the frame
+    /// buffer to become available.
+    Sending,
+    /// Waiting for a flow control frame from the receiver.
+    WaitFlowControl,
+    /// Waiting for the minimum separation time requested by the receiver
+    /// to elapse before sending the next consecutive frame.
+    WaitSeparation,
+}
+
+/// The kind of frame currently owned by the CAN peripheral.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InFlight {
+    None,
+    FlowControl,
+    SingleFrame,
+    FirstFrame,
+    ConsecutiveFrame,
+}
+
+/// Writes the protocol control information of the frame that carries the
+/// message bytes from `offset` of a `length` byte message into `frame`.
+///
+/// Returns the kind of the frame, the length of its header and the number of
+/// message bytes it carries. `sequence` is the sequence number of the frame
+/// if it is a consecutive frame.
+fn data_frame_header(
+    frame: &mut [u8],
+    length: usize,
+    offset: usize,
+    sequence: u8,
+) -> (InFlight, usize, usize) {
+    let packet_size = frame.len();
+    if offset != 0 {
+        frame[0] = (pci::CONSECUTIVE_FRAME << 4) | (sequence & 0xF);
+        (
+            InFlight::ConsecutiveFrame,
+            1,
+            cmp::min(length - offset, packet_size - 1),
+        )
+    } else if length < can::STANDARD_CAN_PACKET_SIZE {
+        frame[0] = (pci::SINGLE_FRAME << 4) | length as u8;
+        (InFlight::SingleFrame, 1, length)
+    } else if packet_size > can::STANDARD_CAN_PACKET_SIZE && length <= packet_size - 2 {
+        frame[0] = pci::SINGLE_FRAME << 4;
+        frame[1] = length as u8;
+        (InFlight::SingleFrame, 2, length)
+    } else if length <= MAX_SHORT_MESSAGE_LENGTH {
+        frame[0] = (pci::FIRST_FRAME << 4) | (length >> 8) as u8;
+        frame[1] = length as u8;
+        (InFlight::FirstFrame, 2, packet_size - 2)
+    } else {
+        frame[0] = pci::FIRST_FRAME << 4;
+        frame[1] = 0;
+        frame[2..6].copy_from_slice(&(length as u32).to_be_bytes());
+        (InFlight::FirstFrame, 6, packet_size - 6)
+    }
+}
+
+/// Decodes the header of a single frame. Returns the message length and the
+/// length of the header, or `None` if the frame is malformed.
+fn single_frame_header(frame: &[u8]) -> Option<(usize, usize)> {
+    let (length, header_length) =
+        if frame[0] & 0xF == 0 && frame.len() > can::STANDARD_CAN_PACKET_SIZE {
+            (*frame.get(1)? as usize, 2)
+        } else {
+            ((frame[0] & 0xF) as usize, 1)
+        };
+    if length == 0 || header_length + length > frame.len() {
+        return None;
+    }
+    Some((length, header_length))
+}
+
+/// Decodes the header of a first frame. Returns the message length and the
+/// length of the header, or `None` if the frame is malformed or the message
+/// fits in a single frame.
+fn first_frame_header(frame: &[u8]) -> Option<(usize, usize)> {
+    if frame.len() < can::STANDARD_CAN_PACKET_SIZE {
+        return None;
+    }
+    let short_length = (((frame[0] & 0xF) as usize) << 8) | frame[1] as usize;
+    let (length, header_length) = if short_length == 0 {
+        (
+            u32::from_be_bytes([frame[2], frame[3], frame[4], frame[5]]) as usize,
+            6,
+        )
+    } else {
+        (short_length, 2)
+    };
+    if length <= frame.len() - header_length {
+        // Such a message must be sent as a single frame.
+        return None;
+    }
+    Some((length, header_length))
+}
+
+/// Writes a flow control frame with the given flow status, block size and
+/// STmin into `frame`. Returns the length of the header.
+fn flow_control_header(frame: &mut [u8], status: u8, block_size: u8, st_min: u8) -> usize {
+    frame[0] = (pci::FLOW_CONTROL << 4) | status;
+    frame[1] = block_size;
+    frame[2] = st_min;
+    3
+}
+
+/// Decodes a flow control frame. Returns the flow status, the block size and
+/// STmin, or `None` if the frame is too short.
+fn flow_control_parameters(frame: &[u8]) -> Option<(u8, u8, u8)> {
+    if frame.len() < 3 {
+        return None;
+    }
+    Some((frame[0] & 0xF, frame[1], frame[2]))
+}
+
+/// Converts an STmin value to the separation time in microseconds.
+fn separation_time_us(st_min: u8) -> u32 {
+    match st_min {
+        0x00..=0x7F => st_min as u32 * 1000,
+        0xF1..=0xF9 => (st_min - 0xF0) as u32 * 100,
+        // Reserved values must be interpreted as the longest separation time.
+        _ => 0x7F * 1000,
+    }
+}
+
+/// Returns the ticks left at `now` until a timer started at `reference` for
+/// `dt` ticks expires, or 0 if it expired.
+fn remaining<T: Ticks>(now: T, reference: T, dt: T) -> T {
+    let expiration = reference.wrapping_add(dt);
+    if now.within_range(reference, expiration) {
+        expiration.wrapping_sub(now)
+    } else {
+        T::from(0)
+    }
+}
+
+/// Reassembly state of a message received in several frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Reassembly {
+    /// Length of the message announced by the first frame.
+    length: usize,
+    /// Number of message bytes received so far.
+    offset: usize,
+    /// Sequence number of the next consecutive frame.
+    sequence: u8,
+    /// Number of consecutive frames received in the current block.
+    block_count: u8,
+}
+
+/// Where the data of a consecutive frame goes, and what to do next.
+#[derive(Debug, PartialEq)]
+struct Segment {
+    offset: usize,
+    length: usize,
+    /// The segment completes the message.
+    complete: bool,
+    /// The block is complete, and a flow control frame must be sent.
+    flow_control: bool,
+}
+
+impl Reassembly {
+    /// Starts reassembling a `length` byte message whose first frame
+    /// carried `received` bytes.
+    fn new(length: usize, received: usize) -> Self {
+        Self {
+            length,
+            offset: received,
+            sequence: 1,
+            block_count: 0,
+        }
+    }
+
+    /// Accounts for a consecutive frame with sequence number `sequence`
+    /// carrying up to `available` bytes, with blocks of `block_size` frames.
+    ///
+    /// Returns `ErrorCode::FAIL` if the frame is out of sequence.
+    fn consecutive_frame(
+        &mut self,
+        sequence: u8,
+        available: usize,
+        block_size: u8,
+    ) -> Result<Segment, ErrorCode> {
+        if sequence != self.sequence {
+            return Err(ErrorCode::FAIL);
+        }
+        let offset = self.offset;
+        let length = cmp::min(self.length - offset, available);
+        self.offset += length;
+        self.sequence = (self.sequence + 1) & 0xF;
+
+        let complete = self.offset == self.length;
+        let mut flow_control = false;
+        if !complete {
+            self.block_count = self.block_count.wrapping_add(1);
+            if block_size != 0 && self.block_count == block_size {
+                self.block_count = 0;
+                flow_control = true;
+            }
+        }
+        Ok(Segment {
+            offset,
+            length,
+            complete,
+            flow_control,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct CanIsoTp<
+    'a,
+    Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+    A: time::Alarm<'a>,
+    const PACKET_SIZE: usize = { can::STANDARD_CAN_PACKET_SIZE },
+> {
+    can: &'a Can,
+    alarm: &'a A,
+
+    tx_frame: TakeCell<'static, [u8; PACKET_SIZE]>,
+    rx_frame: TakeCell<'static, [u8; PACKET_SIZE]>,
+
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    owner: OptionalCell<ProcessId>,
+
+    tx_id: OptionalCell<can::Id>,
+    rx_id: OptionalCell<can::Id>,
+    /// Block size advertised in the flow control frames we send.
+    block_size: Cell<u8>,
+    /// Minimum separation time advertised in the flow control frames we
+    /// send, encoded as in the STmin field.
+    st_min: Cell<u8>,
+
+    in_flight: Cell<InFlight>,
+    flow_control_pending: OptionalCell<u8>,
+
+    tx_state: Cell<TxState>,
+    tx_length: Cell<usize>,
+    tx_offset: Cell<usize>,
+    tx_sequence: Cell<u8>,
+    tx_block_size: Cell<u8>,
+    tx_block_count: Cell<u8>,
+    tx_st_min: Cell<u8>,
+    tx_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+
+    receiving: Cell<bool>,
+    /// The message being received, if any.
+    rx: OptionalCell<Reassembly>,
+    rx_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+}
+
+impl<
+        'a,
+        Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: time::Alarm<'a>,
+        const PACKET_SIZE: usize,
+    > CanIsoTp<'a, Can, A, PACKET_SIZE>
+{
+    pub fn new(
+        can: &'a Can,
+        alarm: &'a A,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        tx_frame: &'static mut [u8; PACKET_SIZE],
+        rx_frame: &'static mut [u8; PACKET_SIZE],
+    ) -> Self {
+        Self {
+            can,
+            alarm,
+            tx_frame: TakeCell::new(tx_frame),
+            rx_frame: TakeCell::new(rx_frame),
+            apps: grant,
+            owner: OptionalCell::empty(),
+            tx_id: OptionalCell::empty(),
+            rx_id: OptionalCell::empty(),
+            block_size: Cell::new(0),
+            st_min: Cell::new(0),
+            in_flight: Cell::new(InFlight::None),
+            flow_control_pending: OptionalCell::empty(),
+            tx_state: Cell::new(TxState::Idle),
+            tx_length: Cell::new(0),
+            tx_offset: Cell::new(0),
+            tx_sequence: Cell::new(0),
+            tx_block_size: Cell::new(0),
+            tx_block_count: Cell::new(0),
+            tx_st_min: Cell::new(0),
+            tx_timer: OptionalCell::empty(),
+            receiving: Cell::new(false),
+            rx: OptionalCell::empty(),
+            rx_timer: OptionalCell::empty(),
+        }
+    }
+
+    /// Check to see if the process or no process at all owns the capsule
+    /// and, if so, mark the process as the owner.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let valid = self.owner.map_or(true, |owner| {
+            self.apps
+                .enter(owner, |_, _| owner == processid)
+                .unwrap_or(true)
+        });
+        if valid {
+            self.owner.set(processid);
+            Ok(())
+        } else {
+            Err(ErrorCode::RESERVE)
+        }
+    }
+
+    fn schedule_upcall(&self, upcall_number: usize, result: Result<(), ErrorCode>, len: usize) {
+        self.owner.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ =
+                    kernel_data.schedule_upcall(upcall_number, (into_statuscode(result), len, 0));
+            });
+        });
+    }
+
+    /// Returns the length of the frame that carries `len` bytes, including
+    /// padding. Frames of up to 8 bytes are always padded to 8 bytes, as
+    /// required for classic CAN.
+    fn frame_length(len: usize) -> usize {
+        if len <= can::STANDARD_CAN_PACKET_SIZE {
+            can::STANDARD_CAN_PACKET_SIZE
+        } else {
+            can::fd_payload_length(len).unwrap_or(PACKET_SIZE)
+        }
+    }
+
+    /// Converts an STmin value to the corresponding delay in ticks.
+    fn separation_time(&self, st_min: u8) -> A::Ticks {
+        self.alarm.ticks_from_us(separation_time_us(st_min))
+    }
+
+    fn set_timer(&self, timer: &OptionalCell<(A::Ticks, A::Ticks)>, dt: A::Ticks) {
+        timer.set((self.alarm.now(), dt));
+        self.arm_alarm();
+    }
+
+    /// Arms the alarm for the earliest of the transmit and receive timers.
+    fn arm_alarm(&self) {
+        let now = self.alarm.now();
+        let next = [self.tx_timer.get(), self.rx_timer.get()]
+            .into_iter()
+            .flatten()
+            .map(|(reference, dt)| remaining(now, reference, dt))
+            .min();
+        match next {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    /// Starts transmitting `length` bytes of the owner's read-only buffer.
+    fn start_send(&self, processid: ProcessId, length: usize) -> Result<(), ErrorCode> {
+        if self.tx_state.get() != TxState::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.tx_id.is_none() {
+            return Err(ErrorCode::INVAL);
+        }
+        if length == 0 || length > u32::MAX as usize {
+            return Err(ErrorCode::SIZE);
+        }
+        let available = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::MESSAGE)
+                    .map_or(0, |buffer| buffer.len())
+            })
+            .unwrap_or(0);
+        if length > available {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.tx_length.set(length);
+        self.tx_offset.set(0);
+        self.tx_sequence.set(0);
+        self.tx_state.set(TxState::Sending);
+        self.send_data_frame();
+        Ok(())
+    }
+
+    /// Sends the next single, first or consecutive frame of the message,
+    /// unless the frame buffer is currently in use.
+    fn send_data_frame(&self) {
+        if self.in_flight.get() != InFlight::None {
+            // The frame is sent when the current transmission completes.
+            return;
+        }
+        let Some(frame) = self.tx_frame.take() else {
+            return;
+        };
+
+        let length = self.tx_length.get();
+        let offset = self.tx_offset.get();
+
+        let (kind, header_length, data_length) =
+            data_frame_header(frame, length, offset, self.tx_sequence.get());
+
+        // Copy the payload from the process.
+        let copied = self.owner.map_or(Err(ErrorCode::RESERVE), |processid| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::MESSAGE)
+                        .and_then(|buffer| {
+                            buffer.enter(|data| {
+                                if offset + data_length > data.len() {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                data[offset..offset + data_length].copy_to_slice(
+                                    &mut frame[header_length..header_length + data_length],
+                                );
+                                Ok(())
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or(Err(ErrorCode::RESERVE))
+        });
+        if let Err(err) = copied {
+            self.tx_frame.replace(frame);
+            self.finish_send(Err(err));
+            return;
+        }
+
+        let frame_length = Self::frame_length(header_length + data_length);
+        frame[header_length + data_length..frame_length].fill(PADDING_BYTE);
+
+        let id = self.tx_id.get().unwrap_or(can::Id::Standard(0));
+        match self.can.send(id, frame, frame_length) {
+            Ok(()) => {
+                self.in_flight.set(kind);
+                self.tx_offset.set(offset + data_length);
+                if kind == InFlight::ConsecutiveFrame {
+                    self.tx_sequence.set((self.tx_sequence.get() + 1) & 0xF);
+                } else {
+                    self.tx_sequence.set(1);
+                }
+            }
+            Err((err, frame)) => {
+                self.tx_frame.replace(frame);
+                self.finish_send(Err(err));
+            }
+        }
+    }
+
+    fn finish_send(&self, result: Result<(), ErrorCode>) {
+        self.tx_state.set(TxState::Idle);
+        self.tx_timer.clear();
+        self.arm_alarm();
+        self.schedule_upcall(upcall::SEND_DONE, result, self.tx_length.get());
+    }
+
+    /// Sends a flow control frame, or queues it if the frame buffer is in
+    /// use.
+    fn send_flow_control(&self, status: u8) {
+        if self.in_flight.get() != InFlight::None {
+            self.flow_control_pending.set(status);
+            return;
+        }
+        let Some(frame) = self.tx_frame.take() else {
+            self.flow_control_pending.set(status);
+            return;
+        };
+
+        let header_length =
+            flow_control_header(frame, status, self.block_size.get(), self.st_min.get());
+        let frame_length = Self::frame_length(header_length);
+        frame[header_length..frame_length].fill(PADDING_BYTE);
+
+        let id = self.tx_id.get().unwrap_or(can::Id::Standard(0));
+        match self.can.send(id, frame, frame_length) {
+            Ok(()) => self.in_flight.set(InFlight::FlowControl),
+            Err((_, frame)) => {
+                // The sender times out and aborts the transfer.
+                self.tx_frame.replace(frame);
+            }
+        }
+    }
+
+    fn handle_flow_control(&self, frame: &[u8]) {
+        if self.tx_state.get() != TxState::WaitFlowControl {
+            return;
+        }
+        let Some((status, block_size, st_min)) = flow_control_parameters(frame) else {
+            return;
+        };
+        match status {
+            flow_status::CONTINUE_TO_SEND => {
+                self.tx_block_size.set(block_size);
+                self.tx_block_count.set(0);
+                self.tx_st_min.set(st_min);
+                self.tx_timer.clear();
+                self.arm_alarm();
+                self.tx_state.set(TxState::Sending);
+                self.send_data_frame();
+            }
+            flow_status::WAIT => {
+                self.set_timer(&self.tx_timer, self.alarm.ticks_from_ms(TIMEOUT_MS));
+            }
+            flow_status::OVERFLOW => self.finish_send(Err(ErrorCode::SIZE)),
+            _ => self.finish_send(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// Copies `data` to the owner's read-write buffer at `offset`.
+    fn write_message(&self, offset: usize, data: &[u8]) -> Result<(), ErrorCode> {
+        self.owner.map_or(Err(ErrorCode::RESERVE), |processid| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::MESSAGE)
+                        .and_then(|buffer| {
+                            buffer.mut_enter(|message| {
+                                if offset + data.len() > message.len() {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                message[offset..offset + data.len()].copy_from_slice(data);
+                                Ok(())
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or(Err(ErrorCode::RESERVE))
+        })
+    }
+
+    fn message_capacity(&self) -> usize {
+        self.owner.map_or(0, |processid| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::MESSAGE)
+                        .map_or(0, |buffer| buffer.len())
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn abort_reception(&self, err: ErrorCode) {
+        let received = self.rx.take().map_or(0, |rx| rx.offset);
+        self.rx_timer.clear();
+        self.arm_alarm();
+        self.schedule_upcall(upcall::MESSAGE_RECEIVED, Err(err), received);
+    }
+
+    fn handle_single_frame(&self, frame: &[u8]) {
+        let Some((length, header_length)) = single_frame_header(frame) else {
+            return;
+        };
+        let result = self.write_message(0, &frame[header_length..header_length + length]);
+        self.schedule_upcall(upcall::MESSAGE_RECEIVED, result, length);
+    }
+
+    fn handle_first_frame(&self, frame: &[u8]) {
+        let Some((length, header_length)) = first_frame_header(frame) else {
+            return;
+        };
+        let data_length = frame.len() - header_length;
+        if length > self.message_capacity() {
+            self.send_flow_control(flow_status::OVERFLOW);
+            return;
+        }
+        if self
+            .write_message(0, &frame[header_length..header_length + data_length])
+            .is_err()
+        {
+            self.send_flow_control(flow_status::OVERFLOW);
+            return;
+        }
+
+        self.rx.set(Reassembly::new(length, data_length));
+        self.send_flow_control(flow_status::CONTINUE_TO_SEND);
+        self.set_timer(&self.rx_timer, self.alarm.ticks_from_ms(TIMEOUT_MS));
+    }
+
+    fn handle_consecutive_frame(&self, frame: &[u8]) {
+        let Some(mut rx) = self.rx.get() else {
+            return;
+        };
+        let segment =
+            match rx.consecutive_frame(frame[0] & 0xF, frame.len() - 1, self.block_size.get()) {
+                Ok(segment) => segment,
+                Err(err) => {
+                    self.abort_reception(err);
+                    return;
+                }
+            };
+        self.rx.set(rx);
+        if let Err(err) = self.write_message(segment.offset, &frame[1..1 + segment.length]) {
+            self.abort_reception(err);
+            return;
+        }
+
+        if segment.complete {
+            self.rx.clear();
+            self.rx_timer.clear();
+            self.arm_alarm();
+            self.schedule_upcall(upcall::MESSAGE_RECEIVED, Ok(()), rx.length);
+            return;
+        }
+        if segment.flow_control {
+            self.send_flow_control(flow_status::CONTINUE_TO_SEND);
+        }
+        self.set_timer(&self.rx_timer, self.alarm.ticks_from_ms(TIMEOUT_MS));
+    }
+
+    fn start_receive(&self) -> Result<(), ErrorCode> {
+        if self.rx_id.is_none() {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.receiving.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.rx_frame.take().map_or(Err(ErrorCode::BUSY), |frame| {
+            match self.can.start_receive_process(frame) {
+                Ok(()) => {
+                    self.receiving.set(true);
+                    Ok(())
+                }
+                Err((err, frame)) => {
+                    self.rx_frame.replace(frame);
+                    Err(err)
+                }
+            }
+        })
+    }
+}
+
+/// Decodes a CAN identifier from command arguments: `arg1` holds the
+/// identifier and `arg2` selects standard (0) or extended (1) format.
+fn id_from_args(arg1: usize, arg2: usize) -> Result<can::Id, ErrorCode> {
+    match arg2 {
+        0 if arg1 <= 0x7FF => Ok(can::Id::Standard(arg1 as u16)),
+        1 if arg1 <= 0x1FFF_FFFF => Ok(can::Id::Extended(arg1 as u32)),
+        _ => Err(ErrorCode::INVAL),
+    }
+}
+
+impl<
+        'a,
+        Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: time::Alarm<'a>,
+        const PACKET_SIZE: usize,
+    > SyscallDriver for CanIsoTp<'a, Can, A, PACKET_SIZE>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Set the transmit identifier. `arg1` is the identifier, `arg2`
+    ///   is 0 for a standard and 1 for an extended identifier.
+    /// - `2`: Set the receive identifier, encoded as for command 1.
+    /// - `3`: Set the flow control parameters sent to the peer when
+    ///   receiving: `arg1` is the block size, `arg2` the STmin value.
+    /// - `4`: Send `arg1` bytes of the read-only buffer. Upcall 0 is
+    ///   scheduled when the transfer finishes.
+    /// - `5`: Start receiving messages into the read-write buffer. Upcall 1
+    ///   is scheduled for each received message.
+    /// - `6`: Stop receiving messages.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+
+        if let Err(err) = self.claim(processid) {
+            return CommandReturn::failure(err);
+        }
+
+        let result = match command_num {
+            1 => id_from_args(arg1, arg2).map(|id| self.tx_id.set(id)),
+            2 => id_from_args(arg1, arg2).map(|id| self.rx_id.set(id)),
+            3 => match (u8::try_from(arg1), u8::try_from(arg2)) {
+                (Ok(block_size), Ok(st_min @ (0x00..=0x7F | 0xF1..=0xF9))) => {
+                    self.block_size.set(block_size);
+                    self.st_min.set(st_min);
+                    Ok(())
+                }
+                _ => Err(ErrorCode::INVAL),
+            },
+            4 => self.start_send(processid, arg1),
+            5 => self.start_receive(),
+            6 => {
+                if self.receiving.get() {
+                    self.can.stop_receive()
+                } else {
+                    Err(ErrorCode::OFF)
+                }
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        CommandReturn::from(result)
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl<
+        'a,
+        Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: time::Alarm<'a>,
+        const PACKET_SIZE: usize,
+    > can::TransmitClient<PACKET_SIZE> for CanIsoTp<'a, Can, A, PACKET_SIZE>
+{
+    fn transmit_complete(
+        &self,
+        status: Result<(), can::Error>,
+        buffer: &'static mut [u8; PACKET_SIZE],
+    ) {
+        self.tx_frame.replace(buffer);
+        let sent = self.in_flight.replace(InFlight::None);
+
+        match (sent, status) {
+            (InFlight::None | InFlight::FlowControl, _) => {}
+            (_, Err(err)) => self.finish_send(Err(err.into())),
+            (InFlight::SingleFrame, Ok(())) => self.finish_send(Ok(())),
+            (InFlight::FirstFrame, Ok(())) => {
+                self.tx_state.set(TxState::WaitFlowControl);
+                self.set_timer(&self.tx_timer, self.alarm.ticks_from_ms(TIMEOUT_MS));
+            }
+            (InFlight::ConsecutiveFrame, Ok(())) => {
+                if self.tx_offset.get() >= self.tx_length.get() {
+                    self.finish_send(Ok(()));
+                } else {
+                    self.tx_block_count
+                        .set(self.tx_block_count.get().wrapping_add(1));
+                    if self.tx_block_size.get() != 0
+                        && self.tx_block_count.get() == self.tx_block_size.get()
+                    {
+                        self.tx_state.set(TxState::WaitFlowControl);
+                        self.set_timer(&self.tx_timer, self.alarm.ticks_from_ms(TIMEOUT_MS));
+                    } else if self.tx_st_min.get() == 0 {
+                        self.tx_state.set(TxState::Sending);
+                    } else {
+                        self.tx_state.set(TxState::WaitSeparation);
+                        self.set_timer(&self.tx_timer, self.separation_time(self.tx_st_min.get()));
+                    }
+                }
+            }
+        }
+
+        // A flow control frame takes precedence over our own data frames.
+        if let Some(status) = self.flow_control_pending.take() {
+            self.send_flow_control(status);
+        } else if self.tx_state.get() == TxState::Sending {
+            self.send_data_frame();
+        }
+    }
+}
+
+impl<
+        'a,
+        Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: time::Alarm<'a>,
+        const PACKET_SIZE: usize,
+    > can::ReceiveClient<PACKET_SIZE> for CanIsoTp<'a, Can, A, PACKET_SIZE>
+{
+    fn message_received(
+        &self,
+        id: can::Id,
+        buffer: &mut [u8; PACKET_SIZE],
+        len: usize,
+        status: Result<(), can::Error>,
+    ) {
+        if status.is_err() || len == 0 || len > PACKET_SIZE || self.rx_id.get() != Some(id) {
+            return;
+        }
+        let frame = &buffer[..len];
+
+        match frame[0] >> 4 {
+            pci::SINGLE_FRAME | pci::FIRST_FRAME => {
+                // A new message interrupts the one being received.
+                if self.rx.is_some() {
+                    self.abort_reception(ErrorCode::CANCEL);
+                }
+                if frame[0] >> 4 == pci::SINGLE_FRAME {
+                    self.handle_single_frame(frame);
+                } else {
+                    self.handle_first_frame(frame);
+                }
+            }
+            pci::CONSECUTIVE_FRAME => self.handle_consecutive_frame(frame),
+            pci::FLOW_CONTROL => self.handle_flow_control(frame),
+            _ => {}
+        }
+    }
+
+    fn stopped(&self, buffer: &'static mut [u8; PACKET_SIZE]) {
+        self.rx_frame.replace(buffer);
+        self.receiving.set(false);
+        if self.rx.is_some() {
+            self.abort_reception(ErrorCode::CANCEL);
+        }
+    }
+}
+
+impl<
+        'a,
+        Can: can::Transmit<PACKET_SIZE> + can::Receive<PACKET_SIZE>,
+        A: time::Alarm<'a>,
+        const PACKET_SIZE: usize,
+    > time::AlarmClient for CanIsoTp<'a, Can, A, PACKET_SIZE>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let expired = |(reference, dt): (A::Ticks, A::Ticks)| {
+            remaining(now, reference, dt) == A::Ticks::from(0)
+        };
+
+        if self.tx_timer.get().is_some_and(expired) {
+            self.tx_timer.clear();
+            match self.tx_state.get() {
+                TxState::WaitFlowControl => self.finish_send(Err(ErrorCode::NOACK)),
+                TxState::WaitSeparation => {
+                    self.tx_state.set(TxState::Sending);
+                    self.send_data_frame();
+                }
+                TxState::Idle | TxState::Sending => {}
+            }
+        }
+
+        if self.rx_timer.get().is_some_and(expired) {
+            self.rx_timer.clear();
+            if self.rx.is_some() {
+                self.abort_reception(ErrorCode::FAIL);
+            }
+        }
+
+        self.arm_alarm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::Ticks32;
+
+    const FD: usize = can::FD_CAN_PACKET_SIZE;
+    const CLASSIC: usize = can::STANDARD_CAN_PACKET_SIZE;
+
+    #[test]
+    fn single_frame_classic() {
+        let mut frame = [0; CLASSIC];
+        assert_eq!(
+            data_frame_header(&mut frame, 7, 0, 0),
+            (InFlight::SingleFrame, 1, 7)
+        );
+        assert_eq!(frame[0], 0x07);
+        assert_eq!(single_frame_header(&frame), Some((7, 1)));
+    }
+
+    #[test]
+    fn single_frame_fd_escape() {
+        let mut frame = [0; FD];
+        assert_eq!(
+            data_frame_header(&mut frame, 62, 0, 0),
+            (InFlight::SingleFrame, 2, 62)
+        );
+        assert_eq!(frame[..2], [0x00, 62]);
+        assert_eq!(single_frame_header(&frame), Some((62, 2)));
+
+        // The escape sequence is not valid on classic CAN.
+        assert_eq!(single_frame_header(&frame[..CLASSIC]), None);
+    }
+
+    #[test]
+    fn single_frame_malformed() {
+        // Zero length.
+        assert_eq!(single_frame_header(&[0x00, 0, 0, 0, 0, 0, 0, 0]), None);
+        // Longer than the frame.
+        assert_eq!(single_frame_header(&[0x05, 1, 2]), None);
+    }
+
+    #[test]
+    fn first_frame_classic() {
+        let mut frame = [0; CLASSIC];
+        assert_eq!(
+            data_frame_header(&mut frame, 0x123, 0, 0),
+            (InFlight::FirstFrame, 2, 6)
+        );
+        assert_eq!(frame[..2], [0x11, 0x23]);
+        assert_eq!(first_frame_header(&frame), Some((0x123, 2)));
+    }
+
+    #[test]
+    fn first_frame_long_message() {
+        let mut frame = [0; CLASSIC];
+        assert_eq!(
+            data_frame_header(&mut frame, 0x12345, 0, 0),
+            (InFlight::FirstFrame, 6, 2)
+        );
+        assert_eq!(frame[..6], [0x10, 0x00, 0x00, 0x01, 0x23, 0x45]);
+        assert_eq!(first_frame_header(&frame), Some((0x12345, 6)));
+    }
+
+    #[test]
+    fn first_frame_fd() {
+        let mut frame = [0; FD];
+        assert_eq!(
+            data_frame_header(&mut frame, 63, 0, 0),
+            (InFlight::FirstFrame, 2, 62)
+        );
+        assert_eq!(first_frame_header(&frame), Some((63, 2)));
+    }
+
+    #[test]
+    fn first_frame_malformed() {
+        // Too short.
+        assert_eq!(first_frame_header(&[0x10, 0x20, 0, 0]), None);
+        // The message fits in the first frame.
+        assert_eq!(first_frame_header(&[0x10, 0x06, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!(first_frame_header(&[0x10, 0x00, 0, 0, 0, 2, 0, 0]), None);
+    }
+
+    #[test]
+    fn consecutive_frame_header() {
+        let mut frame = [0; CLASSIC];
+        assert_eq!(
+            data_frame_header(&mut frame, 20, 6, 1),
+            (InFlight::ConsecutiveFrame, 1, 7)
+        );
+        assert_eq!(frame[0], 0x21);
+        // The last frame carries the rest of the message.
+        assert_eq!(
+            data_frame_header(&mut frame, 20, 20 - 3, 15),
+            (InFlight::ConsecutiveFrame, 1, 3)
+        );
+        assert_eq!(frame[0], 0x2F);
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut frame = [0; CLASSIC];
+        assert_eq!(
+            flow_control_header(&mut frame, flow_status::WAIT, 4, 0xF3),
+            3
+        );
+        assert_eq!(frame[..3], [0x31, 4, 0xF3]);
+        assert_eq!(
+            flow_control_parameters(&frame),
+            Some((flow_status::WAIT, 4, 0xF3))
+        );
+        assert_eq!(flow_control_parameters(&frame[..2]), None);
+    }
+
+    #[test]
+    fn reassembly() {
+        // 20 bytes: 6 in the first frame, then 7, 7.
+        let mut rx = Reassembly::new(20, 6);
+        assert_eq!(
+            rx.consecutive_frame(1, 7, 0),
+            Ok(Segment {
+                offset: 6,
+                length: 7,
+                complete: false,
+                flow_control: false,
+            })
+        );
+        assert_eq!(
+            rx.consecutive_frame(2, 7, 0),
+            Ok(Segment {
+                offset: 13,
+                length: 7,
+                complete: true,
+                flow_control: false,
+            })
+        );
+    }
+
+    #[test]
+    fn reassembly_out_of_sequence() {
+        let mut rx = Reassembly::new(20, 6);
+        let before = rx;
+        assert_eq!(rx.consecutive_frame(2, 7, 0), Err(ErrorCode::FAIL));
+        assert_eq!(rx, before);
+    }
+
+    #[test]
+    fn reassembly_sequence_wraps() {
+        let mut rx = Reassembly::new(1000, 6);
+        for i in 1..=40usize {
+            let sequence = (i & 0xF) as u8;
+            assert!(rx.consecutive_frame(sequence, 7, 0).is_ok());
+        }
+        assert_eq!(rx.sequence, 41 & 0xF);
+        assert_eq!(rx.offset, 6 + 40 * 7);
+        // Sequence numbers restart at 0, not 1, after 15.
+        let mut rx = Reassembly::new(1000, 6);
+        for sequence in 1..=15 {
+            assert!(rx.consecutive_frame(sequence, 7, 0).is_ok());
+        }
+        assert_eq!(rx.consecutive_frame(1, 7, 0), Err(ErrorCode::FAIL));
+        assert!(rx.consecutive_frame(0, 7, 0).is_ok());
+    }
+
+    #[test]
+    fn reassembly_block_size() {
+        let mut rx = Reassembly::new(100, 6);
+        let flow_control: [bool; 6] = core::array::from_fn(|i| {
+            rx.consecutive_frame((i + 1) as u8, 7, 3)
+                .unwrap()
+                .flow_control
+        });
+        assert_eq!(flow_control, [false, false, true, false, false, true]);
+
+        // No flow control frame once the message is complete.
+        let mut rx = Reassembly::new(20, 6);
+        assert!(!rx.consecutive_frame(1, 7, 2).unwrap().flow_control);
+        let last = rx.consecutive_frame(2, 7, 2).unwrap();
+        assert!(last.complete);
+        assert!(!last.flow_control);
+    }
+
+    #[test]
+    fn separation_time() {
+        assert_eq!(separation_time_us(0x00), 0);
+        assert_eq!(separation_time_us(0x7F), 127_000);
+        assert_eq!(separation_time_us(0xF1), 100);
+        assert_eq!(separation_time_us(0xF9), 900);
+        // Reserved values.
+        assert_eq!(separation_time_us(0x80), 127_000);
+        assert_eq!(separation_time_us(0xF0), 127_000);
+        assert_eq!(separation_time_us(0xFA), 127_000);
+    }
+
+    #[test]
+    fn timer_remaining() {
+        let t = Ticks32::from;
+        assert_eq!(remaining(t(100), t(90), t(20)), t(10));
+        assert_eq!(remaining(t(110), t(90), t(20)), t(0));
+        assert_eq!(remaining(t(200), t(90), t(20)), t(0));
+        // Timers started just before the counter wraps.
+        assert_eq!(remaining(t(u32::MAX), t(u32::MAX - 5), t(10)), t(5));
+        assert_eq!(remaining(t(2), t(u32::MAX - 5), t(10)), t(2));
+        assert_eq!(remaining(t(4), t(u32::MAX - 5), t(10)), t(0));
+    }
+}
@@ -0,0 +1,624 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Fault policy that records crash dumps of faulted processes in a log.
+//!
+//! When a process faults, the policy captures a compact crash record and
+//! appends it to a persistent log, then lets another fault policy decide
+//! whether the process is stopped or restarted. Faults of devices in the
+//! field are visible even if nobody was attached to the console.
+//!
+//! Each record holds:
+//!
+//! - the name, short ID and restart count of the process,
+//! - the address that caused the fault and the stack pointer, if the
+//!   architecture records them,
+//! - the memory layout of the process, from which the MPU configuration is
+//!   derived,
+//! - the last system call and the system call, dropped upcall and timeslice
+//!   expiration counts,
+//! - the last `MAX_TRACE_RECORDS` system calls and upcalls of the process, if
+//!   a syscall tracer is set with [`CrashDump::set_syscall_tracer`],
+//! - the architecture-specific stored state (the registers), as returned by
+//!   `Process::get_stored_state()`,
+//! - the top of the stack, up to `MAX_STACK_LEN` bytes.
+//!
+//! The records can be printed with the `crashes` command of the process
+//! console, or read by a process through the system call interface.
+//!
+//! The record is a header of little-endian 32-bit words followed by the trace
+//! records, the stored state and the stack:
+//!
+//! | Word  | Content                                               |
+//! |-------|-------------------------------------------------------|
+//! | 0     | Magic number `0x48535243`                             |
+//! | 1     | Format version                                        |
+//! | 2     | Flags: fault address, stack pointer, last syscall     |
+//! | 3     | Short ID, or 0 if locally unique                      |
+//! | 4     | Restart count                                         |
+//! | 5     | Fault address                                         |
+//! | 6     | Stack pointer                                         |
+//! | 7-13  | Flash start, non-protected start and end, RAM start,  |
+//! |       | break, grant region start and end                     |
+//! | 14-18 | Last system call class and arguments                  |
+//! | 19-21 | System call, dropped upcall and timeslice counts      |
+//! | 22-25 | Process name, truncated to 16 bytes                   |
+//! | 26-27 | Length of the stored state and of the stack           |
+//! | 28    | Number of trace records                               |
+//!
+//! Each trace record is 11 words: the timestamp, the process identifier, the
+//! event (with bit 8 set if the return value is present), the four arguments
+//! and the four return value registers, as in [`TraceRecord`].
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let crash_dump = components::crash_dump::CrashDumpComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crash_dump::DRIVER_NUM,
+//!     log,
+//!     &FAULT_RESPONSE,
+//! )
+//! .finalize(components::crash_dump_component_static!(Log));
+//! process_console.set_crash_dump_printer(crash_dump);
+//! ```
+//!
+//! The crash dump is then passed as the fault policy when loading processes.
+
+use core::cell::Cell;
+use core::fmt;
+
+use capsules_core::process_console::CrashDumpPrinter;
+use kernel::debug;
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::process::{self, Process, ProcessFaultPolicy, ShortId};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::syscall_trace::{SyscallTraceHistory, TraceRecord};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::CrashDump as usize;
+
+/// Length of the buffers holding a crash record.
+pub const RECORD_LEN: usize = 640;
+
+/// Maximum number of bytes of the stack saved in a record.
+pub const MAX_STACK_LEN: usize = 128;
+
+/// Maximum number of syscall trace records saved in a record.
+pub const MAX_TRACE_RECORDS: usize = 4;
+
+const MAGIC: u32 = 0x4853_5243;
+const VERSION: u32 = 2;
+const HEADER_WORDS: usize = 29;
+const HEADER_LEN: usize = HEADER_WORDS * 4;
+const NAME_INDEX: usize = 22;
+const NAME_LEN: usize = 16;
+const CONTEXT_LEN_INDEX: usize = 26;
+const STACK_LEN_INDEX: usize = 27;
+const TRACE_COUNT_INDEX: usize = 28;
+const TRACE_RECORD_WORDS: usize = 11;
+const TRACE_RECORD_LEN: usize = TRACE_RECORD_WORDS * 4;
+const TRACE_RETURN_VALUE: u32 = 1 << 8;
+
+const FLAG_FAULT_ADDRESS: u32 = 1 << 0;
+const FLAG_STACK_POINTER: u32 = 1 << 1;
+const FLAG_LAST_SYSCALL: u32 = 1 << 2;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the next crash record is read into.
+    pub const RECORD: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// A read, rewind or erase operation completed.
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// User of the read side of the log.
+#[derive(Clone, Copy, PartialEq)]
+enum Reader {
+    /// The process console is printing all records.
+    Console,
+    /// A process is reading records.
+    Process(ProcessId),
+}
+
+pub struct CrashDump<'a, L: LogRead<'a> + LogWrite<'a>> {
+    log: &'a L,
+    /// Policy deciding what happens to the process after its crash record is
+    /// captured.
+    policy: &'a dyn ProcessFaultPolicy,
+    apps: Grant<
+        (),
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Buffer crash records are captured in. Empty while a record is written.
+    write_buffer: TakeCell<'static, [u8]>,
+    /// Buffer crash records are read into. Empty while a record is read.
+    read_buffer: TakeCell<'static, [u8]>,
+    reader: OptionalCell<Reader>,
+    /// Process waiting for the log to be erased.
+    eraser: OptionalCell<ProcessId>,
+    /// Number of records that could not be written.
+    dropped: Cell<usize>,
+    /// Tracer the last system calls of the faulted process are read from.
+    syscall_tracer: OptionalCell<&'a dyn SyscallTraceHistory>,
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> CrashDump<'a, L> {
+    pub fn new(
+        log: &'a L,
+        policy: &'a dyn ProcessFaultPolicy,
+        grant: Grant<
+            (),
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        write_buffer: &'static mut [u8],
+        read_buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            log,
+            policy,
+            apps: grant,
+            write_buffer: TakeCell::new(write_buffer),
+            read_buffer: TakeCell::new(read_buffer),
+            reader: OptionalCell::empty(),
+            eraser: OptionalCell::empty(),
+            dropped: Cell::new(0),
+            syscall_tracer: OptionalCell::empty(),
+        }
+    }
+
+    /// Include the last system calls and upcalls of the faulted process
+    /// stored by `tracer` in the crash records.
+    pub fn set_syscall_tracer(&self, tracer: &'a dyn SyscallTraceHistory) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Returns the number of crash records that could not be written because
+    /// the log was busy or failed.
+    pub fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+
+    /// Capture the crash record of `process` and append it to the log.
+    fn record(&self, process: &dyn Process) {
+        let Some(buffer) = self.write_buffer.take() else {
+            // A previous record is still being written.
+            self.dropped.set(self.dropped.get() + 1);
+            return;
+        };
+        let length = capture_record(process, self.syscall_tracer.get(), buffer);
+        if let Err((_, buffer)) = self.log.append(buffer, length) {
+            self.write_buffer.replace(buffer);
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    /// Start reading the records for `reader`, from the oldest one if
+    /// `rewind` is set.
+    fn start_read(&self, reader: Reader, rewind: bool) -> Result<(), ErrorCode> {
+        if self.reader.is_some() || self.eraser.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.reader.set(reader);
+        let result = if rewind {
+            self.log.seek(self.log.log_start())
+        } else {
+            self.read_next()
+        };
+        if result.is_err() {
+            self.reader.clear();
+        }
+        result
+    }
+
+    /// Read the next record into the read buffer.
+    fn read_next(&self) -> Result<(), ErrorCode> {
+        let buffer = self.read_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let length = buffer.len();
+        self.log.read(buffer, length).map_err(|(error, buffer)| {
+            self.read_buffer.replace(buffer);
+            error
+        })
+    }
+
+    /// Signal the end of an operation to `processid`.
+    fn done(&self, processid: ProcessId, result: Result<(), ErrorCode>, length: usize) {
+        let _ = self.apps.enter(processid, |_, kernel_data| {
+            let _ = kernel_data.schedule_upcall(upcall::DONE, (into_statuscode(result), length, 0));
+        });
+    }
+}
+
+/// Writes `value` as a little-endian word at `index` in `record`.
+fn set_word(record: &mut [u8], index: usize, value: u32) {
+    record[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Serializes `record` into the words of a trace record.
+fn trace_record_words(record: &TraceRecord) -> [u32; TRACE_RECORD_WORDS] {
+    let mut event = record.event as u32;
+    if record.return_value.is_some() {
+        event |= TRACE_RETURN_VALUE;
+    }
+    let [a0, a1, a2, a3] = record.arguments;
+    let [r0, r1, r2, r3] = record.return_value.unwrap_or_default();
+    [
+        record.timestamp_us,
+        record.process_id,
+        event,
+        a0,
+        a1,
+        a2,
+        a3,
+        r0,
+        r1,
+        r2,
+        r3,
+    ]
+}
+
+/// Serialize the crash record of `process` into `buffer`, and return its
+/// length. The last system calls of the process are read from `tracer`, if
+/// any.
+fn capture_record(
+    process: &dyn Process,
+    tracer: Option<&dyn SyscallTraceHistory>,
+    buffer: &mut [u8],
+) -> usize {
+    let addresses = process.get_addresses();
+    let fault_address = process.debug_fault_address();
+    let stack_pointer = process.debug_stack_pointer();
+    let last_syscall = process
+        .debug_syscall_last()
+        .map(|syscall| TraceRecord::syscall(process.processid(), &syscall, None));
+
+    let mut flags = 0;
+    if fault_address.is_some() {
+        flags |= FLAG_FAULT_ADDRESS;
+    }
+    if stack_pointer.is_some() {
+        flags |= FLAG_STACK_POINTER;
+    }
+    if last_syscall.is_some() {
+        flags |= FLAG_LAST_SYSCALL;
+    }
+    let (syscall_class, syscall_arguments) = last_syscall.map_or((0, [0; 4]), |record| {
+        (record.event as u32, record.arguments)
+    });
+    let short_id = match process.short_app_id() {
+        ShortId::LocallyUnique => 0,
+        ShortId::Fixed(id) => id.get(),
+    };
+
+    let words: [u32; NAME_INDEX] = [
+        MAGIC,
+        VERSION,
+        flags,
+        short_id,
+        process.get_restart_count() as u32,
+        fault_address.unwrap_or(0) as u32,
+        stack_pointer.unwrap_or(0) as u32,
+        addresses.flash_start as u32,
+        addresses.flash_non_protected_start as u32,
+        addresses.flash_end as u32,
+        addresses.sram_start as u32,
+        addresses.sram_app_brk as u32,
+        addresses.sram_grant_start as u32,
+        addresses.sram_end as u32,
+        syscall_class,
+        syscall_arguments[0],
+        syscall_arguments[1],
+        syscall_arguments[2],
+        syscall_arguments[3],
+        process.debug_syscall_count() as u32,
+        process.debug_dropped_upcall_count() as u32,
+        process.debug_timeslice_expiration_count() as u32,
+    ];
+    for (chunk, word) in buffer.chunks_exact_mut(4).zip(words.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    let name = &mut buffer[NAME_INDEX * 4..NAME_INDEX * 4 + NAME_LEN];
+    name.fill(0);
+    let process_name = process.get_process_name().as_bytes();
+    let name_len = process_name.len().min(NAME_LEN);
+    name[..name_len].copy_from_slice(&process_name[..name_len]);
+
+    let mut trace = [TraceRecord::default(); MAX_TRACE_RECORDS];
+    let trace_count = tracer.map_or(0, |tracer| {
+        tracer.last_records(process.processid().id() as u32, &mut trace)
+    });
+    for (index, record) in trace[..trace_count].iter().enumerate() {
+        let start = HEADER_WORDS + index * TRACE_RECORD_WORDS;
+        for (offset, value) in trace_record_words(record).into_iter().enumerate() {
+            set_word(buffer, start + offset, value);
+        }
+    }
+
+    let context_start = HEADER_LEN + trace_count * TRACE_RECORD_LEN;
+    let context_len = process
+        .get_stored_state(&mut buffer[context_start..])
+        .unwrap_or(0);
+    let stack_start = context_start + context_len;
+    let stack_end = buffer.len().min(stack_start + MAX_STACK_LEN);
+    let stack_len = process
+        .debug_read_stack(&mut buffer[stack_start..stack_end])
+        .unwrap_or(0);
+
+    set_word(buffer, CONTEXT_LEN_INDEX, context_len as u32);
+    set_word(buffer, STACK_LEN_INDEX, stack_len as u32);
+    set_word(buffer, TRACE_COUNT_INDEX, trace_count as u32);
+    stack_start + stack_len
+}
+
+/// Reads the little-endian word at `index` in `record`.
+fn word(record: &[u8], index: usize) -> u32 {
+    record.get(index * 4..index * 4 + 4).map_or(0, |bytes| {
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    })
+}
+
+/// Prints `bytes` as little-endian words.
+struct Words<'b>(&'b [u8]);
+
+impl fmt::Display for Words<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for index in 0..self.0.len() / 4 {
+            write!(f, " {:08x}", word(self.0, index))?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the crash record in `record` with `debug!()`.
+fn print_record(record: &[u8]) {
+    if record.len() < HEADER_LEN || word(record, 0) != MAGIC || word(record, 1) != VERSION {
+        debug!("crash record: invalid");
+        return;
+    }
+    let flags = word(record, 2);
+    let name = &record[NAME_INDEX * 4..NAME_INDEX * 4 + NAME_LEN];
+    let name_len = name.iter().position(|&c| c == 0).unwrap_or(NAME_LEN);
+    debug!(
+        "crash {} (short ID {:#x}), restart {}",
+        core::str::from_utf8(&name[..name_len]).unwrap_or("?"),
+        word(record, 3),
+        word(record, 4),
+    );
+    if flags & FLAG_FAULT_ADDRESS != 0 {
+        debug!(" fault address {:#010x}", word(record, 5));
+    }
+    if flags & FLAG_STACK_POINTER != 0 {
+        debug!(" stack pointer {:#010x}", word(record, 6));
+    }
+    debug!(
+        " flash {:#010x}-{:#010x}-{:#010x} ram {:#010x}-{:#010x}-{:#010x}-{:#010x}",
+        word(record, 7),
+        word(record, 8),
+        word(record, 9),
+        word(record, 10),
+        word(record, 11),
+        word(record, 12),
+        word(record, 13),
+    );
+    if flags & FLAG_LAST_SYSCALL != 0 {
+        debug!(
+            " last syscall class {} ({:#x}, {:#x}, {:#x}, {:#x})",
+            word(record, 14),
+            word(record, 15),
+            word(record, 16),
+            word(record, 17),
+            word(record, 18),
+        );
+    }
+    debug!(
+        " syscalls {}, dropped upcalls {}, timeslice expirations {}",
+        word(record, 19),
+        word(record, 20),
+        word(record, 21),
+    );
+    let context_len = word(record, CONTEXT_LEN_INDEX) as usize;
+    let stack_len = word(record, STACK_LEN_INDEX) as usize;
+    let trace_count = (word(record, TRACE_COUNT_INDEX) as usize)
+        .min((record.len() - HEADER_LEN) / TRACE_RECORD_LEN);
+    for index in 0..trace_count {
+        // Printed like `TraceRecord`, so the syscall trace decoder can read
+        // them.
+        let start = HEADER_WORDS + index * TRACE_RECORD_WORDS;
+        let event = word(record, start + 2);
+        let arguments = &record[(start + 3) * 4..(start + 7) * 4];
+        let return_value = &record[(start + 7) * 4..(start + 11) * 4];
+        if event & TRACE_RETURN_VALUE != 0 {
+            debug!(
+                " trace {:08x} {:x} {:02x}{}{}",
+                word(record, start),
+                word(record, start + 1),
+                event & 0xFF,
+                Words(arguments),
+                Words(return_value),
+            );
+        } else {
+            debug!(
+                " trace {:08x} {:x} {:02x}{}",
+                word(record, start),
+                word(record, start + 1),
+                event & 0xFF,
+                Words(arguments),
+            );
+        }
+    }
+    let context_start = HEADER_LEN + trace_count * TRACE_RECORD_LEN;
+    let context = record
+        .get(context_start..context_start + context_len)
+        .unwrap_or(&[]);
+    let stack = record
+        .get(context_start + context_len..context_start + context_len + stack_len)
+        .unwrap_or(&[]);
+    debug!(" state{}", Words(context));
+    debug!(" stack{}", Words(stack));
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> ProcessFaultPolicy for CrashDump<'a, L> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        self.record(process);
+        self.policy.action(process)
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> CrashDumpPrinter for CrashDump<'a, L> {
+    fn print_crash_dumps(&self) -> Result<(), ErrorCode> {
+        self.start_read(Reader::Console, true)
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> LogReadClient for CrashDump<'a, L> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        match self.reader.get() {
+            Some(Reader::Console) => {
+                if error.is_ok() {
+                    print_record(&buffer[..length]);
+                }
+                self.read_buffer.replace(buffer);
+                // Print records until the end of the log.
+                if error.is_err() || self.read_next().is_err() {
+                    self.reader.clear();
+                }
+            }
+            Some(Reader::Process(processid)) => {
+                self.reader.clear();
+                let result = error.and_then(|()| {
+                    self.apps
+                        .enter(processid, |_, kernel_data| {
+                            kernel_data
+                                .get_readwrite_processbuffer(rw_allow::RECORD)
+                                .and_then(|record| {
+                                    record.mut_enter(|record| {
+                                        let len = length.min(record.len());
+                                        record[..len].copy_from_slice(&buffer[..len]);
+                                    })
+                                })
+                                .map_err(ErrorCode::from)
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                });
+                self.read_buffer.replace(buffer);
+                self.done(processid, result, length);
+            }
+            None => {
+                self.read_buffer.replace(buffer);
+            }
+        }
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        match self.reader.get() {
+            Some(Reader::Console) => {
+                debug!("{} crash records dropped", self.dropped.get());
+                if error.is_err() || self.read_next().is_err() {
+                    self.reader.clear();
+                }
+            }
+            Some(Reader::Process(processid)) => {
+                self.reader.clear();
+                self.done(processid, error, 0);
+            }
+            None => {}
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> LogWriteClient for CrashDump<'a, L> {
+    fn append_done(
+        &self,
+        buffer: &'static mut [u8],
+        _length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.write_buffer.replace(buffer);
+        if error.is_err() {
+            self.dropped.set(self.dropped.get() + 1);
+        } else {
+            // Make the record persistent right away, since the board may be
+            // reset before the page of the log fills up.
+            let _ = self.log.sync();
+        }
+    }
+
+    fn sync_done(&self, _error: Result<(), ErrorCode>) {}
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        if let Some(processid) = self.eraser.take() {
+            self.done(processid, error, 0);
+        }
+    }
+}
+
+/// Provide an interface for userland.
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> SyscallDriver for CrashDump<'a, L> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Read the next crash record into the read-write buffer. The
+    ///   upcall is called with the status and the length of the record. The
+    ///   status is `FAIL` when there are no more records.
+    /// - `2`: Go back to the oldest crash record. The upcall is called with
+    ///   the status.
+    /// - `3`: Erase all crash records. The upcall is called with the status.
+    ///
+    /// The position of the next record to read is shared by all processes and
+    /// the process console.
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start_read(Reader::Process(processid), false).into(),
+
+            2 => self.start_read(Reader::Process(processid), true).into(),
+
+            3 => {
+                if self.reader.is_some() || self.eraser.is_some() {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                let result = self.log.erase();
+                if result.is_ok() {
+                    self.eraser.set(processid);
+                }
+                result.into()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
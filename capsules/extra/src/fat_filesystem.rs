@@ -0,0 +1,1911 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! FAT16/FAT32 filesystem with a userspace syscall interface.
+//!
+//! This capsule lets processes open, read, write, seek, close and list files
+//! on a FAT16 or FAT32 volume, so that the files can later be read by any
+//! computer, for example after removing the SD card from the board.
+//!
+//! The volume is accessed through the `NonvolatileStorage` interface, one
+//! 512 byte sector at a time. It can be an SD card (through
+//! `sdcard::SDCardNonvolatileStorage`) or any other block-style storage. The
+//! storage can either start with the boot sector of the volume or with a
+//! master boot record, in which case the first partition is used. The volume
+//! is mounted on the first request.
+//!
+//! Supported features and limitations:
+//! - Only 8.3 file names are supported. Long file names are ignored when
+//!   searching and listing directories.
+//! - Paths can contain directories, separated by `/`, but directories cannot
+//!   be created or removed.
+//! - Files are created in existing directories, using free directory entries.
+//!   Directories are not extended when they are full.
+//! - Created files get a fixed timestamp, as there is no wall clock.
+//! - Sector numbers are converted to byte addresses for the storage, which
+//!   limits the volume to the first 4 GiB of the storage on 32-bit platforms.
+//!
+//! Each process has a table of `MAX_OPEN_FILES` open files, stored in its
+//! grant. Requests from processes are handled one at a time; each process can
+//! have one request waiting. Data written to a file is written to the storage
+//! before the write completes, along with the updated size of the file.
+//!
+//! ```text
+//! +------------------------------------------------+
+//! |                   userspace                    |
+//! +------------------------------------------------+
+//!                 kernel::SyscallDriver
+//! +------------------------------------------------+
+//! |   fat_filesystem::FatFilesystem (this)         |
+//! +------------------------------------------------+
+//!      hil::nonvolatile_storage::NonvolatileStorage
+//! +------------------------------------------------+
+//! |   sdcard::SDCardNonvolatileStorage, or other   |
+//! +------------------------------------------------+
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sd_storage = static_init!(
+//!     capsules_extra::sdcard::SDCardNonvolatileStorage<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::sdcard::SDCardNonvolatileStorage::new(sdcard)
+//! );
+//! sdcard.set_client(sd_storage);
+//!
+//! let filesystem = components::fat_filesystem::FatFilesystemComponent::new(
+//!     board_kernel,
+//!     capsules_extra::fat_filesystem::DRIVER_NUM,
+//!     sd_storage,
+//! )
+//! .finalize(components::fat_filesystem_component_static!());
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FatFilesystem as usize;
+
+/// Size of the sectors of the volume, and of the buffer of this capsule.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Number of files each process can keep open.
+pub const MAX_OPEN_FILES: usize = 4;
+
+/// Maximum length of a path.
+pub const MAX_PATH_LEN: usize = 64;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Open done callback.
+    pub const OPEN_DONE: usize = 0;
+    /// Read done callback.
+    pub const READ_DONE: usize = 1;
+    /// Write done callback.
+    pub const WRITE_DONE: usize = 2;
+    /// List done callback.
+    pub const LIST_DONE: usize = 3;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 4;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Path of the file to open or of the directory to list.
+    pub const PATH: usize = 0;
+    /// Data to write to a file.
+    pub const WRITE: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Data read from a file, or name of a directory entry.
+    pub const READ: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Flags of the open command.
+mod open_flags {
+    /// Allow writing to the file.
+    pub const WRITE: usize = 1 << 0;
+    /// Create the file if it does not exist.
+    pub const CREATE: usize = 1 << 1;
+    /// Remove the content of the file.
+    pub const TRUNCATE: usize = 1 << 2;
+    /// Always write at the end of the file.
+    pub const APPEND: usize = 1 << 3;
+}
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: u32 = (SECTOR_SIZE / DIR_ENTRY_SIZE) as u32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// First byte of the name of the entry following the last used entry.
+const ENTRY_END: u8 = 0x00;
+/// First byte of the name of a deleted entry.
+const ENTRY_DELETED: u8 = 0xE5;
+
+/// Date of the files created by this capsule (2000-01-01).
+const DEFAULT_DATE: u16 = (20 << 9) | (1 << 5) | 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// Layout of a mounted volume, in sectors from the start of the storage.
+#[derive(Clone, Copy, Debug)]
+struct Volume {
+    fat_type: FatType,
+    sectors_per_cluster: u32,
+    /// First sector of the first FAT.
+    fat_start: u32,
+    /// Number of sectors of each FAT.
+    fat_sectors: u32,
+    num_fats: u32,
+    /// Location of the fixed root directory of FAT16 volumes.
+    root_dir_start: u32,
+    root_dir_sectors: u32,
+    /// First cluster of the root directory, or 0 for the fixed root
+    /// directory of FAT16 volumes.
+    root_cluster: u32,
+    /// Sector of cluster 2, the first data cluster.
+    data_start: u32,
+    /// One more than the number of the last cluster.
+    cluster_limit: u32,
+}
+
+impl Volume {
+    /// Parses the boot sector of a volume that starts at sector `start`.
+    fn from_boot_sector(sector: &[u8], start: u32) -> Result<Volume, ErrorCode> {
+        let u16_at = |i: usize| u16::from_le_bytes([sector[i], sector[i + 1]]) as u32;
+        let u32_at =
+            |i: usize| u32::from_le_bytes([sector[i], sector[i + 1], sector[i + 2], sector[i + 3]]);
+
+        if u16_at(11) != SECTOR_SIZE as u32 {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let sectors_per_cluster = sector[13] as u32;
+        let reserved_sectors = u16_at(14);
+        let num_fats = sector[16] as u32;
+        let root_entries = u16_at(17);
+        let total_sectors = match u16_at(19) {
+            0 => u32_at(32),
+            sectors => sectors,
+        };
+        let fat_sectors = match u16_at(22) {
+            0 => u32_at(36),
+            sectors => sectors,
+        };
+        if !sectors_per_cluster.is_power_of_two() || num_fats == 0 || fat_sectors == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let root_dir_sectors = (root_entries * DIR_ENTRY_SIZE as u32).div_ceil(SECTOR_SIZE as u32);
+        let fat_start = start + reserved_sectors;
+        let root_dir_start = fat_start + num_fats * fat_sectors;
+        let data_start = root_dir_start + root_dir_sectors;
+        let cluster_count = total_sectors
+            .checked_sub(data_start - start)
+            .ok_or(ErrorCode::INVAL)?
+            / sectors_per_cluster;
+
+        let (fat_type, root_cluster) = if cluster_count < 4085 {
+            // FAT12 is not supported.
+            return Err(ErrorCode::NOSUPPORT);
+        } else if cluster_count < 65525 {
+            (FatType::Fat16, 0)
+        } else {
+            (FatType::Fat32, u32_at(44))
+        };
+
+        let volume = Volume {
+            fat_type,
+            sectors_per_cluster,
+            fat_start,
+            fat_sectors,
+            num_fats,
+            root_dir_start,
+            root_dir_sectors,
+            root_cluster,
+            data_start,
+            cluster_limit: 0,
+        };
+        // The FAT may be too small for all the clusters of the volume.
+        let fat_entries = fat_sectors * SECTOR_SIZE as u32 / volume.fat_entry_size();
+        Ok(Volume {
+            cluster_limit: cmp::min(cluster_count + 2, fat_entries),
+            ..volume
+        })
+    }
+
+    fn cluster_size(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    fn fat_entry_size(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Returns the sector of the first FAT holding the entry of `cluster`,
+    /// and the offset of the entry in the sector.
+    fn fat_entry_location(&self, cluster: u32) -> (u32, usize) {
+        let offset = cluster * self.fat_entry_size();
+        (
+            self.fat_start + offset / SECTOR_SIZE as u32,
+            offset as usize % SECTOR_SIZE,
+        )
+    }
+
+    fn read_fat_entry(&self, sector: &[u8], offset: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => u16::from_le_bytes([sector[offset], sector[offset + 1]]) as u32,
+            FatType::Fat32 => {
+                u32::from_le_bytes([
+                    sector[offset],
+                    sector[offset + 1],
+                    sector[offset + 2],
+                    sector[offset + 3],
+                ]) & 0x0FFF_FFFF
+            }
+        }
+    }
+
+    fn write_fat_entry(&self, sector: &mut [u8], offset: usize, value: u32) {
+        match self.fat_type {
+            FatType::Fat16 => {
+                sector[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+            }
+            FatType::Fat32 => {
+                // The upper 4 bits are reserved and must be preserved.
+                let reserved = sector[offset + 3] & 0xF0;
+                sector[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                sector[offset + 3] = (sector[offset + 3] & 0x0F) | reserved;
+            }
+        }
+    }
+
+    /// Whether a FAT entry ends a cluster chain. Entries that do not point to
+    /// a valid cluster are also treated as the end of the chain.
+    fn is_end_of_chain(&self, value: u32) -> bool {
+        value < 2 || value >= self.cluster_limit
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// Number of directory entries in one cluster of a directory, or in the
+    /// fixed FAT16 root directory (cluster 0).
+    fn dir_entries(&self, cluster: u32) -> u32 {
+        if cluster == 0 {
+            self.root_dir_sectors * ENTRIES_PER_SECTOR
+        } else {
+            self.sectors_per_cluster * ENTRIES_PER_SECTOR
+        }
+    }
+
+    fn dir_entry_sector(&self, cluster: u32, index: u32) -> u32 {
+        if cluster == 0 {
+            self.root_dir_start + index / ENTRIES_PER_SECTOR
+        } else {
+            self.cluster_sector(cluster) + index / ENTRIES_PER_SECTOR
+        }
+    }
+}
+
+/// Fields of a directory entry used by this capsule.
+#[derive(Clone, Copy, Debug)]
+struct DirEntry {
+    name: [u8; 11],
+    attributes: u8,
+    first_cluster: u32,
+    size: u32,
+}
+
+impl DirEntry {
+    fn from_bytes(entry: &[u8]) -> DirEntry {
+        let mut name = [0; 11];
+        name.copy_from_slice(&entry[0..11]);
+        DirEntry {
+            name,
+            attributes: entry[11],
+            first_cluster: (u16::from_le_bytes([entry[20], entry[21]]) as u32) << 16
+                | u16::from_le_bytes([entry[26], entry[27]]) as u32,
+            size: u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]),
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+}
+
+/// Converts a path component to the space padded 8.3 name stored in
+/// directory entries. Returns `None` if the component is not a valid 8.3
+/// name.
+fn short_name(component: &[u8]) -> Option<[u8; 11]> {
+    let (base, extension) = match component.iter().rposition(|&c| c == b'.') {
+        Some(dot) => (&component[..dot], &component[dot + 1..]),
+        None => (component, &[][..]),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+
+    let valid_char = |c: u8| {
+        let c = c.to_ascii_uppercase();
+        (c.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&c)).then_some(c)
+    };
+    let mut name = [b' '; 11];
+    for (i, &c) in base.iter().enumerate() {
+        name[i] = valid_char(c)?;
+    }
+    for (i, &c) in extension.iter().enumerate() {
+        name[8 + i] = valid_char(c)?;
+    }
+    Some(name)
+}
+
+/// Converts an 8.3 name of a directory entry to the `NAME.EXT` form. Returns
+/// the buffer and the length of the name.
+fn display_name(name: &[u8; 11]) -> ([u8; 12], usize) {
+    let mut display = [0; 12];
+    let mut len = 0;
+    for &c in name[..8].iter().filter(|&&c| c != b' ') {
+        display[len] = c;
+        len += 1;
+    }
+    if name[8] != b' ' {
+        display[len] = b'.';
+        len += 1;
+        for &c in name[8..].iter().filter(|&&c| c != b' ') {
+            display[len] = c;
+            len += 1;
+        }
+    }
+    (display, len)
+}
+
+/// Position in a directory. Cluster 0 is the fixed FAT16 root directory.
+#[derive(Clone, Copy, Debug)]
+struct DirCursor {
+    cluster: u32,
+    /// Index of the entry in the cluster.
+    index: u32,
+}
+
+/// Result of reading a directory entry.
+enum DirSlot {
+    /// The entry at `sector` and `offset`.
+    Entry {
+        sector: u32,
+        offset: usize,
+        entry: DirEntry,
+    },
+    /// There are no more entries. `free` is the location of the end marker,
+    /// if the directory has one.
+    End { free: Option<(u32, usize)> },
+}
+
+/// A file opened by a process.
+#[derive(Clone, Copy, Debug, Default)]
+struct OpenFile {
+    /// Location of the directory entry of the file.
+    entry_sector: u32,
+    entry_offset: usize,
+    first_cluster: u32,
+    size: u32,
+    position: u32,
+    writable: bool,
+    append: bool,
+    /// The cluster holding the bytes starting at `cluster_index` times the
+    /// cluster size, to avoid following the chain from the start at every
+    /// access. 0 if not known.
+    cluster: u32,
+    cluster_index: u32,
+}
+
+/// Request of a process.
+#[derive(Clone, Copy, Debug)]
+enum Command {
+    Open { flags: usize },
+    Read { handle: usize, length: usize },
+    Write { handle: usize, length: usize },
+    List { index: usize },
+}
+
+impl Command {
+    fn upcall(&self) -> usize {
+        match self {
+            Command::Open { .. } => upcall::OPEN_DONE,
+            Command::Read { .. } => upcall::READ_DONE,
+            Command::Write { .. } => upcall::WRITE_DONE,
+            Command::List { .. } => upcall::LIST_DONE,
+        }
+    }
+}
+
+/// Progress of the request being handled.
+#[derive(Clone, Copy, Debug)]
+enum Phase {
+    /// Mount the volume if needed, then start the request.
+    Start,
+    /// Reading the first sector of the storage, or the boot sector of the
+    /// first partition.
+    Mount { partition: Option<u32> },
+    /// Searching the path. `component` is the offset of the path component
+    /// searched in `cursor`, and `free` an unused directory entry.
+    Lookup {
+        component: usize,
+        cursor: DirCursor,
+        free: Option<(u32, usize)>,
+    },
+    /// Writing the directory entry of a new file.
+    Create { sector: u32, offset: usize },
+    /// Freeing the clusters of a truncated file, starting at `cluster`.
+    /// `next` is the cluster that follows it, once read.
+    Truncate { cluster: u32, next: Option<u32> },
+    /// Reading or writing the file data.
+    Transfer,
+    /// Writing the size and first cluster of the file in its directory entry.
+    UpdateEntry,
+    /// Writing the cached sector to the storage before completing.
+    Flush,
+    /// Listing the directory at `cursor`. `seen` counts the entries skipped.
+    List { cursor: DirCursor, seen: usize },
+}
+
+/// Progress of a cluster allocation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Allocation {
+    Idle,
+    /// A free cluster was found.
+    Found(u32),
+    /// The cluster is marked as the end of a chain in the FAT.
+    Marked(u32),
+}
+
+/// Outcome of a step of the request being handled.
+enum Step {
+    /// The request progressed and can continue right away.
+    Continue,
+    /// A storage operation was started.
+    Wait,
+    /// The request is complete, with the values for the upcall.
+    Done(usize, usize),
+}
+
+/// State stored in the grant region on behalf of each app.
+#[derive(Default)]
+pub struct App {
+    files: [Option<OpenFile>; MAX_OPEN_FILES],
+    pending: Option<Command>,
+}
+
+pub struct FatFilesystem<'a> {
+    /// The underlying storage.
+    storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+    /// Per-app state.
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+
+    /// Buffer holding one sector of the volume.
+    buffer: TakeCell<'static, [u8]>,
+    /// The sector held in the buffer, if any.
+    cached_sector: OptionalCell<u32>,
+    /// Whether the buffer was modified since the sector was read.
+    dirty: Cell<bool>,
+    /// The sector being read or written by the storage.
+    io_sector: OptionalCell<u32>,
+
+    volume: OptionalCell<Volume>,
+
+    /// The request being handled and its progress.
+    current: OptionalCell<(ProcessId, Command)>,
+    phase: Cell<Phase>,
+    /// Path of the open or list request.
+    path: Cell<[u8; MAX_PATH_LEN]>,
+    path_len: Cell<usize>,
+    /// The file being opened, read or written.
+    file: Cell<OpenFile>,
+    /// Number of bytes to transfer, and transferred so far.
+    transfer_len: Cell<usize>,
+    transferred: Cell<usize>,
+
+    /// Number of FATs already updated with the FAT entry being written.
+    fat_copy: Cell<u32>,
+    allocation: Cell<Allocation>,
+    /// Where to start searching for a free cluster.
+    allocation_hint: Cell<u32>,
+    /// Number of clusters checked while searching for a free cluster.
+    allocation_scanned: Cell<u32>,
+}
+
+impl<'a> FatFilesystem<'a> {
+    pub fn new(
+        storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        buffer: &'static mut [u8; SECTOR_SIZE],
+    ) -> FatFilesystem<'a> {
+        FatFilesystem {
+            storage,
+            apps: grant,
+            buffer: TakeCell::new(buffer),
+            cached_sector: OptionalCell::empty(),
+            dirty: Cell::new(false),
+            io_sector: OptionalCell::empty(),
+            volume: OptionalCell::empty(),
+            current: OptionalCell::empty(),
+            phase: Cell::new(Phase::Start),
+            path: Cell::new([0; MAX_PATH_LEN]),
+            path_len: Cell::new(0),
+            file: Cell::new(OpenFile::default()),
+            transfer_len: Cell::new(0),
+            transferred: Cell::new(0),
+            fat_copy: Cell::new(0),
+            allocation: Cell::new(Allocation::Idle),
+            allocation_hint: Cell::new(2),
+            allocation_scanned: Cell::new(0),
+        }
+    }
+
+    fn sector_address(sector: u32) -> Result<usize, ErrorCode> {
+        (sector as usize)
+            .checked_mul(SECTOR_SIZE)
+            .ok_or(ErrorCode::SIZE)
+    }
+
+    /// Makes `sector` the cached sector. Returns `None` if a storage
+    /// operation was started, in which case the request continues once it
+    /// completes.
+    fn load_sector(&self, sector: u32) -> Result<Option<()>, ErrorCode> {
+        if self.cached_sector.contains(&sector) {
+            return Ok(Some(()));
+        }
+        if self.dirty.get() {
+            return self.flush();
+        }
+
+        let address = Self::sector_address(sector)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.cached_sector.clear();
+        self.io_sector.set(sector);
+        self.storage
+            .read(buffer, address, SECTOR_SIZE)
+            .map(|()| None)
+            .inspect_err(|_| self.io_sector.clear())
+    }
+
+    /// Writes the cached sector to the storage if it was modified. Returns
+    /// `None` if a storage operation was started.
+    fn flush(&self) -> Result<Option<()>, ErrorCode> {
+        if !self.dirty.get() {
+            return Ok(Some(()));
+        }
+
+        let sector = self.cached_sector.get().ok_or(ErrorCode::FAIL)?;
+        let address = Self::sector_address(sector)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.io_sector.set(sector);
+        self.storage
+            .write(buffer, address, SECTOR_SIZE)
+            .map(|()| None)
+            .inspect_err(|_| self.io_sector.clear())
+    }
+
+    fn with_sector<R>(
+        &self,
+        sector: u32,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<Option<R>, ErrorCode> {
+        let Some(()) = self.load_sector(sector)? else {
+            return Ok(None);
+        };
+        self.buffer
+            .map(|buffer| f(&buffer[..SECTOR_SIZE]))
+            .ok_or(ErrorCode::FAIL)
+            .map(Some)
+    }
+
+    fn with_sector_mut<R>(
+        &self,
+        sector: u32,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Result<Option<R>, ErrorCode> {
+        let Some(()) = self.load_sector(sector)? else {
+            return Ok(None);
+        };
+        self.dirty.set(true);
+        self.buffer
+            .map(|buffer| f(&mut buffer[..SECTOR_SIZE]))
+            .ok_or(ErrorCode::FAIL)
+            .map(Some)
+    }
+
+    /// Like `with_sector_mut`, for a sector whose previous content does not
+    /// need to be read first. The sector is cleared instead, so that no data
+    /// of other sectors ends up in it.
+    fn overwrite_sector<R>(
+        &self,
+        sector: u32,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Result<Option<R>, ErrorCode> {
+        if !self.cached_sector.contains(&sector) {
+            let Some(()) = self.flush()? else {
+                return Ok(None);
+            };
+            self.buffer.map(|buffer| buffer.fill(0));
+            self.cached_sector.set(sector);
+        }
+        self.with_sector_mut(sector, f)
+    }
+
+    fn fat_entry(&self, volume: &Volume, cluster: u32) -> Result<Option<u32>, ErrorCode> {
+        let (sector, offset) = volume.fat_entry_location(cluster);
+        self.with_sector(sector, |buffer| volume.read_fat_entry(buffer, offset))
+    }
+
+    /// Sets the entry of `cluster` in all the FATs of the volume.
+    fn set_fat_entry(
+        &self,
+        volume: &Volume,
+        cluster: u32,
+        value: u32,
+    ) -> Result<Option<()>, ErrorCode> {
+        let (sector, offset) = volume.fat_entry_location(cluster);
+        while self.fat_copy.get() < volume.num_fats {
+            let copy_sector = sector + self.fat_copy.get() * volume.fat_sectors;
+            let Some(()) = self.with_sector_mut(copy_sector, |buffer| {
+                volume.write_fat_entry(buffer, offset, value)
+            })?
+            else {
+                return Ok(None);
+            };
+            self.fat_copy.set(self.fat_copy.get() + 1);
+        }
+        self.fat_copy.set(0);
+        Ok(Some(()))
+    }
+
+    fn find_free_cluster(&self, volume: &Volume) -> Result<Option<u32>, ErrorCode> {
+        let clusters = volume.cluster_limit - 2;
+        loop {
+            let scanned = self.allocation_scanned.get();
+            if scanned >= clusters {
+                self.allocation_scanned.set(0);
+                return Err(ErrorCode::NOMEM);
+            }
+
+            let cluster = 2 + (self.allocation_hint.get() - 2 + scanned) % clusters;
+            let Some(value) = self.fat_entry(volume, cluster)? else {
+                return Ok(None);
+            };
+            if value == 0 {
+                self.allocation_scanned.set(0);
+                self.allocation_hint.set(cluster);
+                return Ok(Some(cluster));
+            }
+            self.allocation_scanned.set(scanned + 1);
+        }
+    }
+
+    /// Allocates a cluster and appends it to the chain ending at `last`, if
+    /// any.
+    fn allocate_cluster(
+        &self,
+        volume: &Volume,
+        last: Option<u32>,
+    ) -> Result<Option<u32>, ErrorCode> {
+        if self.allocation.get() == Allocation::Idle {
+            let Some(cluster) = self.find_free_cluster(volume)? else {
+                return Ok(None);
+            };
+            self.allocation.set(Allocation::Found(cluster));
+        }
+        if let Allocation::Found(cluster) = self.allocation.get() {
+            let Some(()) = self.set_fat_entry(volume, cluster, volume.end_of_chain())? else {
+                return Ok(None);
+            };
+            self.allocation.set(Allocation::Marked(cluster));
+        }
+        let Allocation::Marked(cluster) = self.allocation.get() else {
+            return Err(ErrorCode::FAIL);
+        };
+        if let Some(last) = last {
+            let Some(()) = self.set_fat_entry(volume, last, cluster)? else {
+                return Ok(None);
+            };
+        }
+        self.allocation.set(Allocation::Idle);
+        Ok(Some(cluster))
+    }
+
+    /// Returns the cluster holding the byte at the position of the file,
+    /// following the cluster chain from the closest known cluster. When
+    /// `extend` is set, clusters are allocated as needed, otherwise `0` is
+    /// returned if the chain is too short.
+    fn file_cluster(&self, volume: &Volume, extend: bool) -> Result<Option<u32>, ErrorCode> {
+        let mut file = self.file.get();
+        let index = file.position / volume.cluster_size();
+
+        if file.first_cluster == 0 {
+            if !extend {
+                return Ok(Some(0));
+            }
+            let Some(cluster) = self.allocate_cluster(volume, None)? else {
+                return Ok(None);
+            };
+            file.first_cluster = cluster;
+            file.cluster = 0;
+        }
+        if file.cluster == 0 || file.cluster_index > index {
+            file.cluster = file.first_cluster;
+            file.cluster_index = 0;
+        }
+        self.file.set(file);
+
+        while file.cluster_index < index {
+            // An allocation in progress is resumed without reading the FAT
+            // entry again, as it may already point to the new cluster.
+            let next = if self.allocation.get() != Allocation::Idle {
+                0
+            } else {
+                let Some(next) = self.fat_entry(volume, file.cluster)? else {
+                    return Ok(None);
+                };
+                next
+            };
+            let next = if !volume.is_end_of_chain(next) {
+                next
+            } else if extend {
+                let Some(cluster) = self.allocate_cluster(volume, Some(file.cluster))? else {
+                    return Ok(None);
+                };
+                cluster
+            } else {
+                return Ok(Some(0));
+            };
+            file.cluster = next;
+            file.cluster_index += 1;
+            self.file.set(file);
+        }
+        Ok(Some(file.cluster))
+    }
+
+    /// Reads the entry of a directory at `cursor`, moving the cursor to the
+    /// next cluster of the directory if needed.
+    fn dir_entry(
+        &self,
+        volume: &Volume,
+        cursor: &mut DirCursor,
+    ) -> Result<Option<DirSlot>, ErrorCode> {
+        if cursor.index >= volume.dir_entries(cursor.cluster) {
+            if cursor.cluster == 0 {
+                return Ok(Some(DirSlot::End { free: None }));
+            }
+            let Some(next) = self.fat_entry(volume, cursor.cluster)? else {
+                return Ok(None);
+            };
+            if volume.is_end_of_chain(next) {
+                return Ok(Some(DirSlot::End { free: None }));
+            }
+            *cursor = DirCursor {
+                cluster: next,
+                index: 0,
+            };
+        }
+
+        let sector = volume.dir_entry_sector(cursor.cluster, cursor.index);
+        let offset = (cursor.index % ENTRIES_PER_SECTOR) as usize * DIR_ENTRY_SIZE;
+        let Some(entry) = self.with_sector(sector, |buffer| {
+            DirEntry::from_bytes(&buffer[offset..offset + DIR_ENTRY_SIZE])
+        })?
+        else {
+            return Ok(None);
+        };
+        if entry.name[0] == ENTRY_END {
+            Ok(Some(DirSlot::End {
+                free: Some((sector, offset)),
+            }))
+        } else {
+            Ok(Some(DirSlot::Entry {
+                sector,
+                offset,
+                entry,
+            }))
+        }
+    }
+
+    /// The cursor at the start of the directory whose first cluster is
+    /// `cluster`.
+    fn dir_start(volume: &Volume, cluster: u32) -> DirCursor {
+        DirCursor {
+            // Entries referring to the root directory use cluster 0.
+            cluster: if cluster == 0 {
+                volume.root_cluster
+            } else {
+                cluster
+            },
+            index: 0,
+        }
+    }
+
+    /// Handles the next part of the current request.
+    fn step(&self, processid: ProcessId, command: Command) -> Result<Step, ErrorCode> {
+        let phase = self.phase.get();
+        if let Phase::Mount { partition } = phase {
+            return self.step_mount(partition);
+        }
+        let Some(volume) = self.volume.get() else {
+            self.phase.set(Phase::Mount { partition: None });
+            return Ok(Step::Continue);
+        };
+
+        match phase {
+            Phase::Mount { .. } => Err(ErrorCode::FAIL),
+            Phase::Start => self.step_start(&volume, processid, command),
+            Phase::Lookup {
+                component,
+                cursor,
+                free,
+            } => self.step_lookup(&volume, command, component, cursor, free),
+            Phase::Create { sector, offset } => {
+                let Some(()) = self.with_sector_mut(sector, |buffer| {
+                    let entry = &mut buffer[offset..offset + DIR_ENTRY_SIZE];
+                    entry.fill(0);
+                    entry[0..11].copy_from_slice(&self.file_name());
+                    entry[11] = ATTR_ARCHIVE;
+                    entry[16..18].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+                    entry[18..20].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+                    entry[24..26].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+                })?
+                else {
+                    return Ok(Step::Wait);
+                };
+                let file = self.file.get();
+                self.file.set(OpenFile {
+                    entry_sector: sector,
+                    entry_offset: offset,
+                    first_cluster: 0,
+                    size: 0,
+                    ..file
+                });
+                self.phase.set(Phase::Flush);
+                Ok(Step::Continue)
+            }
+            Phase::Truncate { cluster, next } => {
+                if volume.is_end_of_chain(cluster) {
+                    self.phase.set(Phase::UpdateEntry);
+                    return Ok(Step::Continue);
+                }
+                let next = match next {
+                    Some(next) => next,
+                    None => {
+                        let Some(next) = self.fat_entry(&volume, cluster)? else {
+                            return Ok(Step::Wait);
+                        };
+                        self.phase.set(Phase::Truncate {
+                            cluster,
+                            next: Some(next),
+                        });
+                        next
+                    }
+                };
+                let Some(()) = self.set_fat_entry(&volume, cluster, 0)? else {
+                    return Ok(Step::Wait);
+                };
+                self.phase.set(Phase::Truncate {
+                    cluster: next,
+                    next: None,
+                });
+                Ok(Step::Continue)
+            }
+            Phase::Transfer => match command {
+                Command::Read { .. } => self.step_read(&volume, processid),
+                Command::Write { .. } => self.step_write(&volume, processid),
+                _ => Err(ErrorCode::FAIL),
+            },
+            Phase::UpdateEntry => {
+                let file = self.file.get();
+                let offset = file.entry_offset;
+                let Some(()) = self.with_sector_mut(file.entry_sector, |buffer| {
+                    let entry = &mut buffer[offset..offset + DIR_ENTRY_SIZE];
+                    entry[20..22]
+                        .copy_from_slice(&((file.first_cluster >> 16) as u16).to_le_bytes());
+                    entry[26..28].copy_from_slice(&(file.first_cluster as u16).to_le_bytes());
+                    entry[28..32].copy_from_slice(&file.size.to_le_bytes());
+                })?
+                else {
+                    return Ok(Step::Wait);
+                };
+                self.phase.set(Phase::Flush);
+                Ok(Step::Continue)
+            }
+            Phase::Flush => {
+                let Some(()) = self.flush()? else {
+                    return Ok(Step::Wait);
+                };
+                Ok(Step::Done(self.transferred.get(), 0))
+            }
+            Phase::List { cursor, seen } => {
+                self.step_list(&volume, processid, command, cursor, seen)
+            }
+        }
+    }
+
+    fn step_mount(&self, partition: Option<u32>) -> Result<Step, ErrorCode> {
+        let sector = partition.unwrap_or(0);
+        let Some(result) = self.with_sector(sector, |buffer| {
+            if buffer[510] != 0x55 || buffer[511] != 0xAA {
+                return Err(ErrorCode::NOSUPPORT);
+            }
+            let is_boot_sector = (buffer[0] == 0xEB || buffer[0] == 0xE9)
+                && u16::from_le_bytes([buffer[11], buffer[12]]) == SECTOR_SIZE as u16;
+            if is_boot_sector {
+                Volume::from_boot_sector(buffer, sector).map(Ok)
+            } else if partition.is_none() {
+                // This is a master boot record, use the first partition if
+                // it is a FAT16 or FAT32 partition.
+                let entry = &buffer[446..462];
+                match entry[4] {
+                    0x04 | 0x06 | 0x0B | 0x0C | 0x0E => Ok(Err(u32::from_le_bytes([
+                        entry[8], entry[9], entry[10], entry[11],
+                    ]))),
+                    _ => Err(ErrorCode::NOSUPPORT),
+                }
+            } else {
+                Err(ErrorCode::NOSUPPORT)
+            }
+        })?
+        else {
+            return Ok(Step::Wait);
+        };
+
+        match result? {
+            Ok(volume) => {
+                self.volume.set(volume);
+                self.allocation_hint.set(2);
+                self.phase.set(Phase::Start);
+            }
+            Err(start) => self.phase.set(Phase::Mount {
+                partition: Some(start),
+            }),
+        }
+        Ok(Step::Continue)
+    }
+
+    fn step_start(
+        &self,
+        volume: &Volume,
+        processid: ProcessId,
+        command: Command,
+    ) -> Result<Step, ErrorCode> {
+        match command {
+            Command::Open { .. } | Command::List { .. } => {
+                let path_len = self
+                    .apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readonly_processbuffer(ro_allow::PATH)
+                            .and_then(|path| {
+                                path.enter(|path| {
+                                    let len = path.len();
+                                    if len > MAX_PATH_LEN {
+                                        return Err(ErrorCode::SIZE);
+                                    }
+                                    let mut buffer = [0; MAX_PATH_LEN];
+                                    path.copy_to_slice_or_err(&mut buffer[..len])?;
+                                    self.path.set(buffer);
+                                    Ok(len)
+                                })
+                            })
+                            .unwrap_or_else(|err| Err(err.into()))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))?;
+                // The path ends at the first NUL byte, if any.
+                let path = self.path.get();
+                self.path_len.set(
+                    path[..path_len]
+                        .iter()
+                        .position(|&c| c == 0)
+                        .unwrap_or(path_len),
+                );
+                self.phase.set(Phase::Lookup {
+                    component: 0,
+                    cursor: Self::dir_start(volume, 0),
+                    free: None,
+                });
+            }
+            Command::Read { handle, length } | Command::Write { handle, length } => {
+                let (file, buffer_len) = self
+                    .apps
+                    .enter(processid, |app, kernel_data| {
+                        let file = app
+                            .files
+                            .get(handle)
+                            .copied()
+                            .flatten()
+                            .ok_or(ErrorCode::INVAL)?;
+                        let buffer_len = match command {
+                            Command::Read { .. } => kernel_data
+                                .get_readwrite_processbuffer(rw_allow::READ)
+                                .map_or(0, |buffer| buffer.len()),
+                            _ => kernel_data
+                                .get_readonly_processbuffer(ro_allow::WRITE)
+                                .map_or(0, |buffer| buffer.len()),
+                        };
+                        Ok::<_, ErrorCode>((file, buffer_len))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))?;
+
+                let mut file = file;
+                if let Command::Write { .. } = command {
+                    if !file.writable {
+                        return Err(ErrorCode::RESERVE);
+                    }
+                    if file.append {
+                        file.position = file.size;
+                    }
+                }
+                self.file.set(file);
+                self.transfer_len.set(cmp::min(length, buffer_len));
+                self.phase.set(Phase::Transfer);
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    /// The name of the last component of the path, which is the name of the
+    /// file to create.
+    fn file_name(&self) -> [u8; 11] {
+        let path = self.path.get();
+        let path = &path[..self.path_len.get()];
+        path.split(|&c| c == b'/')
+            .rfind(|component| !component.is_empty())
+            .and_then(short_name)
+            .unwrap_or([b' '; 11])
+    }
+
+    fn step_lookup(
+        &self,
+        volume: &Volume,
+        command: Command,
+        component: usize,
+        cursor: DirCursor,
+        free: Option<(u32, usize)>,
+    ) -> Result<Step, ErrorCode> {
+        let path = self.path.get();
+        let path = &path[..self.path_len.get()];
+
+        // Skip the separators before the component.
+        let start = component + path[component..].iter().take_while(|&&c| c == b'/').count();
+        if start >= path.len() {
+            // The path names the directory at `cursor`.
+            return match command {
+                Command::List { .. } => {
+                    self.phase.set(Phase::List { cursor, seen: 0 });
+                    Ok(Step::Continue)
+                }
+                _ => Err(ErrorCode::INVAL),
+            };
+        }
+        let end = path[start..]
+            .iter()
+            .position(|&c| c == b'/')
+            .map_or(path.len(), |len| start + len);
+        let last = path[end..].iter().all(|&c| c == b'/');
+        let name = short_name(&path[start..end]).ok_or(ErrorCode::INVAL)?;
+
+        let mut cursor = cursor;
+        let Some(slot) = self.dir_entry(volume, &mut cursor)? else {
+            return Ok(Step::Wait);
+        };
+        let (sector, offset, entry) = match slot {
+            DirSlot::End { free: end_free } => {
+                return match command {
+                    Command::Open { flags } if last && flags & open_flags::CREATE != 0 => {
+                        let (sector, offset) = free.or(end_free).ok_or(ErrorCode::NOMEM)?;
+                        self.file.set(OpenFile {
+                            writable: flags & open_flags::WRITE != 0,
+                            append: flags & open_flags::APPEND != 0,
+                            ..OpenFile::default()
+                        });
+                        self.phase.set(Phase::Create { sector, offset });
+                        Ok(Step::Continue)
+                    }
+                    // The file or directory does not exist.
+                    _ => Err(ErrorCode::INVAL),
+                };
+            }
+            DirSlot::Entry {
+                sector,
+                offset,
+                entry,
+            } => (sector, offset, entry),
+        };
+
+        let skip = entry.name[0] == ENTRY_DELETED
+            || entry.attributes & ATTR_LONG_NAME == ATTR_LONG_NAME
+            || entry.attributes & ATTR_VOLUME_ID != 0;
+        if skip || entry.name != name {
+            let free = match free {
+                None if entry.name[0] == ENTRY_DELETED => Some((sector, offset)),
+                free => free,
+            };
+            cursor.index += 1;
+            self.phase.set(Phase::Lookup {
+                component,
+                cursor,
+                free,
+            });
+            return Ok(Step::Continue);
+        }
+
+        if !last {
+            if !entry.is_directory() {
+                return Err(ErrorCode::INVAL);
+            }
+            self.phase.set(Phase::Lookup {
+                component: end,
+                cursor: Self::dir_start(volume, entry.first_cluster),
+                free: None,
+            });
+            return Ok(Step::Continue);
+        }
+
+        match command {
+            Command::List { .. } => {
+                if !entry.is_directory() {
+                    return Err(ErrorCode::INVAL);
+                }
+                self.phase.set(Phase::List {
+                    cursor: Self::dir_start(volume, entry.first_cluster),
+                    seen: 0,
+                });
+            }
+            Command::Open { flags } => {
+                let writable = flags & open_flags::WRITE != 0;
+                if entry.is_directory() {
+                    return Err(ErrorCode::INVAL);
+                }
+                if writable && entry.attributes & ATTR_READ_ONLY != 0 {
+                    return Err(ErrorCode::RESERVE);
+                }
+                let file = OpenFile {
+                    entry_sector: sector,
+                    entry_offset: offset,
+                    first_cluster: entry.first_cluster,
+                    size: entry.size,
+                    writable,
+                    append: flags & open_flags::APPEND != 0,
+                    ..OpenFile::default()
+                };
+                if writable && flags & open_flags::TRUNCATE != 0 {
+                    self.file.set(OpenFile {
+                        first_cluster: 0,
+                        size: 0,
+                        ..file
+                    });
+                    self.phase.set(Phase::Truncate {
+                        cluster: entry.first_cluster,
+                        next: None,
+                    });
+                } else {
+                    self.file.set(file);
+                    return Ok(Step::Done(0, 0));
+                }
+            }
+            _ => return Err(ErrorCode::FAIL),
+        }
+        Ok(Step::Continue)
+    }
+
+    fn step_read(&self, volume: &Volume, processid: ProcessId) -> Result<Step, ErrorCode> {
+        let file = self.file.get();
+        let done = self.transferred.get();
+        let remaining = cmp::min(
+            self.transfer_len.get() - done,
+            file.size.saturating_sub(file.position) as usize,
+        );
+        if remaining == 0 {
+            return Ok(Step::Done(done, 0));
+        }
+
+        let Some(cluster) = self.file_cluster(volume, false)? else {
+            return Ok(Step::Wait);
+        };
+        if cluster == 0 {
+            // The cluster chain is shorter than the file.
+            return Err(ErrorCode::FAIL);
+        }
+        let offset_in_cluster = file.position % volume.cluster_size();
+        let sector = volume.cluster_sector(cluster) + offset_in_cluster / SECTOR_SIZE as u32;
+        let offset = file.position as usize % SECTOR_SIZE;
+        let len = cmp::min(SECTOR_SIZE - offset, remaining);
+
+        let Some(result) = self.with_sector(sector, |buffer| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .and_then(|read| {
+                            read.mut_enter(|app_buffer| {
+                                app_buffer
+                                    .get(done..done + len)
+                                    .ok_or(ErrorCode::SIZE)?
+                                    .copy_from_slice_or_err(&buffer[offset..offset + len])
+                            })
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        })?
+        else {
+            return Ok(Step::Wait);
+        };
+        result?;
+
+        let file = self.file.get();
+        self.file.set(OpenFile {
+            position: file.position + len as u32,
+            ..file
+        });
+        self.transferred.set(done + len);
+        Ok(Step::Continue)
+    }
+
+    fn step_write(&self, volume: &Volume, processid: ProcessId) -> Result<Step, ErrorCode> {
+        let done = self.transferred.get();
+        let remaining = self.transfer_len.get() - done;
+        if remaining == 0 {
+            self.phase.set(Phase::UpdateEntry);
+            return Ok(Step::Continue);
+        }
+
+        let Some(cluster) = self.file_cluster(volume, true)? else {
+            return Ok(Step::Wait);
+        };
+        let file = self.file.get();
+        let offset_in_cluster = file.position % volume.cluster_size();
+        let sector = volume.cluster_sector(cluster) + offset_in_cluster / SECTOR_SIZE as u32;
+        let offset = file.position as usize % SECTOR_SIZE;
+        let len = cmp::min(SECTOR_SIZE - offset, remaining);
+
+        let copy = |buffer: &mut [u8]| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::WRITE)
+                        .and_then(|write| {
+                            write.enter(|app_buffer| {
+                                app_buffer
+                                    .get(done..done + len)
+                                    .ok_or(ErrorCode::SIZE)?
+                                    .copy_to_slice_or_err(&mut buffer[offset..offset + len])
+                            })
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        };
+        // Sectors entirely overwritten, or past the end of the file, do not
+        // need to be read first.
+        let sector_start = file.position - offset as u32;
+        let result = if len == SECTOR_SIZE || sector_start >= file.size {
+            self.overwrite_sector(sector, copy)?
+        } else {
+            self.with_sector_mut(sector, copy)?
+        };
+        let Some(result) = result else {
+            return Ok(Step::Wait);
+        };
+        result?;
+
+        let position = file.position + len as u32;
+        self.file.set(OpenFile {
+            position,
+            size: cmp::max(file.size, position),
+            ..file
+        });
+        self.transferred.set(done + len);
+        Ok(Step::Continue)
+    }
+
+    fn step_list(
+        &self,
+        volume: &Volume,
+        processid: ProcessId,
+        command: Command,
+        cursor: DirCursor,
+        seen: usize,
+    ) -> Result<Step, ErrorCode> {
+        let Command::List { index } = command else {
+            return Err(ErrorCode::FAIL);
+        };
+
+        let mut cursor = cursor;
+        let Some(slot) = self.dir_entry(volume, &mut cursor)? else {
+            return Ok(Step::Wait);
+        };
+        let entry = match slot {
+            // There is no entry with this index.
+            DirSlot::End { .. } => return Err(ErrorCode::INVAL),
+            DirSlot::Entry { entry, .. } => entry,
+        };
+
+        let skip = entry.name[0] == ENTRY_DELETED
+            || entry.name[0] == b'.'
+            || entry.attributes & ATTR_LONG_NAME == ATTR_LONG_NAME
+            || entry.attributes & ATTR_VOLUME_ID != 0;
+        cursor.index += 1;
+        if skip || seen < index {
+            self.phase.set(Phase::List {
+                cursor,
+                seen: if skip { seen } else { seen + 1 },
+            });
+            return Ok(Step::Continue);
+        }
+
+        // Copy the name, terminated by a NUL byte, to the process.
+        let (name, len) = display_name(&entry.name);
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::READ)
+                    .and_then(|read| {
+                        read.mut_enter(|app_buffer| {
+                            app_buffer
+                                .get(..len + 1)
+                                .ok_or(ErrorCode::SIZE)?
+                                .copy_from_slice_or_err(&name[..len + 1])
+                        })
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        Ok(Step::Done(entry.size as usize, entry.attributes as usize))
+    }
+
+    /// Completes the current request and notifies the process.
+    fn finish(&self, result: Result<(usize, usize), ErrorCode>) {
+        self.phase.set(Phase::Start);
+        self.fat_copy.set(0);
+        self.allocation.set(Allocation::Idle);
+        self.allocation_scanned.set(0);
+        self.transferred.set(0);
+
+        self.current.take().map(|(processid, command)| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                app.pending = None;
+
+                let result = match (command, result) {
+                    (Command::Open { .. }, Ok(_)) => {
+                        // A slot was checked to be free when the request was
+                        // made.
+                        match app.files.iter().position(|file| file.is_none()) {
+                            Some(handle) => {
+                                app.files[handle] = Some(self.file.get());
+                                Ok((handle, 0))
+                            }
+                            None => Err(ErrorCode::NOMEM),
+                        }
+                    }
+                    (Command::Read { handle, .. }, Ok(values))
+                    | (Command::Write { handle, .. }, Ok(values)) => {
+                        app.files[handle] = Some(self.file.get());
+                        Ok(values)
+                    }
+                    (_, result) => result,
+                };
+
+                let (value, extra) = result.unwrap_or((0, 0));
+                let _ = kernel_data.schedule_upcall(
+                    command.upcall(),
+                    (into_statuscode(result.map(|_| ())), value, extra),
+                );
+            });
+        });
+    }
+
+    /// Handles requests until one waits for the storage, or there are no more
+    /// requests.
+    fn run(&self) {
+        loop {
+            if self.io_sector.is_some() {
+                return;
+            }
+
+            let Some((processid, command)) = self.current.get().or_else(|| self.next_request())
+            else {
+                return;
+            };
+            match self.step(processid, command) {
+                Ok(Step::Continue) => {}
+                Ok(Step::Wait) => return,
+                Ok(Step::Done(value, extra)) => self.finish(Ok((value, extra))),
+                Err(error) => self.finish(Err(error)),
+            }
+        }
+    }
+
+    /// Selects the next process request to handle.
+    fn next_request(&self) -> Option<(ProcessId, Command)> {
+        let request = self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| app.pending.map(|command| (processid, command)))
+        });
+        if let Some(request) = request {
+            self.phase.set(Phase::Start);
+            self.current.set(request);
+        }
+        request
+    }
+
+    fn enqueue(&self, processid: ProcessId, command: Command) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                match command {
+                    Command::Open { .. } => {
+                        if app.files.iter().all(|file| file.is_some()) {
+                            return Err(ErrorCode::NOMEM);
+                        }
+                    }
+                    Command::Read { handle, .. } | Command::Write { handle, .. } => {
+                        if app.files.get(handle).copied().flatten().is_none() {
+                            return Err(ErrorCode::INVAL);
+                        }
+                    }
+                    Command::List { .. } => {}
+                }
+                app.pending = Some(command);
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        if self.current.is_none() {
+            self.run();
+        }
+        Ok(())
+    }
+
+    /// Updates a file of the process that is not used by a pending request.
+    fn with_idle_file<R>(
+        &self,
+        processid: ProcessId,
+        handle: usize,
+        f: impl FnOnce(&mut Option<OpenFile>) -> R,
+    ) -> Result<R, ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                match app.files.get_mut(handle) {
+                    Some(file) if file.is_some() => Ok(f(file)),
+                    _ => Err(ErrorCode::INVAL),
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// The storage operation completed. On failure, the volume is unmounted
+    /// so that it is mounted again for the next request, in case the storage
+    /// was replaced.
+    fn storage_done(&self, buffer: &'static mut [u8], length: usize, read: bool) {
+        self.buffer.replace(buffer);
+        let sector = self.io_sector.take();
+        if length == SECTOR_SIZE {
+            if read {
+                self.cached_sector.insert(sector);
+            } else {
+                self.dirty.set(false);
+            }
+            self.run();
+        } else {
+            self.cached_sector.clear();
+            self.dirty.set(false);
+            self.volume.clear();
+            self.finish(Err(ErrorCode::FAIL));
+            self.run();
+        }
+    }
+}
+
+impl hil::nonvolatile_storage::NonvolatileStorageClient for FatFilesystem<'_> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.storage_done(buffer, length, true);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.storage_done(buffer, length, false);
+    }
+}
+
+/// Provide an interface for userland.
+impl SyscallDriver for FatFilesystem<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Open the file whose path is in read-only allow 0. `arg1` holds
+    ///   the open flags.
+    /// - `2`: Read up to `arg2` bytes from file `arg1` into read-write
+    ///   allow 0.
+    /// - `3`: Write up to `arg2` bytes from read-only allow 1 to file `arg1`.
+    /// - `4`: Move the position of file `arg1` to byte `arg2`.
+    /// - `5`: Close file `arg1`.
+    /// - `6`: Get entry `arg1` of the directory whose path is in read-only
+    ///   allow 0.
+    /// - `7`: Return the size of file `arg1`.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            1 => Command::Open { flags: arg1 },
+            2 => Command::Read {
+                handle: arg1,
+                length: arg2,
+            },
+            3 => Command::Write {
+                handle: arg1,
+                length: arg2,
+            },
+            4 => {
+                return self
+                    .with_idle_file(processid, arg1, |file| {
+                        file.as_mut().map(|file| {
+                            file.position = cmp::min(arg2, file.size as usize) as u32;
+                        });
+                    })
+                    .into();
+            }
+            5 => {
+                return self
+                    .with_idle_file(processid, arg1, |file| {
+                        *file = None;
+                    })
+                    .into();
+            }
+            6 => Command::List { index: arg1 },
+            7 => {
+                return match self
+                    .with_idle_file(processid, arg1, |file| file.map_or(0, |file| file.size))
+                {
+                    Ok(size) => CommandReturn::success_u32(size),
+                    Err(e) => CommandReturn::failure(e),
+                };
+            }
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        self.enqueue(processid, command).into()
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fields of a BIOS parameter block, written at their offsets in a boot
+    /// sector.
+    struct Bpb {
+        bytes_per_sector: u16,
+        sectors_per_cluster: u8,
+        reserved_sectors: u16,
+        num_fats: u8,
+        root_entries: u16,
+        total_sectors_16: u16,
+        fat_sectors_16: u16,
+        total_sectors_32: u32,
+        fat_sectors_32: u32,
+        root_cluster: u32,
+    }
+
+    impl Bpb {
+        fn fat16() -> Bpb {
+            Bpb {
+                bytes_per_sector: 512,
+                sectors_per_cluster: 4,
+                reserved_sectors: 4,
+                num_fats: 2,
+                root_entries: 512,
+                total_sectors_16: 0,
+                fat_sectors_16: 100,
+                total_sectors_32: 100_000,
+                fat_sectors_32: 0,
+                root_cluster: 0,
+            }
+        }
+
+        fn fat32() -> Bpb {
+            Bpb {
+                bytes_per_sector: 512,
+                sectors_per_cluster: 8,
+                reserved_sectors: 32,
+                num_fats: 2,
+                root_entries: 0,
+                total_sectors_16: 0,
+                fat_sectors_16: 0,
+                total_sectors_32: 1_000_000,
+                fat_sectors_32: 1000,
+                root_cluster: 2,
+            }
+        }
+
+        fn boot_sector(&self) -> [u8; SECTOR_SIZE] {
+            let mut sector = [0; SECTOR_SIZE];
+            sector[11..13].copy_from_slice(&self.bytes_per_sector.to_le_bytes());
+            sector[13] = self.sectors_per_cluster;
+            sector[14..16].copy_from_slice(&self.reserved_sectors.to_le_bytes());
+            sector[16] = self.num_fats;
+            sector[17..19].copy_from_slice(&self.root_entries.to_le_bytes());
+            sector[19..21].copy_from_slice(&self.total_sectors_16.to_le_bytes());
+            sector[22..24].copy_from_slice(&self.fat_sectors_16.to_le_bytes());
+            sector[32..36].copy_from_slice(&self.total_sectors_32.to_le_bytes());
+            sector[36..40].copy_from_slice(&self.fat_sectors_32.to_le_bytes());
+            sector[44..48].copy_from_slice(&self.root_cluster.to_le_bytes());
+            sector[510] = 0x55;
+            sector[511] = 0xAA;
+            sector
+        }
+
+        fn volume(&self, start: u32) -> Result<Volume, ErrorCode> {
+            Volume::from_boot_sector(&self.boot_sector(), start)
+        }
+    }
+
+    /// Follows the cluster chain starting at `first` in the first sector of
+    /// the FAT, as the capsule does one entry at a time. Returns the clusters
+    /// of the chain and their number.
+    fn chain(volume: &Volume, fat: &[u8], first: u32) -> ([u32; 8], usize) {
+        let mut clusters = [0; 8];
+        let mut len = 0;
+        let mut cluster = first;
+        loop {
+            clusters[len] = cluster;
+            len += 1;
+            let (sector, offset) = volume.fat_entry_location(cluster);
+            assert_eq!(sector, volume.fat_start);
+            let next = volume.read_fat_entry(fat, offset);
+            if volume.is_end_of_chain(next) {
+                return (clusters, len);
+            }
+            cluster = next;
+        }
+    }
+
+    #[test]
+    fn fat16_layout() {
+        let volume = Bpb::fat16().volume(0).unwrap();
+        assert_eq!(volume.fat_type, FatType::Fat16);
+        assert_eq!(volume.fat_start, 4);
+        assert_eq!(volume.root_dir_start, 204);
+        assert_eq!(volume.root_dir_sectors, 32);
+        assert_eq!(volume.root_cluster, 0);
+        assert_eq!(volume.data_start, 236);
+        assert_eq!(volume.cluster_limit, 24941 + 2);
+        assert_eq!(volume.cluster_size(), 2048);
+        assert_eq!(volume.cluster_sector(2), 236);
+        assert_eq!(volume.cluster_sector(5), 248);
+        assert_eq!(volume.dir_entries(0), 512);
+        assert_eq!(volume.dir_entry_sector(0, 17), 205);
+    }
+
+    #[test]
+    fn fat16_in_partition() {
+        let volume = Bpb::fat16().volume(2048).unwrap();
+        assert_eq!(volume.fat_start, 2052);
+        assert_eq!(volume.root_dir_start, 2252);
+        assert_eq!(volume.data_start, 2284);
+        assert_eq!(volume.cluster_limit, 24941 + 2);
+    }
+
+    #[test]
+    fn fat32_layout() {
+        let volume = Bpb::fat32().volume(0).unwrap();
+        assert_eq!(volume.fat_type, FatType::Fat32);
+        assert_eq!(volume.fat_start, 32);
+        assert_eq!(volume.fat_sectors, 1000);
+        assert_eq!(volume.root_dir_sectors, 0);
+        assert_eq!(volume.root_cluster, 2);
+        assert_eq!(volume.data_start, 2032);
+        assert_eq!(volume.cluster_limit, 124_746 + 2);
+        assert_eq!(volume.dir_entries(2), 8 * ENTRIES_PER_SECTOR);
+        assert_eq!(volume.dir_entry_sector(3, 20), 2032 + 8 + 1);
+    }
+
+    #[test]
+    fn fat_type_detection() {
+        // The FAT type depends only on the number of clusters.
+        let bpb = Bpb {
+            sectors_per_cluster: 1,
+            reserved_sectors: 1,
+            root_entries: 224,
+            total_sectors_16: 2880,
+            fat_sectors_16: 9,
+            total_sectors_32: 0,
+            ..Bpb::fat16()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::NOSUPPORT);
+
+        // 65524 clusters is the largest FAT16 volume.
+        let fat16 = Bpb {
+            sectors_per_cluster: 1,
+            fat_sectors_16: 256,
+            total_sectors_32: 4 + 512 + 32 + 65524,
+            ..Bpb::fat16()
+        };
+        assert_eq!(fat16.volume(0).unwrap().fat_type, FatType::Fat16);
+        let fat32 = Bpb {
+            total_sectors_32: 4 + 512 + 32 + 65525,
+            ..fat16
+        };
+        assert_eq!(fat32.volume(0).unwrap().fat_type, FatType::Fat32);
+    }
+
+    #[test]
+    fn invalid_boot_sectors() {
+        let bpb = Bpb {
+            bytes_per_sector: 4096,
+            ..Bpb::fat16()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::NOSUPPORT);
+        let bpb = Bpb {
+            sectors_per_cluster: 3,
+            ..Bpb::fat16()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::INVAL);
+        let bpb = Bpb {
+            num_fats: 0,
+            ..Bpb::fat16()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::INVAL);
+        let bpb = Bpb {
+            fat_sectors_32: 0,
+            ..Bpb::fat32()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::INVAL);
+        // The data region starts after the end of the volume.
+        let bpb = Bpb {
+            total_sectors_32: 200,
+            ..Bpb::fat16()
+        };
+        assert_eq!(bpb.volume(0).unwrap_err(), ErrorCode::INVAL);
+    }
+
+    #[test]
+    fn cluster_limit_bounded_by_fat() {
+        let bpb = Bpb {
+            fat_sectors_16: 50,
+            ..Bpb::fat16()
+        };
+        let volume = bpb.volume(0).unwrap();
+        assert_eq!(volume.cluster_limit, 50 * SECTOR_SIZE as u32 / 2);
+    }
+
+    #[test]
+    fn fat16_chain() {
+        let volume = Bpb::fat16().volume(0).unwrap();
+        let mut fat = [0; SECTOR_SIZE];
+        volume.write_fat_entry(&mut fat, volume.fat_entry_location(2).1, 3);
+        volume.write_fat_entry(&mut fat, volume.fat_entry_location(3).1, 7);
+        volume.write_fat_entry(
+            &mut fat,
+            volume.fat_entry_location(7).1,
+            volume.end_of_chain(),
+        );
+        assert_eq!(fat[4..8], [3, 0, 7, 0]);
+        let (clusters, len) = chain(&volume, &fat, 2);
+        assert_eq!(clusters[..len], [2, 3, 7]);
+
+        // A single cluster file.
+        let (clusters, len) = chain(&volume, &fat, 7);
+        assert_eq!(clusters[..len], [7]);
+    }
+
+    #[test]
+    fn fat32_chain() {
+        let volume = Bpb::fat32().volume(0).unwrap();
+        let mut fat = [0; SECTOR_SIZE];
+        // The upper 4 bits of the entries are reserved.
+        fat[8..12].copy_from_slice(&0xF000_0009u32.to_le_bytes());
+        fat[36..40].copy_from_slice(&0x0FFF_FFF8u32.to_le_bytes());
+        let (clusters, len) = chain(&volume, &fat, 2);
+        assert_eq!(clusters[..len], [2, 9]);
+
+        volume.write_fat_entry(&mut fat, 8, 5);
+        assert_eq!(fat[8..12], [5, 0, 0, 0xF0]);
+        assert_eq!(volume.read_fat_entry(&fat, 8), 5);
+    }
+
+    #[test]
+    fn fat_entry_location() {
+        let fat16 = Bpb::fat16().volume(0).unwrap();
+        assert_eq!(fat16.fat_entry_location(255), (4, 510));
+        assert_eq!(fat16.fat_entry_location(256), (5, 0));
+        let fat32 = Bpb::fat32().volume(0).unwrap();
+        assert_eq!(fat32.fat_entry_location(127), (32, 508));
+        assert_eq!(fat32.fat_entry_location(130), (33, 8));
+    }
+
+    #[test]
+    fn end_of_chain() {
+        let volume = Bpb::fat16().volume(0).unwrap();
+        assert!(volume.is_end_of_chain(0));
+        assert!(volume.is_end_of_chain(1));
+        assert!(!volume.is_end_of_chain(2));
+        assert!(!volume.is_end_of_chain(volume.cluster_limit - 1));
+        assert!(volume.is_end_of_chain(volume.cluster_limit));
+        assert!(volume.is_end_of_chain(0xFFF8));
+        assert!(volume.is_end_of_chain(volume.end_of_chain()));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(short_name(b"readme.txt"), Some(*b"README  TXT"));
+        assert_eq!(short_name(b"DIR"), Some(*b"DIR        "));
+        assert_eq!(short_name(b"toolongname"), None);
+        assert_eq!(short_name(b"a.text"), None);
+        assert_eq!(short_name(b".txt"), None);
+        assert_eq!(short_name(b"a b"), None);
+
+        let (display, len) = display_name(b"README  TXT");
+        assert_eq!(&display[..len], b"README.TXT");
+        let (display, len) = display_name(b"DIR        ");
+        assert_eq!(&display[..len], b"DIR");
+    }
+}
@@ -121,8 +121,10 @@ mod upcall {
     pub const READ_DONE: usize = 1;
     /// Write done callback.
     pub const WRITE_DONE: usize = 2;
+    /// Erase done callback.
+    pub const ERASE_DONE: usize = 3;
     /// Number of upcalls.
-    pub const COUNT: u8 = 3;
+    pub const COUNT: u8 = 4;
 }
 
 /// Ids for read-only allow buffers
@@ -302,6 +304,7 @@ pub enum NvmCommand {
     GetSize,
     Read { offset: usize },
     Write { offset: usize },
+    Erase { offset: usize, length: usize },
 }
 
 impl NvmCommand {
@@ -309,6 +312,7 @@ impl NvmCommand {
         match self {
             NvmCommand::Read { offset } => *offset,
             NvmCommand::Write { offset } => *offset,
+            NvmCommand::Erase { offset, length: _ } => *offset,
             NvmCommand::GetSize => 0,
         }
     }
@@ -318,6 +322,7 @@ impl NvmCommand {
             Self::GetSize => upcall::GET_SIZE_DONE,
             Self::Write { offset: _ } => upcall::WRITE_DONE,
             Self::Read { offset: _ } => upcall::READ_DONE,
+            Self::Erase { .. } => upcall::ERASE_DONE,
         }
     }
 }
@@ -665,7 +670,7 @@ impl<'a, const APP_REGION_SIZE: usize> IsolatedNonvolatileStorage<'a, APP_REGION
                 .check_read_permission(write_id)
                 .then_some(())
                 .ok_or(ErrorCode::NOSUPPORT),
-            NvmCommand::Write { offset: _ } => perms
+            NvmCommand::Write { offset: _ } | NvmCommand::Erase { .. } => perms
                 .check_modify_permission(write_id)
                 .then_some(())
                 .ok_or(ErrorCode::NOSUPPORT),
@@ -821,7 +826,7 @@ impl<'a, const APP_REGION_SIZE: usize> IsolatedNonvolatileStorage<'a, APP_REGION
                     NvmCommand::Write { offset: _ } => kernel_data
                         .get_readonly_processbuffer(ro_allow::WRITE)
                         .map_or(0, |read| read.len()),
-                    NvmCommand::GetSize => 0,
+                    NvmCommand::GetSize | NvmCommand::Erase { .. } => 0,
                 };
 
                 // Check that the matching allowed buffer exists.
@@ -884,7 +889,7 @@ impl<'a, const APP_REGION_SIZE: usize> IsolatedNonvolatileStorage<'a, APP_REGION
                                 .driver
                                 .write(buffer, physical_address, active_len_buf)
                                 .or(Err(ErrorCode::FAIL)),
-                            NvmCommand::GetSize => Err(ErrorCode::FAIL),
+                            NvmCommand::GetSize | NvmCommand::Erase { .. } => Err(ErrorCode::FAIL),
                         }
                     });
                 match res {
@@ -895,6 +900,20 @@ impl<'a, const APP_REGION_SIZE: usize> IsolatedNonvolatileStorage<'a, APP_REGION
                     Err(e) => Err(e),
                 }
             }
+
+            NvmCommand::Erase { offset, length } => {
+                // Fail if the app doesn't have a region assigned to it.
+                let Some(app_region) = &app.region else {
+                    return Err(ErrorCode::NOMEM);
+                };
+
+                self.check_userspace_access(offset, length, app_region)?;
+
+                let physical_address = app_region.absolute_address + offset;
+                self.driver.erase(physical_address, length)?;
+                self.current_user.set(User::App { processid });
+                Ok(true)
+            }
         }
     }
 }
@@ -993,9 +1012,20 @@ impl<const APP_REGION_SIZE: usize> hil::nonvolatile_storage::NonvolatileStorageC
                                 .set(next_header_addr);
 
                             // Erase the userspace accessible content of the region
-                            // before handing it off to an app.
-                            let res =
-                                self.erase_region_content(region.absolute_address, region.length);
+                            // before handing it off to an app. Use the erase
+                            // operation of the storage if it has one, and
+                            // otherwise overwrite the region.
+                            let res = match self
+                                .driver
+                                .erase(region.absolute_address, region.length)
+                            {
+                                // The task holds the range being erased, in
+                                // case the erase stops early.
+                                Ok(()) => Ok((region.absolute_address, region.length)),
+                                Err(ErrorCode::NOSUPPORT) => self
+                                    .erase_region_content(region.absolute_address, region.length),
+                                Err(e) => Err(e),
+                            };
                             match res {
                                 Ok((next_erase_start, remaining_bytes)) => {
                                     self.current_user.set(User::RegionManager(
@@ -1067,6 +1097,56 @@ impl<const APP_REGION_SIZE: usize> hil::nonvolatile_storage::NonvolatileStorageC
             }
         });
     }
+
+    fn erase_done(&self, length: usize) {
+        // Switch on which user of this capsule generated this callback.
+        self.current_user.take().map(|user| match user {
+            User::RegionManager(ManagerTask::EraseRegion {
+                processid,
+                next_erase_start,
+                remaining_bytes,
+            }) if length < remaining_bytes => {
+                // The erase of the region stopped early. Overwrite the rest of
+                // the region instead.
+                match self.erase_region_content(next_erase_start + length, remaining_bytes - length)
+                {
+                    Ok((next_erase_start, remaining_bytes)) => {
+                        self.current_user
+                            .set(User::RegionManager(ManagerTask::EraseRegion {
+                                processid,
+                                next_erase_start,
+                                remaining_bytes,
+                            }));
+                    }
+                    Err(_e) => self.check_queue(),
+                }
+            }
+            User::RegionManager(_) => {
+                // The whole region is erased in a single operation when the
+                // storage supports erasing.
+                self.check_queue();
+            }
+            User::App { processid } => {
+                let _ = self.apps.enter(processid, move |app, kernel_data| {
+                    // The erase failed if it stopped before the end of the
+                    // range.
+                    let result = match app.pending_operation {
+                        Some(NvmCommand::Erase {
+                            offset: _,
+                            length: requested,
+                        }) if length < requested => Err(ErrorCode::FAIL),
+                        _ => Ok(()),
+                    };
+                    // clear pending syscall
+                    app.pending_operation = None;
+                    // Notify app that its erase has completed.
+                    let _ = kernel_data
+                        .schedule_upcall(upcall::ERASE_DONE, (into_statuscode(result), length, 0));
+                });
+                self.check_queue();
+            }
+        });
+    }
 }
 
 /// Provide an interface for userland.
@@ -1083,6 +1163,9 @@ impl<const APP_REGION_SIZE: usize> SyscallDriver
     /// - `1`: Return the number of bytes available to each app.
     /// - `2`: Start a read from the nonvolatile storage.
     /// - `3`: Start a write to the nonvolatile_storage.
+    /// - `4`: Start an erase of the nonvolatile_storage. Unlike the other
+    ///   commands, the arguments are the offset and the length of the range
+    ///   to erase, as app regions always fit in 32 bits.
     fn command(
         &self,
         command_num: usize,
@@ -1118,6 +1201,21 @@ impl<const APP_REGION_SIZE: usize> SyscallDriver
                     Err(e) => CommandReturn::failure(e),
                 }
             }
+            // Erase is initialized the same way, but its arguments are the
+            // offset and the length of the range to erase.
+            4 => {
+                let nvm_command = NvmCommand::Erase {
+                    offset: offset_lo,
+                    length: offset_hi,
+                };
+
+                // Enqueue the operation for the app.
+                let res = self.enqueue_userspace_command(nvm_command, processid);
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -30,8 +30,10 @@ pub mod button_keyboard;
 pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
+pub mod can_isotp;
 pub mod ccs811;
 pub mod chirp_i2c_moisture;
+pub mod crash_dump;
 pub mod crc;
 pub mod cycle_count;
 pub mod dac;
@@ -41,6 +43,7 @@ pub mod dfrobot_rainfall_sensor;
 pub mod distance;
 pub mod ethernet_tap;
 pub mod eui64;
+pub mod fat_filesystem;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
@@ -77,12 +80,16 @@ pub mod ninedof;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
+pub mod ota_udp;
 pub mod panic_button;
 pub mod pca9544a;
 pub mod pressure;
+pub mod process_checkpoint;
 pub mod process_info_driver;
+pub mod process_watchdog;
 pub mod proximity;
 pub mod public_key_crypto;
+pub mod pubsub;
 pub mod pwm;
 pub mod rainfall;
 pub mod read_only_state;
@@ -107,6 +114,7 @@ pub mod sound_pressure;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod symmetric_encryption;
+pub mod sync_ipc;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
@@ -24,7 +24,16 @@
 //! written to a 4 page log, then page #0 will now have an offset of 2048). Thus, the ID of an
 //! entry can be calculated by taking the offset of the page within the log and adding the offset
 //! of the entry within the page to find the position of the entry within the log (which is the
-//! ID). Entries also have a header of their own, which contains the length of the entry.
+//! ID). Entries also have a header of their own, which contains the length of the entry and a
+//! CRC-32 checksum of its data.
+//!
+//! Checksums make the log safe against power loss. Pages are written to flash as a whole, and if
+//! power is lost while a page is being written the page may only be partially written, leaving a
+//! torn entry at its end. When the log is reconstructed at boot, entries of the newest page are
+//! only kept up to the first entry whose header or checksum is invalid, and new entries are
+//! appended in its place. Entries that become corrupted in older pages are skipped when reading:
+//! an entry whose checksum does not match is skipped, and if an entry header is invalid, reading
+//! continues at the next page. Clients thus only ever read back entries that were written in full.
 //!
 //! Logs support the following basic operations:
 //!     * Read:     Read back previously written entries in whole. Entries are read in their
@@ -37,7 +46,14 @@
 //!     * Erase:    Erase a log in its entirety, clearing the underlying flash volume.
 //! See the documentation for each individual function for more detail on how they operate.
 //!
-//! Note that while logs persist across reboots, they will be erased upon flashing a new kernel.
+//! Circular logs write their pages in turn and continue after the last written page when erased,
+//! so all pages of the volume wear evenly.
+//!
+//! Logs persist across reboots. Logs whose volume is allocated with `storage_volume!` are part of
+//! the kernel image and are erased upon flashing a new kernel. To keep a log across kernel
+//! updates, its volume can instead be placed in flash reserved by the board outside of the kernel
+//! image, using `kernel::utilities::storage_volume::fixed_storage_volume`. Such a volume must be
+//! aligned to the flash page size.
 //!
 //! Usage
 //! -----
@@ -61,6 +77,23 @@
 //!     log.set_read_client(log_storage_read_client);
 //!     log.set_append_client(log_storage_append_client);
 //! ```
+//!
+//! To keep the log when a new kernel is flashed, replace the volume with flash reserved in the
+//! board's linker script:
+//!
+//! ```rust,ignore
+//!     // The last 16 kB of flash are excluded from the kernel and application regions.
+//!     let volume = unsafe { kernel::utilities::storage_volume::fixed_storage_volume(0x3C000, 16) };
+//!     let log = static_init!(
+//!         capsules::log::Log,
+//!         capsules::log::Log::new(
+//!             volume,
+//!             &mut sam4l::flashcalw::FLASH_CONTROLLER,
+//!             &mut PAGEBUFFER,
+//!             true
+//!         )
+//!     );
+//! ```
 
 use core::cell::Cell;
 use core::mem::size_of;
@@ -70,6 +103,7 @@ use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::flash::{self, Flash};
 use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::helpers::crc32_posix;
 use kernel::ErrorCode;
 
 /// Globally declare entry ID type.
@@ -77,12 +111,25 @@ type EntryID = usize;
 
 /// Maximum page header size.
 pub const PAGE_HEADER_SIZE: usize = size_of::<EntryID>();
+/// Size of the length field of an entry header.
+const LENGTH_SIZE: usize = size_of::<usize>();
+/// Size of the checksum field of an entry header.
+const CHECKSUM_SIZE: usize = size_of::<u32>();
 /// Maximum entry header size.
-pub const ENTRY_HEADER_SIZE: usize = size_of::<usize>();
+pub const ENTRY_HEADER_SIZE: usize = LENGTH_SIZE + CHECKSUM_SIZE;
 
 /// Byte used to pad the end of a page.
 const PAD_BYTE: u8 = 0xFF;
 
+/// Reasons for which an entry cannot be read.
+#[derive(Clone, Copy, PartialEq)]
+enum EntryError {
+    /// The entry header is invalid, so the position of the following entries is unknown.
+    InvalidHeader,
+    /// The entry header is valid but its data does not match the checksum.
+    Corrupted { length: usize },
+}
+
 /// Log state keeps track of any in-progress asynchronous operations.
 #[derive(Clone, Copy, PartialEq)]
 enum State {
@@ -187,12 +234,6 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
         }
     }
 
-    /// Gets the byte at the given position in the log.
-    fn get_byte(&self, pos: usize, pagebuffer: &mut F::Page) -> u8 {
-        let buffer = self.get_buffer(pos, pagebuffer);
-        buffer[pos % buffer.len()]
-    }
-
     /// Gets a `num_bytes` long slice of bytes starting from a position within the log.
     fn get_bytes<'b>(&self, pos: usize, num_bytes: usize, pagebuffer: &'b mut F::Page) -> &'b [u8] {
         let buffer = self.get_buffer(pos, pagebuffer);
@@ -200,20 +241,46 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
         &buffer[offset..offset + num_bytes]
     }
 
-    /// Resets a log back to an empty log. Returns whether or not the log was reset successfully.
-    fn reset(&self) -> bool {
-        self.oldest_entry_id.set(PAGE_HEADER_SIZE);
-        self.read_entry_id.set(PAGE_HEADER_SIZE);
-        self.append_entry_id.set(PAGE_HEADER_SIZE);
+    /// Resets a log back to an empty log, starting at the page with the given ID. Returns whether
+    /// or not the log was reset successfully.
+    fn reset(&self, page_id: EntryID) -> bool {
+        self.oldest_entry_id.set(page_id + PAGE_HEADER_SIZE);
+        self.read_entry_id.set(page_id + PAGE_HEADER_SIZE);
+        self.append_entry_id.set(page_id + PAGE_HEADER_SIZE);
         self.pagebuffer.take().is_some_and(move |pagebuffer| {
             for e in pagebuffer.as_mut().iter_mut() {
                 *e = 0;
             }
+            let id_bytes = page_id.to_ne_bytes();
+            pagebuffer.as_mut()[..id_bytes.len()].copy_from_slice(&id_bytes[..]);
             self.pagebuffer.replace(pagebuffer);
             true
         })
     }
 
+    /// Checks the entry starting at the given offset within a page, returning the length of its
+    /// data if the entry is intact.
+    fn check_entry(&self, page: &[u8], offset: usize) -> Result<usize, EntryError> {
+        // The header must fit in the page, and padding is not a valid length.
+        if offset + ENTRY_HEADER_SIZE > self.page_size {
+            return Err(EntryError::InvalidHeader);
+        }
+        let length_bytes = &page[offset..offset + LENGTH_SIZE];
+        let length = usize::from_ne_bytes(<[u8; LENGTH_SIZE]>::try_from(length_bytes).unwrap());
+        if length == 0 || length > self.page_size - offset - ENTRY_HEADER_SIZE {
+            return Err(EntryError::InvalidHeader);
+        }
+
+        let checksum_bytes = &page[offset + LENGTH_SIZE..offset + ENTRY_HEADER_SIZE];
+        let checksum = u32::from_ne_bytes(<[u8; CHECKSUM_SIZE]>::try_from(checksum_bytes).unwrap());
+        let data = &page[offset + ENTRY_HEADER_SIZE..offset + ENTRY_HEADER_SIZE + length];
+        if crc32_posix(data) == checksum {
+            Ok(length)
+        } else {
+            Err(EntryError::Corrupted { length })
+        }
+    }
+
     /// Reconstructs a log from flash.
     fn reconstruct(&self) {
         // Read page headers, get IDs of oldest and newest pages.
@@ -241,32 +308,15 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
         // Reconstruct log if at least one valid page was found (meaning oldest page ID was set to
         // something not usize::MAX).
         if oldest_page_id != usize::MAX {
-            // Walk entries in last (newest) page to calculate last page length.
+            // Walk entries in last (newest) page to calculate last page length. The page ends at
+            // the first entry that is not intact: either padding, unwritten space, or an entry
+            // torn by a power loss while the page was being written, which is then overwritten
+            // by the next append.
+            let page_start = newest_page_id % self.volume.len();
+            let page = &self.volume[page_start..page_start + self.page_size];
             let mut last_page_len = PAGE_HEADER_SIZE;
-            loop {
-                // Check if next byte is start of valid entry.
-                let volume_offset = newest_page_id % self.volume.len() + last_page_len;
-                if self.volume[volume_offset] == 0 || self.volume[volume_offset] == PAD_BYTE {
-                    break;
-                }
-
-                // Get next entry length.
-                let entry_length = {
-                    const LENGTH_SIZE: usize = size_of::<usize>();
-                    let length_bytes = &self.volume[volume_offset..volume_offset + LENGTH_SIZE];
-                    let length_bytes = <[u8; LENGTH_SIZE]>::try_from(length_bytes).unwrap();
-                    usize::from_ne_bytes(length_bytes)
-                } + ENTRY_HEADER_SIZE;
-
-                // Add to page length if length is valid (fits within remainder of page.
-                if last_page_len + entry_length <= self.page_size {
-                    last_page_len += entry_length;
-                    if last_page_len == self.page_size {
-                        break;
-                    }
-                } else {
-                    break;
-                }
+            while let Ok(entry_length) = self.check_entry(page, last_page_len) {
+                last_page_len += ENTRY_HEADER_SIZE + entry_length;
             }
 
             // Set tracked entry IDs.
@@ -285,18 +335,18 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
                         copy_pagebuffer = !self.reset_pagebuffer(pagebuffer);
                     }
                     if copy_pagebuffer {
-                        // Copy last page into pagebuffer.
-                        for i in 0..self.page_size {
-                            pagebuffer.as_mut()[i] =
-                                self.volume[newest_page_id % self.volume.len() + i];
-                        }
+                        // Copy the intact part of the last page into pagebuffer, and clear the
+                        // rest.
+                        let pagebuffer = pagebuffer.as_mut();
+                        pagebuffer[..last_page_len].copy_from_slice(&page[..last_page_len]);
+                        pagebuffer[last_page_len..].fill(0);
                     }
                     self.pagebuffer.replace(pagebuffer);
                 })
                 .unwrap();
         } else {
             // No valid pages found, create fresh log.
-            self.reset();
+            self.reset(0);
         }
     }
 
@@ -310,11 +360,10 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
             .map_or(Err(Err(ErrorCode::RESERVE)), move |pagebuffer| {
                 let mut entry_id = self.read_entry_id.get();
 
-                // Skip page header if at start of page or skip padded bytes if at end of page.
+                // Skip page header if at start of page. Padding at the end of a page is skipped
+                // when its header is found to be invalid.
                 if entry_id % self.page_size == 0 {
                     entry_id += PAGE_HEADER_SIZE;
-                } else if self.get_byte(entry_id, pagebuffer) == PAD_BYTE {
-                    entry_id += self.page_size - entry_id % self.page_size + PAGE_HEADER_SIZE;
                 }
 
                 // Check if end of log was reached and return.
@@ -327,70 +376,67 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
             })
     }
 
-    /// Reads and returns the contents of an entry header with the given ID. Fails if the header
-    /// data is invalid.
-    /// Result<(), ErrorCode>s used:
-    ///     * FAIL: entry header invalid.
-    ///     * RESERVE: client or internal pagebuffer missing.
-    fn read_entry_header(&self, entry_id: EntryID) -> Result<usize, Result<(), ErrorCode>> {
-        self.pagebuffer
-            .take()
-            .map_or(Err(Err(ErrorCode::RESERVE)), move |pagebuffer| {
-                // Get length.
-                const LENGTH_SIZE: usize = size_of::<usize>();
-                let length_bytes = self.get_bytes(entry_id, LENGTH_SIZE, pagebuffer);
-                let length_bytes = <[u8; LENGTH_SIZE]>::try_from(length_bytes).unwrap();
-                let length = usize::from_ne_bytes(length_bytes);
-
-                // Return length of next entry.
-                self.pagebuffer.replace(pagebuffer);
-                if length == 0 || length > self.page_size - PAGE_HEADER_SIZE - ENTRY_HEADER_SIZE {
-                    Err(Err(ErrorCode::FAIL))
-                } else {
-                    Ok(length)
-                }
-            })
-    }
-
-    /// Reads the next entry into a buffer. Returns the number of bytes read on success, or an
-    /// error otherwise.
+    /// Reads the next intact entry into a buffer, skipping corrupted entries. Returns the number
+    /// of bytes read on success, or an error otherwise.
     /// Result<(), ErrorCode>s used:
     ///     * FAIL: reached end of log, nothing to read.
     ///     * RESERVE: internal pagebuffer missing, log is presumably broken.
     ///     * SIZE: buffer not large enough to contain entry being read.
     fn read_entry(&self, buffer: &mut [u8], length: usize) -> Result<usize, Result<(), ErrorCode>> {
-        // Get next entry to read. Immediately returns FAIL in event of failure.
-        let entry_id = self.get_next_entry()?;
-        let entry_length = self.read_entry_header(entry_id)?;
-
-        // Read entry into buffer.
-        self.pagebuffer
-            .take()
-            .map_or(Err(Err(ErrorCode::RESERVE)), move |pagebuffer| {
-                // Ensure buffer is large enough to hold log entry.
-                if entry_length > length {
-                    self.pagebuffer.replace(pagebuffer);
-                    return Err(Err(ErrorCode::SIZE));
+        loop {
+            // Get next entry to read. Immediately returns FAIL in event of failure.
+            let entry_id = self.get_next_entry()?;
+
+            let pagebuffer = self.pagebuffer.take().ok_or(Err(ErrorCode::RESERVE))?;
+            let page_offset = entry_id % self.page_size;
+            let page = self.get_bytes(entry_id - page_offset, self.page_size, pagebuffer);
+            let result = match self.check_entry(page, page_offset) {
+                Ok(entry_length) if entry_length > length => {
+                    // Ensure buffer is large enough to hold log entry.
+                    Err(Err(ErrorCode::SIZE))
                 }
-                let entry_id = entry_id + ENTRY_HEADER_SIZE;
-
-                // Copy data into client buffer.
-                let data = self.get_bytes(entry_id, entry_length, pagebuffer);
-                buffer[..entry_length].copy_from_slice(&data[..entry_length]);
+                Ok(entry_length) => {
+                    // Copy data into client buffer.
+                    let data_offset = page_offset + ENTRY_HEADER_SIZE;
+                    buffer[..entry_length]
+                        .copy_from_slice(&page[data_offset..data_offset + entry_length]);
+
+                    // Update read entry ID and return number of bytes read.
+                    self.read_entry_id
+                        .set(entry_id + ENTRY_HEADER_SIZE + entry_length);
+                    Ok(Some(entry_length))
+                }
+                Err(EntryError::Corrupted {
+                    length: entry_length,
+                }) => {
+                    // Skip the corrupted entry.
+                    self.read_entry_id
+                        .set(entry_id + ENTRY_HEADER_SIZE + entry_length);
+                    Ok(None)
+                }
+                Err(EntryError::InvalidHeader) => {
+                    // Padding, or the rest of the page cannot be trusted. Continue at the next
+                    // page.
+                    self.read_entry_id
+                        .set(entry_id - page_offset + self.page_size);
+                    Ok(None)
+                }
+            };
+            self.pagebuffer.replace(pagebuffer);
 
-                // Update read entry ID and return number of bytes read.
-                self.read_entry_id.set(entry_id + entry_length);
-                self.pagebuffer.replace(pagebuffer);
-                Ok(entry_length)
-            })
+            if let Some(entry_length) = result? {
+                return Ok(entry_length);
+            }
+        }
     }
 
     /// Writes an entry header at the given position within a page. Must write at most
     /// ENTRY_HEADER_SIZE bytes.
-    fn write_entry_header(&self, length: usize, pos: usize, pagebuffer: &mut F::Page) {
-        for (offset, byte) in length.to_ne_bytes().iter().enumerate() {
-            pagebuffer.as_mut()[pos + offset] = *byte;
-        }
+    fn write_entry_header(&self, data: &[u8], pos: usize, pagebuffer: &mut F::Page) {
+        let pagebuffer = pagebuffer.as_mut();
+        pagebuffer[pos..pos + LENGTH_SIZE].copy_from_slice(&data.len().to_ne_bytes());
+        pagebuffer[pos + LENGTH_SIZE..pos + ENTRY_HEADER_SIZE]
+            .copy_from_slice(&crc32_posix(data).to_ne_bytes());
     }
 
     /// Appends data from a buffer onto the end of the log. Requires that there is enough space
@@ -406,7 +452,7 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
         let mut page_offset = append_entry_id % self.page_size;
 
         // Write entry header to pagebuffer.
-        self.write_entry_header(length, page_offset, pagebuffer);
+        self.write_entry_header(&buffer[..length], page_offset, pagebuffer);
         page_offset += ENTRY_HEADER_SIZE;
 
         // Copy data to pagebuffer.
@@ -854,8 +900,16 @@ impl<F: Flash + 'static> flash::Client<F> for Log<'_, F> {
             true => {
                 let oldest_entry_id = self.oldest_entry_id.get();
                 if oldest_entry_id >= self.append_entry_id.get() - self.page_size {
-                    // Erased all pages. Reset state and callback client.
-                    if self.reset() {
+                    // Erased all pages. Reset state and callback client. Circular logs continue
+                    // on the page after the last one written, so that erasing the log does not
+                    // wear out its first pages.
+                    let page_id = if self.circular {
+                        (self.append_entry_id.get() - 1) / self.page_size * self.page_size
+                            + self.page_size
+                    } else {
+                        0
+                    };
+                    if self.reset(page_id) {
                         self.error.set(Ok(()));
                     } else {
                         self.error.set(Err(ErrorCode::RESERVE));
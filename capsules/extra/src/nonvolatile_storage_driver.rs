@@ -78,8 +78,10 @@ mod upcall {
     pub const READ_DONE: usize = 0;
     /// Write done callback.
     pub const WRITE_DONE: usize = 1;
+    /// Erase done callback.
+    pub const ERASE_DONE: usize = 2;
     /// Number of upcalls.
-    pub const COUNT: u8 = 2;
+    pub const COUNT: u8 = 3;
 }
 
 /// Ids for read-only allow buffers
@@ -104,8 +106,10 @@ pub const BUF_LEN: usize = 512;
 pub enum NonvolatileCommand {
     UserspaceRead,
     UserspaceWrite,
+    UserspaceErase,
     KernelRead,
     KernelWrite,
+    KernelErase,
 }
 
 #[derive(Clone, Copy)]
@@ -160,15 +164,15 @@ pub struct NonvolatileStorage<'a> {
     // Optional client for the kernel. Only needed if the kernel intends to use
     // this nonvolatile storage.
     kernel_client: OptionalCell<&'a dyn hil::nonvolatile_storage::NonvolatileStorageClient>,
-    // Whether the kernel is waiting for a read/write.
+    // Whether the kernel is waiting for a read/write/erase.
     kernel_pending_command: Cell<bool>,
-    // Whether the kernel wanted a read/write.
+    // Whether the kernel wanted a read/write/erase.
     kernel_command: Cell<NonvolatileCommand>,
     // Holder for the buffer passed from the kernel in case we need to wait.
     kernel_buffer: TakeCell<'static, [u8]>,
-    // How many bytes to read/write from the kernel buffer.
+    // How many bytes to read/write from the kernel buffer, or to erase.
     kernel_readwrite_length: Cell<usize>,
-    // Where to read/write from the kernel request.
+    // Where to read/write/erase from the kernel request.
     kernel_readwrite_address: Cell<usize>,
 }
 
@@ -217,7 +221,9 @@ impl<'a> NonvolatileStorage<'a> {
     ) -> Result<(), ErrorCode> {
         // Do bounds check.
         match command {
-            NonvolatileCommand::UserspaceRead | NonvolatileCommand::UserspaceWrite => {
+            NonvolatileCommand::UserspaceRead
+            | NonvolatileCommand::UserspaceWrite
+            | NonvolatileCommand::UserspaceErase => {
                 // Userspace sees memory that starts at address 0 even if it
                 // is offset in the physical memory.
                 if offset >= self.userspace_length
@@ -227,7 +233,9 @@ impl<'a> NonvolatileStorage<'a> {
                     return Err(ErrorCode::INVAL);
                 }
             }
-            NonvolatileCommand::KernelRead | NonvolatileCommand::KernelWrite => {
+            NonvolatileCommand::KernelRead
+            | NonvolatileCommand::KernelWrite
+            | NonvolatileCommand::KernelErase => {
                 // Because the kernel uses the NonvolatileStorage interface,
                 // its calls are absolute addresses.
                 if offset < self.kernel_start_address
@@ -243,6 +251,45 @@ impl<'a> NonvolatileStorage<'a> {
         // Do very different actions if this is a call from userspace
         // or from the kernel.
         match command {
+            NonvolatileCommand::UserspaceErase => {
+                processid.map_or(Err(ErrorCode::FAIL), |processid| {
+                    self.apps
+                        .enter(processid, |app, _| {
+                            // Erasing does not need any buffer, so it can
+                            // start right away if the storage is free.
+                            if self.current_user.is_none() {
+                                self.current_user.set(NonvolatileUser::App { processid });
+                                self.userspace_call_driver(command, offset, length)
+                                    .inspect_err(|_| self.current_user.clear())
+                            } else if app.pending_command {
+                                Err(ErrorCode::NOMEM)
+                            } else {
+                                app.pending_command = true;
+                                app.command = command;
+                                app.offset = offset;
+                                app.length = length;
+                                Ok(())
+                            }
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                })
+            }
+            NonvolatileCommand::KernelErase => {
+                if self.current_user.is_none() {
+                    self.current_user.set(NonvolatileUser::Kernel);
+                    self.driver
+                        .erase(offset, length)
+                        .inspect_err(|_| self.current_user.clear())
+                } else if self.kernel_pending_command.get() {
+                    Err(ErrorCode::NOMEM)
+                } else {
+                    self.kernel_pending_command.set(true);
+                    self.kernel_command.set(command);
+                    self.kernel_readwrite_length.set(length);
+                    self.kernel_readwrite_address.set(offset);
+                    Ok(())
+                }
+            }
             NonvolatileCommand::UserspaceRead | NonvolatileCommand::UserspaceWrite => {
                 processid.map_or(Err(ErrorCode::FAIL), |processid| {
                     self.apps
@@ -365,6 +412,10 @@ impl<'a> NonvolatileStorage<'a> {
         // storage.
         let physical_address = offset + self.userspace_start_address;
 
+        if command == NonvolatileCommand::UserspaceErase {
+            return self.driver.erase(physical_address, length);
+        }
+
         self.buffer
             .take()
             .map_or(Err(ErrorCode::RESERVE), |buffer| {
@@ -388,6 +439,23 @@ impl<'a> NonvolatileStorage<'a> {
     fn check_queue(&self) {
         // Check if there are any pending events.
         if self.kernel_pending_command.get() {
+            if self.kernel_command.get() == NonvolatileCommand::KernelErase {
+                self.kernel_pending_command.set(false);
+                self.current_user.set(NonvolatileUser::Kernel);
+                if self
+                    .driver
+                    .erase(
+                        self.kernel_readwrite_address.get(),
+                        self.kernel_readwrite_length.get(),
+                    )
+                    .is_err()
+                {
+                    // Nothing was erased.
+                    hil::nonvolatile_storage::NonvolatileStorageClient::erase_done(self, 0);
+                }
+                return;
+            }
+
             self.kernel_buffer.take().map(|kernel_buffer| {
                 self.kernel_pending_command.set(false);
                 self.current_user.set(NonvolatileUser::Kernel);
@@ -410,7 +478,7 @@ impl<'a> NonvolatileStorage<'a> {
             // If the kernel is not requesting anything, check all of the apps.
             for cntr in self.apps.iter() {
                 let processid = cntr.processid();
-                let started_command = cntr.enter(|app, _| {
+                let started_command = cntr.enter(|app, kernel_data| {
                     if app.pending_command {
                         app.pending_command = false;
                         self.current_user.set(NonvolatileUser::App { processid });
@@ -419,6 +487,15 @@ impl<'a> NonvolatileStorage<'a> {
                         {
                             true
                         } else {
+                            // Release the storage and report that nothing
+                            // was done.
+                            self.current_user.clear();
+                            let upcall = match app.command {
+                                NonvolatileCommand::UserspaceRead => upcall::READ_DONE,
+                                NonvolatileCommand::UserspaceWrite => upcall::WRITE_DONE,
+                                _ => upcall::ERASE_DONE,
+                            };
+                            let _ = kernel_data.schedule_upcall(upcall, (0, 0, 0));
                             false
                         }
                     } else {
@@ -496,6 +573,25 @@ impl hil::nonvolatile_storage::NonvolatileStorageClient for NonvolatileStorage<'
 
         self.check_queue();
     }
+
+    fn erase_done(&self, length: usize) {
+        // Switch on which user of this capsule generated this callback.
+        self.current_user.take().map(|user| match user {
+            NonvolatileUser::Kernel => {
+                self.kernel_client.map(move |client| {
+                    client.erase_done(length);
+                });
+            }
+            NonvolatileUser::App { processid } => {
+                let _ = self.apps.enter(processid, move |_app, kernel_data| {
+                    // Signal the app.
+                    let _ = kernel_data.schedule_upcall(upcall::ERASE_DONE, (length, 0, 0));
+                });
+            }
+        });
+
+        self.check_queue();
+    }
 }
 
 /// Provide an interface for the kernel.
@@ -523,6 +619,10 @@ impl<'a> hil::nonvolatile_storage::NonvolatileStorage<'a> for NonvolatileStorage
         self.kernel_buffer.replace(buffer);
         self.enqueue_command(NonvolatileCommand::KernelWrite, address, length, None)
     }
+
+    fn erase(&self, address: usize, length: usize) -> Result<(), ErrorCode> {
+        self.enqueue_command(NonvolatileCommand::KernelErase, address, length, None)
+    }
 }
 
 /// Provide an interface for userland.
@@ -537,6 +637,7 @@ impl SyscallDriver for NonvolatileStorage<'_> {
     /// - `1`: Return the number of bytes available to userspace.
     /// - `2`: Start a read from the nonvolatile storage.
     /// - `3`: Start a write to the nonvolatile_storage.
+    /// - `4`: Start an erase of the nonvolatile_storage.
     fn command(
         &self,
         command_num: usize,
@@ -583,6 +684,21 @@ impl SyscallDriver for NonvolatileStorage<'_> {
                 }
             }
 
+            4 => {
+                // Issue an erase command
+                let res = self.enqueue_command(
+                    NonvolatileCommand::UserspaceErase,
+                    offset,
+                    length,
+                    Some(processid),
+                );
+
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
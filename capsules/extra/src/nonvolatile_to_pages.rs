@@ -5,8 +5,12 @@
 //! Map arbitrary nonvolatile reads and writes to page operations.
 //!
 //! This splits non-page-aligned reads and writes into a series of page level
-//! reads and writes. While it is handling a read or write it returns `BUSY` to
-//! all additional requests.
+//! reads and writes. While it is handling a read, write or erase it returns
+//! `BUSY` to all additional requests.
+//!
+//! Erases use the flash page erase for all the pages entirely covered by the
+//! erased range. The partial pages at the start and at the end of the range
+//! are read, filled with `0xFF` and written back.
 //!
 //! This module is designed to be used on top of any flash storage and below any
 //! user of `NonvolatileStorage`. This module handles different sized pages.
@@ -47,12 +51,14 @@ use kernel::utilities::cells::NumericCellExt;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
-/// This module is either waiting to do something, or handling a read/write.
+/// This module is either waiting to do something, or handling a
+/// read/write/erase.
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum State {
     Idle,
     Read,
     Write,
+    Erase,
 }
 
 pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
@@ -76,6 +82,8 @@ pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
     remaining_length: Cell<usize>,
     /// Where we are in the user buffer.
     buffer_index: Cell<usize>,
+    /// How many bytes of a partly erased page are being written back.
+    partial_length: Cell<usize>,
 }
 
 impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
@@ -90,10 +98,62 @@ impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
             length: Cell::new(0),
             remaining_length: Cell::new(0),
             buffer_index: Cell::new(0),
+            partial_length: Cell::new(0),
         }
     }
 }
 
+impl<F: hil::flash::Flash> NonvolatileToPages<'_, F> {
+    /// Start erasing the next part of the range, either a whole page or a
+    /// partial page. If this fails, the page buffer is put back.
+    fn erase_next(&self, pagebuffer: &'static mut F::Page) -> Result<(), ErrorCode> {
+        let page_size = pagebuffer.as_mut().len();
+        let address = self.address.get();
+
+        if address % page_size == 0 && self.remaining_length.get() >= page_size {
+            // The whole page is erased, no need to read it first. The page
+            // buffer waits for the erase to complete.
+            self.pagebuffer.replace(pagebuffer);
+            self.driver.erase_page(address / page_size)
+        } else {
+            // Read the page to keep the bytes outside of the range.
+            self.driver
+                .read_page(address / page_size, pagebuffer)
+                .map_err(|(error_code, pagebuffer)| {
+                    self.pagebuffer.replace(pagebuffer);
+                    error_code
+                })
+        }
+    }
+
+    /// Continue an erase after `result` bytes were erased, or finish it if the
+    /// whole range is erased or the flash failed.
+    fn erase_continue(
+        &self,
+        pagebuffer: &'static mut F::Page,
+        result: Result<usize, hil::flash::Error>,
+    ) {
+        if let Ok(len) = result {
+            self.remaining_length.subtract(len);
+            self.address.add(len);
+        }
+        if result.is_err() || self.remaining_length.get() == 0 {
+            self.pagebuffer.replace(pagebuffer);
+            self.erase_finish();
+        } else if self.erase_next(pagebuffer).is_err() {
+            self.erase_finish();
+        }
+    }
+
+    /// Report the number of bytes erased, which is less than the length of
+    /// the range if erasing failed.
+    fn erase_finish(&self) {
+        self.state.set(State::Idle);
+        let erased = self.length.get() - self.remaining_length.get();
+        self.client.map(move |client| client.erase_done(erased));
+    }
+}
+
 impl<'a, F: hil::flash::Flash> hil::nonvolatile_storage::NonvolatileStorage<'a>
     for NonvolatileToPages<'a, F>
 {
@@ -189,13 +249,31 @@ impl<'a, F: hil::flash::Flash> hil::nonvolatile_storage::NonvolatileStorage<'a>
                 }
             })
     }
+
+    fn erase(&self, address: usize, length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.pagebuffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), move |pagebuffer| {
+                self.state.set(State::Erase);
+                self.address.set(address);
+                self.length.set(length);
+                self.remaining_length.set(length);
+
+                self.erase_next(pagebuffer)
+                    .inspect_err(|_| self.state.set(State::Idle))
+            })
+    }
 }
 
 impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
     fn read_complete(
         &self,
         pagebuffer: &'static mut F::Page,
-        _result: Result<(), hil::flash::Error>,
+        result: Result<(), hil::flash::Error>,
     ) {
         match self.state.get() {
             State::Read => {
@@ -267,6 +345,32 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
                     }
                 });
             }
+            State::Erase => {
+                if let Err(error) = result {
+                    self.erase_continue(pagebuffer, Err(error));
+                    return;
+                }
+
+                // We read a page that is only partly erased. Clear the part in
+                // the range and write the page back.
+                let page_size = pagebuffer.as_mut().len();
+                let page_index = self.address.get() % page_size;
+                let len = cmp::min(page_size - page_index, self.remaining_length.get());
+                let page_number = self.address.get() / page_size;
+
+                pagebuffer
+                    .as_mut()
+                    .iter_mut()
+                    .skip(page_index)
+                    .take(len)
+                    .for_each(|byte| *byte = 0xFF);
+                self.partial_length.set(len);
+
+                if let Err((_, pagebuffer)) = self.driver.write_page(page_number, pagebuffer) {
+                    self.pagebuffer.replace(pagebuffer);
+                    self.erase_finish();
+                }
+            }
             _ => {}
         }
     }
@@ -274,8 +378,16 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
     fn write_complete(
         &self,
         pagebuffer: &'static mut F::Page,
-        _result: Result<(), hil::flash::Error>,
+        result: Result<(), hil::flash::Error>,
     ) {
+        if self.state.get() == State::Erase {
+            // A partial page was written back, with the part in the range
+            // cleared.
+            let len = self.partial_length.get();
+            self.erase_continue(pagebuffer, result.map(|()| len));
+            return;
+        }
+
         // After a write we could be done, need to do another write, or need to
         // do a read.
         self.buffer.take().map(move |buffer| {
@@ -316,5 +428,13 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
         });
     }
 
-    fn erase_complete(&self, _result: Result<(), hil::flash::Error>) {}
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        if self.state.get() != State::Erase {
+            return;
+        }
+        self.pagebuffer.take().map(|pagebuffer| {
+            let page_size = pagebuffer.as_mut().len();
+            self.erase_continue(pagebuffer, result.map(|()| page_size));
+        });
+    }
 }
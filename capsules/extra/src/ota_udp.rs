@@ -0,0 +1,601 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Over-the-air application update service over UDP.
+//!
+//! This capsule receives a TBF image in chunks over UDP, stores it in flash
+//! through the `DynamicBinaryStore`, and loads it with `DynamicProcessLoad`.
+//! It is meant for deployed boards that are only reachable over the network,
+//! such as 6LoWPAN sensors. The image is loaded like any dynamically loaded
+//! app, so its credentials are checked by the process checker of the board
+//! before it can run; the UDP transfer itself is not authenticated.
+//!
+//! An image can either be installed as a new app, or replace a running app
+//! selected by its process name. A replaced app keeps running until the new
+//! image has passed its credential checks.
+//!
+//! Protocol
+//! --------
+//!
+//! All fields are little endian. Every request is answered with a reply, sent
+//! back to the port it came from. A transfer is bound to the address and port
+//! it was started from: requests other than `STATUS` from any other host are
+//! rejected with `BUSY` until the transfer ends.
+//!
+//! ```text
+//! START   | 0 | name_len: u8 | 0: u16 | image_id: u32 | image_len: u32 | name |
+//! DATA    | 1 | 0: u8 | 0: u16 | image_id: u32 | offset: u32 | crc32: u32 | data |
+//! STATUS  | 2 | 0: u8 | 0: u16 |
+//! ABORT   | 3 | 0: u8 | 0: u16 |
+//!
+//! REPLY   | 0x80 + request | status: u8 | state: u8 | 0: u8 | image_id: u32 | next_offset: u32 |
+//! ```
+//!
+//! - `START` begins the transfer of an image of `image_len` bytes. The
+//!   `image_id` is chosen by the sender, for example the CRC-32 of the image,
+//!   and identifies the transfer. If `name` is not empty, the image replaces
+//!   the running app with this process name. Sending `START` again for the
+//!   transfer in progress resumes it: the reply carries the offset of the
+//!   next chunk to send.
+//! - `DATA` carries the chunk of the image starting at `offset`, with the
+//!   CRC-32 (POSIX) of `data`. Chunks must be sent in order, and the first
+//!   chunk must hold at least the first 8 bytes of the image. The reply to a
+//!   chunk is sent once it is written to flash. The reply to the last chunk
+//!   is sent once the image is loaded, and its status is the result of the
+//!   load.
+//! - `STATUS` returns the state of the transfer.
+//! - `ABORT` cancels the transfer in progress. It is accepted while a flash
+//!   operation is pending, in which case the request waiting for it is
+//!   answered with `CANCEL`. The reply to `ABORT` is sent once the flash
+//!   reserved for the image is freed. An image that is being loaded cannot be
+//!   cancelled.
+//!
+//! A transfer waiting for a chunk is cancelled if no request is received from
+//! its host for [`TIMEOUT_MS`] milliseconds.
+//!
+//! The `status` of a reply is 0 on success, or the value of the `ErrorCode`
+//! of the failure. A corrupted chunk is rejected with `FAIL`, a chunk that
+//! is not the next one with `INVAL`, and any request other than `ABORT`
+//! received while a flash operation is pending with `BUSY`. The `state` is
+//! the [`State`] of the service.
+//!
+//! The service is not virtualized with the `AppLoader` capsule: a board uses
+//! one or the other as the client of its dynamic binary storage.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ota = components::ota_udp::OtaUdpComponent::new(
+//!     board_kernel,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     dynamic_binary_storage,
+//!     dynamic_binary_storage,
+//!     OTA_PORT,
+//! )
+//! .finalize(components::ota_udp_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     DynamicBinaryStorage<'static>,
+//!     DynamicBinaryStorage<'static>,
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::dynamic_binary_storage::{
+    DynamicBinaryStore, DynamicBinaryStoreClient, DynamicProcessLoad, DynamicProcessLoadClient,
+};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::process::ProcessLoadError;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::helpers::crc32_posix;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, Kernel};
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+/// Request types.
+mod request {
+    pub const START: u8 = 0;
+    pub const DATA: u8 = 1;
+    pub const STATUS: u8 = 2;
+    pub const ABORT: u8 = 3;
+    /// Added to the request type in the reply.
+    pub const REPLY: u8 = 0x80;
+}
+
+/// Length of the header of a `START` request.
+const START_HEADER_LEN: usize = 12;
+/// Length of the header of a `DATA` request.
+const DATA_HEADER_LEN: usize = 16;
+/// Length of a reply.
+pub const REPLY_LEN: usize = 12;
+/// Maximum length of the process name of the app to replace.
+pub const MAX_NAME_LEN: usize = 32;
+/// Time after the last request from the host of a transfer after which the
+/// transfer is cancelled.
+pub const TIMEOUT_MS: u32 = 30_000;
+
+/// State of the service, as reported in replies.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum State {
+    /// No transfer in progress.
+    Idle = 0,
+    /// Reserving flash for the image.
+    Setup = 1,
+    /// Waiting for the next chunk.
+    Receiving = 2,
+    /// Writing a chunk to flash.
+    Writing = 3,
+    /// Finalizing and loading the image.
+    Loading = 4,
+    /// Cancelling the transfer.
+    Aborting = 5,
+}
+
+/// Returns the little endian `u32` at `index` of `payload`.
+fn u32_at(payload: &[u8], index: usize) -> Option<u32> {
+    payload
+        .get(index..index + 4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_le_bytes)
+}
+
+pub struct OtaUdp<
+    'a,
+    A: Alarm<'a>,
+    S: DynamicBinaryStore + 'static,
+    L: DynamicProcessLoad + 'static,
+    C: ProcessManagementCapability,
+> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    storage_driver: &'a S,
+    load_driver: &'a L,
+    udp_sender: &'a dyn UDPSender<'a>,
+    net_cap: &'static NetworkCapability,
+    /// Buffer for the replies.
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// Buffer the chunks are copied into to be written to flash.
+    write_buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    image_id: Cell<u32>,
+    image_len: Cell<usize>,
+    /// Offset of the next chunk to write.
+    next_offset: Cell<usize>,
+    /// Process name of the app the image replaces.
+    replaced_name: OptionalCell<([u8; MAX_NAME_LEN], usize)>,
+    /// Address and port of the host that started the transfer in progress.
+    peer: OptionalCell<(IPAddr, u16)>,
+    /// Address, port and request type of the request waiting for a flash
+    /// operation to be answered.
+    pending_reply: OptionalCell<(IPAddr, u16, u8)>,
+    capability: C,
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > OtaUdp<'a, A, S, L, C>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        storage_driver: &'a S,
+        load_driver: &'a L,
+        udp_sender: &'a dyn UDPSender<'a>,
+        net_cap: &'static NetworkCapability,
+        tx_buffer: SubSliceMut<'static, u8>,
+        write_buffer: &'static mut [u8],
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            storage_driver,
+            load_driver,
+            udp_sender,
+            net_cap,
+            tx_buffer: MapCell::new(tx_buffer),
+            write_buffer: TakeCell::new(write_buffer),
+            state: Cell::new(State::Idle),
+            image_id: Cell::new(0),
+            image_len: Cell::new(0),
+            next_offset: Cell::new(0),
+            replaced_name: OptionalCell::empty(),
+            peer: OptionalCell::empty(),
+            pending_reply: OptionalCell::empty(),
+            capability,
+        }
+    }
+
+    /// Send a reply to a request of type `kind`. Replies are dropped while
+    /// the previous one is being sent; the sender retries on timeout.
+    fn reply(&self, dest: IPAddr, dst_port: u16, kind: u8, result: Result<(), ErrorCode>) {
+        let Some(mut buf) = self.tx_buffer.take() else {
+            return;
+        };
+        buf.reset();
+        if buf.len() < REPLY_LEN {
+            self.tx_buffer.replace(buf);
+            return;
+        }
+        buf[0] = kind | request::REPLY;
+        buf[1] = match result {
+            Ok(()) => 0,
+            Err(e) => usize::from(e) as u8,
+        };
+        buf[2] = self.state.get() as u8;
+        buf[3] = 0;
+        buf[4..8].copy_from_slice(&self.image_id.get().to_le_bytes());
+        buf[8..12].copy_from_slice(&(self.next_offset.get() as u32).to_le_bytes());
+        buf.slice(0..REPLY_LEN);
+        if let Err(mut buf) = self.udp_sender.send_to(dest, dst_port, buf, self.net_cap) {
+            buf.reset();
+            self.tx_buffer.replace(buf);
+        }
+    }
+
+    /// Answer the request waiting for the flash operation that just finished.
+    fn reply_pending(&self, result: Result<(), ErrorCode>) {
+        self.pending_reply.take().map(|(dest, dst_port, kind)| {
+            self.reply(dest, dst_port, kind, result);
+        });
+    }
+
+    /// End the transfer and answer the pending request with `result`.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.replaced_name.clear();
+        self.peer.clear();
+        let _ = self.alarm.disarm();
+        self.reply_pending(result);
+    }
+
+    /// Cancel the transfer if no request is received from its host for
+    /// `TIMEOUT_MS`.
+    fn restart_timeout(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TIMEOUT_MS));
+    }
+
+    /// Free the flash reserved for the image once the flash operation the
+    /// transfer was waiting for when it was cancelled has finished.
+    fn abort_storage(&self) {
+        if let Err(e) = self.storage_driver.abort() {
+            self.finish(Err(e));
+        }
+    }
+
+    /// Returns the identifier of the process named `name`.
+    fn process_id_by_name(&self, name: &[u8]) -> Option<usize> {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.get_process_name().as_bytes() == name {
+                    found = Some(process.processid().id());
+                }
+            });
+        found
+    }
+
+    fn start(&self, src_addr: IPAddr, src_port: u16, payload: &[u8]) -> Result<(), ErrorCode> {
+        let (Some(image_id), Some(image_len)) = (u32_at(payload, 4), u32_at(payload, 8)) else {
+            return Err(ErrorCode::SIZE);
+        };
+        let name_len = payload[1] as usize;
+        let name = payload
+            .get(START_HEADER_LEN..START_HEADER_LEN + name_len)
+            .ok_or(ErrorCode::SIZE)?;
+
+        match self.state.get() {
+            State::Idle => {}
+            // Resume the transfer in progress.
+            State::Receiving
+                if image_id == self.image_id.get()
+                    && image_len as usize == self.image_len.get() =>
+            {
+                return Ok(());
+            }
+            _ => return Err(ErrorCode::BUSY),
+        }
+
+        if name_len > MAX_NAME_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        if name_len > 0 {
+            // Check the app exists now, rather than after the whole image is
+            // received.
+            self.process_id_by_name(name).ok_or(ErrorCode::INVAL)?;
+        }
+
+        self.storage_driver.setup(image_len as usize)?;
+        if name_len > 0 {
+            let mut replaced_name = [0; MAX_NAME_LEN];
+            replaced_name[..name_len].copy_from_slice(name);
+            self.replaced_name.set((replaced_name, name_len));
+        }
+        self.image_id.set(image_id);
+        self.image_len.set(image_len as usize);
+        self.next_offset.set(0);
+        self.peer.set((src_addr, src_port));
+        self.state.set(State::Setup);
+        Ok(())
+    }
+
+    fn data(&self, payload: &[u8]) -> Result<(), ErrorCode> {
+        let (Some(image_id), Some(offset), Some(crc)) =
+            (u32_at(payload, 4), u32_at(payload, 8), u32_at(payload, 12))
+        else {
+            return Err(ErrorCode::SIZE);
+        };
+        let data = &payload[DATA_HEADER_LEN.min(payload.len())..];
+        let offset = offset as usize;
+        let end = offset.checked_add(data.len()).ok_or(ErrorCode::INVAL)?;
+
+        match self.state.get() {
+            State::Receiving => {}
+            State::Idle => return Err(ErrorCode::OFF),
+            _ => return Err(ErrorCode::BUSY),
+        }
+        if image_id != self.image_id.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        if crc32_posix(data) != crc {
+            return Err(ErrorCode::FAIL);
+        }
+        if offset < self.next_offset.get() && end <= self.next_offset.get() {
+            // A chunk that was already written, whose reply was lost.
+            return Ok(());
+        }
+        if offset != self.next_offset.get()
+            || data.is_empty()
+            || (offset == 0 && data.len() < 8)
+            || end > self.image_len.get()
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let buffer = self.write_buffer.take().ok_or(ErrorCode::BUSY)?;
+        if data.len() > buffer.len() {
+            self.write_buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        let mut write_buffer = SubSliceMut::new(buffer);
+        write_buffer.slice(..data.len());
+        self.storage_driver.write(write_buffer, offset)?;
+        self.state.set(State::Writing);
+        Ok(())
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Receiving => {
+                self.storage_driver.abort()?;
+                self.state.set(State::Aborting);
+                Ok(())
+            }
+            // The flash is freed once the pending flash operation finishes.
+            State::Setup | State::Writing => {
+                self.state.set(State::Aborting);
+                Ok(())
+            }
+            State::Idle => Ok(()),
+            State::Aborting => Err(ErrorCode::ALREADY),
+            State::Loading => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > UDPRecvClient for OtaUdp<'a, A, S, L, C>
+{
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        let Some(&kind) = payload.first() else {
+            return;
+        };
+        if kind == request::STATUS {
+            self.reply(src_addr, src_port, kind, Ok(()));
+            return;
+        }
+        // Only the host that started the transfer can continue or cancel it.
+        let from_peer = self.peer.map_or(true, |peer| peer == (src_addr, src_port));
+        if !from_peer || (self.pending_reply.is_some() && kind != request::ABORT) {
+            self.reply(src_addr, src_port, kind, Err(ErrorCode::BUSY));
+            return;
+        }
+
+        let result = match kind {
+            request::START => self.start(src_addr, src_port, payload),
+            request::DATA => self.data(payload),
+            request::ABORT => self.abort(),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        if self.state.get() != State::Idle {
+            self.restart_timeout();
+        }
+        let waiting = matches!(
+            self.state.get(),
+            State::Setup | State::Writing | State::Loading | State::Aborting
+        );
+        if result.is_ok() && waiting {
+            // The request is answered when the flash operation finishes. A
+            // request still waiting for it is cancelled by `ABORT`.
+            self.reply_pending(Err(ErrorCode::CANCEL));
+            self.pending_reply.set((src_addr, src_port, kind));
+        } else {
+            self.reply(src_addr, src_port, kind, result);
+        }
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > UDPSendClient for OtaUdp<'a, A, S, L, C>
+{
+    fn send_done(&self, _result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
+        dgram.reset();
+        self.tx_buffer.replace(dgram);
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > DynamicBinaryStoreClient for OtaUdp<'a, A, S, L, C>
+{
+    fn setup_done(&self, result: Result<(), ErrorCode>) {
+        match (self.state.get(), result) {
+            (State::Setup, Ok(())) => {
+                self.state.set(State::Receiving);
+                self.reply_pending(Ok(()));
+            }
+            (State::Setup, Err(e)) => self.finish(Err(e)),
+            // The transfer was cancelled while reserving flash.
+            (State::Aborting, Ok(())) => self.abort_storage(),
+            (State::Aborting, Err(_)) => self.finish(Ok(())),
+            _ => {}
+        }
+    }
+
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], length: usize) {
+        self.write_buffer.replace(buffer);
+        if self.state.get() == State::Aborting {
+            // The transfer was cancelled while writing the chunk.
+            self.abort_storage();
+            return;
+        }
+        if self.state.get() != State::Writing {
+            return;
+        }
+        if let Err(e) = result {
+            self.finish(Err(e));
+            return;
+        }
+
+        self.next_offset.set(self.next_offset.get() + length);
+        if self.next_offset.get() < self.image_len.get() {
+            self.state.set(State::Receiving);
+            self.reply_pending(Ok(()));
+            return;
+        }
+
+        // The whole image is written. The last chunk is answered once the
+        // image is loaded.
+        self.state.set(State::Loading);
+        if let Err(e) = self.storage_driver.finalize() {
+            self.finish(Err(e));
+        }
+    }
+
+    fn finalize_done(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() != State::Loading {
+            return;
+        }
+        if let Err(e) = result {
+            self.finish(Err(e));
+            return;
+        }
+
+        // The replaced app may have been removed while the image was being
+        // received, in which case the image is installed as a new app.
+        let replaced = self
+            .replaced_name
+            .get()
+            .and_then(|(name, len)| self.process_id_by_name(&name[..len]));
+        let result = match replaced {
+            Some(process_id) => self.load_driver.load_replacing(process_id),
+            None => self.load_driver.load(),
+        };
+        if let Err(e) = result {
+            self.finish(Err(e));
+        }
+    }
+
+    fn abort_done(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() == State::Aborting {
+            self.finish(result);
+        }
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > DynamicProcessLoadClient for OtaUdp<'a, A, S, L, C>
+{
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        // The loader may report more than once for a single load: only the
+        // first report is the result of loading the image.
+        if self.state.get() != State::Loading {
+            return;
+        }
+        self.finish(result.map_err(|e| match e {
+            ProcessLoadError::NotEnoughMemory => ErrorCode::NOMEM,
+            ProcessLoadError::MpuInvalidFlashLength => ErrorCode::INVAL,
+            ProcessLoadError::InternalError => ErrorCode::OFF,
+            _ => ErrorCode::FAIL,
+        }));
+    }
+
+    fn uninstall_done(&self, _result: Result<(), ErrorCode>) {}
+
+    fn compact_done(&self, _result: Result<(), ErrorCode>) {}
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        S: DynamicBinaryStore + 'static,
+        L: DynamicProcessLoad + 'static,
+        C: ProcessManagementCapability,
+    > AlarmClient for OtaUdp<'a, A, S, L, C>
+{
+    fn alarm(&self) {
+        match self.state.get() {
+            // The host stopped sending chunks.
+            State::Receiving => match self.storage_driver.abort() {
+                Ok(()) => self.state.set(State::Aborting),
+                Err(_) => self.restart_timeout(),
+            },
+            // Wait for the pending flash operation to finish.
+            State::Setup | State::Writing | State::Loading => self.restart_timeout(),
+            State::Idle | State::Aborting => {}
+        }
+    }
+}
@@ -0,0 +1,395 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Checkpoint processes to nonvolatile storage and restore them.
+//!
+//! This capsule saves the checkpoint of a process, as produced by
+//! `Process::read_checkpoint()`, into a slot of a nonvolatile storage region,
+//! and later writes it back into the process. This lets long-running
+//! processes survive a reboot or a power loss, for example on
+//! energy-harvesting devices.
+//!
+//! The process is stopped while its checkpoint is taken or restored, and
+//! resumed afterwards if it was running before. A checkpoint holds the
+//! registers and the memory the process can access, but no kernel-owned
+//! state: after a restore, the process must subscribe to upcalls and allow
+//! buffers again.
+//!
+//! Each slot starts with a small header that commits the checkpoint. It is
+//! invalidated before a new checkpoint is written and written last, so a
+//! power loss while a checkpoint is taken leaves no valid checkpoint in the
+//! slot instead of a corrupted one.
+//!
+//! To restore processes at boot, call `restore()` before the kernel loop
+//! starts: the process is stopped immediately, so it does not run before its
+//! checkpoint is restored.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let process_checkpoint = components::process_checkpoint::ProcessCheckpointComponent::new(
+//!     board_kernel,
+//!     nv_to_page,
+//!     0x60000, // Start address of the checkpoint region
+//!     0x10000, // Length of each slot
+//!     4,       // Number of slots
+//!     ProcessMgmtCap,
+//! )
+//! .finalize(components::process_checkpoint_component_static!(
+//!     capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, sam4l::flashcalw::FLASHCALW>,
+//!     ProcessMgmtCap,
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::process::{self, Process, CHECKPOINT_HEADER_LEN};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Length of the header at the start of each slot: a magic number and the
+/// length of the checkpoint.
+pub const SLOT_HEADER_LEN: usize = 8;
+
+/// Length of the buffer for the transfers to the storage used by the
+/// component. It must hold at least `CHECKPOINT_HEADER_LEN` bytes.
+pub const BUF_LEN: usize = 512;
+
+/// Marks a slot that holds a committed checkpoint ("CKPT").
+const SLOT_MAGIC: u32 = 0x5450_4B43;
+
+pub trait ProcessCheckpointClient {
+    /// Called when the checkpoint of `processid` was written to storage.
+    fn checkpoint_done(&self, processid: ProcessId, result: Result<(), ErrorCode>);
+
+    /// Called when `processid` was restored from its checkpoint. If restoring
+    /// failed after the process was modified, the process was restarted.
+    fn restore_done(&self, processid: ProcessId, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    /// Invalidating the slot header before writing a checkpoint.
+    Invalidate,
+    /// Writing the checkpoint, of which `offset` bytes were already written.
+    Write {
+        offset: usize,
+    },
+    /// Writing the slot header that commits the checkpoint.
+    Commit,
+    /// Reading the slot header before restoring a checkpoint.
+    ReadHeader,
+    /// Restoring a checkpoint of `len` bytes, of which `offset` bytes were
+    /// already restored.
+    Read {
+        offset: usize,
+        len: usize,
+    },
+}
+
+pub struct ProcessCheckpoint<'a, S: NonvolatileStorage<'a>, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    storage: &'a S,
+    /// Buffer for the transfers to the storage. Must hold at least
+    /// `CHECKPOINT_HEADER_LEN` bytes.
+    buffer: TakeCell<'static, [u8]>,
+    /// Start address of the first slot in the storage.
+    region_start: usize,
+    /// Length of each slot, including its header.
+    slot_len: usize,
+    num_slots: usize,
+    capability: C,
+    client: OptionalCell<&'a dyn ProcessCheckpointClient>,
+    operation: Cell<Operation>,
+    /// Process the current operation applies to.
+    processid: OptionalCell<ProcessId>,
+    /// Slot the current operation applies to.
+    slot: Cell<usize>,
+    /// Whether the process must be resumed once the operation finishes.
+    resume: Cell<bool>,
+    /// Whether the header of the checkpoint being restored was written into
+    /// the process.
+    header_restored: Cell<bool>,
+}
+
+impl<'a, S: NonvolatileStorage<'a>, C: ProcessManagementCapability> ProcessCheckpoint<'a, S, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        storage: &'a S,
+        buffer: &'static mut [u8],
+        region_start: usize,
+        slot_len: usize,
+        num_slots: usize,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            storage,
+            buffer: TakeCell::new(buffer),
+            region_start,
+            slot_len,
+            num_slots,
+            capability,
+            client: OptionalCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            processid: OptionalCell::empty(),
+            slot: Cell::new(0),
+            resume: Cell::new(false),
+            header_restored: Cell::new(false),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn ProcessCheckpointClient) {
+        self.client.set(client);
+    }
+
+    /// Save the checkpoint of `processid` into `slot`, replacing the
+    /// checkpoint the slot held. The process is stopped until the checkpoint
+    /// is written.
+    pub fn checkpoint(&self, processid: ProcessId, slot: usize) -> Result<(), ErrorCode> {
+        self.start(processid, slot)?;
+        self.operation.set(Operation::Invalidate);
+        self.write_slot_header(0, 0).inspect_err(|_| {
+            self.end();
+        })
+    }
+
+    /// Restore `processid` from the checkpoint in `slot`. The process is
+    /// stopped until the checkpoint is restored.
+    ///
+    /// The checkpoint must have been taken from the same process binary,
+    /// loaded at the same addresses.
+    pub fn restore(&self, processid: ProcessId, slot: usize) -> Result<(), ErrorCode> {
+        if self.buffer.map_or(0, |buffer| buffer.len()) < CHECKPOINT_HEADER_LEN {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.start(processid, slot)?;
+        self.operation.set(Operation::ReadHeader);
+        self.buffer
+            .take()
+            .map_or(Err(ErrorCode::NOMEM), |buffer| {
+                self.storage
+                    .read(buffer, self.slot_address(), SLOT_HEADER_LEN)
+            })
+            .inspect_err(|_| {
+                self.end();
+            })
+    }
+
+    /// Stop the process and record the operation.
+    fn start(&self, processid: ProcessId, slot: usize) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if slot >= self.num_slots || self.slot_len <= SLOT_HEADER_LEN {
+            return Err(ErrorCode::INVAL);
+        }
+        let was_running = self.kernel.process_map_or_external(
+            Err(ErrorCode::INVAL),
+            processid,
+            |process| match process.get_state() {
+                process::State::Running
+                | process::State::Yielded
+                | process::State::YieldedFor(_) => {
+                    process.stop();
+                    Ok(true)
+                }
+                process::State::Stopped(_) => Ok(false),
+                process::State::Faulted | process::State::Terminated => Err(ErrorCode::OFF),
+            },
+            &self.capability,
+        )?;
+        self.processid.set(processid);
+        self.slot.set(slot);
+        self.resume.set(was_running);
+        self.header_restored.set(false);
+        Ok(())
+    }
+
+    /// Resume the process if it was stopped by this capsule, and end the
+    /// current operation.
+    fn end(&self) -> Option<ProcessId> {
+        self.operation.set(Operation::Idle);
+        self.processid.take().inspect(|&processid| {
+            if self.resume.get() {
+                self.kernel.process_map_or_external(
+                    (),
+                    processid,
+                    |process| process.resume(),
+                    &self.capability,
+                );
+            }
+        })
+    }
+
+    fn checkpoint_done(&self, result: Result<(), ErrorCode>) {
+        if let Some(processid) = self.end() {
+            self.client
+                .map(|client| client.checkpoint_done(processid, result));
+        }
+    }
+
+    fn restore_done(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() && self.header_restored.get() {
+            // The process holds a partially restored checkpoint.
+            self.resume.set(false);
+            self.with_process((), |process| process.try_restart(None));
+        }
+        if let Some(processid) = self.end() {
+            self.client
+                .map(|client| client.restore_done(processid, result));
+        }
+    }
+
+    fn with_process<R>(&self, default: R, closure: impl FnOnce(&dyn Process) -> R) -> R {
+        match self.processid.get() {
+            Some(processid) => {
+                self.kernel
+                    .process_map_or_external(default, processid, closure, &self.capability)
+            }
+            None => default,
+        }
+    }
+
+    fn slot_address(&self) -> usize {
+        self.region_start + self.slot.get() * self.slot_len
+    }
+
+    fn write_slot_header(&self, magic: u32, len: usize) -> Result<(), ErrorCode> {
+        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+            buffer[0..4].copy_from_slice(&magic.to_le_bytes());
+            buffer[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+            self.storage
+                .write(buffer, self.slot_address(), SLOT_HEADER_LEN)
+        })
+    }
+
+    /// Write the part of the checkpoint that starts at `offset`, or commit
+    /// the checkpoint if it was entirely written.
+    fn write_next_chunk(&self, offset: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let len = match self.with_process(Err(ErrorCode::FAIL), |process| {
+            process.read_checkpoint(offset, buffer)
+        }) {
+            Ok(len) => len,
+            Err(e) => {
+                self.buffer.replace(buffer);
+                return Err(e);
+            }
+        };
+        if len == 0 {
+            self.buffer.replace(buffer);
+            self.operation.set(Operation::Commit);
+            return self.write_slot_header(SLOT_MAGIC, offset);
+        }
+        if SLOT_HEADER_LEN + offset + len > self.slot_len {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        self.operation.set(Operation::Write {
+            offset: offset + len,
+        });
+        self.storage
+            .write(buffer, self.slot_address() + SLOT_HEADER_LEN + offset, len)
+    }
+
+    /// Read the part of the checkpoint that starts at `offset`.
+    fn read_next_chunk(&self, offset: usize, len: usize) -> Result<(), ErrorCode> {
+        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+            let chunk = buffer.len().min(len - offset);
+            self.storage.read(
+                buffer,
+                self.slot_address() + SLOT_HEADER_LEN + offset,
+                chunk,
+            )
+        })
+    }
+
+    /// Parse the slot header in `buffer`. Returns the length of the
+    /// checkpoint.
+    fn parse_slot_header(&self, buffer: &[u8]) -> Result<usize, ErrorCode> {
+        let magic = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        let len = u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+        if magic != SLOT_MAGIC
+            || len < CHECKPOINT_HEADER_LEN
+            || len > self.slot_len - SLOT_HEADER_LEN
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(len)
+    }
+
+    /// Write a chunk of the checkpoint that starts at `offset` into the
+    /// process.
+    fn restore_chunk(&self, offset: usize, chunk: &[u8]) -> Result<(), ErrorCode> {
+        self.with_process(Err(ErrorCode::FAIL), |process| {
+            if offset == 0 {
+                // Restore the header on its own, so that it is known whether
+                // the process was modified if restoring fails.
+                process.write_checkpoint(0, &chunk[..CHECKPOINT_HEADER_LEN])?;
+                self.header_restored.set(true);
+                process.write_checkpoint(CHECKPOINT_HEADER_LEN, &chunk[CHECKPOINT_HEADER_LEN..])
+            } else {
+                process.write_checkpoint(offset, chunk)
+            }
+        })
+    }
+}
+
+impl<'a, S: NonvolatileStorage<'a>, C: ProcessManagementCapability> NonvolatileStorageClient
+    for ProcessCheckpoint<'a, S, C>
+{
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let result = match self.operation.get() {
+            Operation::ReadHeader => {
+                let len = self.parse_slot_header(buffer);
+                self.buffer.replace(buffer);
+                len.and_then(|len| {
+                    self.operation.set(Operation::Read { offset: 0, len });
+                    self.read_next_chunk(0, len)
+                })
+            }
+            Operation::Read { offset, len } => {
+                let restored = self.restore_chunk(offset, &buffer[..length]);
+                self.buffer.replace(buffer);
+                restored.and_then(|()| {
+                    let offset = offset + length;
+                    if offset >= len {
+                        self.restore_done(Ok(()));
+                        return Ok(());
+                    }
+                    self.operation.set(Operation::Read { offset, len });
+                    self.read_next_chunk(offset, len)
+                })
+            }
+            _ => {
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.restore_done(Err(e));
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        let result = match self.operation.get() {
+            Operation::Invalidate => self.write_next_chunk(0),
+            Operation::Write { offset } => self.write_next_chunk(offset),
+            Operation::Commit => {
+                self.checkpoint_done(Ok(()));
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            self.checkpoint_done(Err(e));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Software watchdog that monitors the liveness of processes.
+//!
+//! The hardware watchdog only detects a hung kernel, as the kernel loop
+//! tickles it. A process that deadlocks while the kernel keeps running goes
+//! unnoticed. With this capsule, a critical process asks to be monitored with
+//! a period, and must then check in at least once per period. When a process
+//! misses its deadline, it is put in the fault state, so its fault policy
+//! decides whether it is stopped or restarted. A restarted process must ask
+//! to be monitored again.
+//!
+//! The capsule is used as the watchdog of the board: it wraps the hardware
+//! watchdog, and only tickles it while no monitored process is late. If a
+//! late process is not recovered, for example because the alarm can no longer
+//! fire, the hardware watchdog resets the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let process_watchdog = components::process_watchdog::ProcessWatchdogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::process_watchdog::DRIVER_NUM,
+//!     mux_alarm,
+//!     &peripherals.wdt,
+//! )
+//! .finalize(components::process_watchdog_component_static!(
+//!     sam4l::ast::Ast,
+//!     sam4l::wdt::Wdt
+//! ));
+//! ```
+//!
+//! The board then returns `process_watchdog` from `KernelResources::watchdog()`.
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::platform::watchdog::WatchDog;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessWatchdog as usize;
+
+/// Monitoring state of one process.
+pub struct App<T: Ticks> {
+    /// Check-in period, in milliseconds, or 0 if the process is not
+    /// monitored.
+    period_ms: u32,
+    /// When the process last checked in.
+    reference: T,
+    /// Time from the last check-in to the deadline.
+    dt: T,
+}
+
+impl<T: Ticks> Default for App<T> {
+    fn default() -> Self {
+        Self {
+            period_ms: 0,
+            reference: T::from(0),
+            dt: T::from(0),
+        }
+    }
+}
+
+impl<T: Ticks> App<T> {
+    fn is_late(&self, now: T) -> bool {
+        self.period_ms != 0 && now.wrapping_sub(self.reference) >= self.dt
+    }
+}
+
+pub struct ProcessWatchdog<'a, A: Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    /// Hardware watchdog of the board.
+    watchdog: &'a W,
+    apps: Grant<App<A::Ticks>, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// Earliest deadline of the monitored processes, as a reference and a
+    /// time from the reference.
+    deadline: OptionalCell<(A::Ticks, A::Ticks)>,
+    capability: C,
+}
+
+impl<'a, A: Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> ProcessWatchdog<'a, A, W, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        watchdog: &'a W,
+        grant: Grant<App<A::Ticks>, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            watchdog,
+            apps: grant,
+            deadline: OptionalCell::empty(),
+            capability,
+        }
+    }
+
+    /// Returns whether a monitored process missed its deadline.
+    fn late(&self) -> bool {
+        self.deadline.map_or(false, |(reference, dt)| {
+            self.alarm.now().wrapping_sub(reference) >= dt
+        })
+    }
+
+    /// Set the alarm for the earliest deadline of the monitored processes.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let mut earliest: Option<(A::Ticks, A::Ticks)> = None;
+        self.apps.each(|_, app, _| {
+            if app.period_ms == 0 {
+                return;
+            }
+            let remaining = if app.is_late(now) {
+                A::Ticks::from(0)
+            } else {
+                app.dt.wrapping_sub(now.wrapping_sub(app.reference))
+            };
+            if earliest.is_none_or(|(_, dt)| remaining < dt) {
+                earliest = Some((now, remaining));
+            }
+        });
+        match earliest {
+            Some((reference, dt)) => {
+                self.deadline.set((reference, dt));
+                self.alarm.set_alarm(reference, dt);
+            }
+            None => {
+                self.deadline.clear();
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    /// Restart the check-in period of `processid`. The period is set to
+    /// `period_ms` if it is not `None`.
+    fn check_in(&self, processid: ProcessId, period_ms: Option<u32>) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                if let Some(period_ms) = period_ms {
+                    app.period_ms = period_ms;
+                }
+                if app.period_ms == 0 {
+                    return Err(ErrorCode::INVAL);
+                }
+                app.reference = self.alarm.now();
+                app.dt = self.alarm.ticks_from_ms(app.period_ms);
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.arm();
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> AlarmClient
+    for ProcessWatchdog<'a, A, W, C>
+{
+    fn alarm(&self) {
+        // Faulting a process frees its grant, so find and fault the late
+        // processes one at a time.
+        loop {
+            let now = self.alarm.now();
+            let mut late = None;
+            self.apps.each(|processid, app, _| {
+                if late.is_none() && app.is_late(now) {
+                    // Stop monitoring the process even if it cannot be
+                    // faulted, so it does not keep the watchdog from being
+                    // tickled forever.
+                    app.period_ms = 0;
+                    late = Some(processid);
+                }
+            });
+            let Some(processid) = late else {
+                break;
+            };
+            self.kernel.process_map_or_external(
+                (),
+                processid,
+                |process| process.set_fault_state(),
+                &self.capability,
+            );
+        }
+        self.arm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> WatchDog
+    for ProcessWatchdog<'a, A, W, C>
+{
+    fn setup(&self) {
+        self.watchdog.setup();
+    }
+
+    fn tickle(&self) {
+        if !self.late() {
+            self.watchdog.tickle();
+        }
+    }
+
+    fn suspend(&self) {
+        self.watchdog.suspend();
+    }
+
+    fn resume(&self) {
+        self.watchdog.resume();
+    }
+}
+
+/// Provide an interface for userland.
+impl<'a, A: Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> SyscallDriver
+    for ProcessWatchdog<'a, A, W, C>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Start monitoring the process. The process must check in at
+    ///   least every `data1` milliseconds.
+    /// - `2`: Check in.
+    /// - `3`: Stop monitoring the process.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if data1 == 0 || data1 > u32::MAX as usize {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                self.check_in(processid, Some(data1 as u32)).into()
+            }
+
+            2 => self.check_in(processid, None).into(),
+
+            3 => {
+                let result = self
+                    .apps
+                    .enter(processid, |app, _| app.period_ms = 0)
+                    .map_err(ErrorCode::from);
+                self.arm();
+                result.into()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,373 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Publish/subscribe event bus between processes.
+//!
+//! Processes subscribe to topics, identified by 32-bit numbers, and any
+//! process can publish a small message on a topic. The message is copied into
+//! the receive buffer of every process subscribed to the topic, and the
+//! subscriber is notified with an upcall.
+//!
+//! A subscriber receives one message at a time: after handling the message in
+//! its buffer, it acknowledges it, and the next message is copied into the
+//! buffer. Messages published in the meantime wait in a per-subscriber queue
+//! stored in the grant of the subscriber. Each subscriber chooses the depth of
+//! its queue, up to `MAX_QUEUE_DEPTH`. When the queue is full, new messages
+//! are dropped and counted, so that a slow subscriber never blocks publishers
+//! or other subscribers.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pubsub = components::pubsub::PubSubComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pubsub::DRIVER_NUM,
+//! )
+//! .finalize(components::pubsub_component_static!());
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::PubSub as usize;
+
+/// Maximum length of a message.
+pub const MAX_MESSAGE_LEN: usize = 32;
+
+/// Maximum number of topics a process can subscribe to.
+pub const MAX_TOPICS: usize = 8;
+
+/// Maximum number of messages waiting for a subscriber.
+pub const MAX_QUEUE_DEPTH: usize = 4;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Message to publish.
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Message received by a subscriber.
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// A message was copied to the subscriber.
+    pub const MESSAGE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// A message published on a topic.
+#[derive(Clone, Copy, Default)]
+struct Message {
+    topic: u32,
+    length: usize,
+    data: [u8; MAX_MESSAGE_LEN],
+}
+
+pub struct App {
+    /// Topics the process subscribed to.
+    topics: [Option<u32>; MAX_TOPICS],
+    /// Messages waiting to be copied to the process, oldest first.
+    queue: [Message; MAX_QUEUE_DEPTH],
+    /// Index of the oldest message in `queue`.
+    head: usize,
+    /// Number of messages in `queue`.
+    queued: usize,
+    /// Maximum number of messages in `queue`.
+    depth: usize,
+    /// Whether the message buffer holds a message that was not acknowledged.
+    pending: bool,
+    /// Number of messages dropped because the queue was full or the process
+    /// had no buffer to receive them.
+    dropped: u32,
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            topics: [None; MAX_TOPICS],
+            queue: [Message::default(); MAX_QUEUE_DEPTH],
+            head: 0,
+            queued: 0,
+            depth: MAX_QUEUE_DEPTH,
+            pending: false,
+            dropped: 0,
+        }
+    }
+}
+
+impl App {
+    fn is_subscribed(&self, topic: u32) -> bool {
+        self.topics.contains(&Some(topic))
+    }
+
+    fn push(&mut self, message: Message) -> Result<(), ErrorCode> {
+        if self.queued >= self.depth {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.queue[(self.head + self.queued) % MAX_QUEUE_DEPTH] = message;
+        self.queued += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Message> {
+        if self.queued == 0 {
+            return None;
+        }
+        let message = self.queue[self.head];
+        self.head = (self.head + 1) % MAX_QUEUE_DEPTH;
+        self.queued -= 1;
+        Some(message)
+    }
+}
+
+pub struct PubSub {
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl PubSub {
+    pub fn new(
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> PubSub {
+        PubSub { apps: grant }
+    }
+
+    /// Copies the oldest queued message into the message buffer of the
+    /// process, if the buffer is free. Fails if the process has no buffer to
+    /// receive the message, in which case the message is removed from the
+    /// queue.
+    fn deliver_next(app: &mut App, kernel_data: &GrantKernelData) -> Result<(), ErrorCode> {
+        if app.pending {
+            return Ok(());
+        }
+        let Some(message) = app.pop() else {
+            return Ok(());
+        };
+        let buffer = kernel_data.get_readwrite_processbuffer(rw_allow::MESSAGE)?;
+        if buffer.len() == 0 {
+            return Err(ErrorCode::RESERVE);
+        }
+        let length = buffer.mut_enter(|buffer| {
+            let length = message.length.min(buffer.len());
+            buffer[..length].copy_from_slice(&message.data[..length]);
+            length
+        })?;
+        app.pending = true;
+        let _ = kernel_data.schedule_upcall(
+            upcall::MESSAGE,
+            (message.topic as usize, length, app.queued),
+        );
+        Ok(())
+    }
+
+    fn subscribe(&self, processid: ProcessId, topic: u32) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                if app.is_subscribed(topic) {
+                    return Err(ErrorCode::ALREADY);
+                }
+                let slot = app
+                    .topics
+                    .iter_mut()
+                    .find(|slot| slot.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                *slot = Some(topic);
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn unsubscribe(&self, processid: ProcessId, topic: u32) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                let slot = app
+                    .topics
+                    .iter_mut()
+                    .find(|slot| **slot == Some(topic))
+                    .ok_or(ErrorCode::INVAL)?;
+                *slot = None;
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Publishes the first `length` bytes of the message buffer of the process
+    /// on `topic`. Returns the number of subscribers that received or queued
+    /// the message, and the number of subscribers that dropped it.
+    fn publish(
+        &self,
+        processid: ProcessId,
+        topic: u32,
+        length: usize,
+    ) -> Result<(u32, u32), ErrorCode> {
+        if length > MAX_MESSAGE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        let mut message = Message {
+            topic,
+            length,
+            data: [0; MAX_MESSAGE_LEN],
+        };
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::MESSAGE)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            if length > buffer.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            buffer[..length].copy_to_slice(&mut message.data[..length]);
+                            Ok(())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::INVAL))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        let mut received = 0;
+        let mut dropped = 0;
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if !app.is_subscribed(topic) {
+                    return;
+                }
+                if app.pending || app.queued > 0 {
+                    if app.push(message).is_err() {
+                        app.dropped = app.dropped.saturating_add(1);
+                        dropped += 1;
+                        return;
+                    }
+                } else {
+                    // Bypass the queue so that subscribers with a depth of 0
+                    // still receive messages.
+                    let head = app.head;
+                    app.queue[head] = message;
+                    app.queued = 1;
+                }
+                match Self::deliver_next(app, kernel_data) {
+                    Ok(()) => received += 1,
+                    Err(_) => {
+                        // The subscriber has no buffer to receive messages.
+                        app.dropped = app.dropped.saturating_add(1);
+                        dropped += 1;
+                    }
+                }
+            });
+        }
+        Ok((received, dropped))
+    }
+
+    /// Frees the message buffer of the process, and copies the next queued
+    /// message into it.
+    fn acknowledge(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                if !app.pending {
+                    return Err(ErrorCode::INVAL);
+                }
+                app.pending = false;
+                while app.queued > 0 {
+                    if Self::deliver_next(app, kernel_data).is_ok() {
+                        break;
+                    }
+                    // The message buffer was withdrawn.
+                    app.dropped = app.dropped.saturating_add(1);
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn set_queue_depth(&self, processid: ProcessId, depth: usize) -> Result<(), ErrorCode> {
+        if depth > MAX_QUEUE_DEPTH {
+            return Err(ErrorCode::INVAL);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                // Drop the newest messages that no longer fit.
+                let excess = app.queued.saturating_sub(depth);
+                app.queued -= excess;
+                app.dropped = app.dropped.saturating_add(excess as u32);
+                app.depth = depth;
+            })
+            .map_err(ErrorCode::from)
+    }
+}
+
+impl SyscallDriver for PubSub {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Subscribe to topic `data1`.
+    /// - `2`: Unsubscribe from topic `data1`.
+    /// - `3`: Publish the first `data2` bytes of read-only allow 0 on topic
+    ///   `data1`. Returns the number of subscribers that received or queued
+    ///   the message and the number of subscribers that dropped it.
+    /// - `4`: Acknowledge the message in read-write allow 0, so that the next
+    ///   queued message can be copied into it.
+    /// - `5`: Set the maximum number of messages queued for the process to
+    ///   `data1`.
+    /// - `6`: Return the number of messages the process dropped.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.subscribe(processid, data1 as u32).into(),
+
+            2 => self.unsubscribe(processid, data1 as u32).into(),
+
+            3 => match self.publish(processid, data1 as u32, data2) {
+                Ok((received, dropped)) => CommandReturn::success_u32_u32(received, dropped),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            4 => self.acknowledge(processid).into(),
+
+            5 => self.set_queue_depth(processid, data1).into(),
+
+            6 => self
+                .apps
+                .enter(processid, |app, _| CommandReturn::success_u32(app.dropped))
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -999,17 +999,25 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                 if r1 == SUCCESS_STATUS {
                     if count <= 1 {
                         let bytes_written = self.client_buffer.map_or(0, |buffer| {
-                            // copy over data from client buffer
+                            // copy over data from client buffer, starting at
+                            // the offset of the block
                             // Limit to minimum length between write_buffer,
                             // buffer, and 512 (block size)
-                            for (write_byte, &client_byte) in
-                                write_buffer.iter_mut().skip(1).zip(buffer.iter()).take(512)
+                            let offset = self.client_offset.get();
+                            for (write_byte, &client_byte) in write_buffer
+                                .iter_mut()
+                                .skip(1)
+                                .zip(buffer.iter().skip(offset))
+                                .take(512)
                             {
                                 *write_byte = client_byte;
                             }
 
                             // calculate number of bytes written
-                            cmp::min(write_buffer.len(), cmp::min(buffer.len(), 512))
+                            cmp::min(
+                                write_buffer.len(),
+                                cmp::min(buffer.len().saturating_sub(offset), 512),
+                            )
                         });
 
                         // set a known value for remaining bytes
@@ -1365,52 +1373,68 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         sector: u32,
         count: u32,
     ) -> Result<(), ErrorCode> {
-        // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
-
-                                self.state.set(SpiState::StartWriteBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
+        if count != 1 {
+            // can't write multiple blocks yet
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.write_block_at(buffer, 0, sector)
+            .map_err(|(error_code, _buffer)| error_code)
+    }
 
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
-        } else {
+    /// Writes the block at `offset` in `buffer` to `sector`. `write_done`
+    /// returns the whole buffer, and the buffer is returned if the write
+    /// cannot start.
+    pub fn write_block_at(
+        &self,
+        buffer: &'static mut [u8],
+        offset: usize,
+        sector: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if !self.is_installed() {
             // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            return Err((ErrorCode::UNINSTALLED, buffer));
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err((ErrorCode::RESERVE, buffer));
+        }
+        if offset >= buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
         }
+        let Some(txbuffer) = self.txbuffer.take() else {
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+        let Some(rxbuffer) = self.rxbuffer.take() else {
+            self.txbuffer.replace(txbuffer);
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(offset);
+
+        // convert block address to byte address for non-block access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
+
+        self.state.set(SpiState::StartWriteBlocks { count: 1 });
+        self.send_command(SDCmd::CMD24_WriteSingle, address, txbuffer, rxbuffer, 10);
+
+        // command started successfully
+        Ok(())
+    }
+
+    /// Whether the card can start an operation: no operation is using the
+    /// SPI buffers.
+    pub fn is_idle(&self) -> bool {
+        self.txbuffer.is_some() && self.rxbuffer.is_some()
+    }
+
+    /// Takes back the buffer of a read or write that ended with an error.
+    pub fn take_client_buffer(&self) -> Option<&'static mut [u8]> {
+        self.client_buffer.take()
     }
 }
 
@@ -1465,6 +1489,202 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
     }
 }
 
+/// Block operation waiting for or using the SD card.
+#[derive(Clone, Copy, PartialEq)]
+enum BlockOperation {
+    Read { address: usize, length: usize },
+    Write { address: usize, length: usize },
+}
+
+/// Nonvolatile storage interface to an SD card.
+///
+/// This allows capsules built on the generic `NonvolatileStorage` interface,
+/// such as a filesystem, to use an SD card. Addresses and lengths must be
+/// multiples of the 512 byte block size. Writes of several blocks are done
+/// one block at a time. The card is initialized on the first access.
+///
+/// This replaces `SDCardDriver` as the client of the `SDCard`.
+pub struct SDCardNonvolatileStorage<'a, A: hil::time::Alarm<'a>> {
+    sdcard: &'a SDCard<'a, A>,
+    client: OptionalCell<&'a dyn hil::nonvolatile_storage::NonvolatileStorageClient>,
+    // Buffer of an operation waiting for the card to be initialized.
+    buffer: TakeCell<'static, [u8]>,
+    operation: OptionalCell<BlockOperation>,
+    // Number of bytes of the current write already written.
+    written: Cell<usize>,
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardNonvolatileStorage<'a, A> {
+    pub fn new(sdcard: &'a SDCard<'a, A>) -> SDCardNonvolatileStorage<'a, A> {
+        SDCardNonvolatileStorage {
+            sdcard,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            operation: OptionalCell::empty(),
+            written: Cell::new(0),
+        }
+    }
+
+    /// Starts `operation`, or initializes the card first if needed.
+    ///
+    /// Returns `RESERVE` if the card is used by another operation, in which
+    /// case the card would not give the buffer back. The buffer is returned
+    /// with the error unless the card kept it.
+    fn start(
+        &self,
+        buffer: &'static mut [u8],
+        operation: BlockOperation,
+    ) -> Result<(), (ErrorCode, Option<&'static mut [u8]>)> {
+        if !self.sdcard.is_installed() {
+            return Err((ErrorCode::UNINSTALLED, Some(buffer)));
+        }
+        if !self.sdcard.is_idle() {
+            return Err((ErrorCode::RESERVE, Some(buffer)));
+        }
+
+        self.operation.set(operation);
+        let res = if !self.sdcard.is_initialized() {
+            self.buffer.replace(buffer);
+            self.sdcard.initialize().map_err(|error_code| {
+                // The buffer was stored above.
+                (error_code, self.buffer.take())
+            })
+        } else {
+            match operation {
+                // The card is idle, so it cannot fail after taking the
+                // buffer.
+                BlockOperation::Read { address, length } => self
+                    .sdcard
+                    .read_blocks(
+                        buffer,
+                        (address / SD_BLOCK_SIZE) as u32,
+                        (length / SD_BLOCK_SIZE) as u32,
+                    )
+                    .map_err(|error_code| (error_code, None)),
+                BlockOperation::Write { address, .. } => self
+                    .sdcard
+                    .write_block_at(buffer, 0, (address / SD_BLOCK_SIZE) as u32)
+                    .map_err(|(error_code, buffer)| (error_code, Some(buffer))),
+            }
+        };
+        res.inspect_err(|_| self.operation.clear())
+    }
+
+    fn check_alignment(&self, address: usize, length: usize) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            Err(ErrorCode::BUSY)
+        } else if address % SD_BLOCK_SIZE != 0 || length % SD_BLOCK_SIZE != 0 || length == 0 {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Size of the blocks accessed by `SDCardNonvolatileStorage`.
+const SD_BLOCK_SIZE: usize = 512;
+
+impl<'a, A: hil::time::Alarm<'a>> hil::nonvolatile_storage::NonvolatileStorage<'a>
+    for SDCardNonvolatileStorage<'a, A>
+{
+    fn set_client(&self, client: &'a dyn hil::nonvolatile_storage::NonvolatileStorageClient) {
+        self.client.set(client);
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.check_alignment(address, length)?;
+        if buffer.len() < length {
+            return Err(ErrorCode::SIZE);
+        }
+        self.start(buffer, BlockOperation::Read { address, length })
+            .map_err(|(error_code, _buffer)| error_code)
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.check_alignment(address, length)?;
+        if buffer.len() < length {
+            return Err(ErrorCode::SIZE);
+        }
+        self.written.set(0);
+        self.start(buffer, BlockOperation::Write { address, length })
+            .map_err(|(error_code, _buffer)| error_code)
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardNonvolatileStorage<'a, A> {
+    fn card_detection_changed(&self, _installed: bool) {}
+
+    fn init_done(&self, _block_size: u32, _total_size: u64) {
+        // Start the operation that was waiting for the initialization.
+        self.buffer.take().map(|buffer| {
+            self.operation.take().map(|operation| {
+                if let Err((_, Some(buffer))) = self.start(buffer, operation) {
+                    self.client.map(move |client| match operation {
+                        BlockOperation::Read { .. } => client.read_done(buffer, 0),
+                        BlockOperation::Write { .. } => client.write_done(buffer, 0),
+                    });
+                }
+            });
+        });
+    }
+
+    fn read_done(&self, data: &'static mut [u8], len: usize) {
+        self.operation.clear();
+        self.client.map(move |client| client.read_done(data, len));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        let Some(BlockOperation::Write { address, length }) = self.operation.get() else {
+            return;
+        };
+        let written = self.written.get() + SD_BLOCK_SIZE;
+        self.written.set(written);
+        if written < length {
+            // The SD card writes one block at a time.
+            let sector = ((address + written) / SD_BLOCK_SIZE) as u32;
+            match self.sdcard.write_block_at(buffer, written, sector) {
+                Ok(()) => return,
+                Err((_, buffer)) => {
+                    self.operation.clear();
+                    self.client
+                        .map(move |client| client.write_done(buffer, written));
+                    return;
+                }
+            }
+        }
+        self.operation.clear();
+        self.client
+            .map(move |client| client.write_done(buffer, length));
+    }
+
+    fn error(&self, _error: u32) {
+        // Give the buffer back to the client with nothing read, or with the
+        // blocks written before the error.
+        let buffer = self
+            .buffer
+            .take()
+            .or_else(|| self.sdcard.take_client_buffer());
+        self.operation.take().map(|operation| {
+            buffer.map(|buffer| {
+                self.client.map(move |client| match operation {
+                    BlockOperation::Read { .. } => client.read_done(buffer, 0),
+                    BlockOperation::Write { .. } => client.write_done(buffer, self.written.get()),
+                });
+            });
+        });
+    }
+}
+
 /// Application driver for SD Card capsule.
 ///
 /// This is used if the SDCard is going to be attached directly to userspace
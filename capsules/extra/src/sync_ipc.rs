@@ -0,0 +1,537 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Request/response inter-process communication.
+//!
+//! Processes register as named services, and other processes call them: a
+//! call copies a request from the client into the service, and the service's
+//! reply is copied back into the client. Clients typically block on a call
+//! with `yield-wait-for` on the response upcall.
+//!
+//! Buffers are always copied, so neither process ever gets access to the
+//! memory of the other.
+//!
+//! A service handles one request at a time. Calls to a busy service are queued
+//! in arrival order, and a service accepts at most `MAX_QUEUED_CALLS` queued
+//! calls: further calls fail with `NOMEM` until the service catches up.
+//!
+//! A registration lives in the grant of the service, so it ends when the
+//! service exits or restarts. Calls to a service that is no longer registered
+//! fail with `NODEVICE`.
+//!
+//! Only processes with a fixed `ShortId`, i.e. with an AppID assigned by the
+//! board's verification policy, can register services, and a service name can
+//! only be registered by one process at a time. Discovering a service returns
+//! its `ShortId`, so clients can check which application provides it.
+//!
+//! Which clients may discover and call a service is decided by the same
+//! [`IPCPolicy`] as for the kernel IPC driver. By default, services restrict
+//! their clients with the IPC clients TBF header, and boards can supply their
+//! own policy with [`SyncIpc::set_policy`].
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sync_ipc = components::sync_ipc::SyncIpcComponent::new(
+//!     board_kernel,
+//!     capsules_extra::sync_ipc::DRIVER_NUM,
+//! )
+//! .finalize(components::sync_ipc_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::ipc::{self, IPCPolicy, TbfHeaderIPCPolicy};
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SyncIpc as usize;
+
+/// Maximum length of a service name.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Maximum number of calls waiting for a service.
+pub const MAX_QUEUED_CALLS: usize = 4;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Name of the service to register or discover.
+    pub const NAME: usize = 0;
+    /// Request sent by a client.
+    pub const REQUEST: usize = 1;
+    /// Reply sent by a service.
+    pub const REPLY: usize = 2;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Response received by a client.
+    pub const RESPONSE: usize = 0;
+    /// Request received by a service.
+    pub const REQUEST: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// A call of the client completed.
+    pub const RESPONSE: usize = 0;
+    /// A request was copied to the service.
+    pub const REQUEST: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// A call from a client to a service.
+#[derive(Clone, Copy)]
+struct Call {
+    service: ProcessId,
+    /// Length of the request.
+    length: usize,
+    /// Order of the call among all calls, to serve them in arrival order.
+    sequence: u32,
+    /// Whether the request was copied to the service.
+    delivered: bool,
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Name of the service this process provides, if any.
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    /// Call this process made as a client.
+    call: Option<Call>,
+    /// Client whose request this process is handling as a service.
+    serving: Option<ProcessId>,
+}
+
+impl App {
+    fn is_service(&self) -> bool {
+        self.name_len > 0
+    }
+
+    fn has_name(&self, name: &[u8]) -> bool {
+        self.is_service() && &self.name[..self.name_len] == name
+    }
+}
+
+pub struct SyncIpc {
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Sequence number of the next call.
+    sequence: Cell<u32>,
+    /// Policy deciding which clients may connect to each service.
+    policy: Cell<&'static dyn IPCPolicy>,
+}
+
+/// Returns the `ShortId` of the process as a number, 0 if it has none.
+fn short_id_value(processid: ProcessId) -> usize {
+    match processid.short_app_id() {
+        ShortId::LocallyUnique => 0,
+        ShortId::Fixed(id) => u32::from(id) as usize,
+    }
+}
+
+/// Copies as much of the `src` read-only allow as fits in the `dst`
+/// read-write allow, up to `length` bytes. Returns the number of bytes copied.
+fn copy_buffer(
+    src_data: &GrantKernelData,
+    src: usize,
+    dst_data: &GrantKernelData,
+    dst: usize,
+    length: usize,
+) -> Result<usize, ErrorCode> {
+    let src = src_data.get_readonly_processbuffer(src)?;
+    let dst = dst_data.get_readwrite_processbuffer(dst)?;
+    src.enter(|src| {
+        dst.mut_enter(|dst| {
+            let length = length.min(src.len()).min(dst.len());
+            for (d, s) in dst.iter().zip(src.iter()).take(length) {
+                d.set(s.get());
+            }
+            length
+        })
+    })?
+    .map_err(ErrorCode::from)
+}
+
+impl SyncIpc {
+    pub fn new(
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> SyncIpc {
+        SyncIpc {
+            apps: grant,
+            sequence: Cell::new(0),
+            policy: Cell::new(&TbfHeaderIPCPolicy {}),
+        }
+    }
+
+    /// Replace the default [`TbfHeaderIPCPolicy`] with a board-specific
+    /// policy.
+    pub fn set_policy(&self, policy: &'static dyn IPCPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Copies the name in the `NAME` allow of the process into `name`, and
+    /// returns its length.
+    fn read_name(
+        &self,
+        processid: ProcessId,
+        name: &mut [u8; MAX_NAME_LEN],
+    ) -> Result<usize, ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            let len = buffer.len();
+                            if len == 0 || len > MAX_NAME_LEN {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            buffer.copy_to_slice(&mut name[..len]);
+                            Ok(len)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::INVAL))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Returns the service registered with `name`.
+    fn find_service(&self, name: &[u8]) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| app.has_name(name).then_some(processid))
+        })
+    }
+
+    /// Returns whether `service` is alive and still registered as a service.
+    fn is_registered(&self, service: ProcessId) -> bool {
+        self.apps
+            .iter()
+            .any(|app| app.processid() == service && app.enter(|app, _| app.is_service()))
+    }
+
+    /// Returns the service whose process identifier is `handle`.
+    fn find_service_by_handle(&self, handle: usize) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| (app.is_service() && processid.id() == handle).then_some(processid))
+        })
+    }
+
+    /// Returns the number of calls waiting for `service`, and the client of
+    /// the oldest one.
+    fn queued_calls(&self, service: ProcessId) -> (usize, Option<ProcessId>) {
+        let now = self.sequence.get();
+        let mut count = 0;
+        let mut oldest: Option<(u32, ProcessId)> = None;
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            app.enter(|app, _| {
+                if let Some(call) = app.call {
+                    if call.service == service && !call.delivered {
+                        count += 1;
+                        let age = now.wrapping_sub(call.sequence);
+                        if oldest.is_none_or(|(oldest_age, _)| age > oldest_age) {
+                            oldest = Some((age, processid));
+                        }
+                    }
+                }
+            });
+        }
+        (count, oldest.map(|(_, processid)| processid))
+    }
+
+    /// Completes the call of `client` with `status` and `length`.
+    fn complete_call(&self, client: ProcessId, status: Result<(), ErrorCode>, length: usize) {
+        let _ = self.apps.enter(client, |app, kernel_data| {
+            if app.call.take().is_some() {
+                let _ = kernel_data.schedule_upcall(
+                    upcall::RESPONSE,
+                    (kernel::errorcode::into_statuscode(status), length, 0),
+                );
+            }
+        });
+    }
+
+    /// Copies the oldest queued request to `service` if it is idle.
+    fn deliver_next(&self, service: ProcessId) {
+        loop {
+            let idle = self
+                .apps
+                .enter(service, |app, _| app.is_service() && app.serving.is_none())
+                .unwrap_or(false);
+            if !idle {
+                return;
+            }
+            let Some(client) = self.queued_calls(service).1 else {
+                return;
+            };
+
+            let delivered = self
+                .apps
+                .enter(service, |service_app, service_data| {
+                    self.apps
+                        .enter(client, |client_app, client_data| {
+                            let Some(call) = client_app.call.as_mut() else {
+                                return Err(ErrorCode::FAIL);
+                            };
+                            let length = copy_buffer(
+                                client_data,
+                                ro_allow::REQUEST,
+                                service_data,
+                                rw_allow::REQUEST,
+                                call.length,
+                            )?;
+                            if length < call.length {
+                                // The request does not fit in the service's
+                                // buffer.
+                                return Err(ErrorCode::SIZE);
+                            }
+                            call.delivered = true;
+                            service_app.serving = Some(client);
+                            let _ = service_data.schedule_upcall(
+                                upcall::REQUEST,
+                                (length, short_id_value(client), 0),
+                            );
+                            Ok(())
+                        })
+                        .unwrap_or(Err(ErrorCode::FAIL))
+                })
+                .unwrap_or(Err(ErrorCode::FAIL));
+
+            match delivered {
+                Ok(()) => return,
+                // Fail the call and try the next one.
+                Err(e) => self.complete_call(client, Err(e), 0),
+            }
+        }
+    }
+
+    /// Fails the calls to services that exited, restarted or unregistered,
+    /// as their clients would otherwise wait for a response forever.
+    fn fail_orphaned_calls(&self) {
+        for app in self.apps.iter() {
+            let client = app.processid();
+            let service = app.enter(|app, _| app.call.map(|call| call.service));
+            if service.is_some_and(|service| !self.is_registered(service)) {
+                self.complete_call(client, Err(ErrorCode::NODEVICE), 0);
+            }
+        }
+    }
+
+    fn register(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if short_id_value(processid) == 0 {
+            // Services must be identified by an AppID.
+            return Err(ErrorCode::RESERVE);
+        }
+        let mut name = [0; MAX_NAME_LEN];
+        let len = self.read_name(processid, &mut name)?;
+        match self.find_service(&name[..len]) {
+            Some(owner) if owner != processid => Err(ErrorCode::ALREADY),
+            _ => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.name = name;
+                    app.name_len = len;
+                })
+                .map_err(ErrorCode::from),
+        }
+    }
+
+    fn unregister(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let was_service = self
+            .apps
+            .enter(processid, |app, _| {
+                let was_service = app.is_service();
+                app.name_len = 0;
+                app.serving = None;
+                was_service
+            })
+            .map_err(ErrorCode::from)?;
+        if !was_service {
+            return Err(ErrorCode::INVAL);
+        }
+        self.fail_orphaned_calls();
+        Ok(())
+    }
+
+    fn call(&self, processid: ProcessId, handle: usize, length: usize) -> Result<(), ErrorCode> {
+        let service = self
+            .find_service_by_handle(handle)
+            .ok_or(ErrorCode::INVAL)?;
+        if service == processid {
+            return Err(ErrorCode::INVAL);
+        }
+        ipc::check_connection(self.policy.get(), processid, service)?;
+        if self.queued_calls(service).0 >= MAX_QUEUED_CALLS {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        let sequence = self.sequence.get();
+        self.apps
+            .enter(processid, |app, _| {
+                if app.call.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                app.call = Some(Call {
+                    service,
+                    length,
+                    sequence,
+                    delivered: false,
+                });
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.sequence.set(sequence.wrapping_add(1));
+
+        self.deliver_next(service);
+        Ok(())
+    }
+
+    fn reply(&self, processid: ProcessId, length: usize) -> Result<(), ErrorCode> {
+        let client = self
+            .apps
+            .enter(processid, |app, _| app.serving.take())
+            .map_err(ErrorCode::from)?
+            .ok_or(ErrorCode::INVAL)?;
+
+        // The client may have cancelled its call or exited, in which case the
+        // reply is dropped.
+        let copied = self
+            .apps
+            .enter(processid, |_, service_data| {
+                self.apps.enter(client, |client_app, client_data| {
+                    client_app.call.filter(|call| call.service == processid)?;
+                    Some(copy_buffer(
+                        service_data,
+                        ro_allow::REPLY,
+                        client_data,
+                        rw_allow::RESPONSE,
+                        length,
+                    ))
+                })
+            })
+            .ok()
+            .and_then(|copied| copied.ok().flatten());
+        match copied {
+            Some(Ok(copied)) if copied < length => {
+                self.complete_call(client, Err(ErrorCode::SIZE), copied)
+            }
+            Some(Ok(copied)) => self.complete_call(client, Ok(()), copied),
+            Some(Err(e)) => self.complete_call(client, Err(e), 0),
+            None => {}
+        }
+
+        self.deliver_next(processid);
+        Ok(())
+    }
+
+    fn cancel(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let call = self
+            .apps
+            .enter(processid, |app, _| app.call.take())
+            .map_err(ErrorCode::from)?
+            .ok_or(ErrorCode::INVAL)?;
+        if call.delivered {
+            // Let the service accept another request; its reply to this call
+            // will be dropped.
+            let _ = self.apps.enter(call.service, |app, _| {
+                if app.serving == Some(processid) {
+                    app.serving = None;
+                }
+            });
+            self.deliver_next(call.service);
+        }
+        Ok(())
+    }
+}
+
+impl SyscallDriver for SyncIpc {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Register the calling process as the service named in read-only
+    ///   allow 0.
+    /// - `2`: Unregister the service of the calling process, failing the calls
+    ///   to it with `NODEVICE`.
+    /// - `3`: Discover the service named in read-only allow 0. Returns its
+    ///   handle and its `ShortId`, or `NODEVICE` if there is no such service
+    ///   or the process may not connect to it.
+    /// - `4`: Call the service whose handle is `data1` with the first `data2`
+    ///   bytes of read-only allow 1. The response is copied to read-write
+    ///   allow 0.
+    /// - `5`: Reply to the request being handled with the first `data1` bytes
+    ///   of read-only allow 2.
+    /// - `6`: Cancel the call of the calling process.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        // Services that exited or restarted since the last command may have
+        // left calls behind.
+        self.fail_orphaned_calls();
+
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.register(processid).into(),
+
+            2 => self.unregister(processid).into(),
+
+            3 => {
+                let mut name = [0; MAX_NAME_LEN];
+                match self.read_name(processid, &mut name) {
+                    Ok(len) => match self.find_service(&name[..len]).filter(|service| {
+                        ipc::check_connection(self.policy.get(), processid, *service).is_ok()
+                    }) {
+                        Some(service) => CommandReturn::success_u32_u32(
+                            service.id() as u32,
+                            short_id_value(service) as u32,
+                        ),
+                        None => CommandReturn::failure(ErrorCode::NODEVICE),
+                    },
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            4 => self.call(processid, data1, data2).into(),
+
+            5 => self.reply(processid, data1).into(),
+
+            6 => self.cancel(processid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,523 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Tests recovery of a log after power loss and corruption.
+//!
+//! The log is stored on the board's flash through `FaultInjectingFlash`,
+//! which can simulate a power loss in the middle of a page write: the page is
+//! written with only its start programmed and the rest left erased, and the
+//! write never completes for the log. A power cycle is simulated by creating
+//! a new `Log` over the same volume, which reconstructs the log from flash.
+//!
+//! The test starts by erasing the log, then:
+//! 1. Appends entries spanning more than one page, syncs, and checks that they
+//!    are all read back after a power cycle.
+//! 2. Appends two entries and loses power while syncing, in the middle of the
+//!    second entry. After a power cycle, the first entry must be read back
+//!    and the torn entry skipped.
+//! 3. Appends an entry after the recovery, syncs, and checks that it follows
+//!    the intact entries after another power cycle.
+//! 4. Corrupts an entry of the first page and checks that only this entry is
+//!    skipped.
+//!
+//! The volume must hold at least three flash pages. The test prints
+//! `LogRecoveryTest: passed` when it succeeds, and panics otherwise. See
+//! `boards/imix/src/test/log_recovery_test.rs` for an example.
+
+use core::cell::Cell;
+use core::mem::MaybeUninit;
+
+use crate::log::{Log, ENTRY_HEADER_SIZE, PAGE_HEADER_SIZE};
+use kernel::debug;
+use kernel::hil::flash::{self, Flash};
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Number of times the log is created, once per simulated boot.
+pub const BOOTS: usize = 5;
+/// Size of the buffer used to append and read entries.
+pub const BUFFER_LEN: usize = 32;
+
+pub type TestLog<F> = Log<'static, FaultInjectingFlash<'static, F>>;
+
+/// Client notified when a fault was written to flash.
+pub trait FaultClient {
+    fn fault_injected(&self);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    /// An operation of the client is in progress.
+    Forwarded,
+    /// A torn or corrupted page is being written.
+    Fault,
+}
+
+/// Flash wrapper that can write torn and corrupted pages.
+pub struct FaultInjectingFlash<'a, F: Flash + 'static> {
+    flash: &'a F,
+    client: OptionalCell<&'a dyn flash::Client<FaultInjectingFlash<'a, F>>>,
+    fault_client: OptionalCell<&'a dyn FaultClient>,
+    operation: Cell<Operation>,
+    /// Buffer for the pages written with a fault.
+    page: TakeCell<'static, F::Page>,
+    /// Page buffers of the writes interrupted by a power loss.
+    lost_page: TakeCell<'static, F::Page>,
+    /// Number of bytes programmed by the next page write before power is lost.
+    tear_after: OptionalCell<usize>,
+}
+
+impl<'a, F: Flash + 'static> FaultInjectingFlash<'a, F> {
+    pub fn new(flash: &'a F, page: &'static mut F::Page) -> Self {
+        Self {
+            flash,
+            client: OptionalCell::empty(),
+            fault_client: OptionalCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            page: TakeCell::new(page),
+            lost_page: TakeCell::empty(),
+            tear_after: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_fault_client(&self, client: &'a dyn FaultClient) {
+        self.fault_client.set(client);
+    }
+
+    /// Loses power after programming `length` bytes of the next page write.
+    fn tear_next_write(&self, length: usize) {
+        self.tear_after.set(length);
+    }
+
+    /// Writes the page holding `content`, with the bits of the byte at
+    /// `offset` flipped.
+    fn corrupt(&self, page_number: usize, content: &[u8], offset: usize) -> Result<(), ErrorCode> {
+        let page = self.page.take().ok_or(ErrorCode::BUSY)?;
+        let buffer = page.as_mut();
+        buffer.copy_from_slice(content);
+        buffer[offset] = !buffer[offset];
+        self.write_fault(page_number, page)
+    }
+
+    fn write_fault(&self, page_number: usize, page: &'static mut F::Page) -> Result<(), ErrorCode> {
+        self.operation.set(Operation::Fault);
+        self.flash
+            .write_page(page_number, page)
+            .map_err(|(error, page)| {
+                self.operation.set(Operation::Idle);
+                self.page.replace(page);
+                error
+            })
+    }
+}
+
+impl<F: Flash + 'static> Flash for FaultInjectingFlash<'_, F> {
+    type Page = F::Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.operation.set(Operation::Forwarded);
+        self.flash.read_page(page_number, buf).inspect_err(|_| {
+            self.operation.set(Operation::Idle);
+        })
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, buf));
+        }
+
+        match self.tear_after.take() {
+            Some(length) => {
+                // The page is programmed up to `length`, the rest stays erased.
+                // The write never completes for the client.
+                let Some(page) = self.page.take() else {
+                    return Err((ErrorCode::BUSY, buf));
+                };
+                let content = page.as_mut();
+                content[..length].copy_from_slice(&buf.as_mut()[..length]);
+                content[length..].fill(0xFF);
+                self.lost_page.replace(buf);
+                self.write_fault(page_number, page)
+                    .map_err(|error| (error, self.lost_page.take().unwrap()))
+            }
+            None => {
+                self.operation.set(Operation::Forwarded);
+                self.flash.write_page(page_number, buf).inspect_err(|_| {
+                    self.operation.set(Operation::Idle);
+                })
+            }
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.operation.set(Operation::Forwarded);
+        self.flash.erase_page(page_number).inspect_err(|_| {
+            self.operation.set(Operation::Idle);
+        })
+    }
+}
+
+impl<'a, F: Flash + 'static, C: flash::Client<Self>> flash::HasClient<'a, C>
+    for FaultInjectingFlash<'a, F>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<F: Flash + 'static> flash::Client<F> for FaultInjectingFlash<'_, F> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, result: Result<(), flash::Error>) {
+        self.operation.set(Operation::Idle);
+        self.client
+            .map(move |client| client.read_complete(read_buffer, result));
+    }
+
+    fn write_complete(&self, write_buffer: &'static mut F::Page, result: Result<(), flash::Error>) {
+        match self.operation.replace(Operation::Idle) {
+            Operation::Fault => {
+                self.page.replace(write_buffer);
+                if result.is_err() {
+                    panic!("FaultInjectingFlash: writing fault failed: {:?}", result);
+                }
+                self.fault_client.map(|client| client.fault_injected());
+            }
+            _ => {
+                self.client
+                    .map(move |client| client.write_complete(write_buffer, result));
+            }
+        }
+    }
+
+    fn erase_complete(&self, result: Result<(), flash::Error>) {
+        self.operation.set(Operation::Idle);
+        self.client.map(|client| client.erase_complete(result));
+    }
+}
+
+/// Stages of the test, each ending with a power cycle or a check.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Stage {
+    /// Erase the log left by previous runs.
+    Erase,
+    /// Append the first entries and sync.
+    Fill,
+    /// Read back the first entries.
+    CheckFill,
+    /// Append two entries, losing power while syncing the second one.
+    Tear,
+    /// Read back the entries preceding the torn one.
+    CheckTear,
+    /// Append an entry after the recovery and sync.
+    Recover,
+    /// Read back the entries, including the new one.
+    CheckRecover,
+    /// Corrupt the second entry.
+    Corrupt,
+    /// Read back the entries after the corruption.
+    CheckCorruption,
+}
+
+/// Length of the entry with the given number.
+fn entry_len(entry: u16) -> usize {
+    8 + entry as usize % 16
+}
+
+/// Content of the entry with the given number.
+fn entry_data(entry: u16, buffer: &mut [u8]) -> usize {
+    let length = entry_len(entry);
+    for (i, byte) in buffer[..length].iter_mut().enumerate() {
+        *byte = (entry as u8).wrapping_mul(31).wrapping_add(i as u8);
+    }
+    length
+}
+
+pub struct LogRecoveryTest<F: Flash + 'static> {
+    /// The test itself, to be set as the client of each new log.
+    this: OptionalCell<&'static LogRecoveryTest<F>>,
+    flash: &'static FaultInjectingFlash<'static, F>,
+    volume: &'static [u8],
+    page_size: usize,
+    log: OptionalCell<&'static TestLog<F>>,
+    /// Memory for the log of each boot.
+    logs: TakeCell<'static, [MaybeUninit<TestLog<F>>]>,
+    pagebuffers: TakeCell<'static, [F::Page]>,
+    buffer: TakeCell<'static, [u8]>,
+    stage: Cell<Stage>,
+    /// Number of entries appended before the first power cycle.
+    entries: Cell<u16>,
+    /// Number of the next entry to append.
+    next_append: Cell<u16>,
+    /// Number of entries read back at this stage.
+    entries_read: Cell<usize>,
+}
+
+impl<F: Flash + 'static> LogRecoveryTest<F> {
+    pub fn new(
+        flash: &'static FaultInjectingFlash<'static, F>,
+        volume: &'static [u8],
+        logs: &'static mut [MaybeUninit<TestLog<F>>; BOOTS],
+        pagebuffers: &'static mut [F::Page; BOOTS],
+        buffer: &'static mut [u8; BUFFER_LEN],
+    ) -> Self {
+        let page_size = pagebuffers[0].as_mut().len();
+        Self {
+            this: OptionalCell::empty(),
+            flash,
+            volume,
+            page_size,
+            log: OptionalCell::empty(),
+            logs: TakeCell::new(logs),
+            pagebuffers: TakeCell::new(pagebuffers),
+            buffer: TakeCell::new(buffer),
+            stage: Cell::new(Stage::Erase),
+            entries: Cell::new(0),
+            next_append: Cell::new(1),
+            entries_read: Cell::new(0),
+        }
+    }
+
+    pub fn run(&'static self) {
+        debug!("LogRecoveryTest: starting");
+        if self.volume.len() < 3 * self.page_size {
+            self.fail("volume smaller than three pages", Err(ErrorCode::SIZE));
+        }
+        self.this.set(self);
+        self.flash.set_fault_client(self);
+        self.boot();
+        if let Err(error) = self.log.get().unwrap().erase() {
+            self.fail("erase failed", Err(error));
+        }
+    }
+
+    /// Simulates a boot, creating a new log over the volume.
+    fn boot(&self) {
+        let Some(this) = self.this.get() else {
+            self.fail("test not started", Ok(()));
+        };
+        let log = self.logs.take().and_then(|logs| {
+            let (slot, logs) = logs.split_first_mut()?;
+            self.logs.replace(logs);
+            let pagebuffer = self.pagebuffers.take().and_then(|pagebuffers| {
+                let (pagebuffer, pagebuffers) = pagebuffers.split_first_mut()?;
+                self.pagebuffers.replace(pagebuffers);
+                Some(pagebuffer)
+            })?;
+            Some(&*slot.write(Log::new(self.volume, self.flash, pagebuffer, false)))
+        });
+        let Some(log) = log else {
+            self.fail("no memory left for the log", Err(ErrorCode::NOMEM));
+        };
+
+        kernel::deferred_call::DeferredCallClient::register(log);
+        flash::HasClient::set_client(self.flash, log);
+        log.set_read_client(this);
+        log.set_append_client(this);
+        self.log.set(log);
+    }
+
+    fn fail(&self, message: &str, result: Result<(), ErrorCode>) -> ! {
+        panic!(
+            "LogRecoveryTest: {:?} failed: {} ({:?})",
+            self.stage.get(),
+            message,
+            result
+        );
+    }
+
+    fn append_next(&self) {
+        let entry = self.next_append.get();
+        let buffer = self.buffer.take().unwrap();
+        let length = entry_data(entry, buffer);
+        self.next_append.set(entry + 1);
+        if let Err((error, _)) = self.log.get().unwrap().append(buffer, length) {
+            self.fail("append failed", Err(error));
+        }
+    }
+
+    fn read_next(&self) {
+        let buffer = self.buffer.take().unwrap();
+        match self.log.get().unwrap().read(buffer, BUFFER_LEN) {
+            Ok(()) => {}
+            Err((ErrorCode::FAIL, buffer)) => {
+                // Reached the end of the log.
+                self.buffer.replace(buffer);
+                if self.expected_entry().is_some() {
+                    self.fail("entries are missing", Err(ErrorCode::FAIL));
+                }
+                self.check_done();
+            }
+            Err((error, _)) => self.fail("read failed", Err(error)),
+        }
+    }
+
+    fn sync(&self) {
+        if let Err(error) = self.log.get().unwrap().sync() {
+            self.fail("sync failed", Err(error));
+        }
+    }
+
+    /// Reboots and reads back the entries.
+    fn power_cycle(&self, stage: Stage) {
+        self.boot();
+        self.stage.set(stage);
+        self.entries_read.set(0);
+        self.read_next();
+    }
+
+    /// Whether the entry with the given number should be read back at this
+    /// stage.
+    fn expects(&self, entry: u16) -> bool {
+        let entries = self.entries.get();
+        match self.stage.get() {
+            Stage::CheckFill => entry <= entries,
+            Stage::CheckTear => entry <= entries + 1,
+            Stage::CheckRecover => entry <= entries + 1 || entry == entries + 3,
+            Stage::CheckCorruption => (entry <= entries + 1 || entry == entries + 3) && entry != 2,
+            _ => false,
+        }
+    }
+
+    /// Number of the entry expected to be read next at this stage.
+    fn expected_entry(&self) -> Option<u16> {
+        (1..=self.entries.get() + 3)
+            .filter(|&entry| self.expects(entry))
+            .nth(self.entries_read.get())
+    }
+
+    /// All entries were read back at the current stage.
+    fn check_done(&self) {
+        match self.stage.get() {
+            Stage::CheckFill => {
+                self.stage.set(Stage::Tear);
+                self.append_next();
+            }
+            Stage::CheckTear => {
+                self.stage.set(Stage::Recover);
+                // Skip the number of the torn entry.
+                self.next_append.set(self.entries.get() + 3);
+                self.append_next();
+            }
+            Stage::CheckRecover => {
+                // Corrupt the data of the second entry of the first page.
+                self.stage.set(Stage::Corrupt);
+                let log = self.log.get().unwrap();
+                let page_start = log.log_start() - PAGE_HEADER_SIZE;
+                let offset =
+                    PAGE_HEADER_SIZE + ENTRY_HEADER_SIZE + entry_len(1) + ENTRY_HEADER_SIZE;
+                let volume_offset = page_start % self.volume.len();
+                let content = &self.volume[volume_offset..volume_offset + self.page_size];
+                let page_number = (self.volume.as_ptr() as usize + volume_offset) / self.page_size;
+                if let Err(error) = self.flash.corrupt(page_number, content, offset) {
+                    self.fail("corrupting the log failed", Err(error));
+                }
+            }
+            Stage::CheckCorruption => debug!("LogRecoveryTest: passed"),
+            _ => self.fail("unexpected end of log", Ok(())),
+        }
+    }
+}
+
+impl<F: Flash + 'static> FaultClient for LogRecoveryTest<F> {
+    fn fault_injected(&self) {
+        match self.stage.get() {
+            Stage::Tear => self.power_cycle(Stage::CheckTear),
+            Stage::Corrupt => self.power_cycle(Stage::CheckCorruption),
+            _ => self.fail("unexpected fault", Ok(())),
+        }
+    }
+}
+
+impl<F: Flash + 'static> LogReadClient for LogRecoveryTest<F> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        let mut expected = [0; BUFFER_LEN];
+        let matches = self.expected_entry().is_some_and(|entry| {
+            let expected_length = entry_data(entry, &mut expected);
+            buffer[..length] == expected[..expected_length]
+        });
+        self.buffer.replace(buffer);
+        if error.is_err() || !matches {
+            self.fail("entry read back does not match", error);
+        }
+
+        self.entries_read.set(self.entries_read.get() + 1);
+        self.read_next();
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        self.fail("unexpected seek", error);
+    }
+}
+
+impl<F: Flash + 'static> LogWriteClient for LogRecoveryTest<F> {
+    fn append_done(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.buffer.replace(buffer);
+        if error.is_err() {
+            self.fail("append failed", error);
+        }
+
+        let entry = self.next_append.get() - 1;
+        let log = self.log.get().unwrap();
+        match self.stage.get() {
+            // Fill the first page and a quarter of the second one.
+            Stage::Fill if log.log_end() < self.page_size + self.page_size / 4 => {
+                self.append_next()
+            }
+            Stage::Fill => {
+                self.entries.set(entry);
+                self.sync();
+            }
+            Stage::Recover => self.sync(),
+            Stage::Tear if entry == self.entries.get() + 1 => self.append_next(),
+            Stage::Tear => {
+                // Lose power in the middle of the data of the last entry.
+                let entry_start = log.log_end() - length - ENTRY_HEADER_SIZE;
+                self.flash
+                    .tear_next_write(entry_start % self.page_size + ENTRY_HEADER_SIZE + length / 2);
+                self.sync();
+            }
+            _ => self.fail("unexpected append", Ok(())),
+        }
+    }
+
+    fn sync_done(&self, error: Result<(), ErrorCode>) {
+        if error.is_err() {
+            self.fail("sync failed", error);
+        }
+        match self.stage.get() {
+            Stage::Fill => self.power_cycle(Stage::CheckFill),
+            Stage::Recover => self.power_cycle(Stage::CheckRecover),
+            _ => self.fail("unexpected sync", Ok(())),
+        }
+    }
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        if self.stage.get() != Stage::Erase || error.is_err() {
+            self.fail("erase failed", error);
+        }
+        self.stage.set(Stage::Fill);
+        self.append_next();
+    }
+}
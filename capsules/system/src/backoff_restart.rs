@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Fault policy that restarts faulted processes with exponential backoff.
+//!
+//! Unlike `ThresholdRestartFaultPolicy`, which restarts a faulted process
+//! immediately, this policy stops the process and restarts it after a delay.
+//! The delay starts at `initial_delay_ms` and doubles with every fault, up to
+//! `max_delay_ms`, so a process that crashes in a loop does not keep the
+//! board awake and flood the logs.
+//!
+//! A process that runs for `healthy_ms` after a restart without faulting is
+//! considered healthy again: its next fault is restarted after the initial
+//! delay. After `max_restarts` faults without a healthy run, the policy
+//! escalates as selected with [`BackoffEscalation`], either stopping the
+//! process for good or resetting the board.
+//!
+//! Processes are tracked by the start of their flash region, which, unlike
+//! their `ProcessId`, stays the same across restarts.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let fault_policy = components::backoff_restart::BackoffRestartComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     BackoffConfig {
+//!         initial_delay_ms: 100,
+//!         max_delay_ms: 60_000,
+//!         healthy_ms: 600_000,
+//!         max_restarts: 10,
+//!         escalation: BackoffEscalation::Stop,
+//!     },
+//! )
+//! .finalize(components::backoff_restart_component_static!(
+//!     sam4l::ast::Ast,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::process::{self, Process, ProcessFaultPolicy, State};
+use kernel::utilities::cells::OptionalCell;
+use kernel::Kernel;
+
+/// What the policy does with a process that keeps faulting.
+#[derive(Clone, Copy, Debug)]
+pub enum BackoffEscalation {
+    /// Stop the process and no longer restart it.
+    Stop,
+    /// Reset the board with the given function.
+    Reset(fn() -> !),
+}
+
+/// Configuration of the backoff.
+#[derive(Clone, Copy, Debug)]
+pub struct BackoffConfig {
+    /// Delay before the first restart, in milliseconds.
+    pub initial_delay_ms: u32,
+    /// Maximum delay before a restart, in milliseconds.
+    pub max_delay_ms: u32,
+    /// Time a process must run after a restart to be considered healthy, in
+    /// milliseconds.
+    pub healthy_ms: u32,
+    /// Number of faults without a healthy run after which the policy
+    /// escalates.
+    pub max_restarts: u32,
+    pub escalation: BackoffEscalation,
+}
+
+/// Backoff state of one process.
+struct ProcessBackoff<T: Ticks> {
+    /// Start of the flash region of the process.
+    flash_start: OptionalCell<usize>,
+    /// Number of faults since the process was last healthy.
+    faults: Cell<u32>,
+    /// When the process was last restarted.
+    restarted_at: Cell<T>,
+    /// When the pending restart was scheduled and its delay.
+    pending: OptionalCell<(T, T)>,
+}
+
+impl<T: Ticks> ProcessBackoff<T> {
+    fn new() -> Self {
+        Self {
+            flash_start: OptionalCell::empty(),
+            faults: Cell::new(0),
+            restarted_at: Cell::new(T::from(0)),
+            pending: OptionalCell::empty(),
+        }
+    }
+}
+
+pub struct BackoffRestartFaultPolicy<
+    'a,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability,
+    const NUM_PROCS: usize,
+> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    config: BackoffConfig,
+    processes: [ProcessBackoff<A::Ticks>; NUM_PROCS],
+    capability: C,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize>
+    BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        config: BackoffConfig,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            config,
+            processes: core::array::from_fn(|_| ProcessBackoff::new()),
+            capability,
+        }
+    }
+
+    /// Returns the backoff state of the process whose flash region starts at
+    /// `flash_start`, allocating one if the process has none.
+    fn backoff_of(&self, flash_start: usize) -> Option<&ProcessBackoff<A::Ticks>> {
+        self.processes
+            .iter()
+            .find(|backoff| backoff.flash_start.contains(&flash_start))
+            .or_else(|| {
+                let backoff = self
+                    .processes
+                    .iter()
+                    .find(|backoff| backoff.flash_start.is_none())?;
+                backoff.flash_start.set(flash_start);
+                backoff.faults.set(0);
+                Some(backoff)
+            })
+    }
+
+    /// Delay before restarting a process that faulted `faults` times.
+    fn delay_ms(&self, faults: u32) -> u32 {
+        let mut delay = self.config.initial_delay_ms;
+        for _ in 1..faults {
+            if delay >= self.config.max_delay_ms {
+                break;
+            }
+            delay = delay.saturating_mul(2);
+        }
+        delay.min(self.config.max_delay_ms)
+    }
+
+    /// Set the alarm for the earliest pending restart.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let earliest = self
+            .processes
+            .iter()
+            .filter_map(|backoff| backoff.pending.get())
+            .map(|(reference, delay)| {
+                let elapsed = now.wrapping_sub(reference);
+                if elapsed >= delay {
+                    A::Ticks::from(0)
+                } else {
+                    delay.wrapping_sub(elapsed)
+                }
+            })
+            .min();
+        match earliest {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize> ProcessFaultPolicy
+    for BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let Some(backoff) = self.backoff_of(process.get_addresses().flash_start) else {
+            // More processes than slots: restart right away.
+            return process::FaultAction::Restart;
+        };
+
+        let now = self.alarm.now();
+        let healthy = now.wrapping_sub(backoff.restarted_at.get())
+            >= self.alarm.ticks_from_ms(self.config.healthy_ms);
+        if healthy {
+            backoff.faults.set(0);
+        }
+        backoff.faults.set(backoff.faults.get().saturating_add(1));
+
+        if backoff.faults.get() > self.config.max_restarts {
+            backoff.pending.clear();
+            return match self.config.escalation {
+                BackoffEscalation::Stop => process::FaultAction::Stop,
+                BackoffEscalation::Reset(reset) => reset(),
+            };
+        }
+
+        // Stop the process now, and restart it when the alarm fires.
+        let delay = self
+            .alarm
+            .ticks_from_ms(self.delay_ms(backoff.faults.get()));
+        backoff.pending.set((now, delay));
+        self.arm();
+        process::FaultAction::Stop
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize> AlarmClient
+    for BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for backoff in self.processes.iter() {
+            let Some((reference, delay)) = backoff.pending.get() else {
+                continue;
+            };
+            if now.wrapping_sub(reference) < delay {
+                continue;
+            }
+            backoff.pending.clear();
+            backoff.restarted_at.set(now);
+            self.kernel
+                .process_each_capability(&self.capability, |process| {
+                    // The process may have been restarted or stopped from the
+                    // process console in the meantime.
+                    if backoff
+                        .flash_start
+                        .contains(&process.get_addresses().flash_start)
+                        && process.get_state() == State::Faulted
+                    {
+                        process.try_restart(None);
+                    }
+                });
+        }
+        self.arm();
+    }
+}
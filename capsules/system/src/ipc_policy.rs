@@ -0,0 +1,51 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! IPC policy implementations for the Tock kernel.
+//!
+//! These policies decide which processes may connect to the IPC services of
+//! other processes.
+
+use kernel::ipc::{IPCPolicy, TbfHeaderIPCPolicy};
+use kernel::process::{Process, ShortId};
+use kernel::ErrorCode;
+
+/// Allow list of IPC clients assigned by the board, by ShortId.
+///
+/// Each entry lists the ShortIds of the clients allowed to connect to the
+/// service with the ShortId of the entry. As ShortIds are assigned from the
+/// AppIDs of credential-checked processes, this restricts services to the
+/// applications signed by trusted parties. Processes without a fixed ShortId
+/// are never allowed to connect to a listed service.
+///
+/// Services that are not listed fall back to the IPC clients TBF header, see
+/// [`TbfHeaderIPCPolicy`].
+pub struct ShortIdIPCPolicy {
+    services: &'static [(u32, &'static [u32])],
+}
+
+impl ShortIdIPCPolicy {
+    pub const fn new(services: &'static [(u32, &'static [u32])]) -> Self {
+        Self { services }
+    }
+}
+
+impl IPCPolicy for ShortIdIPCPolicy {
+    fn check_connection(
+        &self,
+        client: &dyn Process,
+        service: &dyn Process,
+    ) -> Result<(), ErrorCode> {
+        let ShortId::Fixed(service_id) = service.short_app_id() else {
+            return TbfHeaderIPCPolicy {}.check_connection(client, service);
+        };
+        match self.services.iter().find(|(id, _)| *id == service_id.get()) {
+            Some((_, clients)) => match client.short_app_id() {
+                ShortId::Fixed(client_id) if clients.contains(&client_id.get()) => Ok(()),
+                _ => Err(ErrorCode::NODEVICE),
+            },
+            None => TbfHeaderIPCPolicy {}.check_connection(client, service),
+        }
+    }
+}
@@ -5,7 +5,13 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod backoff_restart;
+pub mod ipc_policy;
+pub mod power_manager;
 pub mod process_checker;
 pub mod process_policies;
 pub mod process_printer;
 pub mod storage_permissions;
+pub mod syscall_rate_limit;
+pub mod syscall_trace;
+pub mod version_rollback;
@@ -0,0 +1,295 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Power manager that selects sleep states from registered constraints.
+//!
+//! Each time the kernel has no work to do, the chip enters the deepest sleep
+//! state that the chip supports and that all registered [`PowerConstraint`]s
+//! allow. The manager measures the time spent in each state with a
+//! low-frequency alarm that keeps running in all sleep states. The alarm wakes
+//! the chip up before the timer wraps around, so long sleeps are fully
+//! counted.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let power_manager = components::power_manager::PowerManagerComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//! )
+//! .finalize(components::power_manager_component_static!(sam4l::ast::Ast, 4));
+//! power_manager.register(mux_alarm).unwrap();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::hil::time::{Alarm, AlarmClient, Frequency, Ticks};
+use kernel::platform::power::{PowerConstraint, PowerManager, PowerRequirement, SleepState};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+pub struct ConstraintPowerManager<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    /// Registered constraints.
+    constraints: &'a [OptionalCell<&'a dyn PowerConstraint>],
+    /// Time at which the chip entered its current sleep state.
+    sleep_start: Cell<A::Ticks>,
+    /// Time spent in each sleep state, in ticks of the alarm.
+    sleep_ticks: [Cell<u64>; SleepState::COUNT],
+    /// Number of times the chip entered each sleep state.
+    sleep_count: [Cell<u32>; SleepState::COUNT],
+}
+
+impl<'a, A: Alarm<'a>> ConstraintPowerManager<'a, A> {
+    pub fn new(alarm: &'a A, constraints: &'a [OptionalCell<&'a dyn PowerConstraint>]) -> Self {
+        Self {
+            alarm,
+            constraints,
+            sleep_start: Cell::new(A::Ticks::from(0)),
+            sleep_ticks: Default::default(),
+            sleep_count: Default::default(),
+        }
+    }
+
+    /// Start the alarm that wakes the chip up before the timer wraps around.
+    /// The power manager must be the client of the alarm.
+    pub fn start(&self) {
+        // Half of the timer range, and at most 2^31 ticks so that the ticks
+        // of a sleep fit in a `u32`.
+        let interval = cmp::min(A::Ticks::half_max_value(), A::Ticks::from_or_max(1 << 31));
+        self.alarm.set_alarm(self.alarm.now(), interval);
+    }
+
+    /// Register a constraint on the sleep states. Returns `NOMEM` if all
+    /// constraint slots are used.
+    pub fn register(&self, constraint: &'a dyn PowerConstraint) -> Result<(), ErrorCode> {
+        self.constraints
+            .iter()
+            .find(|slot| slot.is_none())
+            .map(|slot| slot.set(constraint))
+            .ok_or(ErrorCode::NOMEM)
+    }
+
+    /// Returns the time the chip spent in `state`, in microseconds.
+    pub fn sleep_time_us(&self, state: SleepState) -> u64 {
+        let ticks = self.sleep_ticks[state as usize].get();
+        let frequency = u64::from(A::Frequency::frequency());
+        ticks / frequency * 1_000_000 + ticks % frequency * 1_000_000 / frequency
+    }
+
+    /// Returns the number of times the chip entered `state`.
+    pub fn sleep_count(&self, state: SleepState) -> u32 {
+        self.sleep_count[state as usize].get()
+    }
+
+    /// Returns the deepest state and the longest wake-up latency that all
+    /// constraints allow.
+    fn limits(&self) -> (SleepState, u32) {
+        let deepest = SleepState::ALL[SleepState::COUNT - 1];
+        self.constraints.iter().filter_map(OptionalCell::get).fold(
+            (deepest, u32::MAX),
+            |(state, latency), constraint| match constraint.power_requirement() {
+                PowerRequirement::Unconstrained => (state, latency),
+                PowerRequirement::MaxSleepState(max) => (cmp::min(state, max), latency),
+                PowerRequirement::WakeupWithinUs(us) => (state, cmp::min(latency, us)),
+            },
+        )
+    }
+}
+
+impl<'a, A: Alarm<'a>> PowerManager for ConstraintPowerManager<'a, A> {
+    fn select_sleep_state(
+        &self,
+        wakeup_latency_us: &dyn Fn(SleepState) -> Option<u32>,
+    ) -> SleepState {
+        let (max_state, max_latency) = self.limits();
+        SleepState::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|&state| {
+                state <= max_state
+                    && wakeup_latency_us(state).is_some_and(|latency| latency <= max_latency)
+            })
+            .unwrap_or(SleepState::Sleep)
+    }
+
+    fn sleep_started(&self, state: SleepState) {
+        let count = &self.sleep_count[state as usize];
+        count.set(count.get().saturating_add(1));
+        self.sleep_start.set(self.alarm.now());
+    }
+
+    fn sleep_ended(&self, state: SleepState) {
+        // The alarm wakes the chip up before the timer wraps around, so the
+        // elapsed ticks are exact.
+        let elapsed = self.alarm.now().wrapping_sub(self.sleep_start.get());
+        let ticks = &self.sleep_ticks[state as usize];
+        ticks.set(ticks.get() + u64::from(elapsed.into_u32()));
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for ConstraintPowerManager<'a, A> {
+    fn alarm(&self) {
+        self.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::{Freq16KHz, Ticks32, Time};
+
+    struct MockAlarm {
+        now: Cell<u32>,
+        alarm: Cell<u32>,
+    }
+
+    impl MockAlarm {
+        fn new(now: u32) -> Self {
+            Self {
+                now: Cell::new(now),
+                alarm: Cell::new(0),
+            }
+        }
+    }
+
+    impl Time for MockAlarm {
+        type Frequency = Freq16KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get().into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+            self.alarm.set(reference.wrapping_add(dt).into_u32());
+        }
+
+        fn get_alarm(&self) -> Ticks32 {
+            self.alarm.get().into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            true
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            1.into()
+        }
+    }
+
+    struct MockConstraint(Cell<PowerRequirement>);
+
+    impl PowerConstraint for MockConstraint {
+        fn power_requirement(&self) -> PowerRequirement {
+            self.0.get()
+        }
+    }
+
+    /// Deep sleep takes 500 us to wake up from.
+    fn latency(state: SleepState) -> Option<u32> {
+        match state {
+            SleepState::Sleep => Some(0),
+            SleepState::DeepSleep => Some(500),
+        }
+    }
+
+    #[test]
+    fn test_select_without_constraints() {
+        let alarm = MockAlarm::new(0);
+        let slots: [OptionalCell<&dyn PowerConstraint>; 2] = Default::default();
+        let manager = ConstraintPowerManager::new(&alarm, &slots);
+
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::DeepSleep);
+        assert_eq!(
+            manager.select_sleep_state(&|state| (state == SleepState::Sleep).then_some(0)),
+            SleepState::Sleep
+        );
+    }
+
+    #[test]
+    fn test_select_as_constraints_change() {
+        let uart = MockConstraint(Cell::new(PowerRequirement::Unconstrained));
+        let timer = MockConstraint(Cell::new(PowerRequirement::Unconstrained));
+        let alarm = MockAlarm::new(0);
+        let slots: [OptionalCell<&dyn PowerConstraint>; 2] = Default::default();
+        let manager = ConstraintPowerManager::new(&alarm, &slots);
+        manager.register(&uart).unwrap();
+        manager.register(&timer).unwrap();
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::DeepSleep);
+
+        // Each constraint alone keeps the chip out of deep sleep.
+        uart.0
+            .set(PowerRequirement::MaxSleepState(SleepState::Sleep));
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::Sleep);
+        timer.0.set(PowerRequirement::WakeupWithinUs(100));
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::Sleep);
+        uart.0.set(PowerRequirement::Unconstrained);
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::Sleep);
+
+        // A wake-up deadline later than the latency allows deep sleep.
+        timer.0.set(PowerRequirement::WakeupWithinUs(500));
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::DeepSleep);
+        uart.0
+            .set(PowerRequirement::MaxSleepState(SleepState::DeepSleep));
+        assert_eq!(manager.select_sleep_state(&latency), SleepState::DeepSleep);
+    }
+
+    #[test]
+    fn test_register_full() {
+        let constraint = MockConstraint(Cell::new(PowerRequirement::Unconstrained));
+        let alarm = MockAlarm::new(0);
+        let slots: [OptionalCell<&dyn PowerConstraint>; 1] = Default::default();
+        let manager = ConstraintPowerManager::new(&alarm, &slots);
+
+        assert_eq!(manager.register(&constraint), Ok(()));
+        assert_eq!(manager.register(&constraint), Err(ErrorCode::NOMEM));
+    }
+
+    #[test]
+    fn test_sleep_time() {
+        let alarm = MockAlarm::new(u32::MAX - 7999);
+        let slots: [OptionalCell<&dyn PowerConstraint>; 1] = Default::default();
+        let manager = ConstraintPowerManager::new(&alarm, &slots);
+
+        // One second across the wrap around of the timer.
+        manager.sleep_started(SleepState::DeepSleep);
+        alarm.now.set(8000);
+        manager.sleep_ended(SleepState::DeepSleep);
+        assert_eq!(manager.sleep_time_us(SleepState::DeepSleep), 1_000_000);
+
+        // Longer than `u32::MAX` microseconds.
+        manager.sleep_started(SleepState::DeepSleep);
+        alarm.now.set(8000 + 160_000_000);
+        manager.sleep_ended(SleepState::DeepSleep);
+        assert_eq!(manager.sleep_time_us(SleepState::DeepSleep), 10_001_000_000);
+        assert_eq!(manager.sleep_count(SleepState::DeepSleep), 2);
+        assert_eq!(manager.sleep_time_us(SleepState::Sleep), 0);
+    }
+
+    #[test]
+    fn test_alarm_wakes_before_wrap() {
+        let alarm = MockAlarm::new(100);
+        let slots: [OptionalCell<&dyn PowerConstraint>; 1] = Default::default();
+        let manager = ConstraintPowerManager::new(&alarm, &slots);
+
+        manager.start();
+        assert_eq!(alarm.alarm.get(), 100 + (1 << 31));
+        alarm.now.set(alarm.alarm.get());
+        manager.alarm();
+        // Two alarms for each wrap around of the timer.
+        assert_eq!(alarm.alarm.get(), 100);
+    }
+}
@@ -23,6 +23,11 @@ use tock_tbf::types::TbfFooterV2CredentialsType;
 /// This assumes the `TbfFooterV2CredentialsType` data format only contains the
 /// signature (i.e. the data length of the credential in the TBF footer is the
 /// same as `SIGNATURE_LEN`).
+///
+/// `EcdsaNistP256` credentials are checked with a SHA-256 hasher and 64-byte
+/// signatures, `EcdsaNistP384` credentials with a SHA-384 hasher and 96-byte
+/// signatures, and `Ed25519ph` credentials with a SHA-512 hasher (the prehash
+/// of Ed25519ph) and 64-byte signatures.
 pub struct AppCheckerSignature<
     'a,
     S: hil::public_key_crypto::signature::SignatureVerify<'static, HASH_LEN, SIGNATURE_LEN>
@@ -10,7 +10,9 @@
 
 use kernel::process;
 use kernel::process::Process;
-use kernel::process::ProcessFaultPolicy;
+use kernel::process::{ProcessFaultPolicy, ProcessMemoryGrowthPolicy};
+use kernel::process::{ProcessUpcallQueuePolicy, UpcallQueueAction, UpcallQueueUsage};
+use kernel::upcall::UpcallId;
 
 /// Simply panic the entire board if a process faults.
 pub struct PanicFaultPolicy {}
@@ -116,3 +118,78 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// Implementation of `ProcessMemoryGrowthPolicy` that lets every process grow
+/// into the shared memory pool, up to a limit per process.
+///
+/// The limit keeps a single process with a growing number of grants from
+/// taking the whole pool.
+pub struct ThresholdMemoryGrowthPolicy {
+    max_growth: usize,
+}
+
+impl ThresholdMemoryGrowthPolicy {
+    pub const fn new(max_growth: usize) -> ThresholdMemoryGrowthPolicy {
+        ThresholdMemoryGrowthPolicy { max_growth }
+    }
+}
+
+impl ProcessMemoryGrowthPolicy for ThresholdMemoryGrowthPolicy {
+    fn allow_growth(&self, _process: &dyn Process, used: usize, size: usize) -> bool {
+        used.saturating_add(size) <= self.max_growth
+    }
+}
+
+/// Maximum number of upcalls from one driver pending for a process.
+#[derive(Clone, Copy, Debug)]
+pub struct DriverUpcallQuota {
+    pub driver_num: usize,
+    pub max_pending: usize,
+}
+
+/// Implementation of `ProcessUpcallQueuePolicy` that coalesces repeated
+/// upcalls and limits the number of upcalls each driver can queue.
+///
+/// An upcall is coalesced with the pending upcall for the same `UpcallId` if
+/// `coalesce` is set, if its driver reached its quota, or if the task queue
+/// is full, so that the process is only called with the latest values. An
+/// upcall that cannot be coalesced is dropped if its driver reached its
+/// quota. This keeps a noisy driver, such as a button being pressed
+/// repeatedly, from filling the queue and starving the upcalls of other
+/// drivers, such as timers.
+pub struct QuotaUpcallQueuePolicy {
+    quotas: &'static [DriverUpcallQuota],
+    coalesce: bool,
+}
+
+impl QuotaUpcallQueuePolicy {
+    pub const fn new(
+        quotas: &'static [DriverUpcallQuota],
+        coalesce: bool,
+    ) -> QuotaUpcallQueuePolicy {
+        QuotaUpcallQueuePolicy { quotas, coalesce }
+    }
+}
+
+impl ProcessUpcallQueuePolicy for QuotaUpcallQueuePolicy {
+    fn enqueue_action(
+        &self,
+        _process: &dyn Process,
+        upcall_id: UpcallId,
+        usage: UpcallQueueUsage,
+    ) -> UpcallQueueAction {
+        let over_quota = self
+            .quotas
+            .iter()
+            .find(|quota| quota.driver_num == upcall_id.driver_num)
+            .is_some_and(|quota| usage.same_driver >= quota.max_pending);
+
+        if usage.same_upcall > 0 && (self.coalesce || over_quota || usage.free == 0) {
+            UpcallQueueAction::Coalesce
+        } else if over_quota {
+            UpcallQueueAction::Drop
+        } else {
+            UpcallQueueAction::Enqueue
+        }
+    }
+}
@@ -59,6 +59,7 @@ impl ProcessPrinter for ProcessPrinterText {
         let syscall_count = process.debug_syscall_count();
         let dropped_upcall_count = process.debug_dropped_upcall_count();
         let restart_count = process.get_restart_count();
+        let cpu_time_us = process.debug_cpu_time_us();
 
         let addresses = process.get_addresses();
         let sizes = process.get_sizes();
@@ -76,7 +77,7 @@ impl ProcessPrinter for ProcessPrinterText {
             "\
                  𝐀𝐩𝐩: {}   -   [{:?}]\
                  \r\n Events Queued: {}   Syscall Count: {}   Dropped Upcall Count: {}\
-                 \r\n Restart Count: {}\
+                 \r\n Restart Count: {}   CPU Time: {}.{:03} ms\
                  \r\n",
             process.get_process_name(),
             process.get_state(),
@@ -84,6 +85,8 @@ impl ProcessPrinter for ProcessPrinterText {
             syscall_count,
             dropped_upcall_count,
             restart_count,
+            cpu_time_us / 1000,
+            cpu_time_us % 1000,
         ));
 
         let _ = match process.debug_syscall_last() {
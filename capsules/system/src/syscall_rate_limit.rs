@@ -0,0 +1,199 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! System call filter that limits how often each process can call a driver.
+//!
+//! Each [`SyscallRateLimit`] gives every process a token bucket for one
+//! driver. Every system call to the driver (command, subscribe or allow)
+//! takes a token from the bucket of the calling process, and each period of
+//! the alarm adds `refill` tokens to every bucket, up to `burst`. When the
+//! bucket is empty the system call is rejected with `BUSY`, or the process is
+//! faulted, as selected with [`RateLimitResponse`].
+//!
+//! Buckets are only allocated to processes while they are limited: a process
+//! whose buckets are all full uses no slot, and the alarm only runs while a
+//! process has spent tokens. If more processes than slots are limited at the
+//! same time, system calls of the processes without a slot are allowed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! static LIMITS: [SyscallRateLimit; 1] = [SyscallRateLimit {
+//!     driver_num: capsules_core::spi_controller::DRIVER_NUM,
+//!     burst: 20,
+//!     refill: 10,
+//! }];
+//!
+//! let rate_limiter = components::syscall_rate_limit::SyscallRateLimitComponent::new(
+//!     mux_alarm,
+//!     &LIMITS,
+//!     100,
+//!     RateLimitResponse::Busy,
+//! )
+//! .finalize(components::syscall_rate_limit_component_static!(
+//!     sam4l::ast::Ast,
+//!     NUM_PROCS,
+//!     1
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::errorcode::ErrorCode;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::platform::SyscallFilter;
+use kernel::process::{Process, ProcessId};
+use kernel::syscall::Syscall;
+use kernel::utilities::cells::OptionalCell;
+
+/// Rate limit of the system calls to one driver.
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallRateLimit {
+    pub driver_num: usize,
+    /// Maximum number of system calls a process can make in a burst.
+    pub burst: u16,
+    /// Number of system calls a process can make each period.
+    pub refill: u16,
+}
+
+/// How the filter responds to system calls over the rate limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitResponse {
+    /// Return `BUSY` to the process.
+    Busy,
+    /// Put the process in the fault state. The fault policy of the process
+    /// decides whether it is stopped or restarted.
+    Fault,
+}
+
+/// Token buckets of one process.
+struct ProcessTokens<const NUM_LIMITS: usize> {
+    owner: OptionalCell<ProcessId>,
+    tokens: [Cell<u16>; NUM_LIMITS],
+}
+
+impl<const NUM_LIMITS: usize> ProcessTokens<NUM_LIMITS> {
+    fn new() -> Self {
+        Self {
+            owner: OptionalCell::empty(),
+            tokens: core::array::from_fn(|_| Cell::new(0)),
+        }
+    }
+}
+
+pub struct SyscallRateLimiter<'a, A: Alarm<'a>, const NUM_PROCS: usize, const NUM_LIMITS: usize> {
+    alarm: &'a A,
+    limits: &'a [SyscallRateLimit; NUM_LIMITS],
+    /// Period at which tokens are added to the buckets, in milliseconds.
+    period_ms: u32,
+    response: RateLimitResponse,
+    buckets: [ProcessTokens<NUM_LIMITS>; NUM_PROCS],
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize, const NUM_LIMITS: usize>
+    SyscallRateLimiter<'a, A, NUM_PROCS, NUM_LIMITS>
+{
+    pub fn new(
+        alarm: &'a A,
+        limits: &'a [SyscallRateLimit; NUM_LIMITS],
+        period_ms: u32,
+        response: RateLimitResponse,
+    ) -> Self {
+        Self {
+            alarm,
+            limits,
+            period_ms,
+            response,
+            buckets: core::array::from_fn(|_| ProcessTokens::new()),
+        }
+    }
+
+    /// Returns the buckets of `process_id`, allocating full buckets if the
+    /// process has none.
+    fn buckets_of(&self, process_id: ProcessId) -> Option<&ProcessTokens<NUM_LIMITS>> {
+        self.buckets
+            .iter()
+            .find(|buckets| buckets.owner.contains(&process_id))
+            .or_else(|| {
+                let buckets = self
+                    .buckets
+                    .iter()
+                    .find(|buckets| buckets.owner.is_none())?;
+                buckets.owner.set(process_id);
+                for (tokens, limit) in buckets.tokens.iter().zip(self.limits.iter()) {
+                    tokens.set(limit.burst);
+                }
+                Some(buckets)
+            })
+    }
+
+    /// Refill the buckets each period while a process has buckets.
+    fn start_refill(&self) {
+        if !self.alarm.is_armed() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize, const NUM_LIMITS: usize> SyscallFilter
+    for SyscallRateLimiter<'a, A, NUM_PROCS, NUM_LIMITS>
+{
+    fn filter_syscall(&self, process: &dyn Process, syscall: &Syscall) -> Result<(), ErrorCode> {
+        let Some(driver_num) = syscall.driver_number() else {
+            return Ok(());
+        };
+        let Some(limit) = self
+            .limits
+            .iter()
+            .position(|limit| limit.driver_num == driver_num)
+        else {
+            return Ok(());
+        };
+        let Some(buckets) = self.buckets_of(process.processid()) else {
+            return Ok(());
+        };
+        self.start_refill();
+
+        let tokens = &buckets.tokens[limit];
+        if tokens.get() > 0 {
+            tokens.set(tokens.get() - 1);
+            return Ok(());
+        }
+        if self.response == RateLimitResponse::Fault {
+            process.set_fault_state();
+        }
+        Err(ErrorCode::BUSY)
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize, const NUM_LIMITS: usize> AlarmClient
+    for SyscallRateLimiter<'a, A, NUM_PROCS, NUM_LIMITS>
+{
+    fn alarm(&self) {
+        let mut limited = false;
+        for buckets in self
+            .buckets
+            .iter()
+            .filter(|buckets| buckets.owner.is_some())
+        {
+            let mut full = true;
+            for (tokens, limit) in buckets.tokens.iter().zip(self.limits.iter()) {
+                tokens.set(tokens.get().saturating_add(limit.refill).min(limit.burst));
+                full &= tokens.get() == limit.burst;
+            }
+            // A process with full buckets is not limited, so it does not need
+            // a slot until it spends tokens again.
+            if full {
+                buckets.owner.clear();
+            } else {
+                limited = true;
+            }
+        }
+        if limited {
+            self.start_refill();
+        }
+    }
+}
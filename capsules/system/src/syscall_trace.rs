@@ -0,0 +1,110 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! System call tracer that stores records in a ring buffer.
+//!
+//! When the buffer is full, the oldest record is overwritten, so the buffer
+//! always holds the most recent system calls. The records can be read with
+//! the `trace` command of the process console, or printed on the debug UART
+//! with [`SyscallTraceBuffer::dump()`], and decoded on the host with
+//! `tools/debugging-and-development/syscall_trace_decoder.py`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let syscall_tracer = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     &peripherals.ast,
+//! )
+//! .finalize(components::syscall_trace_component_static!(
+//!     sam4l::ast::Ast,
+//!     64
+//! ));
+//! process_console.set_syscall_tracer(syscall_tracer);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::queue::Queue;
+use kernel::collections::ring_buffer::RingBuffer;
+use kernel::debug;
+use kernel::hil::time::{ConvertTicks, Time};
+use kernel::syscall_trace::{SyscallTraceHistory, SyscallTracer, TraceRecord};
+use kernel::utilities::cells::MapCell;
+
+pub struct SyscallTraceBuffer<'a, T: Time> {
+    time: &'a T,
+    records: MapCell<RingBuffer<'a, TraceRecord>>,
+    /// Number of records overwritten before they were read.
+    dropped: Cell<usize>,
+}
+
+impl<'a, T: Time> SyscallTraceBuffer<'a, T> {
+    /// Create a tracer storing up to `buffer.len() - 1` records in `buffer`.
+    pub fn new(time: &'a T, buffer: &'a mut [TraceRecord]) -> Self {
+        Self {
+            time,
+            records: MapCell::new(RingBuffer::new(buffer)),
+            dropped: Cell::new(0),
+        }
+    }
+
+    /// Print and remove all stored records with `debug!()`.
+    pub fn dump(&self) {
+        debug!("syscall trace: {} dropped", self.dropped.get());
+        while let Some(record) = self.take() {
+            debug!("{}", record);
+        }
+    }
+}
+
+impl<T: Time> SyscallTracer for SyscallTraceBuffer<'_, T> {
+    fn record(&self, mut record: TraceRecord) {
+        record.timestamp_us = self.time.ticks_to_us(self.time.now());
+        self.records.map(|records| {
+            if records.push(record).is_some() {
+                self.dropped.set(self.dropped.get() + 1);
+            }
+        });
+    }
+
+    fn take(&self) -> Option<TraceRecord> {
+        self.records.map(|records| records.dequeue()).flatten()
+    }
+
+    fn len(&self) -> usize {
+        self.records.map_or(0, |records| records.len())
+    }
+
+    fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+}
+
+impl<T: Time> SyscallTraceHistory for SyscallTraceBuffer<'_, T> {
+    fn last_records(&self, process_id: u32, records: &mut [TraceRecord]) -> usize {
+        self.records.map_or(0, |stored| {
+            let matching = stored
+                .iter()
+                .filter(|record| record.process_id == process_id)
+                .count();
+            // Skip the older records that do not fit in `records`.
+            let mut skip = matching.saturating_sub(records.len());
+            let mut count = 0;
+            for record in stored.iter() {
+                if record.process_id != process_id {
+                    continue;
+                }
+                if skip > 0 {
+                    skip -= 1;
+                } else if let Some(slot) = records.get_mut(count) {
+                    *slot = *record;
+                    count += 1;
+                }
+            }
+            count
+        })
+    }
+}
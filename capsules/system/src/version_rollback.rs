@@ -0,0 +1,482 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Fault policy that rolls apps back to their previous version when a new
+//! version keeps faulting.
+//!
+//! When an app is updated with `DynamicProcessLoad::load_upgrading()`, the
+//! binary of the previous version stays in flash, and the loader keeps
+//! preferring the binary with the highest version, at runtime and at boot.
+//! This policy records, for every app, the highest version that is known to
+//! be good: a version whose process is still running `window_ms` after it
+//! started, without faulting `max_faults` times. The known good versions are
+//! stored in a region of nonvolatile storage, so they survive a reboot.
+//!
+//! A version higher than the known good version of its app is on trial. If
+//! it faults `max_faults` times within `window_ms` of its start, the process
+//! is stopped, its binary is retired, and the binary with the previous
+//! version is loaded in its place with `DynamicProcessLoad::rollback()`. If
+//! there is no previous binary, the process is restarted instead. Faults of
+//! processes that are not on trial, and faults of a trial below
+//! `max_faults`, are handled by the inner fault policy.
+//!
+//! Apps are identified by their `ShortId`, the identifier the AppID policy
+//! of the board assigns and the loader uses to keep two versions of an app
+//! from running at once, so the table of known good versions has one entry
+//! per app rather than one per binary. Apps with a locally unique `ShortId`
+//! cannot be told apart from other versions of themselves, and their faults
+//! are handled by the inner fault policy only.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let fault_policy = components::version_rollback::VersionRollbackComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     dynamic_binary_storage,
+//!     rollback_pages,
+//!     ROLLBACK_TABLE_ADDRESS,
+//!     &DEFAULT_FAULT_POLICY,
+//!     RollbackConfig {
+//!         window_ms: 60_000,
+//!         max_faults: 3,
+//!     },
+//! )
+//! .finalize(components::version_rollback_component_static!(
+//!     sam4l::ast::Ast,
+//!     DynamicBinaryStorage<'static>,
+//!     NonVolatilePages,
+//!     NUM_PROCS
+//! ));
+//! ```
+//!
+//! The storage region must not be shared with the application flash, and
+//! `ROLLBACK_TABLE_ADDRESS` must be the start of a region of at least
+//! [`storage_len`] bytes.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::dynamic_binary_storage::DynamicProcessLoad;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::process::{self, Process, ProcessFaultPolicy, ProcessId, ShortId, State};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::helpers::crc32_posix;
+use kernel::{ErrorCode, Kernel};
+
+/// Marks a valid table of known good versions, keyed by `ShortId`, in storage.
+const TABLE_MAGIC: u32 = 0x5652_4b53;
+/// Length of the table header: magic, number of entries and CRC of the
+/// entries.
+const HEADER_LEN: usize = 12;
+/// Length of a table entry: app key and version.
+const ENTRY_LEN: usize = 8;
+
+/// Returns the length, in bytes, of the storage region for a table of known
+/// good versions of `num_apps` apps.
+pub const fn storage_len(num_apps: usize) -> usize {
+    HEADER_LEN + num_apps * ENTRY_LEN
+}
+
+/// Configuration of the rollback.
+#[derive(Clone, Copy, Debug)]
+pub struct RollbackConfig {
+    /// Time a new version must run to be known good, in milliseconds.
+    pub window_ms: u32,
+    /// Number of faults within the window after which a new version is
+    /// rolled back.
+    pub max_faults: u32,
+}
+
+/// Highest known good version of one app.
+struct KnownGood {
+    /// ShortId of the app.
+    key: OptionalCell<u32>,
+    version: Cell<u32>,
+}
+
+/// A version of one app on trial.
+struct Trial<T: Ticks> {
+    /// ShortId of the app.
+    key: OptionalCell<u32>,
+    version: Cell<u32>,
+    /// When the version was first seen running.
+    started_at: Cell<T>,
+    faults: Cell<u32>,
+    /// Whether the version must be rolled back.
+    rollback: Cell<bool>,
+}
+
+impl<T: Ticks> Trial<T> {
+    fn new() -> Self {
+        Self {
+            key: OptionalCell::empty(),
+            version: Cell::new(0),
+            started_at: Cell::new(T::from(0)),
+            faults: Cell::new(0),
+            rollback: Cell::new(false),
+        }
+    }
+}
+
+/// Returns the key and the version of `process`, or `None` if the process
+/// has a locally unique ShortId. Processes without a version have version 0.
+fn key_and_version(process: &dyn Process) -> Option<(u32, u32)> {
+    match process.short_app_id() {
+        ShortId::Fixed(id) => Some((
+            id.get(),
+            process.binary_version().map_or(0, |version| version.get()),
+        )),
+        ShortId::LocallyUnique => None,
+    }
+}
+
+/// Returns whether a process in `state` is running, rather than stopped,
+/// faulted or terminated.
+fn is_running(state: State) -> bool {
+    matches!(
+        state,
+        State::Running | State::Yielded | State::YieldedFor(_)
+    )
+}
+
+pub struct VersionRollbackFaultPolicy<
+    'a,
+    A: Alarm<'a>,
+    L: DynamicProcessLoad,
+    S: NonvolatileStorage<'a>,
+    C: ProcessManagementCapability,
+    const NUM_APPS: usize,
+> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    load_driver: &'a L,
+    storage: &'a S,
+    /// Start address of the table in storage.
+    storage_address: usize,
+    buffer: TakeCell<'static, [u8]>,
+    inner: &'a dyn ProcessFaultPolicy,
+    config: RollbackConfig,
+    known_good: [KnownGood; NUM_APPS],
+    trials: [Trial<A::Ticks>; NUM_APPS],
+    /// Whether the table was read from storage.
+    loaded: Cell<bool>,
+    /// Whether the table changed since it was last written to storage.
+    dirty: Cell<bool>,
+    capability: C,
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        L: DynamicProcessLoad,
+        S: NonvolatileStorage<'a>,
+        C: ProcessManagementCapability,
+        const NUM_APPS: usize,
+    > VersionRollbackFaultPolicy<'a, A, L, S, C, NUM_APPS>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        load_driver: &'a L,
+        storage: &'a S,
+        storage_address: usize,
+        buffer: &'static mut [u8],
+        inner: &'a dyn ProcessFaultPolicy,
+        config: RollbackConfig,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            load_driver,
+            storage,
+            storage_address,
+            buffer: TakeCell::new(buffer),
+            inner,
+            config,
+            known_good: core::array::from_fn(|_| KnownGood {
+                key: OptionalCell::empty(),
+                version: Cell::new(0),
+            }),
+            trials: core::array::from_fn(|_| Trial::new()),
+            loaded: Cell::new(false),
+            dirty: Cell::new(false),
+            capability,
+        }
+    }
+
+    /// Read the table of known good versions from storage, and start
+    /// checking new versions.
+    pub fn init(&self) -> Result<(), ErrorCode> {
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(self.config.window_ms),
+        );
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let length = buffer.len();
+        self.storage
+            .read(buffer, self.storage_address, length)
+            .inspect_err(|_| {
+                // Start from an empty table, which is not persisted as the
+                // storage keeps the buffer.
+                self.loaded.set(true);
+            })
+    }
+
+    /// Returns the known good version of the app with `key`, or 0 if the app
+    /// has none.
+    fn known_good_version(&self, key: u32) -> u32 {
+        self.known_good
+            .iter()
+            .find(|entry| entry.key.contains(&key))
+            .map_or(0, |entry| entry.version.get())
+    }
+
+    /// Record `version` as the known good version of the app with `key`.
+    fn set_known_good(&self, key: u32, version: u32) {
+        let entry = self
+            .known_good
+            .iter()
+            .find(|entry| entry.key.contains(&key))
+            .or_else(|| self.known_good.iter().find(|entry| entry.key.is_none()));
+        if let Some(entry) = entry {
+            if entry.key.is_none() || entry.version.get() < version {
+                entry.key.set(key);
+                entry.version.set(version);
+                self.dirty.set(true);
+            }
+        }
+    }
+
+    /// Returns the trial of `version` of the app with `key`, starting one if
+    /// the version is not on trial yet.
+    fn trial_of(&self, key: u32, version: u32) -> Option<&Trial<A::Ticks>> {
+        let trial = self
+            .trials
+            .iter()
+            .find(|trial| trial.key.contains(&key))
+            .or_else(|| self.trials.iter().find(|trial| trial.key.is_none()))?;
+        if !trial.key.contains(&key) || trial.version.get() != version {
+            trial.key.set(key);
+            trial.version.set(version);
+            trial.started_at.set(self.alarm.now());
+            trial.faults.set(0);
+            trial.rollback.set(false);
+        }
+        Some(trial)
+    }
+
+    /// Returns whether the trial ran for the whole window.
+    fn survived(&self, trial: &Trial<A::Ticks>) -> bool {
+        self.alarm.now().wrapping_sub(trial.started_at.get())
+            >= self.alarm.ticks_from_ms(self.config.window_ms)
+    }
+
+    /// Returns the identifier and the state of the process of the version on
+    /// `trial`, if it is loaded.
+    fn trial_process(&self, trial: &Trial<A::Ticks>) -> Option<(ProcessId, State)> {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if key_and_version(process).is_some_and(|(key, version)| {
+                    trial.key.contains(&key) && version == trial.version.get()
+                }) {
+                    found = Some((process.processid(), process.get_state()));
+                }
+            });
+        found
+    }
+
+    /// Roll back the faulted process of the app on `trial`, or restart it if
+    /// it cannot be rolled back.
+    fn roll_back(&self, trial: &Trial<A::Ticks>) {
+        let faulted = self
+            .trial_process(trial)
+            .filter(|(_, state)| *state == State::Faulted)
+            .map(|(processid, _)| processid);
+        trial.key.clear();
+        let Some(processid) = faulted else {
+            // The process was restarted or stopped from the process console
+            // in the meantime.
+            return;
+        };
+        if self.load_driver.rollback(processid.id()).is_err() {
+            self.kernel.process_map_or_external(
+                (),
+                processid,
+                |process| process.try_restart(None),
+                &self.capability,
+            );
+        }
+    }
+
+    /// Write the table of known good versions to storage, if it changed.
+    fn persist(&self) {
+        if !self.loaded.get() || !self.dirty.get() {
+            return;
+        }
+        let Some(buffer) = self.buffer.take() else {
+            // A write is in progress, the table is written again once it is
+            // done.
+            return;
+        };
+        buffer.fill(0);
+        let mut count = 0;
+        for entry in self.known_good.iter() {
+            entry.key.map(|key| {
+                let offset = HEADER_LEN + count * ENTRY_LEN;
+                buffer[offset..offset + 4].copy_from_slice(&key.to_le_bytes());
+                buffer[offset + 4..offset + 8].copy_from_slice(&entry.version.get().to_le_bytes());
+                count += 1;
+            });
+        }
+        let crc = crc32_posix(&buffer[HEADER_LEN..HEADER_LEN + count * ENTRY_LEN]);
+        buffer[0..4].copy_from_slice(&TABLE_MAGIC.to_le_bytes());
+        buffer[4..8].copy_from_slice(&(count as u32).to_le_bytes());
+        buffer[8..12].copy_from_slice(&crc.to_le_bytes());
+
+        self.dirty.set(false);
+        let length = buffer.len();
+        if self
+            .storage
+            .write(buffer, self.storage_address, length)
+            .is_err()
+        {
+            // The storage keeps the buffer, so the known good versions are
+            // only kept until the next reboot.
+            self.dirty.set(true);
+        }
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        L: DynamicProcessLoad,
+        S: NonvolatileStorage<'a>,
+        C: ProcessManagementCapability,
+        const NUM_APPS: usize,
+    > ProcessFaultPolicy for VersionRollbackFaultPolicy<'a, A, L, S, C, NUM_APPS>
+{
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let Some((key, version)) = key_and_version(process) else {
+            return self.inner.action(process);
+        };
+        if version <= self.known_good_version(key) {
+            return self.inner.action(process);
+        }
+        let Some(trial) = self.trial_of(key, version) else {
+            return self.inner.action(process);
+        };
+        if self.survived(trial) {
+            // Only a version found running when its window ends is known good.
+            // This one faulted first: start a new trial, with this fault.
+            trial.started_at.set(self.alarm.now());
+            trial.faults.set(0);
+        }
+
+        trial.faults.set(trial.faults.get().saturating_add(1));
+        if trial.faults.get() < self.config.max_faults {
+            return self.inner.action(process);
+        }
+        // The process cannot be removed while it faults. Stop it now, and
+        // roll it back when the alarm fires.
+        trial.rollback.set(true);
+        self.alarm.set_alarm(self.alarm.now(), A::Ticks::from(0));
+        process::FaultAction::Stop
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        L: DynamicProcessLoad,
+        S: NonvolatileStorage<'a>,
+        C: ProcessManagementCapability,
+        const NUM_APPS: usize,
+    > AlarmClient for VersionRollbackFaultPolicy<'a, A, L, S, C, NUM_APPS>
+{
+    fn alarm(&self) {
+        for trial in self.trials.iter() {
+            if trial.rollback.get() {
+                self.roll_back(trial);
+            } else if self.survived(trial) {
+                // Only a version whose process is still running is known
+                // good. A version that was stopped, faulted or removed during
+                // the window is put on trial again when it runs.
+                let running = self
+                    .trial_process(trial)
+                    .is_some_and(|(_, state)| is_running(state));
+                if running {
+                    trial
+                        .key
+                        .map(|key| self.set_known_good(key, trial.version.get()));
+                }
+                trial.key.clear();
+            }
+        }
+
+        // Put the new versions that are running on trial.
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                let Some((key, version)) = key_and_version(process) else {
+                    return;
+                };
+                if version > self.known_good_version(key) && is_running(process.get_state()) {
+                    let _ = self.trial_of(key, version);
+                }
+            });
+
+        self.persist();
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(self.config.window_ms),
+        );
+    }
+}
+
+impl<
+        'a,
+        A: Alarm<'a>,
+        L: DynamicProcessLoad,
+        S: NonvolatileStorage<'a>,
+        C: ProcessManagementCapability,
+        const NUM_APPS: usize,
+    > NonvolatileStorageClient for VersionRollbackFaultPolicy<'a, A, L, S, C, NUM_APPS>
+{
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let magic = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        let count = u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+        let crc = u32::from_le_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+        let entries_end = count
+            .checked_mul(ENTRY_LEN)
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|&end| end <= length && count <= NUM_APPS);
+        // An erased or corrupted region is an empty table. Versions promoted
+        // before the table was read are merged in.
+        let dirty = self.dirty.get();
+        if let Some(end) = entries_end {
+            if magic == TABLE_MAGIC && crc32_posix(&buffer[HEADER_LEN..end]) == crc {
+                for entry in buffer[HEADER_LEN..end].chunks_exact(ENTRY_LEN) {
+                    let key = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                    let version = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+                    self.set_known_good(key, version);
+                }
+            }
+        }
+        self.dirty.set(dirty);
+        self.buffer.replace(buffer);
+        self.loaded.set(true);
+        self.persist();
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        // Write the changes made during the write.
+        self.persist();
+    }
+}
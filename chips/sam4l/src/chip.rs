@@ -9,6 +9,12 @@ use crate::pm;
 use core::fmt::Write;
 use cortexm4::{CortexM4, CortexMVariant};
 use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::power::SleepState;
+
+/// Time the chip needs to resume execution after deep sleep, which includes
+/// restarting the main clock. This is a conservative bound rather than a
+/// measured value.
+const DEEP_SLEEP_WAKEUP_LATENCY_US: u32 = 100;
 
 pub struct Sam4l<I: InterruptService + 'static> {
     mpu: cortexm4::mpu::MPU,
@@ -267,13 +273,29 @@ impl<I: InterruptService + 'static> Chip for Sam4l<I> {
 
     fn sleep(&self) {
         if pm::deep_sleep_ready() {
-            unsafe {
-                cortexm4::scb::set_sleepdeep();
-            }
+            self.sleep_in_state(SleepState::DeepSleep);
         } else {
-            unsafe {
+            self.sleep_in_state(SleepState::Sleep);
+        }
+    }
+
+    fn sleep_wakeup_latency_us(&self, state: SleepState) -> Option<u32> {
+        match state {
+            SleepState::Sleep => Some(0),
+            // Deep sleep stops the clocks of the peripherals on the high-speed
+            // buses, so it is only possible when they are all idle.
+            SleepState::DeepSleep => pm::deep_sleep_ready().then_some(DEEP_SLEEP_WAKEUP_LATENCY_US),
+        }
+    }
+
+    fn sleep_in_state(&self, state: SleepState) {
+        match state {
+            SleepState::Sleep => unsafe {
                 cortexm4::scb::unset_sleepdeep();
-            }
+            },
+            SleepState::DeepSleep => unsafe {
+                cortexm4::scb::set_sleepdeep();
+            },
         }
 
         unsafe {
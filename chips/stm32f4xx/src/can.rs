@@ -590,6 +590,9 @@ impl<'a> Can<'a> {
     }
 
     /// Configure a filter to receive messages
+    ///
+    /// The filter bank accepts all the frames: the `id` and `mask` of
+    /// `filter_info` are not programmed into it.
     pub fn config_filter(&self, filter_info: can::FilterParameters, enable: bool) {
         // get position of the filter number
         let filter_number = 1 << filter_info.number;
@@ -1358,6 +1361,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 0,
+                        id: can::Id::Standard(0),
+                        mask: 0,
                     },
                     true,
                 );
@@ -1367,6 +1372,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 1,
+                        id: can::Id::Standard(0),
+                        mask: 0,
                     },
                     true,
                 );
@@ -1390,6 +1397,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 0,
+                        id: can::Id::Standard(0),
+                        mask: 0,
                     },
                     false,
                 );
@@ -1399,6 +1408,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 1,
+                        id: can::Id::Standard(0),
+                        mask: 0,
                     },
                     false,
                 );
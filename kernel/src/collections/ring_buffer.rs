@@ -54,6 +54,14 @@ impl<'a, T: Copy> RingBuffer<'a, T> {
             (None, None)
         }
     }
+
+    /// Returns an iterator over the elements of the ring buffer, from the
+    /// oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let len = self.ring.len();
+        let count = queue::Queue::len(self);
+        (0..count).map(move |offset| &self.ring[(self.head + offset) % len])
+    }
 }
 
 impl<T: Copy> queue::Queue<T> for RingBuffer<'_, T> {
@@ -317,4 +325,17 @@ mod test {
         assert_eq!(buf.dequeue(), Some(9));
         assert_eq!(buf.dequeue(), None);
     }
+
+    #[test]
+    fn test_iter() {
+        const LEN: usize = 10;
+        let mut ring = [0; LEN];
+        let mut buf = RingBuffer::new(&mut ring);
+
+        move_head(&mut buf, LEN - 2);
+        enqueue_iota(&mut buf, LEN);
+
+        assert!(buf.iter().copied().eq(1..LEN));
+        assert_eq!(buf.len(), LEN - 1);
+    }
 }
@@ -8,6 +8,7 @@
 //! during runtime without requiring the user to restart the device.
 
 use core::cell::Cell;
+use core::cmp;
 
 use crate::config;
 use crate::debug;
@@ -16,10 +17,10 @@ use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClie
 use crate::platform::chip::Chip;
 use crate::process::ProcessLoadingAsyncClient;
 use crate::process_loading::{
-    PaddingRequirement, ProcessLoadError, SequentialProcessLoaderMachine,
+    PaddingRequirement, ProcessLoadError, Relocation, SequentialProcessLoaderMachine,
 };
 use crate::process_standard::ProcessStandardDebug;
-use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use crate::utilities::leasable_buffer::SubSliceMut;
 use crate::ErrorCode;
 
@@ -38,6 +39,41 @@ pub enum State {
     Abort,
     PaddingWrite,
     Fail,
+    /// Loading a new binary that replaces a loaded process.
+    Replace,
+    /// Writing a padding header over the binary that lost the replacement.
+    Retire,
+    /// Writing a padding header over the binary of an uninstalled process.
+    Uninstall,
+    /// Moving binaries to compact the application flash.
+    Compact,
+    /// Writing a padding header over the binary of a process that is rolled
+    /// back to a previous version.
+    Rollback,
+}
+
+/// Steps of moving a binary to compact the application flash.
+///
+/// The steps are ordered so that the list of binaries in flash stays valid
+/// if the board loses power at any point: the new copy of the binary only
+/// becomes part of the list once it is complete, and the old copy is only
+/// retired once the new copy is part of the list.
+#[derive(Clone, Copy, PartialEq)]
+enum CompactStep {
+    /// Covering the gap with a single padding binary, so the padding
+    /// headers inside the gap are no longer part of the list.
+    Merge,
+    /// Writing a padding binary after the new location of the binary.
+    PostPad,
+    /// Copying the binary, except its base header.
+    Copy,
+    /// Writing the base header of the binary at its new location.
+    Header,
+    /// Shortening the padding binary before the new location of the binary,
+    /// which adds the new copy to the list.
+    PrePad,
+    /// Writing a padding header over the old location of the binary.
+    Retire,
 }
 
 /// Addresses of where the new process will be stored.
@@ -49,6 +85,8 @@ struct ProcessLoadMetadata {
     next_app_start_addr: usize,
     padding_requirement: PaddingRequirement,
     setup_padding: bool,
+    replaced_app_start_addr: usize,
+    replaced_app_length: usize,
 }
 
 /// This interface supports flashing binaries at runtime.
@@ -115,6 +153,53 @@ pub trait DynamicProcessLoad {
     /// Call to request kernel to load a new process.
     fn load(&self) -> Result<(), ErrorCode>;
 
+    /// Call to request kernel to load the new process in place of the
+    /// process with identifier `process_id`, as returned by `ProcessId::id()`.
+    ///
+    /// The old process keeps running until the new binary has passed its
+    /// credential checks and the new process is created. Only then is the
+    /// old process terminated and its binary retired. If the new binary
+    /// cannot be loaded, it is retired instead and the old process is left
+    /// untouched. `load_done()` is called when the binary that lost is
+    /// retired.
+    fn load_replacing(&self, process_id: usize) -> Result<(), ErrorCode>;
+
+    /// Call to request kernel to load the new process in place of the
+    /// process with identifier `process_id`, like `load_replacing()`, but
+    /// keep the binary of the old process in flash so the process can be
+    /// rolled back to it with `rollback()`.
+    ///
+    /// The new binary must have a higher version than the binary of the old
+    /// process, so the new binary is the one loaded at boot. Returns
+    /// `ErrorCode::INVAL` otherwise.
+    fn load_upgrading(&self, process_id: usize) -> Result<(), ErrorCode>;
+
+    /// Call to request kernel to terminate and remove the process with
+    /// identifier `process_id`, retire its binary, and load the binary of the
+    /// same app with the highest lower version in its place.
+    ///
+    /// `load_done()` is called when the previous binary is loaded. Returns
+    /// `ErrorCode::INVAL` if there is no previous binary of the app.
+    fn rollback(&self, process_id: usize) -> Result<(), ErrorCode>;
+
+    /// Call to request kernel to terminate and remove the process with
+    /// identifier `process_id`, and retire its binary so the flash it uses
+    /// can be reused.
+    ///
+    /// `uninstall_done()` is called when the binary is retired and the
+    /// process removed. The process keeps running until then.
+    fn uninstall(&self, process_id: usize) -> Result<(), ErrorCode>;
+
+    /// Call to request kernel to compact the application flash, by moving
+    /// binaries into the gaps before them.
+    ///
+    /// The process of a binary is terminated before the binary is moved, and
+    /// loaded again from the new location once it is moved, so it restarts.
+    /// `compact_done()` is called when no binary can be moved anymore.
+    ///
+    /// Returns `ErrorCode::ALREADY` if no binary can be moved.
+    fn compact(&self) -> Result<(), ErrorCode>;
+
     /// Sets a client for the SequentialDynamicProcessLoading Object
     ///
     /// When the client operation is done, it calls the `load_done()`
@@ -126,6 +211,14 @@ pub trait DynamicProcessLoad {
 pub trait DynamicProcessLoadClient {
     /// The new app has been loaded.
     fn load_done(&self, result: Result<(), ProcessLoadError>);
+
+    /// The process has been removed and its binary retired.
+    fn uninstall_done(&self, result: Result<(), ErrorCode>);
+
+    /// The application flash has been compacted. The result is an error if
+    /// a binary could not be moved, or if a moved process could not be loaded
+    /// again.
+    fn compact_done(&self, result: Result<(), ErrorCode>);
 }
 
 /// Dynamic process loading machine.
@@ -142,6 +235,21 @@ pub struct SequentialDynamicBinaryStorage<
     storage_client: OptionalCell<&'static dyn DynamicBinaryStoreClient>,
     load_client: OptionalCell<&'static dyn DynamicProcessLoadClient>,
     process_metadata: OptionalCell<ProcessLoadMetadata>,
+    /// Result of loading a binary that replaces a loaded process.
+    replace_result: MapCell<Result<(), ProcessLoadError>>,
+    /// Whether the binary of the replaced process is kept in flash.
+    keep_replaced: Cell<bool>,
+    /// Binary being moved to compact the application flash.
+    relocation: OptionalCell<Relocation>,
+    compact_step: Cell<CompactStep>,
+    /// Number of bytes of the binary copied to its new location.
+    compact_offset: Cell<usize>,
+    /// Whether the binary being moved was loaded as a process.
+    relocated_process: Cell<bool>,
+    /// Identifier of the process being uninstalled or rolled back. It is
+    /// removed once its binary is retired.
+    retired_process: OptionalCell<usize>,
+    compact_result: Cell<Result<(), ErrorCode>>,
     state: Cell<State>,
     deferred_call: DeferredCall,
 }
@@ -161,6 +269,14 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
             storage_client: OptionalCell::empty(),
             load_client: OptionalCell::empty(),
             process_metadata: OptionalCell::empty(),
+            replace_result: MapCell::empty(),
+            keep_replaced: Cell::new(false),
+            relocation: OptionalCell::empty(),
+            compact_step: Cell::new(CompactStep::Merge),
+            compact_offset: Cell::new(0),
+            relocated_process: Cell::new(false),
+            retired_process: OptionalCell::empty(),
+            compact_result: Cell::new(Ok(())),
             state: Cell::new(State::Idle),
             deferred_call: DeferredCall::new(),
         }
@@ -210,7 +326,14 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
             // If we are going to write the padding header, we already know
             // where to write in flash, so we don't have to add the start
             // address
-            State::Setup | State::Load | State::PaddingWrite | State::Abort => Ok(offset),
+            State::Setup
+            | State::Load
+            | State::PaddingWrite
+            | State::Abort
+            | State::Retire
+            | State::Uninstall
+            | State::Compact
+            | State::Rollback => Ok(offset),
             // We aren't supposed to be able to write unless we are in one of
             // the first two write states
             _ => Err(ErrorCode::FAIL),
@@ -284,6 +407,199 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
         self.flash_driver.write(buffer, physical_address, length)
     }
 
+    /// Load the new binary in place of the process with identifier
+    /// `process_id`. If `keep_replaced` is set, the binary of the old process
+    /// is kept in flash, and the new binary must have a higher version.
+    fn start_replacement(&self, process_id: usize, keep_replaced: bool) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Load => {
+                let (Some(mut metadata), Some((replaced_start, replaced_length))) = (
+                    self.process_metadata.get(),
+                    self.loader_driver.process_binary_region(process_id),
+                ) else {
+                    return Err(ErrorCode::INVAL);
+                };
+                if keep_replaced {
+                    let new_version = self
+                        .loader_driver
+                        .binary_version_at(metadata.new_app_start_addr);
+                    let replaced_version = self.loader_driver.binary_version_at(replaced_start);
+                    if new_version <= replaced_version {
+                        return Err(ErrorCode::INVAL);
+                    }
+                }
+                metadata.replaced_app_start_addr = replaced_start;
+                metadata.replaced_app_length = replaced_length;
+                self.process_metadata.set(metadata);
+
+                self.replace_result.take();
+                self.keep_replaced.set(keep_replaced);
+                self.state.set(State::Replace);
+                match self.loader_driver.load_new_process_binary_replacing(
+                    metadata.new_app_start_addr,
+                    metadata.new_app_length,
+                    process_id,
+                ) {
+                    Ok(()) => Ok(()),
+                    Err(_e) => {
+                        // Stay ready to load, so the caller can still load
+                        // the new binary or abort.
+                        self.state.set(State::Load);
+                        Err(ErrorCode::FAIL)
+                    }
+                }
+            }
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Retire the binary that lost a replacement: the old binary if the new
+    /// process was loaded, otherwise the new binary.
+    fn retire_replaced_binary(&self) {
+        let Some(metadata) = self.process_metadata.get() else {
+            self.reset_process_loading_metadata();
+            return;
+        };
+        // The new binary was not loaded if no process was created, for
+        // example because another process with the same AppID is running.
+        if self.replace_result.is_none() {
+            self.replace_result
+                .replace(Err(ProcessLoadError::InternalError));
+        }
+        let replaced = self.replace_result.map_or(false, |result| result.is_ok());
+        if replaced && self.keep_replaced.get() {
+            // The old binary stays in flash as a rollback target.
+            self.reset_process_loading_metadata();
+            let result = self
+                .replace_result
+                .take()
+                .unwrap_or(Err(ProcessLoadError::InternalError));
+            self.load_client.map(|client| {
+                client.load_done(result);
+            });
+            return;
+        }
+        let (start, length) = if replaced {
+            (
+                metadata.replaced_app_start_addr,
+                metadata.replaced_app_length,
+            )
+        } else {
+            (metadata.new_app_start_addr, metadata.new_app_length)
+        };
+
+        self.state.set(State::Retire);
+        if let Err(e) = self.write_padding_app(length, start) {
+            // The loser cannot be retired now. Both binaries stay in flash,
+            // and the one with the lower version is not loaded at boot.
+            if config::CONFIG.debug_load_processes {
+                debug!("Unable to retire replaced binary: {:?}", e);
+            }
+            self.reset_process_loading_metadata();
+            let result = self
+                .replace_result
+                .take()
+                .unwrap_or(Err(ProcessLoadError::InternalError));
+            self.load_client.map(|client| {
+                client.load_done(result);
+            });
+        }
+    }
+
+    /// Start moving the binary described by `relocation`.
+    fn start_relocation(&self, relocation: Relocation) -> Result<(), ErrorCode> {
+        // The process cannot keep running while its binary is moved. The
+        // loader gives its memory back to it when it is loaded again.
+        let process_id = self.loader_driver.process_id_at(relocation.from);
+        if let Some(process_id) = process_id {
+            self.loader_driver
+                .remove_process(process_id, Some(relocation.to))?;
+        }
+        self.relocated_process.set(process_id.is_some());
+        self.relocation.set(relocation);
+        self.compact_offset.set(PADDING_TBF_HEADER_LENGTH);
+        self.run_compact_step(CompactStep::Merge)
+    }
+
+    /// Run `step` of moving the current binary, or the first step after it
+    /// with something to write.
+    fn run_compact_step(&self, step: CompactStep) -> Result<(), ErrorCode> {
+        let relocation = self.relocation.get().ok_or(ErrorCode::FAIL)?;
+        let new_end = relocation.to + relocation.length;
+        let step = match step {
+            CompactStep::PostPad if new_end == relocation.gap_end => CompactStep::Copy,
+            CompactStep::PrePad if relocation.to == relocation.gap_start => CompactStep::Retire,
+            step => step,
+        };
+        self.compact_step.set(step);
+        match step {
+            CompactStep::Merge => self.write_padding_app(
+                relocation.gap_end - relocation.gap_start,
+                relocation.gap_start,
+            ),
+            CompactStep::PostPad => self.write_padding_app(relocation.gap_end - new_end, new_end),
+            CompactStep::Copy => self.copy_relocated_binary(relocation, self.compact_offset.get()),
+            CompactStep::Header => self.copy_relocated_binary(relocation, 0),
+            CompactStep::PrePad => {
+                self.write_padding_app(relocation.to - relocation.gap_start, relocation.gap_start)
+            }
+            CompactStep::Retire => self.write_padding_app(relocation.length, relocation.from),
+        }
+    }
+
+    /// Copy the part of the binary being moved that starts at `offset` to its
+    /// new location. The base header is copied on its own, as it is copied
+    /// last.
+    fn copy_relocated_binary(
+        &self,
+        relocation: Relocation,
+        offset: usize,
+    ) -> Result<(), ErrorCode> {
+        let end = if offset == 0 {
+            PADDING_TBF_HEADER_LENGTH
+        } else {
+            relocation.length
+        };
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let length = cmp::min(end - offset, buffer.len());
+        let Some(source) = self
+            .loader_driver
+            .flash_region(relocation.from + offset, length)
+        else {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::FAIL);
+        };
+        buffer[..length].copy_from_slice(source);
+        self.flash_driver
+            .write(buffer, relocation.to + offset, length)
+    }
+
+    /// Move the next binary that can be moved, or end the compaction.
+    fn next_relocation(&self) {
+        let result = match self.loader_driver.find_relocation() {
+            Some(relocation) => self.start_relocation(relocation),
+            None => {
+                self.finish_compaction(self.compact_result.get());
+                return;
+            }
+        };
+        if let Err(e) = result {
+            self.finish_compaction(Err(e));
+        }
+    }
+
+    /// End the compaction and report `result`.
+    ///
+    /// If the compaction stops while a binary is being moved, its old copy
+    /// stays in flash, and its process is loaded again at the next boot.
+    fn finish_compaction(&self, result: Result<(), ErrorCode>) {
+        self.relocation.clear();
+        self.reset_process_loading_metadata();
+        self.load_client.map(|client| {
+            client.compact_done(result);
+        });
+    }
+
     /// Function to generate the padding header to append after the new app.
     /// This header is created and written to ensure the integrity of the
     /// processes linked list
@@ -426,11 +742,112 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
                     client.abort_done(Ok(()));
                 });
             }
-            State::Idle => {
+            State::Retire => {
+                // The binary that lost the replacement is retired, report
+                // the result of the replacement.
+                self.buffer.replace(buffer);
+                self.reset_process_loading_metadata();
+                let result = self
+                    .replace_result
+                    .take()
+                    .unwrap_or(Err(ProcessLoadError::InternalError));
+                self.load_client.map(|client| {
+                    client.load_done(result);
+                });
+            }
+            State::Uninstall => {
+                // The binary is retired, so the process can be removed.
+                self.buffer.replace(buffer);
+                self.reset_process_loading_metadata();
+                let result = self
+                    .retired_process
+                    .take()
+                    .map_or(Err(ErrorCode::FAIL), |process_id| {
+                        self.loader_driver.remove_process(process_id, None)
+                    });
+                self.load_client.map(|client| {
+                    client.uninstall_done(result);
+                });
+            }
+            State::Compact => {
+                self.buffer.replace(buffer);
+                let next = match self.compact_step.get() {
+                    CompactStep::Merge => CompactStep::PostPad,
+                    CompactStep::PostPad => CompactStep::Copy,
+                    CompactStep::Copy => {
+                        self.compact_offset.set(self.compact_offset.get() + length);
+                        let copied = self
+                            .relocation
+                            .map_or(true, |r| self.compact_offset.get() >= r.length);
+                        if copied {
+                            CompactStep::Header
+                        } else {
+                            CompactStep::Copy
+                        }
+                    }
+                    CompactStep::Header => CompactStep::PrePad,
+                    CompactStep::PrePad => CompactStep::Retire,
+                    CompactStep::Retire => {
+                        // The binary is moved. Load its process again, and
+                        // move the next binary once it is loaded.
+                        let relocated = self
+                            .relocation
+                            .get()
+                            .filter(|_| self.relocated_process.get());
+                        match relocated {
+                            Some(relocation) => {
+                                if self
+                                    .loader_driver
+                                    .load_new_process_binary(relocation.to, relocation.length)
+                                    .is_err()
+                                {
+                                    self.compact_result.set(Err(ErrorCode::FAIL));
+                                    self.next_relocation();
+                                }
+                            }
+                            None => self.next_relocation(),
+                        }
+                        return;
+                    }
+                };
+                if let Err(e) = self.run_compact_step(next) {
+                    self.finish_compaction(Err(e));
+                }
+            }
+            State::Rollback => {
+                // The binary of the rolled back process is retired, remove the
+                // process and load the previous binary. The loader reports the
+                // result.
+                self.buffer.replace(buffer);
+                if let Some(process_id) = self.retired_process.take() {
+                    let _ = self.loader_driver.remove_process(process_id, None);
+                }
+                let previous = self.process_metadata.get();
+                self.reset_process_loading_metadata();
+                let loaded = previous.map_or(Err(ProcessLoadError::InternalError), |metadata| {
+                    self.loader_driver
+                        .load_new_process_binary(
+                            metadata.new_app_start_addr,
+                            metadata.new_app_length,
+                        )
+                        .map_err(|_| ProcessLoadError::InternalError)
+                });
+                if let Err(e) = loaded {
+                    self.load_client.map(|client| {
+                        client.load_done(Err(e));
+                    });
+                }
+            }
+            State::Idle | State::Replace => {
                 self.buffer.replace(buffer);
             }
         }
     }
+
+    fn erase_done(&self, _length: usize) {
+        // Binaries are never erased, they are overwritten with padding. There
+        // is nothing to do.
+    }
 }
 
 /// Callback client for the async process loader
@@ -438,12 +855,31 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
     ProcessLoadingAsyncClient for SequentialDynamicBinaryStorage<'_, 'b, C, D, F>
 {
     fn process_loaded(&self, result: Result<(), ProcessLoadError>) {
+        if self.state.get() == State::Compact {
+            if result.is_err() {
+                self.compact_result.set(Err(ErrorCode::FAIL));
+            }
+            return;
+        }
+        if self.state.get() == State::Replace {
+            // Report the result once the binary that lost is retired.
+            self.replace_result.replace(result);
+            return;
+        }
         self.load_client.map(|client| {
             client.load_done(result);
         });
     }
 
     fn process_loading_finished(&self) {
+        if self.state.get() == State::Compact {
+            self.next_relocation();
+            return;
+        }
+        if self.state.get() == State::Replace {
+            self.retire_replaced_binary();
+            return;
+        }
         self.load_client.map(|client| {
             client.load_done(Ok(()));
         });
@@ -671,4 +1107,78 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
             _ => Err(ErrorCode::INVAL),
         }
     }
+
+    fn load_replacing(&self, process_id: usize) -> Result<(), ErrorCode> {
+        self.start_replacement(process_id, false)
+    }
+
+    fn load_upgrading(&self, process_id: usize) -> Result<(), ErrorCode> {
+        self.start_replacement(process_id, true)
+    }
+
+    fn uninstall(&self, process_id: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let (start, length) = self
+            .loader_driver
+            .process_binary_region(process_id)
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Turn the binary into a padding app, so its flash is reused for new
+        // apps and the process is not loaded again at boot. The process keeps
+        // running until the padding is written, so it is left untouched if
+        // the write fails.
+        self.state.set(State::Uninstall);
+        self.retired_process.set(process_id);
+        self.write_padding_app(length, start).inspect_err(|_| {
+            self.retired_process.clear();
+            self.state.set(State::Idle);
+        })
+    }
+
+    fn rollback(&self, process_id: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let (previous_start, previous_length) = self
+            .loader_driver
+            .previous_binary(process_id)
+            .ok_or(ErrorCode::INVAL)?;
+        let (start, length) = self
+            .loader_driver
+            .process_binary_region(process_id)
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Retire the binary first, so it is not loaded again at boot in place
+        // of the previous binary. The process is removed once the padding is
+        // written.
+        self.process_metadata.set(ProcessLoadMetadata {
+            new_app_start_addr: previous_start,
+            new_app_length: previous_length,
+            ..Default::default()
+        });
+        self.state.set(State::Rollback);
+        self.retired_process.set(process_id);
+        self.write_padding_app(length, start).inspect_err(|_| {
+            self.retired_process.clear();
+            self.reset_process_loading_metadata();
+        })
+    }
+
+    fn compact(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let relocation = self
+            .loader_driver
+            .find_relocation()
+            .ok_or(ErrorCode::ALREADY)?;
+        self.state.set(State::Compact);
+        self.compact_result.set(Ok(()));
+        self.start_relocation(relocation).inspect_err(|_| {
+            self.relocation.clear();
+            self.state.set(State::Idle);
+        })
+    }
 }
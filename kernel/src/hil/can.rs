@@ -44,6 +44,17 @@ use core::cmp;
 pub const STANDARD_CAN_PACKET_SIZE: usize = 8;
 pub const FD_CAN_PACKET_SIZE: usize = 64;
 
+/// The payload lengths that can be encoded in the Data Length Code (DLC)
+/// field of a CAN FD frame. Lengths up to 8 bytes map directly to the
+/// DLC, larger payloads must be padded up to one of these values.
+pub const FD_PAYLOAD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Returns the smallest CAN FD payload length that can hold `len` bytes,
+/// or `None` if `len` is larger than `FD_CAN_PACKET_SIZE`.
+pub fn fd_payload_length(len: usize) -> Option<usize> {
+    FD_PAYLOAD_LENGTHS.iter().copied().find(|&l| l >= len)
+}
+
 /// Defines the possible states of the peripheral
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum State {
@@ -138,19 +149,65 @@ pub enum ScaleBits {
 /// an identifier or by bitwise matching multiple identifiers.
 #[derive(Debug, Copy, Clone)]
 pub enum IdentifierMode {
-    /// A mask is used to filter the messages
-    List,
     /// The value of the identifier is used to filter the messages
+    List,
+    /// A mask is used to filter the messages
     Mask,
 }
 
+/// The bits of a standard (11 bit) identifier
+pub const STANDARD_ID_MASK: u32 = 0x7FF;
+
+/// The bits of an extended (29 bit) identifier
+pub const EXTENDED_ID_MASK: u32 = 0x1FFF_FFFF;
+
+/// Number of acceptance filters of the capsules that filter the received
+/// frames in software
+pub const SOFTWARE_FILTER_COUNT: usize = 4;
+
 /// The identifier can be standard (11 bits) or extended (29 bits)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Id {
     Standard(u16),
     Extended(u32),
 }
 
+impl Id {
+    /// Returns the bits that an identifier of this format can use.
+    pub fn mask(&self) -> u32 {
+        match *self {
+            Id::Standard(_) => STANDARD_ID_MASK,
+            Id::Extended(_) => EXTENDED_ID_MASK,
+        }
+    }
+
+    /// Returns whether the identifier fits in 11 bits for a standard
+    /// identifier or in 29 bits for an extended identifier.
+    pub fn is_valid(&self) -> bool {
+        let id = match *self {
+            Id::Standard(id) => id as u32,
+            Id::Extended(id) => id,
+        };
+        id & !self.mask() == 0
+    }
+
+    /// Returns the priority of a frame with this identifier during bus
+    /// arbitration. Frames with a lower value win the arbitration.
+    ///
+    /// The 11 most significant bits of an extended identifier are
+    /// arbitrated first, and a standard frame wins against an extended
+    /// frame with the same 11 bit prefix.
+    pub fn arbitration_priority(&self) -> u32 {
+        match *self {
+            Id::Standard(id) => ((id as u32) & STANDARD_ID_MASK) << 19,
+            Id::Extended(id) => {
+                let id = id & EXTENDED_ID_MASK;
+                ((id >> 18) << 19) | (1 << 18) | (id & 0x3_FFFF)
+            }
+        }
+    }
+}
+
 /// This structure defines the parameters to configure a filter bank
 #[derive(Copy, Clone)]
 pub struct FilterParameters {
@@ -168,6 +225,68 @@ pub struct FilterParameters {
 
     /// The receive FIFO Id that the filter will be applied to
     pub fifo_number: usize,
+
+    /// The identifier the filter accepts
+    ///
+    /// Standard identifiers only match standard frames and extended
+    /// identifiers only match extended frames.
+    ///
+    /// `id` and `mask` are used by the software filters (see
+    /// [`FilterParameters::matches`] and [`FilterParameters::accepted_by`]).
+    /// Hardware filter banks may ignore them and only use the fields above.
+    pub id: Id,
+
+    /// The bits of the identifier that must match in `IdentifierMode::Mask`
+    /// mode. A mask of 0 accepts all the frames with the same identifier
+    /// format.
+    ///
+    /// Like `id`, this only applies to the software filters.
+    pub mask: u32,
+}
+
+impl FilterParameters {
+    /// Creates a filter that accepts the frames whose identifier matches
+    /// `id` on the bits set in `mask`.
+    ///
+    /// Returns `ErrorCode::INVAL` if the identifier or the mask does not
+    /// fit in the identifier format.
+    pub fn mask(number: u32, id: Id, mask: u32) -> Result<Self, ErrorCode> {
+        if !id.is_valid() || mask & !id.mask() != 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(Self {
+            number,
+            scale_bits: ScaleBits::Bits32,
+            identifier_mode: IdentifierMode::Mask,
+            fifo_number: 0,
+            id,
+            mask,
+        })
+    }
+
+    /// Returns whether a frame with the identifier `id` is accepted by a
+    /// set of software filters: it must match at least one of the
+    /// `filters`, unless there are none.
+    pub fn accepted_by(filters: impl IntoIterator<Item = FilterParameters>, id: Id) -> bool {
+        let mut filters = filters.into_iter().peekable();
+        filters.peek().is_none() || filters.any(|filter| filter.matches(id))
+    }
+
+    /// Returns whether a frame with the identifier `id` is accepted by
+    /// this filter.
+    pub fn matches(&self, id: Id) -> bool {
+        let (filter_id, frame_id) = match (self.id, id) {
+            (Id::Standard(filter_id), Id::Standard(frame_id)) => {
+                (filter_id as u32, frame_id as u32)
+            }
+            (Id::Extended(filter_id), Id::Extended(frame_id)) => (filter_id, frame_id),
+            _ => return false,
+        };
+        match self.identifier_mode {
+            IdentifierMode::List => filter_id == frame_id,
+            IdentifierMode::Mask => filter_id & self.mask == frame_id & self.mask,
+        }
+    }
 }
 
 /// This structure defines the parameters for the timing mode
@@ -502,6 +621,8 @@ pub trait Configure {
 ///
 /// - Call `set_bit_timing` to configure the timing settings
 /// - Call `set_operation_mode` to configure the testing mode
+/// - (Optional) Call `set_payload_bit_timing` and `set_bit_rate_switching`
+///   to send the data phase of frames at a higher bit rate
 /// - (Optional) Call `set_automatic_retransmission` and/or
 ///   `set_wake_up` to configure the behaviour of the peripheral
 /// - To apply the settings and be able to use the peripheral, call `enable`
@@ -538,6 +659,36 @@ pub trait ConfigureFd: Configure {
     ///     supported
     fn get_payload_bit_timing(&self) -> Result<BitTiming, ErrorCode>;
 
+    /// Configures whether CAN FD frames are sent with bit rate switching
+    /// (BRS), meaning that the data phase uses the payload bit timing set
+    /// with `set_payload_bit_timing`. This function is supposed to be
+    /// called before the `enable` function. This function is synchronous
+    /// as the driver should only store the argument, and should not
+    /// configure the hardware.
+    ///
+    /// # Arguments:
+    ///
+    /// * `enabled` - Whether the data phase of transmitted frames is sent
+    ///   at the payload bit rate
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok()` - The setting was stored.
+    /// * `Err(ErrorCode)` - Indicates the error because of which the request
+    ///   cannot be completed
+    ///   - `ErrorCode::NOSUPPORT` indicates that bit rate switching is not
+    ///     supported
+    fn set_bit_rate_switching(&self, enabled: bool) -> Result<(), ErrorCode>;
+
+    /// Returns the current bit rate switching setting of the peripheral.
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok(bool)` - The current bit rate switching setting
+    /// * `Err(ErrorCode)` - Indicates the error because of which the request
+    ///   cannot be completed
+    fn get_bit_rate_switching(&self) -> Result<bool, ErrorCode>;
+
     /// Returns the maximum accepted frame size in bytes.
     ///
     /// - for CanFD BRS this should be 8 bytes
@@ -656,7 +807,9 @@ pub trait Transmit<const PACKET_SIZE: usize> {
     ///
     /// * `id` - The identifier of the message (standard or extended)
     /// * `buffer` - Data to be written on the bus
-    /// * `len` - Length of the current message
+    /// * `len` - Length of the current message. For CAN FD frames, lengths
+    ///   that cannot be encoded in the DLC field are padded up to the next
+    ///   value returned by `fd_payload_length`.
     ///
     /// # Return values:
     /// * `Ok()` - The transmission request was successful and the caller will
@@ -808,8 +961,10 @@ pub trait Can:
 {
 }
 
+/// Convenience type for capsules that configure, send
+/// and receive data using a CAN FD peripheral
 pub trait CanFd:
-    Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Receive<FD_CAN_PACKET_SIZE>
+    Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Controller + Receive<FD_CAN_PACKET_SIZE>
 {
 }
 
@@ -824,7 +979,76 @@ impl<
 }
 
 /// Provide blanket implementation for CanFd trait group
-impl<T: Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Receive<FD_CAN_PACKET_SIZE>> CanFd
-    for T
+impl<
+        T: Transmit<FD_CAN_PACKET_SIZE>
+            + Configure
+            + ConfigureFd
+            + Controller
+            + Receive<FD_CAN_PACKET_SIZE>,
+    > CanFd for T
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_ranges() {
+        assert!(Id::Standard(0x7FF).is_valid());
+        assert!(!Id::Standard(0x800).is_valid());
+        assert!(Id::Extended(0x1FFF_FFFF).is_valid());
+        assert!(!Id::Extended(0x2000_0000).is_valid());
+
+        assert!(FilterParameters::mask(0, Id::Standard(0x123), 0x7FF).is_ok());
+        assert!(FilterParameters::mask(0, Id::Standard(0x123), 0x800).is_err());
+        assert!(FilterParameters::mask(0, Id::Standard(0x923), 0x7FF).is_err());
+        assert!(FilterParameters::mask(0, Id::Extended(0x123), 0x1FFF_FFFF).is_ok());
+        assert!(FilterParameters::mask(0, Id::Extended(0x123), 0x3FFF_FFFF).is_err());
+    }
+
+    #[test]
+    fn software_filters() {
+        let filter = |id, mask| FilterParameters::mask(0, id, mask).unwrap();
+        let none: [FilterParameters; 0] = [];
+        assert!(FilterParameters::accepted_by(none, Id::Extended(5)));
+
+        let filters = [
+            filter(Id::Standard(0x120), 0x7F0),
+            filter(Id::Extended(0x42), 0x1FFF_FFFF),
+        ];
+        assert!(FilterParameters::accepted_by(filters, Id::Standard(0x12A)));
+        assert!(!FilterParameters::accepted_by(filters, Id::Standard(0x13A)));
+        assert!(FilterParameters::accepted_by(filters, Id::Extended(0x42)));
+        // Standard filters do not match extended frames.
+        assert!(!FilterParameters::accepted_by(filters, Id::Extended(0x120)));
+    }
+
+    #[test]
+    fn fd_payload_length_rounds_up() {
+        assert_eq!(fd_payload_length(0), Some(0));
+        assert_eq!(fd_payload_length(8), Some(8));
+        assert_eq!(fd_payload_length(9), Some(12));
+        assert_eq!(fd_payload_length(12), Some(12));
+        assert_eq!(fd_payload_length(13), Some(16));
+        assert_eq!(fd_payload_length(21), Some(24));
+        assert_eq!(fd_payload_length(25), Some(32));
+        assert_eq!(fd_payload_length(33), Some(48));
+        assert_eq!(fd_payload_length(49), Some(64));
+        assert_eq!(fd_payload_length(64), Some(64));
+        assert_eq!(fd_payload_length(65), None);
+    }
+
+    #[test]
+    fn fd_payload_lengths_match_dlc() {
+        // Every valid length maps to itself, in DLC order.
+        for (dlc, &length) in FD_PAYLOAD_LENGTHS.iter().enumerate() {
+            assert_eq!(fd_payload_length(length), Some(length));
+            assert_eq!(
+                FD_PAYLOAD_LENGTHS.iter().position(|&l| l == length),
+                Some(dlc)
+            );
+        }
+        assert_eq!(FD_PAYLOAD_LENGTHS[15], FD_CAN_PACKET_SIZE);
+    }
+}
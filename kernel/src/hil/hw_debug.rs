@@ -5,6 +5,26 @@
 //! Interfaces for interacting with debug hardware integrated in various SoCs.
 //! Currently allows reading the cycle counter.
 
+/// Returns the number of cycles a counter `width` bits wide advanced from
+/// `start` to `end`, accounting for the counter wrapping around once.
+pub fn elapsed_cycles(start: u64, end: u64, width: u32) -> u64 {
+    let mask = match width {
+        0..=63 => (1 << width) - 1,
+        _ => u64::MAX,
+    };
+    end.wrapping_sub(start) & mask
+}
+
+/// Converts `cycles` of a counter running at `frequency_hz` to microseconds.
+pub fn cycles_to_us(cycles: u64, frequency_hz: u32) -> u64 {
+    let frequency_hz = u64::from(frequency_hz.max(1));
+    // Convert whole seconds and the remaining cycles separately so that the
+    // multiplication cannot overflow.
+    (cycles / frequency_hz)
+        .saturating_mul(1_000_000)
+        .saturating_add((cycles % frequency_hz) * 1_000_000 / frequency_hz)
+}
+
 pub trait CycleCounter {
     /// Enable and start the cycle counter.
     /// Depending on the underlying hardware, it may be necessary to call reset
@@ -18,12 +38,21 @@ pub trait CycleCounter {
     /// Return the current value of the cycle counter.
     fn count(&self) -> u64;
 
+    /// Return the width of the cycle counter in bits. The value returned by
+    /// [`CycleCounter::count`] wraps around to zero after `2^width - 1`.
+    fn width(&self) -> u32 {
+        64
+    }
+
     /// Reset the counter to zero and stop the cycle counter.
     fn reset(&self);
 
     /// Benchmark the number of cycles to run a passed closure.
     /// This function is intended for use debugging in-kernel routines.
-    fn profile_closure<F: FnOnce()>(&self, f: F) -> u64 {
+    fn profile_closure<F: FnOnce()>(&self, f: F) -> u64
+    where
+        Self: Sized,
+    {
         self.reset();
         self.start();
         f();
@@ -31,3 +60,38 @@ pub trait CycleCounter {
         self.count()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_cycles_32_bit() {
+        assert_eq!(elapsed_cycles(10, 25, 32), 15);
+        assert_eq!(elapsed_cycles(0xFFFF_FFF0, 0x10, 32), 0x20);
+        assert_eq!(elapsed_cycles(5, 5, 32), 0);
+    }
+
+    #[test]
+    fn elapsed_cycles_64_bit() {
+        assert_eq!(elapsed_cycles(10, 25, 64), 15);
+        assert_eq!(elapsed_cycles(0xFFFF_FFF0, 0x1_0000_0010, 64), 0x20);
+        assert_eq!(elapsed_cycles(u64::MAX - 0xF, 0x10, 64), 0x20);
+    }
+
+    #[test]
+    fn elapsed_cycles_narrow() {
+        assert_eq!(elapsed_cycles(0xFFFF_FF, 0x0, 24), 1);
+        assert_eq!(elapsed_cycles(0x10, 0xF, 8), 0xFF);
+    }
+
+    #[test]
+    fn cycles_to_us_whole_and_fractional() {
+        assert_eq!(cycles_to_us(64_000_000, 64_000_000), 1_000_000);
+        assert_eq!(cycles_to_us(63, 64_000_000), 0);
+        // Many short intervals add up once converted together.
+        assert_eq!(cycles_to_us(63 * 1000, 64_000_000), 984);
+        assert_eq!(cycles_to_us(u64::MAX, 1_000_000), u64::MAX);
+        assert_eq!(cycles_to_us(u64::MAX, u32::MAX), 4_294_967_297_000_000);
+    }
+}
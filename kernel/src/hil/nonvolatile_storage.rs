@@ -6,8 +6,8 @@
 
 use crate::errorcode::ErrorCode;
 
-/// Simple interface for reading and writing nonvolatile memory. It is expected
-/// that drivers for nonvolatile memory would implement this trait.
+/// Simple interface for reading, writing and erasing nonvolatile memory. It is
+/// expected that drivers for nonvolatile memory would implement this trait.
 pub trait NonvolatileStorage<'a> {
     fn set_client(&self, client: &'a dyn NonvolatileStorageClient);
 
@@ -30,6 +30,17 @@ pub trait NonvolatileStorage<'a> {
         address: usize,
         length: usize,
     ) -> Result<(), ErrorCode>;
+
+    /// Erase `length` bytes starting at address `address`. This address must
+    /// be in the address space of the physical storage. After the erase
+    /// completes, the erased bytes read as `0xFF`.
+    ///
+    /// Implementations backed by storage with a native erase operation (such
+    /// as flash pages) should use it for the parts of the range it covers.
+    /// Storage that cannot be erased returns `NOSUPPORT`, which is the default.
+    fn erase(&self, _address: usize, _length: usize) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 /// Client interface for nonvolatile storage.
@@ -43,4 +54,12 @@ pub trait NonvolatileStorageClient {
     /// buffer. The callback returns the buffer and the number of bytes that
     /// were actually written.
     fn write_done(&self, buffer: &'static mut [u8], length: usize);
+
+    /// `erase_done` is called when the implementor is finished erasing. The
+    /// callback returns the number of bytes that were actually erased. This is
+    /// less than the requested length if erasing failed, and 0 if a queued
+    /// erase could not be started.
+    ///
+    /// Clients that never erase can rely on the default, which does nothing.
+    fn erase_done(&self, _length: usize) {}
 }
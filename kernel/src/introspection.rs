@@ -127,6 +127,18 @@ impl KernelInfo {
             .process_map_or(0, app, |process| process.debug_timeslice_expiration_count())
     }
 
+    /// Returns the CPU time, in microseconds, this app has used since it
+    /// started. This includes the time the kernel spent handling its system
+    /// calls.
+    pub fn app_cpu_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_cpu_time_us())
+    }
+
     /// Returns a tuple of the (the number of grants in the grant region this
     /// app has allocated, total number of grants that exist in the system).
     pub fn number_app_grant_uses(
@@ -156,4 +168,23 @@ impl KernelInfo {
         });
         count.get()
     }
+
+    /// Returns the total CPU time, in microseconds, all processes have used
+    /// since they started.
+    pub fn processes_cpu_time_us(&self, _capability: &dyn ProcessManagementCapability) -> u64 {
+        let time: Cell<u64> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            time.set(time.get() + proc.debug_cpu_time_us());
+        });
+        time.get()
+    }
+
+    /// Returns the CPU time, in microseconds, the kernel has spent handling
+    /// interrupts and deferred calls since boot.
+    ///
+    /// This is only measured if the board provided a cycle counter with
+    /// `Kernel::set_cycle_counter()`, and is 0 otherwise.
+    pub fn kernel_cpu_time_us(&self, _capability: &dyn ProcessManagementCapability) -> u64 {
+        self.kernel.kernel_cpu_time_us()
+    }
 }
@@ -6,14 +6,23 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! Which processes may connect to the service of another process is decided
+//! by an [`IPCPolicy`]. By default, the IPC clients TBF header of the service
+//! lists the ShortIds of the processes allowed to connect to it, and services
+//! without the header accept any client. Boards can supply their own policy
+//! with [`IPC::set_policy`].
+
+use core::cell::Cell;
 
 use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use crate::kernel::Kernel;
 use crate::process;
-use crate::process::ProcessId;
+use crate::process::{Process, ProcessId};
 use crate::processbuffer::ReadableProcessBuffer;
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::utilities::cells::OptionalCell;
 use crate::ErrorCode;
 
 /// Syscall number
@@ -37,6 +46,77 @@ pub enum IPCUpcallType {
     Client,
 }
 
+/// Client notified when processes notify each other over IPC, for example a
+/// scheduler implementing priority inheritance.
+pub trait IPCNotifyClient {
+    /// `client` notified `service` of a request.
+    fn service_notified(&self, client: ProcessId, service: ProcessId);
+
+    /// `service` notified `client`, typically of the completion of a request.
+    fn client_notified(&self, service: ProcessId, client: ProcessId);
+}
+
+/// Trait for implementing policies that decide which processes may connect to
+/// the IPC service of another process.
+pub trait IPCPolicy {
+    /// Check whether `client` may connect to the service provided by
+    /// `service`, that is discover it, notify it and be notified by it. If the
+    /// connection is allowed return `Ok(())`. Otherwise, return `Err()` with
+    /// an `ErrorCode` that will be returned to the process that attempted the
+    /// operation. The default implementation allows all connections.
+    fn check_connection(
+        &self,
+        _client: &dyn Process,
+        _service: &dyn Process,
+    ) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+/// Implement default allow all IPCPolicy trait for unit.
+impl IPCPolicy for () {}
+
+/// An allow list IPC policy based on the TBF header, with a default allow all
+/// fallback.
+///
+/// If the service has the IPC clients TBF header, only the processes with a
+/// ShortId listed in the header may connect to it. Otherwise, any process may
+/// connect to the service.
+pub struct TbfHeaderIPCPolicy {}
+
+impl IPCPolicy for TbfHeaderIPCPolicy {
+    fn check_connection(
+        &self,
+        client: &dyn Process,
+        service: &dyn Process,
+    ) -> Result<(), ErrorCode> {
+        if service.is_ipc_client_allowed(client.short_app_id()) {
+            Ok(())
+        } else {
+            Err(ErrorCode::NODEVICE)
+        }
+    }
+}
+
+/// Check `policy` for a connection from the process `client` to the service
+/// provided by the process `service`. Fails with `INVAL` if either process
+/// does not exist.
+pub fn check_connection(
+    policy: &dyn IPCPolicy,
+    client: ProcessId,
+    service: ProcessId,
+) -> Result<(), ErrorCode> {
+    client
+        .kernel
+        .process_map_or(Err(ErrorCode::INVAL), client, |client| {
+            service
+                .kernel
+                .process_map_or(Err(ErrorCode::INVAL), service, |service| {
+                    policy.check_connection(client, service)
+                })
+        })
+}
+
 /// State that is stored in each process's grant region to support IPC.
 #[derive(Default)]
 struct IPCData;
@@ -50,6 +130,10 @@ pub struct IPC<const NUM_PROCS: u8> {
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<NUM_PROCS>,
     >,
+    /// Client notified of successful notifies.
+    notify_client: OptionalCell<&'static dyn IPCNotifyClient>,
+    /// Policy deciding which processes may connect to each service.
+    policy: Cell<&'static dyn IPCPolicy>,
 }
 
 impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
@@ -60,9 +144,21 @@ impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
     ) -> Self {
         Self {
             data: kernel.create_grant(driver_num, capability),
+            notify_client: OptionalCell::empty(),
+            policy: Cell::new(&TbfHeaderIPCPolicy {}),
         }
     }
 
+    pub fn set_notify_client(&self, client: &'static dyn IPCNotifyClient) {
+        self.notify_client.set(client);
+    }
+
+    /// Replace the default [`TbfHeaderIPCPolicy`] with a board-specific
+    /// policy.
+    pub fn set_policy(&self, policy: &'static dyn IPCPolicy) {
+        self.policy.set(policy);
+    }
+
     /// Schedule an IPC upcall for a process. This is called by the main
     /// scheduler loop if an IPC task was queued for the process.
     pub(crate) unsafe fn schedule_upcall(
@@ -124,14 +220,17 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     ///
     /// - `0`: Driver existence check, always returns Ok(())
     /// - `1`: Perform discovery on the package name passed to `allow_readonly`.
-    ///   Returns the service descriptor if the service is found, otherwise
-    ///   returns an error.
+    ///   Returns the service descriptor if the service is found and the
+    ///   [`IPCPolicy`] allows the process to connect to it, otherwise returns
+    ///   an error.
     /// - `2`: Notify a service previously discovered to have the service
     ///   descriptor in `target_id`. Returns an error if `target_id` refers to
-    ///   an invalid service or the notify fails to enqueue.
+    ///   an invalid service, the [`IPCPolicy`] does not allow the process to
+    ///   connect to the service, or the notify fails to enqueue.
     /// - `3`: Notify a client with descriptor `target_id`, typically in
     ///   response to a previous notify from the client. Returns an error if
-    ///   `target_id` refers to an invalid client or the notify fails to
+    ///   `target_id` refers to an invalid client, the [`IPCPolicy`] does not
+    ///   allow the client to connect to the process, or the notify fails to
     ///   enqueue.
     fn command(
         &self,
@@ -160,6 +259,12 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                                                 && s.iter()
                                                     .zip(slice.iter())
                                                     .all(|(c1, c2)| *c1 == c2.get())
+                                                && check_connection(
+                                                    self.policy.get(),
+                                                    processid,
+                                                    p.processid(),
+                                                )
+                                                .is_ok()
                                             {
                                                 // Return the index of the process which is used for
                                                 // subscribe number
@@ -195,9 +300,17 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                         CommandReturn::failure(ErrorCode::INVAL),
                         otherapp,
                         |target| {
+                            if let Err(e) = check_connection(self.policy.get(), processid, otherapp)
+                            {
+                                return CommandReturn::failure(e);
+                            }
                             let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
                             match ret {
-                                Ok(()) => CommandReturn::success(),
+                                Ok(()) => {
+                                    self.notify_client
+                                        .map(|client| client.service_notified(processid, otherapp));
+                                    CommandReturn::success()
+                                }
                                 Err(e) => {
                                     // `enqueue_task` does not provide information on whether the
                                     // recipient has set a non-null callback. It only reports
@@ -228,9 +341,17 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                         CommandReturn::failure(ErrorCode::INVAL),
                         otherapp,
                         |target| {
+                            if let Err(e) = check_connection(self.policy.get(), otherapp, processid)
+                            {
+                                return CommandReturn::failure(e);
+                            }
                             let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
                             match ret {
-                                Ok(()) => CommandReturn::success(),
+                                Ok(()) => {
+                                    self.notify_client
+                                        .map(|client| client.client_notified(processid, otherapp));
+                                    CommandReturn::success()
+                                }
                                 Err(e) => {
                                     // `enqueue_task` does not provide information on whether the
                                     // recipient has set a non-null callback. It only reports
@@ -17,24 +17,29 @@ use crate::debug;
 use crate::deferred_call::DeferredCall;
 use crate::errorcode::ErrorCode;
 use crate::grant::{AllowRoSize, AllowRwSize, Grant, UpcallSize};
+use crate::hil::hw_debug::{cycles_to_us, elapsed_cycles, CycleCounter};
 use crate::ipc;
 use crate::memop;
+use crate::memory_pool::MemoryPool;
 use crate::platform::chip::Chip;
 use crate::platform::mpu::MPU;
 use crate::platform::platform::ContextSwitchCallback;
 use crate::platform::platform::KernelResources;
 use crate::platform::platform::{ProcessFault, SyscallDriverLookup, SyscallFilter};
+use crate::platform::power::PowerManager;
 use crate::platform::scheduler_timer::SchedulerTimer;
 use crate::platform::watchdog::WatchDog;
-use crate::process::ProcessSlot;
 use crate::process::{self, ProcessId, Task};
+use crate::process::{ProcessMemoryGrowthPolicy, ProcessSlot, ProcessUpcallQueuePolicy};
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::syscall_trace::{SyscallTracer, TraceRecord};
 use crate::upcall::{Upcall, UpcallId};
 use crate::utilities::cells::NumericCellExt;
+use crate::utilities::cells::OptionalCell;
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
 /// That is, Tock will skip re-scheduling a process if its remaining timeslice
@@ -60,6 +65,27 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Cycle counter used to measure the CPU time used by processes and the
+    /// kernel, along with its frequency in Hz.
+    cycle_counter: OptionalCell<(&'static dyn CycleCounter, u32)>,
+
+    /// CPU time, in cycles of the cycle counter, spent executing kernel work
+    /// (interrupt bottom halves and deferred calls).
+    kernel_cpu_cycles: Cell<u64>,
+
+    /// Power manager selecting the sleep state of the chip.
+    power_manager: OptionalCell<&'static dyn PowerManager>,
+
+    /// Shared memory pool processes can grow their grant region into, and the
+    /// policy deciding which processes can.
+    memory_pool: OptionalCell<(&'static MemoryPool, &'static dyn ProcessMemoryGrowthPolicy)>,
+
+    /// Tracer recording every system call and upcall.
+    syscall_tracer: OptionalCell<&'static dyn SyscallTracer>,
+
+    /// Policy deciding how upcalls are queued for processes.
+    upcall_queue_policy: OptionalCell<&'static dyn ProcessUpcallQueuePolicy>,
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -94,9 +120,106 @@ impl Kernel {
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            cycle_counter: OptionalCell::empty(),
+            kernel_cpu_cycles: Cell::new(0),
+            power_manager: OptionalCell::empty(),
+            memory_pool: OptionalCell::empty(),
+            syscall_tracer: OptionalCell::empty(),
+            upcall_queue_policy: OptionalCell::empty(),
+        }
+    }
+
+    /// Let processes allocate grants from `pool` when their own memory is
+    /// exhausted, as allowed by `policy`.
+    pub fn set_memory_pool(
+        &self,
+        pool: &'static MemoryPool,
+        policy: &'static dyn ProcessMemoryGrowthPolicy,
+    ) {
+        self.memory_pool.set((pool, policy));
+    }
+
+    /// Returns the shared memory pool and its policy, if the board set one.
+    pub(crate) fn memory_pool(
+        &self,
+    ) -> Option<(&'static MemoryPool, &'static dyn ProcessMemoryGrowthPolicy)> {
+        self.memory_pool.get()
+    }
+
+    /// Let `policy` decide whether upcalls scheduled for processes are
+    /// enqueued, coalesced with pending upcalls or dropped. Without a policy,
+    /// upcalls are dropped only when the task queue of the process is full.
+    pub fn set_upcall_queue_policy(&self, policy: &'static dyn ProcessUpcallQueuePolicy) {
+        self.upcall_queue_policy.set(policy);
+    }
+
+    /// Returns the upcall queue policy, if the board set one.
+    pub(crate) fn upcall_queue_policy(&self) -> Option<&'static dyn ProcessUpcallQueuePolicy> {
+        self.upcall_queue_policy.get()
+    }
+
+    /// Record every system call and upcall with `tracer`.
+    pub fn set_syscall_tracer(&self, tracer: &'static dyn SyscallTracer) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Let `power_manager` select the sleep state of the chip each time the
+    /// kernel has no work to do. Without a power manager, the kernel calls
+    /// `Chip::sleep()`.
+    pub fn set_power_manager(&self, power_manager: &'static dyn PowerManager) {
+        self.power_manager.set(power_manager);
+    }
+
+    /// Put the chip to sleep until the next interrupt, in the deepest state
+    /// the power manager allows.
+    fn sleep<C: Chip>(&self, chip: &C) {
+        match self.power_manager.get() {
+            Some(power_manager) => {
+                let state =
+                    power_manager.select_sleep_state(&|state| chip.sleep_wakeup_latency_us(state));
+                power_manager.sleep_started(state);
+                chip.sleep_in_state(state);
+                power_manager.sleep_ended(state);
+            }
+            None => chip.sleep(),
         }
     }
 
+    /// Measure the CPU time used by processes and the kernel with a cycle
+    /// counter running at `frequency_hz`.
+    ///
+    /// Without a cycle counter, only the time processes run with a timeslice
+    /// is measured, using the scheduler timer, and the time spent in the
+    /// kernel is not measured.
+    pub fn set_cycle_counter(&self, cycle_counter: &'static dyn CycleCounter, frequency_hz: u32) {
+        cycle_counter.reset();
+        cycle_counter.start();
+        self.cycle_counter.set((cycle_counter, frequency_hz));
+    }
+
+    /// Returns the current value of the cycle counter, if there is one.
+    fn cycle_count(&self) -> Option<u64> {
+        self.cycle_counter
+            .map(|(cycle_counter, _)| cycle_counter.count())
+    }
+
+    /// Returns the number of cycles since the cycle counter had the value
+    /// `start`, along with the frequency of the cycle counter.
+    fn cycles_since(&self, start: Option<u64>) -> Option<(u64, u32)> {
+        let start = start?;
+        self.cycle_counter.map(|(cycle_counter, frequency_hz)| {
+            let cycles = elapsed_cycles(start, cycle_counter.count(), cycle_counter.width());
+            (cycles, frequency_hz)
+        })
+    }
+
+    /// Returns the CPU time, in microseconds, spent executing kernel work.
+    pub(crate) fn kernel_cpu_time_us(&self) -> u64 {
+        self.cycle_counter.map_or(0, |(_, frequency_hz)| {
+            cycles_to_us(self.kernel_cpu_cycles.get(), frequency_hz)
+        })
+    }
+
     /// Helper function that moves all non-generic portions of process_map_or
     /// into a non-generic function to reduce code bloat from monomorphization.
     pub(crate) fn get_process(&self, processid: ProcessId) -> Option<&dyn process::Process> {
@@ -314,6 +437,20 @@ impl Kernel {
         Err(())
     }
 
+    /// Find the slot storing the process with identifier `identifier`, as
+    /// returned by `ProcessId::id()`.
+    ///
+    /// Returns `None` if no loaded process has this identifier.
+    pub(crate) fn process_slot_by_identifier(
+        &self,
+        identifier: usize,
+    ) -> Option<(usize, &ProcessSlot)> {
+        self.processes
+            .iter()
+            .enumerate()
+            .find(|(_, slot)| slot.contains_process_with_id(identifier))
+    }
+
     /// Cause all apps to fault.
     ///
     /// This will call `set_fault_state()` on each app, causing the app to enter
@@ -367,7 +504,12 @@ impl Kernel {
                     // Execute kernel work. This includes handling
                     // interrupts and is how code in the chips/ and capsules
                     // crates is able to execute.
+                    let start = self.cycle_count();
                     scheduler.execute_kernel_work(chip);
+                    if let Some((cycles, _)) = self.cycles_since(start) {
+                        self.kernel_cpu_cycles
+                            .set(self.kernel_cpu_cycles.get() + cycles);
+                    }
                 }
                 false => {
                     // No kernel work ready, so ask scheduler for a process.
@@ -398,7 +540,7 @@ impl Kernel {
                                     if !chip.has_pending_interrupts() && !DeferredCall::has_tasks()
                                     {
                                         resources.watchdog().suspend();
-                                        chip.sleep();
+                                        self.sleep(chip);
                                         resources.watchdog().resume();
                                     }
                                 });
@@ -477,6 +619,9 @@ impl Kernel {
             resources.scheduler_timer()
         };
 
+        // Record when the process starts to account for its CPU time.
+        let start = self.cycle_count();
+
         // Clear the scheduler timer and then start the counter. This starts the
         // process's timeslice. Since the kernel is still executing at this
         // point, the scheduler timer need not have an interrupt enabled after
@@ -608,6 +753,13 @@ impl Kernel {
                                         ccb.argument3,
                                     );
                                 }
+                                self.syscall_tracer.map(|tracer| {
+                                    tracer.record(TraceRecord::upcall(
+                                        process.processid(),
+                                        Some(ccb.pc.as_ptr::<()>() as usize),
+                                        [ccb.argument0, ccb.argument1, ccb.argument2],
+                                    ));
+                                });
                                 process.set_process_function(ccb);
                             }
                             Task::IPC((otherapp, ipc_type)) => {
@@ -674,6 +826,13 @@ impl Kernel {
                                 }
                                 Task::IPC(_) => todo!(),
                             };
+                            self.syscall_tracer.map(|tracer| {
+                                tracer.record(TraceRecord::upcall(
+                                    process.processid(),
+                                    None,
+                                    [a0, a1, a2],
+                                ));
+                            });
                             process
                                 .set_syscall_return_value(SyscallReturn::YieldWaitFor(a0, a1, a2));
                         }
@@ -708,6 +867,16 @@ impl Kernel {
             }
         });
 
+        // Charge the process for the time it used. The cycle counter is
+        // preferred as it also measures processes run cooperatively.
+        match (self.cycles_since(start), time_executed_us) {
+            (Some((cycles, frequency_hz)), _) => {
+                process.debug_cpu_cycles_used(cycles, frequency_hz);
+            }
+            (None, Some(us)) => process.debug_cpu_time_used(u64::from(us)),
+            (None, None) => {}
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
@@ -716,6 +885,30 @@ impl Kernel {
         (return_reason, time_executed_us)
     }
 
+    /// Record `syscall`, issued by `process_id`, with the tracer, if the board
+    /// set one.
+    fn trace_syscall(
+        &self,
+        process_id: ProcessId,
+        syscall: &Syscall,
+        return_value: Option<SyscallReturn>,
+    ) {
+        self.syscall_tracer.map(|tracer| {
+            tracer.record(TraceRecord::syscall(process_id, syscall, return_value));
+        });
+    }
+
+    /// Return `return_value` to `process` as the result of `syscall`.
+    fn set_syscall_return(
+        &self,
+        process: &dyn process::Process,
+        syscall: &Syscall,
+        return_value: SyscallReturn,
+    ) {
+        self.trace_syscall(process.processid(), syscall, Some(return_value));
+        process.set_syscall_return_value(return_value);
+    }
+
     /// Method to invoke a system call on a particular process. Applies the
     /// kernel system call filtering policy (if any). Handles `Yield` and
     /// `Exit`, dispatches `Memop` to `memop::memop`, and dispatches peripheral
@@ -760,7 +953,15 @@ impl Kernel {
                 // Check all other syscalls for filtering.
                 if let Err(response) = resources.syscall_filter().filter_syscall(process, &syscall)
                 {
-                    process.set_syscall_return_value(SyscallReturn::Failure(response));
+                    // The filter may have put the process in the fault state,
+                    // in which case it does not get a return value.
+                    if process.get_state() == process::State::Running {
+                        self.set_syscall_return(
+                            process,
+                            &syscall,
+                            SyscallReturn::Failure(response),
+                        );
+                    }
 
                     if config::CONFIG.trace_syscalls {
                         debug!(
@@ -789,7 +990,7 @@ impl Kernel {
                         rval
                     );
                 }
-                process.set_syscall_return_value(rval);
+                self.set_syscall_return(process, &syscall, rval);
             }
             Syscall::Yield {
                 which,
@@ -799,6 +1000,7 @@ impl Kernel {
                 if config::CONFIG.trace_syscalls {
                     debug!("[{:?}] yield. which: {}", process.processid(), which);
                 }
+                self.trace_syscall(process.processid(), &syscall, None);
                 match which.try_into() {
                     Ok(YieldCall::NoWait) => {
                         // If this is a `Yield-WaitFor` AND there are no pending
@@ -1021,7 +1223,7 @@ impl Kernel {
                             );
                         }
 
-                        process.set_syscall_return_value(rval);
+                        self.set_syscall_return(process, &syscall, rval);
                     }
                     Syscall::Command {
                         driver_number,
@@ -1047,7 +1249,7 @@ impl Kernel {
                                 res,
                             );
                         }
-                        process.set_syscall_return_value(res);
+                        self.set_syscall_return(process, &syscall, res);
                     }
                     Syscall::ReadWriteAllow {
                         driver_number,
@@ -1172,7 +1374,7 @@ impl Kernel {
                                 res
                             );
                         }
-                        process.set_syscall_return_value(res);
+                        self.set_syscall_return(process, &syscall, res);
                     }
                     Syscall::UserspaceReadableAllow {
                         driver_number,
@@ -1252,7 +1454,7 @@ impl Kernel {
                                 res
                             );
                         }
-                        process.set_syscall_return_value(res);
+                        self.set_syscall_return(process, &syscall, res);
                     }
                     Syscall::ReadOnlyAllow {
                         driver_number,
@@ -1378,7 +1580,7 @@ impl Kernel {
                             );
                         }
 
-                        process.set_syscall_return_value(res);
+                        self.set_syscall_return(process, &syscall, res);
                     }
                     Syscall::Yield { .. }
                     | Syscall::Exit { .. }
@@ -1414,6 +1616,7 @@ impl Kernel {
                         Some(return_value)
                     }
                 };
+                self.trace_syscall(old_process_id, &syscall, optional_return_value);
                 if config::CONFIG.trace_syscalls {
                     debug!(
                         "[{:?}] exit(which: {}, completion_code: {}) = {:?}",
@@ -156,6 +156,7 @@ pub mod grant;
 pub mod hil;
 pub mod introspection;
 pub mod ipc;
+pub mod memory_pool;
 pub mod platform;
 pub mod process;
 pub mod process_checker;
@@ -163,6 +164,7 @@ pub mod processbuffer;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod syscall_trace;
 pub mod upcall;
 pub mod utilities;
 
@@ -42,6 +42,20 @@ use crate::ErrorCode;
 ///   where the app has put the start of its heap. This is not strictly
 ///   necessary for correct operation, but allows for better debugging if the
 ///   app crashes.
+/// - `12`: Register the function at address r1 as the memory pressure handler
+///   of the app, or unregister it if r1 is 0. The kernel calls the handler
+///   with the reason (0: free memory is low, 1: the grant region grew into
+///   the shared memory pool, 2: a grant could not be allocated), the free
+///   memory between the program break and the grant region, and the memory
+///   the grant region grew into.
+/// - `13`: Set the amount of free memory, in bytes, below which the memory
+///   pressure handler is called.
+/// - `14`: Get the amount of free memory, in bytes, between the program break
+///   and the grant region.
+/// - `15`: Register the function at address r1 as the upcall drop handler of
+///   the app, or unregister it if r1 is 0. The kernel calls the handler with
+///   the number of upcalls dropped since the last call, and the driver and
+///   subscribe numbers of the last dropped upcall.
 pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> SyscallReturn {
     match op_type {
         // Op Type 0: BRK
@@ -156,6 +170,30 @@ pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> Syscall
             SyscallReturn::Success
         }
 
+        12 => {
+            process.set_memory_pressure_handler((r1 != 0).then(|| CapabilityPtr::from(r1)));
+            SyscallReturn::Success
+        }
+
+        13 => {
+            process.set_memory_pressure_threshold(r1);
+            SyscallReturn::Success
+        }
+
+        14 => {
+            let addresses = process.get_addresses();
+            SyscallReturn::SuccessU32(
+                addresses
+                    .sram_grant_start
+                    .saturating_sub(addresses.sram_app_brk) as u32,
+            )
+        }
+
+        15 => {
+            process.set_upcall_drop_handler((r1 != 0).then(|| CapabilityPtr::from(r1)));
+            SyscallReturn::Success
+        }
+
         _ => SyscallReturn::Failure(ErrorCode::NOSUPPORT),
     }
 }
@@ -0,0 +1,210 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Shared pool of kernel memory that processes can grow into.
+//!
+//! Each process gets a fixed block of RAM when it is loaded. The process
+//! accessible memory grows up from the start of the block, and the grant
+//! region grows down from its end. When both meet, grant allocations fail,
+//! so boards have to over-provision the RAM of every process to avoid rare
+//! allocation failures.
+//!
+//! A [`MemoryPool`], set with `Kernel::set_memory_pool()`, holds memory that
+//! is not part of any process. When a grant does not fit in the block of a
+//! process, and the [`ProcessMemoryGrowthPolicy`] allows it, the grant is
+//! allocated from the pool instead. The pool is divided into blocks of
+//! [`BLOCK_SIZE`] bytes, which are returned to the pool when the process is
+//! terminated or restarted.
+//!
+//! Only the grant region can grow: the process accessible memory cannot move,
+//! so `brk` and `sbrk` are still limited to the block of the process.
+//!
+//! [`ProcessMemoryGrowthPolicy`]: crate::process::ProcessMemoryGrowthPolicy
+
+use core::cell::Cell;
+use core::ptr::NonNull;
+
+/// Size of the blocks the pool is divided into, in bytes. It is also the
+/// maximum alignment of allocations from the pool, and must match the
+/// alignment of [`MemoryBlock`].
+pub const BLOCK_SIZE: usize = 64;
+
+/// Reason the kernel calls the memory pressure handler of a process,
+/// registered with the memop system call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryPressure {
+    /// The free memory between the memory break and the grant region of the
+    /// process fell below the threshold set by the process.
+    Low = 0,
+    /// A grant did not fit in the memory of the process and was allocated
+    /// from the shared pool.
+    Grown = 1,
+    /// A grant could not be allocated, so a capsule could not serve the
+    /// process.
+    Exhausted = 2,
+}
+
+/// A block of memory in the pool, aligned to its size.
+#[repr(C, align(64))]
+pub struct MemoryBlock([u8; BLOCK_SIZE]);
+
+impl MemoryBlock {
+    pub const fn new() -> Self {
+        Self([0; BLOCK_SIZE])
+    }
+}
+
+impl Default for MemoryBlock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A pool of memory blocks allocated to processes.
+pub struct MemoryPool {
+    /// Start of the first block.
+    start: *mut u8,
+    /// The process identifier that owns each block, or `None` if the block is
+    /// free.
+    owners: &'static [Cell<Option<usize>>],
+}
+
+impl MemoryPool {
+    /// Create a pool from `blocks`, using `owners` to record the process each
+    /// block is allocated to. Both slices should have the same length.
+    pub fn new(blocks: &'static mut [MemoryBlock], owners: &'static [Cell<Option<usize>>]) -> Self {
+        let num_blocks = owners.len().min(blocks.len());
+        Self {
+            start: blocks.as_mut_ptr().cast(),
+            owners: &owners[..num_blocks],
+        }
+    }
+
+    /// Total size of the pool, in bytes.
+    pub fn size(&self) -> usize {
+        self.owners.len() * BLOCK_SIZE
+    }
+
+    /// Number of bytes not allocated to any process.
+    pub fn available(&self) -> usize {
+        self.owners
+            .iter()
+            .filter(|owner| owner.get().is_none())
+            .count()
+            * BLOCK_SIZE
+    }
+
+    /// Number of bytes allocated to the process with identifier `owner`.
+    pub fn used_by(&self, owner: usize) -> usize {
+        self.owners
+            .iter()
+            .filter(|block| block.get() == Some(owner))
+            .count()
+            * BLOCK_SIZE
+    }
+
+    /// Allocate `size` bytes aligned to `align` bytes for the process with
+    /// identifier `owner`. The size is rounded up to a whole number of blocks.
+    ///
+    /// Returns `None` if no run of free blocks is large enough, or if `align`
+    /// is larger than `BLOCK_SIZE`.
+    pub(crate) fn allocate(&self, owner: usize, size: usize, align: usize) -> Option<NonNull<u8>> {
+        if align > BLOCK_SIZE {
+            return None;
+        }
+        let blocks = size.div_ceil(BLOCK_SIZE).max(1);
+        let mut run = 0;
+        for (index, block) in self.owners.iter().enumerate() {
+            if block.get().is_some() {
+                run = 0;
+                continue;
+            }
+            run += 1;
+            if run == blocks {
+                let first = index + 1 - blocks;
+                for block in &self.owners[first..=index] {
+                    block.set(Some(owner));
+                }
+                return NonNull::new(self.start.wrapping_add(first * BLOCK_SIZE));
+            }
+        }
+        None
+    }
+
+    /// Return all blocks allocated to the process with identifier `owner` to
+    /// the pool.
+    pub(crate) fn release(&self, owner: usize) {
+        for block in self.owners.iter() {
+            if block.get() == Some(owner) {
+                block.set(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{MemoryBlock, MemoryPool, BLOCK_SIZE};
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    /// Creates a pool of `blocks` blocks. The memory is leaked.
+    fn pool(blocks: usize) -> MemoryPool {
+        let memory = (0..blocks).map(|_| MemoryBlock::new()).collect::<Vec<_>>();
+        let owners = (0..blocks).map(|_| Cell::new(None)).collect::<Vec<_>>();
+        MemoryPool::new(memory.leak(), owners.leak())
+    }
+
+    #[test]
+    fn test_allocate_exhaust_release_reallocate() {
+        let pool = pool(4);
+        assert_eq!(pool.size(), 4 * BLOCK_SIZE);
+
+        let first = pool.allocate(1, 100, 4).unwrap();
+        assert!(pool.allocate(2, BLOCK_SIZE, 8).is_some());
+        assert_eq!(pool.used_by(1), 2 * BLOCK_SIZE);
+        assert_eq!(pool.available(), BLOCK_SIZE);
+
+        // Only one block is left.
+        assert!(pool.allocate(3, 2 * BLOCK_SIZE, 4).is_none());
+        assert!(pool.allocate(3, 1, 1).is_some());
+        assert_eq!(pool.available(), 0);
+        assert!(pool.allocate(3, 1, 1).is_none());
+
+        // Terminating process 1 gives its blocks back.
+        pool.release(1);
+        assert_eq!(pool.used_by(1), 0);
+        assert_eq!(pool.available(), 2 * BLOCK_SIZE);
+        assert_eq!(pool.allocate(3, 2 * BLOCK_SIZE, 4), Some(first));
+        assert_eq!(pool.used_by(3), 3 * BLOCK_SIZE);
+        assert_eq!(pool.used_by(2), BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_blocks() {
+        let pool = pool(3);
+        assert!(pool.allocate(1, 1, 1).is_some());
+        assert!(pool.allocate(2, 1, 1).is_some());
+        assert!(pool.allocate(1, 1, 1).is_some());
+        pool.release(1);
+
+        // The two free blocks are not next to each other.
+        assert_eq!(pool.available(), 2 * BLOCK_SIZE);
+        assert!(pool.allocate(3, 2 * BLOCK_SIZE, 4).is_none());
+        assert!(pool.allocate(3, BLOCK_SIZE, 4).is_some());
+    }
+
+    #[test]
+    fn test_allocate_alignment() {
+        let pool = pool(2);
+        assert!(pool.allocate(1, 1, 2 * BLOCK_SIZE).is_none());
+
+        // Empty allocations still take a block.
+        let block = pool.allocate(1, 0, BLOCK_SIZE).unwrap();
+        assert_eq!(block.as_ptr().align_offset(BLOCK_SIZE), 0);
+        assert_eq!(pool.used_by(1), BLOCK_SIZE);
+    }
+}
@@ -5,6 +5,7 @@
 //! Interfaces for implementing microcontrollers in Tock.
 
 use crate::platform::mpu;
+use crate::platform::power::SleepState;
 use crate::syscall;
 use core::fmt::Write;
 
@@ -50,6 +51,28 @@ pub trait Chip {
     /// chip and resumes the scheduler.
     fn sleep(&self);
 
+    /// Returns the time in microseconds the chip needs to resume execution
+    /// after sleeping in `state`, or `None` if the chip cannot currently enter
+    /// `state`, for example because a peripheral it does not track otherwise
+    /// is active.
+    ///
+    /// The default implementation only supports [`SleepState::Sleep`].
+    fn sleep_wakeup_latency_us(&self, state: SleepState) -> Option<u32> {
+        match state {
+            SleepState::Sleep => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Like [`Chip::sleep`], but enter the sleep state `state`, which the
+    /// power manager selected among the states for which
+    /// [`Chip::sleep_wakeup_latency_us`] returns a latency.
+    ///
+    /// The default implementation calls [`Chip::sleep`].
+    fn sleep_in_state(&self, _state: SleepState) {
+        self.sleep();
+    }
+
     /// Run a function in an atomic state w.r.t. to the current core. This
     /// means that interrupts are disabled so that an interrupt will not fire
     /// during the passed in function's execution, but *does not* make any
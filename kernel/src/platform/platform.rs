@@ -125,6 +125,10 @@ pub trait SyscallFilter {
     /// be returned to the calling application. The default implementation
     /// allows all system calls.
     ///
+    /// A filter may also respond to a system call by putting the process in
+    /// the fault state with `Process::set_fault_state()` and returning `Err()`,
+    /// in which case no value is returned to the process.
+    ///
     /// This API should be considered unstable, and is likely to change in the
     /// future.
     fn filter_syscall(
@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Interfaces for power management.
+//!
+//! When there is no work to do, the kernel puts the chip to sleep. Chips can
+//! support several [`SleepState`]s: deeper states use less power, but take
+//! longer to wake up from and stop more peripherals.
+//!
+//! Peripherals and capsules that need the chip to stay in a shallow state, for
+//! example while a UART transfer is in progress or shortly before an alarm
+//! fires, implement [`PowerConstraint`]. A [`PowerManager`], set with
+//! `Kernel::set_power_manager()`, combines these constraints with the wake-up
+//! latencies of the chip to select the state the chip enters each time it
+//! sleeps. Without a power manager, the kernel calls `Chip::sleep()`.
+
+/// A low power state the chip can enter when the kernel has no work to do.
+///
+/// States are ordered from the shallowest to the deepest. In all states, RAM
+/// is retained and interrupts from the enabled wake-up sources resume
+/// execution after the sleep instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepState {
+    /// The core clock is stopped, but all peripherals keep running. This is
+    /// the state `Chip::sleep()` enters on most chips.
+    Sleep = 0,
+    /// High-frequency clocks are stopped. Only low-frequency timers and
+    /// asynchronous wake-up sources such as GPIO interrupts keep running.
+    DeepSleep = 1,
+}
+
+impl SleepState {
+    /// Number of sleep states.
+    pub const COUNT: usize = 2;
+
+    /// All sleep states, from the shallowest to the deepest.
+    pub const ALL: [SleepState; Self::COUNT] = [SleepState::Sleep, SleepState::DeepSleep];
+}
+
+/// Requirement of a peripheral on the sleep state of the chip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerRequirement {
+    /// The peripheral does not need the chip to stay in any particular state.
+    Unconstrained,
+    /// The chip must not sleep deeper than this state, for example because the
+    /// peripheral needs a clock that deeper states stop.
+    MaxSleepState(SleepState),
+    /// The chip must be able to resume execution within this many
+    /// microseconds, for example because an alarm fires by then.
+    WakeupWithinUs(u32),
+}
+
+/// Implemented by peripherals and capsules that constrain the sleep state of
+/// the chip.
+pub trait PowerConstraint {
+    /// Returns the current requirement of the peripheral. This is called with
+    /// interrupts disabled each time the chip is about to sleep, so it must be
+    /// fast.
+    fn power_requirement(&self) -> PowerRequirement;
+}
+
+/// Selects the sleep state of the chip and accounts the time spent in each
+/// state.
+pub trait PowerManager {
+    /// Returns the deepest state the chip may enter. `wakeup_latency_us`
+    /// returns the time the chip needs to resume execution from a state, or
+    /// `None` if the chip cannot currently enter the state.
+    /// [`SleepState::Sleep`] is always possible.
+    fn select_sleep_state(
+        &self,
+        wakeup_latency_us: &dyn Fn(SleepState) -> Option<u32>,
+    ) -> SleepState;
+
+    /// Called right before the chip enters `state`.
+    fn sleep_started(&self, state: SleepState);
+
+    /// Called right after the chip resumed execution from `state`.
+    fn sleep_ended(&self, state: SleepState);
+}
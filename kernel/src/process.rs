@@ -32,7 +32,10 @@ pub use crate::process_loading::load_processes;
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
 pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
-pub use crate::process_policies::{ProcessFaultPolicy, ProcessStandardStoragePermissionsPolicy};
+pub use crate::process_policies::{
+    ProcessFaultPolicy, ProcessMemoryGrowthPolicy, ProcessStandardStoragePermissionsPolicy,
+    ProcessUpcallQueuePolicy, UpcallQueueAction, UpcallQueueUsage,
+};
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext};
 pub use crate::process_standard::ProcessStandard;
 pub use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
@@ -326,8 +329,17 @@ impl BinaryVersion {
     pub fn new(value: NonZeroU32) -> Self {
         Self(value)
     }
+
+    /// Returns the version number.
+    pub fn get(&self) -> u32 {
+        self.0.get()
+    }
 }
 
+/// Length, in bytes, of the header at the start of a process checkpoint. See
+/// [`Process::read_checkpoint`].
+pub const CHECKPOINT_HEADER_LEN: usize = 256;
+
 /// This trait represents a generic process that the Tock scheduler can
 /// schedule.
 pub trait Process {
@@ -372,6 +384,10 @@ pub trait Process {
     /// - [`Err(ErrorCode::NOMEM)`] if the task could not be enqueued because
     ///   there is insufficient space in the internal task queue.
     ///
+    /// Upcalls are queued according to the
+    /// [`ProcessUpcallQueuePolicy`] of the kernel, if any, which may coalesce
+    /// them with pending upcalls or drop them.
+    ///
     /// Other return values must be treated as kernel-internal errors.
     fn enqueue_task(&self, task: Task) -> Result<(), ErrorCode>;
 
@@ -596,6 +612,23 @@ pub trait Process {
     /// Also optional.
     fn update_heap_start_pointer(&self, heap_pointer: *const u8);
 
+    /// Set the function the kernel calls when the process is running out of
+    /// memory, or stop the notifications with `None`. See
+    /// [`MemoryPressure`](crate::memory_pool::MemoryPressure) for when the
+    /// function is called.
+    fn set_memory_pressure_handler(&self, handler: Option<CapabilityPtr>);
+
+    /// Set the number of bytes of free memory between the memory break and
+    /// the grant region below which the process is notified that its memory
+    /// is low.
+    fn set_memory_pressure_threshold(&self, threshold: usize);
+
+    /// Set the function the kernel calls when upcalls for the process were
+    /// dropped, or stop the notifications with `None`. The function is called
+    /// with the number of upcalls dropped since the last notification and the
+    /// driver and subscribe numbers of the last dropped upcall.
+    fn set_upcall_drop_handler(&self, handler: Option<CapabilityPtr>);
+
     /// Creates a [`ReadWriteProcessBuffer`] from the given offset and size in
     /// process memory.
     ///
@@ -667,6 +700,27 @@ pub trait Process {
     /// Returns `None` if the process has no storage permissions.
     fn get_storage_permissions(&self) -> storage_permissions::StoragePermissions;
 
+    /// Get the real-time scheduling parameters of the process as
+    /// `(period_us, budget_us)`.
+    ///
+    /// Returns `None` if the process did not request periodic real-time
+    /// execution, in which case real-time schedulers run it in the background.
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)>;
+
+    /// Get the scheduling priority the process requested, where lower values
+    /// are higher priorities.
+    ///
+    /// Returns `None` if the process did not request a priority.
+    fn get_priority(&self) -> Option<u32>;
+
+    /// Check whether the process allows the process with ShortId `client` to
+    /// connect to its IPC service.
+    ///
+    /// Returns `true` if the process does not restrict its IPC clients.
+    /// Processes with a [`ShortId::LocallyUnique`] are never in the list of
+    /// allowed clients.
+    fn is_ipc_client_allowed(&self, client: ShortId) -> bool;
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -850,6 +904,35 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Copy the part of the checkpoint of the process that starts at byte
+    /// `offset` into `out`. Returns the number of bytes copied, which is less
+    /// than `out.len()` only at the end of the checkpoint.
+    ///
+    /// A checkpoint starts with a header of [`CHECKPOINT_HEADER_LEN`] bytes
+    /// holding the registers, memory break and state of the process, followed
+    /// by the content of the memory the process can access. It does not
+    /// include kernel-owned state such as grants, subscribed upcalls and
+    /// allowed buffers.
+    ///
+    /// Returns `ErrorCode::BUSY` if the process is not stopped, as the
+    /// checkpoint would be inconsistent if the process ran while it is read.
+    fn read_checkpoint(&self, offset: usize, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Write the part of a checkpoint that starts at byte `offset` back into
+    /// the process.
+    ///
+    /// The header must be written first, in a single call. It is rejected
+    /// with `ErrorCode::INVAL` if the checkpoint was taken from a different
+    /// binary or from a process loaded at different addresses. Writing the
+    /// header restores the registers, memory break and state of the process
+    /// and discards its pending tasks. The following calls restore the memory
+    /// of the process.
+    ///
+    /// Returns `ErrorCode::BUSY` if the process is not stopped. The process
+    /// stays stopped and runs from the checkpoint once resumed. If this fails
+    /// after the header was written, the process must be restarted.
+    fn write_checkpoint(&self, offset: usize, data: &[u8]) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
@@ -868,6 +951,16 @@ pub trait Process {
     /// Increment the number of times the process has exceeded its timeslice.
     fn debug_timeslice_expired(&self);
 
+    /// Returns how much CPU time, in microseconds, this process has used.
+    fn debug_cpu_time_us(&self) -> u64;
+
+    /// Add `us` microseconds to the CPU time this process has used.
+    fn debug_cpu_time_used(&self, us: u64);
+
+    /// Add `cycles` cycles of a counter running at `frequency_hz` to the CPU
+    /// time this process has used.
+    fn debug_cpu_cycles_used(&self, cycles: u64, frequency_hz: u32);
+
     /// Increment the number of times the process called a syscall and record
     /// the last syscall that was called.
     fn debug_syscall_called(&self, last_syscall: Syscall);
@@ -875,6 +968,22 @@ pub trait Process {
     /// Return the last syscall the process called. Returns `None` if the
     /// process has not called any syscalls or the information is unknown.
     fn debug_syscall_last(&self) -> Option<Syscall>;
+
+    /// Return the stack pointer of the process when it last stopped running.
+    /// Returns `None` if the architecture does not record it.
+    fn debug_stack_pointer(&self) -> Option<usize>;
+
+    /// Return the memory address that caused the last fault of the process.
+    /// Returns `None` if the process did not fault on a memory access or the
+    /// architecture does not record it.
+    fn debug_fault_address(&self) -> Option<usize>;
+
+    /// Copy the top of the stack of the process, starting at its stack
+    /// pointer, into `out`. Only memory the process can access is copied.
+    ///
+    /// Returns the number of bytes copied, or `ErrorCode::NOSUPPORT` if the
+    /// stack pointer is not known.
+    fn debug_read_stack(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 }
 
 /// Opaque identifier for custom grants allocated dynamically from a process's
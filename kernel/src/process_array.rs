@@ -36,6 +36,11 @@ impl ProcessSlot {
         self.proc.set(Some(process));
     }
 
+    /// Remove the process from the slot, making the slot available.
+    pub(crate) fn clear(&self) {
+        self.proc.set(None);
+    }
+
     /// Return the underlying [`process::Process`] if the slot contains a
     /// process.
     pub fn get(&self) -> Option<&'static dyn process::Process> {
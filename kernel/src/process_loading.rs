@@ -30,6 +30,9 @@ use crate::process_policies::ProcessStandardStoragePermissionsPolicy;
 use crate::process_standard::ProcessStandard;
 use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
 use crate::utilities::cells::{MapCell, OptionalCell};
+use crate::ErrorCode;
+
+use tock_tbf::types::TbfHeader;
 
 /// Errors that can occur when trying to load and create processes.
 pub enum ProcessLoadError {
@@ -488,6 +491,107 @@ pub enum PaddingRequirement {
     PreAndPostPad,
 }
 
+/// A move of a process binary to an earlier gap in the application flash,
+/// to compact it.
+#[derive(Clone, Copy, Default)]
+pub struct Relocation {
+    /// Start address of the binary.
+    pub from: usize,
+    /// Address the binary is moved to.
+    pub to: usize,
+    /// Length of the binary.
+    pub length: usize,
+    /// Start address of the run of padding binaries the binary is moved into.
+    pub gap_start: usize,
+    /// End address of the run of padding binaries the binary is moved into.
+    pub gap_end: usize,
+}
+
+/// Number of separate regions of free memory the
+/// `SequentialProcessLoaderMachine` keeps to assign to new processes.
+const APP_MEMORY_REGIONS: usize = 3;
+
+/// Memory available to assign to new processes.
+///
+/// The memory is kept as the start address and length of each free region.
+/// The first region starts as all of the application memory, the others hold
+/// memory of removed processes. Unused entries have a length of zero.
+struct FreeAppMemory {
+    regions: [Cell<(usize, usize)>; APP_MEMORY_REGIONS],
+    /// Flash address a removed process's binary is moved to, and the start
+    /// address of the memory the process had. The binary loaded from that
+    /// address gets the same memory back, if it is still free.
+    reserved: OptionalCell<(usize, usize)>,
+}
+
+impl FreeAppMemory {
+    fn new(start: usize, length: usize) -> Self {
+        let regions: [Cell<(usize, usize)>; APP_MEMORY_REGIONS] = Default::default();
+        regions[0].set((start, length));
+        Self {
+            regions,
+            reserved: OptionalCell::empty(),
+        }
+    }
+
+    /// Returns the region to assign to the process of the binary at
+    /// `flash_start`: the memory reserved for the binary if it is still free,
+    /// or else the largest region.
+    fn region_for(&self, flash_start: usize) -> &Cell<(usize, usize)> {
+        let reserved = self
+            .reserved
+            .take()
+            .filter(|(binary, _)| *binary == flash_start);
+        let mut largest = &self.regions[0];
+        for region in self.regions.iter() {
+            let (start, length) = region.get();
+            if reserved.is_some_and(|(_, memory)| start <= memory && memory < start + length) {
+                return region;
+            }
+            if length > largest.get().1 {
+                largest = region;
+            }
+        }
+        largest
+    }
+
+    /// Make the memory `start..end` available again, merging it with
+    /// adjacent free memory.
+    ///
+    /// If there is no room to keep track of the region, the smallest region
+    /// is dropped and its memory is not available until reboot.
+    fn reclaim(&self, mut start: usize, mut end: usize) {
+        // Free regions are never adjacent, so at most one region ends at
+        // `start` and at most one region starts at `end`.
+        for region in self.regions.iter() {
+            let (region_start, length) = region.get();
+            if region_start + length == start {
+                start = region_start;
+                region.take();
+            } else if region_start == end {
+                end = region_start + length;
+                region.take();
+            }
+        }
+
+        let mut smallest = &self.regions[0];
+        for region in self.regions.iter() {
+            if region.get().1 < smallest.get().1 {
+                smallest = region;
+            }
+        }
+        if smallest.get().1 < end - start {
+            smallest.set((start, end - start));
+        }
+    }
+
+    /// Keep the free memory starting at `memory` for the binary loaded next
+    /// from `flash_start`.
+    fn reserve(&self, flash_start: usize, memory: usize) {
+        self.reserved.set((flash_start, memory));
+    }
+}
+
 /// A machine for loading processes stored sequentially in a region of flash.
 ///
 /// Load processes (stored as TBF objects in flash) into runnable process
@@ -509,7 +613,7 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     /// Flash memory region to load processes from.
     flash: Cell<&'static [u8]>,
     /// Memory available to assign to applications.
-    app_memory: Cell<&'static mut [u8]>,
+    app_memory: FreeAppMemory,
     /// Mechanism for generating async callbacks.
     deferred_call: DeferredCall,
     /// Reference to the kernel object for creating Processes.
@@ -526,6 +630,8 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     state: OptionalCell<SequentialProcessLoaderMachineState>,
     /// Current operating mode of the loading machine.
     run_mode: OptionalCell<SequentialProcessLoaderMachineRunMode>,
+    /// Identifier of the process the binary being loaded at runtime replaces.
+    replaced_process: OptionalCell<usize>,
 }
 
 impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C, D> {
@@ -556,11 +662,12 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
             chip,
             flash_bank: Cell::new(flash),
             flash: Cell::new(flash),
-            app_memory: Cell::new(app_memory),
+            app_memory: FreeAppMemory::new(app_memory.as_ptr() as usize, app_memory.len()),
             policy: OptionalCell::new(policy),
             fault_policy,
             storage_policy,
             state: OptionalCell::empty(),
+            replaced_process: OptionalCell::empty(),
         }
     }
 
@@ -627,6 +734,19 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
         }
     }
 
+    /// Take the region of memory to assign to the process of the binary at
+    /// `flash_start`. Returns the memory and the entry of `app_memory` it was
+    /// taken from.
+    fn take_app_memory(&self, flash_start: usize) -> (&'static mut [u8], &Cell<(usize, usize)>) {
+        let region = self.app_memory.region_for(flash_start);
+        let (start, length) = region.take();
+        // The regions are only created from the application memory passed to
+        // `new()` and the memory of removed processes, which nothing else
+        // references.
+        let memory = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, length) };
+        (memory, region)
+    }
+
     /// Try to parse a process binary from flash.
     ///
     /// Returns the process binary object or an error if a valid process
@@ -687,6 +807,10 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                 // are already loaded, we just need to check if this process
                 // binary has the same AppID as an already loaded process.
                 for proc in self.kernel.get_process_iter() {
+                    // The process this binary replaces does not block it.
+                    if self.replaced_process.contains(&proc.processid().id()) {
+                        continue;
+                    }
                     let blocked = self.is_blocked_from_loading_by_process(&process_binary, proc);
                     if blocked {
                         ok_to_load = false;
@@ -698,8 +822,16 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                     continue;
                 }
 
-                // If we get here it is ok to load the process.
-                match self.kernel.next_available_process_slot() {
+                // If we get here it is ok to load the process. A process
+                // replacing another one takes the slot of the old process,
+                // which keeps running until the new process is created.
+                let available_slot = match self.replaced_process.take() {
+                    Some(identifier) => {
+                        self.kernel.process_slot_by_identifier(identifier).ok_or(())
+                    }
+                    None => self.kernel.next_available_process_slot(),
+                };
+                match available_slot {
                     Ok((index, slot)) => {
                         // Calculate the ShortId for this new process.
                         let short_app_id = self.policy.map_or(ShortId::LocallyUnique, |policy| {
@@ -707,11 +839,13 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                         });
 
                         // Try to create a `Process` object.
+                        let (app_memory, memory_region) =
+                            self.take_app_memory(process_binary.flash.as_ptr() as usize);
                         let load_result = load_process(
                             self.kernel,
                             self.chip,
                             process_binary,
-                            self.app_memory.take(),
+                            app_memory,
                             short_app_id,
                             index,
                             self.fault_policy,
@@ -719,7 +853,7 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                         );
                         match load_result {
                             Ok((new_mem, proc)) => {
-                                self.app_memory.set(new_mem);
+                                memory_region.set((new_mem.as_ptr() as usize, new_mem.len()));
                                 match proc {
                                     Some(p) => {
                                         if config::CONFIG.debug_load_processes {
@@ -729,6 +863,18 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                                             )
                                         }
 
+                                        // Terminate the process being
+                                        // replaced, if any, which frees its
+                                        // grants and memory pool blocks. Its
+                                        // memory is given to processes loaded
+                                        // later.
+                                        if let Some(old) = slot.get() {
+                                            let addresses = old.get_addresses();
+                                            old.terminate(None);
+                                            self.app_memory
+                                                .reclaim(addresses.sram_start, addresses.sram_end);
+                                        }
+
                                         // Store the `ProcessStandard` object in the `PROCESSES`
                                         // array.
                                         slot.set(p);
@@ -746,7 +892,7 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
                                 }
                             }
                             Err((new_mem, err)) => {
-                                self.app_memory.set(new_mem);
+                                memory_region.set((new_mem.as_ptr() as usize, new_mem.len()));
                                 if config::CONFIG.debug_load_processes {
                                     debug!("Could not load process: {:?}.", err);
                                 }
@@ -766,6 +912,7 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
             }
         }
         self.proc_binaries.put(proc_binaries);
+        self.replaced_process.clear();
 
         // We have iterated all discovered `ProcessBinary`s and loaded what we
         // could so now we can signal that process loading is finished.
@@ -1230,6 +1377,215 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
             )),
         }
     }
+
+    /// Function to start loading the new application at address `app_address`
+    /// with size `app_size` in place of the loaded process with identifier
+    /// `process_id`.
+    ///
+    /// The old process keeps running until the new binary passed its
+    /// credential checks and the new process was created. The old process is
+    /// then terminated, its slot is given to the new process and its memory
+    /// to processes loaded later. If the new binary cannot be loaded, the old
+    /// process is left untouched.
+    pub fn load_new_process_binary_replacing(
+        &self,
+        app_address: usize,
+        app_size: usize,
+        process_id: usize,
+    ) -> Result<(), ProcessLoadError> {
+        if self.kernel.process_slot_by_identifier(process_id).is_none() {
+            return Err(ProcessLoadError::InternalError);
+        }
+        self.replaced_process.set(process_id);
+        self.load_new_process_binary(app_address, app_size)
+            .inspect_err(|_| self.replaced_process.clear())
+    }
+
+    /// Returns the start address and the length of the binary of the loaded
+    /// process with identifier `process_id`, or `None` if there is no such
+    /// process.
+    pub fn process_binary_region(&self, process_id: usize) -> Option<(usize, usize)> {
+        let (_, slot) = self.kernel.process_slot_by_identifier(process_id)?;
+        let addresses = slot.get()?.get_addresses();
+        Some((
+            addresses.flash_start,
+            addresses.flash_end - addresses.flash_start,
+        ))
+    }
+
+    /// Terminate the loaded process with identifier `process_id` and remove it
+    /// from the kernel.
+    ///
+    /// Terminating the process frees its grants and the memory pool blocks it
+    /// used. The memory of the process itself is given to processes loaded
+    /// later. If the binary is moved to `reload_at`, the memory is kept for
+    /// the binary loaded next from there, so a relocated process gets its
+    /// memory back. The binary stays in flash; the caller is responsible for
+    /// retiring it.
+    pub fn remove_process(
+        &self,
+        process_id: usize,
+        reload_at: Option<usize>,
+    ) -> Result<(), ErrorCode> {
+        let (_, slot) = self
+            .kernel
+            .process_slot_by_identifier(process_id)
+            .ok_or(ErrorCode::INVAL)?;
+        if let Some(process) = slot.get() {
+            // The process is removed from its slot before another process is
+            // loaded, so its memory is no longer used once it is terminated.
+            let addresses = process.get_addresses();
+            process.terminate(None);
+            self.app_memory
+                .reclaim(addresses.sram_start, addresses.sram_end);
+            if let Some(flash_start) = reload_at {
+                self.app_memory.reserve(flash_start, addresses.sram_start);
+            }
+        }
+        slot.clear();
+        Ok(())
+    }
+
+    /// Returns the identifier of the loaded process whose binary starts at
+    /// `flash_start`, or `None` if the binary is not loaded.
+    pub fn process_id_at(&self, flash_start: usize) -> Option<usize> {
+        self.kernel.process_until(|process| {
+            (process.get_addresses().flash_start == flash_start).then(|| process.processid().id())
+        })
+    }
+
+    /// Returns the `length` bytes of the application flash starting at
+    /// `address`.
+    pub fn flash_region(&self, address: usize, length: usize) -> Option<&'static [u8]> {
+        let flash = self.flash_bank.get();
+        let offset = address.checked_sub(flash.as_ptr() as usize)?;
+        flash.get(offset..offset.checked_add(length)?)
+    }
+
+    /// Returns an iterator over the binaries in the application flash, with
+    /// their parsed TBF header, or `None` if the header is invalid.
+    fn flash_binaries(&self) -> impl Iterator<Item = (&'static [u8], Option<TbfHeader<'static>>)> {
+        let mut flash = self.flash_bank.get();
+        core::iter::from_fn(move || {
+            let header = flash.get(0..8)?.try_into().ok()?;
+            let (version, header_length, length) =
+                match tock_tbf::parse::parse_tbf_header_lengths(header) {
+                    Ok((v, hl, l)) => (v, hl as usize, l as usize),
+                    Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(l)) => {
+                        (0, 0, l as usize)
+                    }
+                    Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => return None,
+                };
+            let binary = flash.get(..length).filter(|b| !b.is_empty())?;
+            flash = flash.get(length..)?;
+            let header = binary
+                .get(..header_length)
+                .filter(|_| header_length != 0)
+                .and_then(|header| tock_tbf::parse::parse_tbf_header(header, version).ok());
+            Some((binary, header))
+        })
+    }
+
+    /// Find a process binary that can be moved to an earlier gap in the
+    /// application flash.
+    ///
+    /// Gaps are runs of padding binaries left by removed apps and by the
+    /// alignment of new apps. A binary is only moved to an address aligned to
+    /// its length, in a gap that holds all of it, so the binary is never
+    /// overwritten while it is copied. Binaries compiled for a fixed flash
+    /// address are never moved. Returns the move of the first binary that
+    /// fits in a gap, to the lowest such gap.
+    pub fn find_relocation(&self) -> Option<Relocation> {
+        const MAX_GAPS: usize = 10;
+        let mut gaps: [(usize, usize); MAX_GAPS] = [(0, 0); MAX_GAPS];
+        let mut gap_count = 0;
+        // Start of the run of padding binaries before the current binary.
+        let mut run_start: Option<usize> = None;
+
+        for (binary, header) in self.flash_binaries() {
+            let address = binary.as_ptr() as usize;
+            let length = binary.len();
+
+            if let Some(TbfHeader::Padding(_)) = header {
+                run_start.get_or_insert(address);
+                continue;
+            }
+            if let Some(start) = run_start.take() {
+                if gap_count < MAX_GAPS {
+                    gaps[gap_count] = (start, address);
+                    gap_count += 1;
+                }
+            }
+
+            let relocatable = header.is_some_and(|header| {
+                header.is_app() && header.get_fixed_address_flash().is_none()
+            });
+            if relocatable {
+                for &(gap_start, gap_end) in gaps[..gap_count].iter() {
+                    let to = self.find_next_cortex_m_aligned_address(gap_start, length);
+                    if to + length <= gap_end {
+                        return Some(Relocation {
+                            from: address,
+                            to,
+                            length,
+                            gap_start,
+                            gap_end,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the version of the binary starting at `address`, or `None` if
+    /// there is no valid binary at `address`. Binaries without a version have
+    /// version 0.
+    pub fn binary_version_at(&self, address: usize) -> Option<u32> {
+        self.flash_binaries()
+            .find(|(binary, _)| binary.as_ptr() as usize == address)
+            .and_then(|(_, header)| header)
+            .map(|header| header.get_binary_version())
+    }
+
+    /// Returns the start address and the length of the binary to roll the
+    /// loaded process with identifier `process_id` back to: the binary of the
+    /// same app, as identified by the AppID and ShortId the loader uses to
+    /// keep two versions of an app from running at once, with the highest
+    /// version lower than the version of the process.
+    ///
+    /// The returned binary still has to pass its credential checks when it is
+    /// loaded.
+    pub fn previous_binary(&self, process_id: usize) -> Option<(usize, usize)> {
+        let (_, slot) = self.kernel.process_slot_by_identifier(process_id)?;
+        let process = slot.get()?;
+        let current_version = self.binary_version_at(process.get_addresses().flash_start)?;
+
+        let mut previous: Option<(&'static [u8], u32)> = None;
+        let mut flash = self.flash_bank.get();
+        loop {
+            let process_binary = match discover_process_binary(flash) {
+                Ok((remaining_flash, process_binary)) => {
+                    flash = remaining_flash;
+                    process_binary
+                }
+                Err((_, ProcessBinaryError::NotEnoughFlash))
+                | Err((_, ProcessBinaryError::TbfHeaderNotFound)) => break,
+                Err((remaining_flash, _)) => {
+                    flash = remaining_flash;
+                    continue;
+                }
+            };
+            let version = process_binary.header.get_binary_version();
+            if version < current_version
+                && previous.is_none_or(|(_, previous_version)| version > previous_version)
+                && self.is_blocked_from_loading_by_process(&process_binary, process)
+            {
+                previous = Some((process_binary.flash, version));
+            }
+        }
+        previous.map(|(binary, _)| (binary.as_ptr() as usize, binary.len()))
+    }
 }
 
 impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingAsync<'a>
@@ -1335,3 +1691,73 @@ impl<C: Chip, D: ProcessStandardDebug> crate::process_checker::ProcessCheckerMac
         self.deferred_call.set();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FreeAppMemory;
+
+    #[test]
+    fn test_region_for_takes_largest() {
+        let memory = FreeAppMemory::new(0x1000, 0x8000);
+        // Two processes are loaded, in 0x1000..0x1800 and 0x1800..0x2000.
+        memory.regions[0].set((0x2000, 0x7000));
+
+        // Remove the first one and load a bigger, unrelated app: it gets the
+        // largest region, not the memory of the removed process.
+        memory.reclaim(0x1000, 0x1800);
+        assert_eq!(memory.region_for(0x4_0000).get(), (0x2000, 0x7000));
+        assert!(memory.regions.iter().any(|r| r.get() == (0x1000, 0x800)));
+    }
+
+    #[test]
+    fn test_region_for_relocated_binary() {
+        let memory = FreeAppMemory::new(0x1000, 0x8000);
+        // A process is loaded in 0x1000..0x1800.
+        memory.regions[0].set((0x1800, 0x7800));
+
+        // The binary of the process is moved to 0x4_0000: the process gets
+        // its memory back when it is loaded from there.
+        memory.reclaim(0x1000, 0x1800);
+        memory.reserve(0x4_0000, 0x1000);
+        assert_eq!(memory.region_for(0x4_0000).get(), (0x1000, 0x8000));
+    }
+
+    #[test]
+    fn test_region_for_other_binary_drops_reservation() {
+        let memory = FreeAppMemory::new(0x2000, 0x7000);
+        memory.reclaim(0x1000, 0x1400);
+        memory.reserve(0x4_0000, 0x1000);
+
+        assert_eq!(memory.region_for(0x5_0000).get(), (0x2000, 0x7000));
+        // The reservation is only for the next binary loaded.
+        assert_eq!(memory.region_for(0x4_0000).get(), (0x2000, 0x7000));
+    }
+
+    #[test]
+    fn test_reclaim_merges_adjacent_regions() {
+        let memory = FreeAppMemory::new(0x3000, 0x1000);
+        memory.reclaim(0x1000, 0x2000);
+        memory.reclaim(0x2000, 0x3000);
+
+        assert_eq!(memory.region_for(0).get(), (0x1000, 0x3000));
+        assert_eq!(memory.regions.iter().filter(|r| r.get().1 != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_reclaim_drops_smallest_region() {
+        let memory = FreeAppMemory::new(0x8000, 0x1000);
+        memory.reclaim(0x1000, 0x1100);
+        memory.reclaim(0x2000, 0x2200);
+        memory.reclaim(0x3000, 0x3400);
+
+        let mut regions: [(usize, usize); 3] = Default::default();
+        for (region, memory) in regions.iter_mut().zip(memory.regions.iter()) {
+            *region = memory.get();
+        }
+        regions.sort_unstable();
+        assert_eq!(
+            regions,
+            [(0x2000, 0x200), (0x3000, 0x400), (0x8000, 0x1000)]
+        );
+    }
+}
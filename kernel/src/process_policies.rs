@@ -14,6 +14,7 @@ use crate::process::Process;
 use crate::process_standard::ProcessStandard;
 use crate::process_standard::ProcessStandardDebug;
 use crate::storage_permissions::StoragePermissions;
+use crate::upcall::UpcallId;
 
 /// Generic trait for implementing a policy on what to do when a process faults.
 ///
@@ -25,6 +26,56 @@ pub trait ProcessFaultPolicy {
     fn action(&self, process: &dyn Process) -> process::FaultAction;
 }
 
+/// Generic trait for implementing a policy on which processes can grow into
+/// the shared memory pool when their own memory is exhausted.
+pub trait ProcessMemoryGrowthPolicy {
+    /// Decide whether `process`, which already allocated `used` bytes from
+    /// the pool, can allocate `size` more bytes from it.
+    fn allow_growth(&self, process: &dyn Process, used: usize, size: usize) -> bool;
+}
+
+/// Upcalls already waiting in the task queue of a process when a new upcall is
+/// scheduled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpcallQueueUsage {
+    /// Number of pending upcalls with the same `UpcallId` as the new upcall.
+    pub same_upcall: usize,
+    /// Number of pending upcalls from the same driver as the new upcall.
+    pub same_driver: usize,
+    /// Number of free slots in the task queue.
+    pub free: usize,
+}
+
+/// What the kernel does with an upcall scheduled for a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpcallQueueAction {
+    /// Add the upcall to the end of the task queue. The upcall is dropped if
+    /// the queue is full.
+    Enqueue,
+    /// Replace the oldest pending upcall with the same `UpcallId` with the new
+    /// upcall, which moves to the end of the task queue. The upcall is
+    /// enqueued if there is no pending upcall with the same `UpcallId`.
+    Coalesce,
+    /// Drop the upcall.
+    Drop,
+}
+
+/// Generic trait for implementing a policy on how upcalls are queued when the
+/// task queue of a process fills up.
+///
+/// The kernel counts dropped upcalls, and notifies the process of them if it
+/// registered a handler with the memop system call.
+pub trait ProcessUpcallQueuePolicy {
+    /// Decide what the kernel does with an upcall for `upcall_id` scheduled
+    /// for `process`, whose task queue holds the upcalls described by `usage`.
+    fn enqueue_action(
+        &self,
+        process: &dyn Process,
+        upcall_id: UpcallId,
+        usage: UpcallQueueUsage,
+    ) -> UpcallQueueAction;
+}
+
 /// Generic trait for implementing a policy on how applications should be
 /// assigned storage permissions.
 pub trait ProcessStandardStoragePermissionsPolicy<C: Chip, D: ProcessStandardDebug> {
@@ -19,19 +19,22 @@ use crate::collections::ring_buffer::RingBuffer;
 use crate::config;
 use crate::debug;
 use crate::errorcode::ErrorCode;
+use crate::hil::hw_debug::cycles_to_us;
 use crate::kernel::Kernel;
+use crate::memory_pool::MemoryPressure;
 use crate::platform::chip::Chip;
 use crate::platform::mpu::{self, MPU};
 use crate::process::BinaryVersion;
 use crate::process::ProcessBinary;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, Task};
 use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
-use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
+use crate::process::{ProcessAddresses, ProcessSizes, ShortId, CHECKPOINT_HEADER_LEN};
 use crate::process::{State, StoppedState};
 use crate::process_checker::AcceptedCredential;
 use crate::process_loading::ProcessLoadError;
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_policies::ProcessStandardStoragePermissionsPolicy;
+use crate::process_policies::{UpcallQueueAction, UpcallQueueUsage};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
 use crate::storage_permissions::StoragePermissions;
 use crate::syscall::{self, Syscall, SyscallReturn, UserspaceKernelBoundary};
@@ -128,6 +131,19 @@ pub trait ProcessStandardDebug: Default {
     /// Reset the recorded count of the number of the process has exceeded its
     /// timeslice to 0.
     fn reset_timeslice_expiration_count(&self);
+
+    /// Add to the recorded CPU time the process has used, in microseconds.
+    fn add_cpu_time_us(&self, us: u64);
+    /// Add to the recorded CPU time the process has used, in cycles of a
+    /// counter running at `frequency_hz`.
+    fn add_cpu_cycles(&self, cycles: u64, frequency_hz: u32);
+    /// Get the recorded CPU time the process has used, in microseconds.
+    ///
+    /// This should return 0 if neither [`ProcessStandardDebug::add_cpu_time_us()`]
+    /// nor [`ProcessStandardDebug::add_cpu_cycles()`] is ever called.
+    fn get_cpu_time_us(&self) -> u64;
+    /// Reset the recorded CPU time the process has used to 0.
+    fn reset_cpu_time_us(&self);
 }
 
 /// A debugging implementation for [`ProcessStandard`] that records the full
@@ -179,6 +195,19 @@ struct ProcessStandardDebugFullInner {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How much CPU time, in microseconds, this process has used, including
+    /// the time the kernel spent handling its system calls, when measured with
+    /// the scheduler timer.
+    cpu_time_us: u64,
+
+    /// How much CPU time, in cycles, this process has used when measured with
+    /// a cycle counter. Cycles are only converted to microseconds when read so
+    /// that short runs are not rounded down one at a time.
+    cpu_cycles: u64,
+
+    /// Frequency, in Hz, of the cycle counter `cpu_cycles` was measured with.
+    cpu_cycles_frequency_hz: u32,
 }
 
 impl ProcessStandardDebug for ProcessStandardDebugFull {
@@ -265,6 +294,27 @@ impl ProcessStandardDebug for ProcessStandardDebugFull {
     fn reset_timeslice_expiration_count(&self) {
         self.debug.map(|d| d.timeslice_expiration_count = 0);
     }
+
+    fn add_cpu_time_us(&self, us: u64) {
+        self.debug.map(|d| d.cpu_time_us += us);
+    }
+    fn add_cpu_cycles(&self, cycles: u64, frequency_hz: u32) {
+        self.debug.map(|d| {
+            d.cpu_cycles += cycles;
+            d.cpu_cycles_frequency_hz = frequency_hz;
+        });
+    }
+    fn get_cpu_time_us(&self) -> u64 {
+        self.debug.map_or(0, |d| {
+            d.cpu_time_us + cycles_to_us(d.cpu_cycles, d.cpu_cycles_frequency_hz)
+        })
+    }
+    fn reset_cpu_time_us(&self) {
+        self.debug.map(|d| {
+            d.cpu_time_us = 0;
+            d.cpu_cycles = 0;
+        });
+    }
 }
 
 impl Default for ProcessStandardDebugFull {
@@ -319,6 +369,12 @@ impl ProcessStandardDebug for () {
         0
     }
     fn reset_timeslice_expiration_count(&self) {}
+    fn add_cpu_time_us(&self, _us: u64) {}
+    fn add_cpu_cycles(&self, _cycles: u64, _frequency_hz: u32) {}
+    fn get_cpu_time_us(&self) -> u64 {
+        0
+    }
+    fn reset_cpu_time_us(&self) {}
 }
 
 /// Entry that is stored in the grant pointer table at the top of process
@@ -492,10 +548,44 @@ pub struct ProcessStandard<'a, C: 'static + Chip, D: 'static + ProcessStandardDe
     /// be stored as `Some(completion code)`.
     completion_code: OptionalCell<Option<u32>>,
 
+    /// Function the kernel calls when the process is running out of memory,
+    /// set with the memop system call.
+    memory_pressure_handler: OptionalCell<CapabilityPtr>,
+
+    /// The process is notified when the free memory between its memory break
+    /// and its grant region falls below this number of bytes.
+    memory_pressure_threshold: Cell<usize>,
+
+    /// Whether the process was notified that its free memory fell below the
+    /// threshold, so that it is notified only once until memory is freed.
+    memory_pressure_notified: Cell<bool>,
+
+    /// Function the kernel calls when upcalls for the process were dropped,
+    /// set with the memop system call.
+    upcall_drop_handler: OptionalCell<CapabilityPtr>,
+
+    /// Number of upcalls dropped since the process was last notified.
+    upcall_drops: Cell<usize>,
+
+    /// The last upcall dropped since the process was last notified.
+    last_dropped_upcall: OptionalCell<UpcallId>,
+
     /// Values kept so that we can print useful debug messages when apps fault.
     debug: D,
 }
 
+/// Returns the upcall that scheduled `task`, if it was scheduled by a driver.
+fn task_upcall_id(task: &Task) -> Option<UpcallId> {
+    match task {
+        Task::FunctionCall(function_call) => match function_call.source {
+            FunctionCallSource::Kernel => None,
+            FunctionCallSource::Driver(upcall_id) => Some(upcall_id),
+        },
+        Task::ReturnValue(return_arguments) => Some(return_arguments.upcall_id),
+        Task::IPC(_) => None,
+    }
+}
+
 impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_, C, D> {
     fn processid(&self) -> ProcessId {
         self.process_id.get()
@@ -524,7 +614,26 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             return Err(ErrorCode::NODEVICE);
         }
 
+        // Upcalls from drivers are queued according to the policy of the
+        // kernel, if any. Other tasks are always enqueued if there is space.
+        let upcall_id = task_upcall_id(&task);
+        let action = match (upcall_id, self.kernel.upcall_queue_policy()) {
+            (Some(upcall_id), Some(policy)) => {
+                let usage = self.upcall_queue_usage(upcall_id);
+                policy.enqueue_action(self, upcall_id, usage)
+            }
+            _ => UpcallQueueAction::Enqueue,
+        };
+
         let ret = self.tasks.map_or(Err(ErrorCode::FAIL), |tasks| {
+            if action == UpcallQueueAction::Drop {
+                return Err(ErrorCode::NOMEM);
+            }
+            if action == UpcallQueueAction::Coalesce {
+                // Remove the pending upcall the new one replaces, which makes
+                // space for it.
+                tasks.remove_first_matching(|pending| task_upcall_id(pending) == upcall_id);
+            }
             match tasks.enqueue(task) {
                 true => {
                     // The task has been successfully enqueued.
@@ -542,6 +651,12 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             // On any error we were unable to enqueue the task. Record the
             // error, but importantly do _not_ increment kernel work.
             self.debug.increment_dropped_upcall_count();
+
+            if let Some(upcall_id) = upcall_id {
+                self.upcall_drops.increment();
+                self.last_dropped_upcall.set(upcall_id);
+                self.notify_upcall_drops();
+            }
         }
 
         ret
@@ -706,6 +821,11 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             self.grant_ptrs_reset();
         }
 
+        // Return the memory the grant region grew into to the shared pool.
+        if let Some((pool, _)) = self.kernel.memory_pool() {
+            pool.release(self.process_id.get().id());
+        }
+
         // Save the completion code.
         self.completion_code.set(completion_code);
 
@@ -722,7 +842,12 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     }
 
     fn dequeue_task(&self) -> Option<Task> {
-        self.tasks.map_or(None, |tasks| tasks.dequeue())
+        let task = self.tasks.map_or(None, |tasks| tasks.dequeue());
+        // Dequeuing made space to notify the process of dropped upcalls.
+        if task.is_some() {
+            self.notify_upcall_drops();
+        }
+        task
     }
 
     fn remove_upcall(&self, upcall_id: UpcallId) -> Option<Task> {
@@ -750,6 +875,26 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.storage_permissions
     }
 
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        self.header.get_real_time_parameters()
+    }
+
+    fn get_priority(&self) -> Option<u32> {
+        self.header.get_priority()
+    }
+
+    fn is_ipc_client_allowed(&self, client: ShortId) -> bool {
+        self.header
+            .get_ipc_client_ids()
+            .is_none_or(|(length, client_ids)| match client {
+                ShortId::Fixed(id) => client_ids
+                    .iter()
+                    .take(length)
+                    .any(|client_id| *client_id == id.get()),
+                ShortId::LocallyUnique => false,
+            })
+    }
+
     fn number_writeable_flash_regions(&self) -> usize {
         self.header.number_writeable_flash_regions()
     }
@@ -773,6 +918,21 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         }
     }
 
+    fn set_memory_pressure_handler(&self, handler: Option<CapabilityPtr>) {
+        self.memory_pressure_handler.insert(handler);
+    }
+
+    fn set_memory_pressure_threshold(&self, threshold: usize) {
+        self.memory_pressure_threshold.set(threshold);
+        self.memory_pressure_notified.set(false);
+        self.check_memory_pressure();
+    }
+
+    fn set_upcall_drop_handler(&self, handler: Option<CapabilityPtr>) {
+        self.upcall_drop_handler.insert(handler);
+        self.notify_upcall_drops();
+    }
+
     fn setup_mpu(&self) {
         self.mpu_config.map(|config| {
             self.chip.mpu().configure_mpu(config);
@@ -857,6 +1017,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
                 let old_break = self.app_break.get();
                 self.app_break.set(new_break);
                 self.chip.mpu().configure_mpu(config);
+                self.check_memory_pressure();
 
                 let base = self.mem_start() as usize;
                 let break_result = unsafe {
@@ -1079,9 +1240,13 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             return Err(());
         }
 
-        // Use the shared grant allocator function to actually allocate memory.
+        // Use the shared grant allocator function to actually allocate memory,
+        // and grow into the shared memory pool if the grant region is full.
         // Returns `None` if the allocation cannot be created.
-        if let Some(grant_ptr) = self.allocate_in_grant_region_internal(size, align) {
+        let grant_ptr = self
+            .allocate_in_grant_region_internal(size, align)
+            .or_else(|| self.allocate_in_memory_pool(size, align));
+        if let Some(grant_ptr) = grant_ptr {
             // Update the grant pointer to the address of the new allocation.
             self.grant_pointers.map_or(Err(()), |grant_pointers| {
                 // Implement `grant_pointers[grant_num] = grant_ptr` without a
@@ -1099,6 +1264,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             })
         } else {
             // Could not allocate the memory for the grant region.
+            self.notify_memory_pressure(MemoryPressure::Exhausted);
             Err(())
         }
     }
@@ -1389,6 +1555,18 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.debug.increment_timeslice_expiration_count();
     }
 
+    fn debug_cpu_time_us(&self) -> u64 {
+        self.debug.get_cpu_time_us()
+    }
+
+    fn debug_cpu_time_used(&self, us: u64) {
+        self.debug.add_cpu_time_us(us);
+    }
+
+    fn debug_cpu_cycles_used(&self, cycles: u64, frequency_hz: u32) {
+        self.debug.add_cpu_cycles(cycles, frequency_hz);
+    }
+
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.increment_syscall_count();
         self.debug.set_last_syscall(last_syscall);
@@ -1398,6 +1576,46 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.debug.get_last_syscall()
     }
 
+    fn debug_stack_pointer(&self) -> Option<usize> {
+        self.stored_state
+            .map(|stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .stack_pointer(stored_state)
+            })
+            .flatten()
+    }
+
+    fn debug_fault_address(&self) -> Option<usize> {
+        self.stored_state
+            .map(|stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .fault_address(stored_state)
+            })
+            .flatten()
+    }
+
+    fn debug_read_stack(&self, out: &mut [u8]) -> Result<usize, ErrorCode> {
+        let stack_pointer = self.debug_stack_pointer().ok_or(ErrorCode::NOSUPPORT)?;
+        let start = self.mem_start() as usize;
+        let end = self.app_break.get() as usize;
+        if stack_pointer < start || stack_pointer >= end {
+            return Ok(0);
+        }
+        let len = (end - stack_pointer).min(out.len());
+        // # Safety
+        //
+        // The range is within the memory of the process below its memory
+        // break, which is valid for reads. The process is not running while
+        // the kernel executes, so it does not modify the memory while it is
+        // copied.
+        let stack =
+            unsafe { slice::from_raw_parts(self.mem_start().add(stack_pointer - start), len) };
+        out[..len].copy_from_slice(stack);
+        Ok(len)
+    }
+
     fn get_addresses(&self) -> ProcessAddresses {
         ProcessAddresses {
             flash_start: self.flash_start() as usize,
@@ -1525,6 +1743,69 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn read_checkpoint(&self, offset: usize, out: &mut [u8]) -> Result<usize, ErrorCode> {
+        let State::Stopped(stopped_state) = self.state.get() else {
+            return Err(ErrorCode::BUSY);
+        };
+        let ram_len = self.app_break.get() as usize - self.mem_start() as usize;
+
+        let mut copied = 0;
+        if offset < CHECKPOINT_HEADER_LEN {
+            let mut header = [0; CHECKPOINT_HEADER_LEN];
+            self.write_checkpoint_header(stopped_state, ram_len, &mut header)?;
+            let header = &header[offset..];
+            copied = header.len().min(out.len());
+            out[..copied].copy_from_slice(&header[..copied]);
+        }
+
+        let ram_offset = (offset + copied).saturating_sub(CHECKPOINT_HEADER_LEN);
+        if copied < out.len() && ram_offset < ram_len {
+            let len = (ram_len - ram_offset).min(out.len() - copied);
+            // # Safety
+            //
+            // The range is within the memory of the process below its memory
+            // break, which is valid for reads. The process is stopped, so it
+            // does not modify the memory while it is copied.
+            let ram = unsafe { slice::from_raw_parts(self.mem_start().add(ram_offset), len) };
+            out[copied..copied + len].copy_from_slice(ram);
+            copied += len;
+        }
+        Ok(copied)
+    }
+
+    fn write_checkpoint(&self, offset: usize, data: &[u8]) -> Result<(), ErrorCode> {
+        if !matches!(self.state.get(), State::Stopped(_)) {
+            return Err(ErrorCode::BUSY);
+        }
+
+        if offset < CHECKPOINT_HEADER_LEN {
+            if offset != 0 || data.len() < CHECKPOINT_HEADER_LEN {
+                return Err(ErrorCode::INVAL);
+            }
+            self.restore_checkpoint_header(&data[..CHECKPOINT_HEADER_LEN])?;
+            return self.write_checkpoint(CHECKPOINT_HEADER_LEN, &data[CHECKPOINT_HEADER_LEN..]);
+        }
+
+        let ram_offset = offset - CHECKPOINT_HEADER_LEN;
+        let ram_len = self.app_break.get() as usize - self.mem_start() as usize;
+        if ram_offset
+            .checked_add(data.len())
+            .is_none_or(|end| end > ram_len)
+        {
+            return Err(ErrorCode::SIZE);
+        }
+        // # Safety
+        //
+        // The range is within the memory of the process below its memory
+        // break, which the kernel does not use and the process does not access
+        // while it is stopped.
+        let ram = unsafe {
+            slice::from_raw_parts_mut(self.mem_start().add(ram_offset).cast_mut(), data.len())
+        };
+        ram.copy_from_slice(data);
+        Ok(())
+    }
 }
 
 impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C, D> {
@@ -1535,6 +1816,12 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
     // Memory offset to make room for this process's metadata.
     const PROCESS_STRUCT_OFFSET: usize = mem::size_of::<ProcessStandard<C, D>>();
 
+    // Identifies the format of process checkpoints ("TCKP").
+    const CHECKPOINT_MAGIC: u32 = 0x504B_4354;
+    const CHECKPOINT_VERSION: u32 = 1;
+    // Offset of the stored state in the checkpoint header, after nine words.
+    const CHECKPOINT_CONTEXT_OFFSET: usize = 9 * 4;
+
     /// Create a `ProcessStandard` object based on the found `ProcessBinary`.
     pub(crate) unsafe fn create<'a>(
         kernel: &'static Kernel,
@@ -1911,6 +2198,12 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         process.fault_policy = fault_policy;
         process.restart_count = Cell::new(0);
         process.completion_code = OptionalCell::empty();
+        process.memory_pressure_handler = OptionalCell::empty();
+        process.memory_pressure_threshold = Cell::new(0);
+        process.memory_pressure_notified = Cell::new(false);
+        process.upcall_drop_handler = OptionalCell::empty();
+        process.upcall_drops = Cell::new(0);
+        process.last_dropped_upcall = OptionalCell::empty();
 
         process.mpu_config = MapCell::new(mpu_config);
         process.mpu_regions = [
@@ -2021,6 +2314,7 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         self.debug.reset_syscall_count();
         self.debug.reset_dropped_upcall_count();
         self.debug.reset_timeslice_expiration_count();
+        self.debug.reset_cpu_time_us();
 
         // Reset MPU region configuration.
         //
@@ -2107,6 +2401,15 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         // process's memory region.
         self.allow_high_water_mark.set(app_mpu_mem_start);
 
+        // The restarted process has to register for memory pressure
+        // notifications again.
+        self.memory_pressure_handler.clear();
+        self.memory_pressure_threshold.set(0);
+        self.memory_pressure_notified.set(false);
+        self.upcall_drop_handler.clear();
+        self.upcall_drops.set(0);
+        self.last_dropped_upcall.clear();
+
         // Store the adjusted MPU configuration:
         self.mpu_config.replace(mpu_config);
 
@@ -2258,6 +2561,7 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
                 // We always allocate down, so we must lower the
                 // kernel_memory_break.
                 self.kernel_memory_break.set(new_break);
+                self.check_memory_pressure();
 
                 // We need `grant_ptr` as a mutable pointer.
                 let grant_ptr = new_break as *mut u8;
@@ -2364,4 +2668,191 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
     fn app_memory_break(&self) -> *const u8 {
         self.app_break.get()
     }
+
+    /// Allocate memory for a grant from the shared memory pool, if the board
+    /// set one and its policy lets this process grow.
+    fn allocate_in_memory_pool(&self, size: usize, align: usize) -> Option<NonNull<u8>> {
+        let (pool, policy) = self.kernel.memory_pool()?;
+        let owner = self.process_id.get().id();
+        if !policy.allow_growth(self, pool.used_by(owner), size) {
+            return None;
+        }
+        let grant_ptr = pool.allocate(owner, size, align)?;
+        self.notify_memory_pressure(MemoryPressure::Grown);
+        Some(grant_ptr)
+    }
+
+    /// Notify the process if the free memory between its memory break and
+    /// its grant region fell below its threshold.
+    fn check_memory_pressure(&self) {
+        let free = self.kernel_memory_break.get() as usize - self.app_break.get() as usize;
+        if free >= self.memory_pressure_threshold.get() {
+            self.memory_pressure_notified.set(false);
+        } else if !self.memory_pressure_notified.get() {
+            self.memory_pressure_notified.set(true);
+            self.notify_memory_pressure(MemoryPressure::Low);
+        }
+    }
+
+    /// Call the memory pressure handler of the process, if it registered one.
+    ///
+    /// The handler receives the reason, the free memory between the memory
+    /// break and the grant region, and the memory the grant region grew into
+    /// in the shared pool.
+    fn notify_memory_pressure(&self, reason: MemoryPressure) {
+        if let Some(handler) = self.memory_pressure_handler.get() {
+            let free = self.kernel_memory_break.get() as usize - self.app_break.get() as usize;
+            let grown = self
+                .kernel
+                .memory_pool()
+                .map_or(0, |(pool, _)| pool.used_by(self.process_id.get().id()));
+            let _ = self.enqueue_task(Task::FunctionCall(FunctionCall {
+                source: FunctionCallSource::Kernel,
+                pc: handler,
+                argument0: reason as usize,
+                argument1: free,
+                argument2: grown,
+                argument3: 0.into(),
+            }));
+        }
+    }
+
+    /// Count the pending upcalls the upcall queue policy decides on when an
+    /// upcall for `upcall_id` is scheduled.
+    fn upcall_queue_usage(&self, upcall_id: UpcallId) -> UpcallQueueUsage {
+        self.tasks.map_or(
+            UpcallQueueUsage {
+                same_upcall: 0,
+                same_driver: 0,
+                free: 0,
+            },
+            |tasks| {
+                let mut usage = UpcallQueueUsage {
+                    same_upcall: 0,
+                    same_driver: 0,
+                    free: tasks.available_len(),
+                };
+                for pending in tasks.iter().filter_map(task_upcall_id) {
+                    if pending.driver_num == upcall_id.driver_num {
+                        usage.same_driver += 1;
+                        if pending == upcall_id {
+                            usage.same_upcall += 1;
+                        }
+                    }
+                }
+                usage
+            },
+        )
+    }
+
+    /// Call the upcall drop handler of the process, if it registered one and
+    /// upcalls were dropped since it was last called. If the task queue is
+    /// full, the process is notified after the next task is dequeued.
+    fn notify_upcall_drops(&self) {
+        let drops = self.upcall_drops.get();
+        let full = self.tasks.map_or(true, |tasks| tasks.is_full());
+        if drops == 0 || full {
+            return;
+        }
+        if let (Some(handler), Some(upcall_id)) = (
+            self.upcall_drop_handler.get(),
+            self.last_dropped_upcall.get(),
+        ) {
+            let enqueued = self.enqueue_task(Task::FunctionCall(FunctionCall {
+                source: FunctionCallSource::Kernel,
+                pc: handler,
+                argument0: drops,
+                argument1: upcall_id.driver_num,
+                argument2: upcall_id.subscribe_num,
+                argument3: 0.into(),
+            }));
+            if enqueued.is_ok() {
+                self.upcall_drops.set(0);
+                self.last_dropped_upcall.clear();
+            }
+        }
+    }
+
+    /// Serialize the header of a checkpoint of the process into `header`.
+    ///
+    /// The header is a sequence of little-endian 32-bit words: the magic
+    /// number and version of the format, the start addresses of the flash
+    /// and memory of the process, the length of the memory checkpointed, the
+    /// stopped state and the upcall the process waits for, and the length of
+    /// the architecture-specific stored state that follows them.
+    fn write_checkpoint_header(
+        &self,
+        stopped_state: StoppedState,
+        ram_len: usize,
+        header: &mut [u8; CHECKPOINT_HEADER_LEN],
+    ) -> Result<(), ErrorCode> {
+        let (state, upcall) = match stopped_state {
+            StoppedState::Running => (0, None),
+            StoppedState::Yielded => (1, None),
+            StoppedState::YieldedFor(upcall) => (2, Some(upcall)),
+        };
+        let context_len = self.get_stored_state(&mut header[Self::CHECKPOINT_CONTEXT_OFFSET..])?;
+        let words = [
+            Self::CHECKPOINT_MAGIC,
+            Self::CHECKPOINT_VERSION,
+            self.flash_start() as u32,
+            self.mem_start() as u32,
+            ram_len as u32,
+            state,
+            upcall.map_or(0, |upcall| upcall.driver_num as u32),
+            upcall.map_or(0, |upcall| upcall.subscribe_num as u32),
+            context_len as u32,
+        ];
+        for (chunk, word) in header.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Restore the memory break, registers and state of the process from the
+    /// header of a checkpoint.
+    fn restore_checkpoint_header(&self, header: &[u8]) -> Result<(), ErrorCode> {
+        let word = |index: usize| -> u32 {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&header[index * 4..index * 4 + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        if word(0) != Self::CHECKPOINT_MAGIC || word(1) != Self::CHECKPOINT_VERSION {
+            return Err(ErrorCode::INVAL);
+        }
+        if word(2) != self.flash_start() as u32 || word(3) != self.mem_start() as u32 {
+            return Err(ErrorCode::INVAL);
+        }
+        let ram_len = word(4) as usize;
+        let stopped_state = match word(5) {
+            0 => StoppedState::Running,
+            1 => StoppedState::Yielded,
+            2 => StoppedState::YieldedFor(UpcallId {
+                driver_num: word(6) as usize,
+                subscribe_num: word(7) as usize,
+            }),
+            _ => return Err(ErrorCode::INVAL),
+        };
+        let context = header
+            .get(Self::CHECKPOINT_CONTEXT_OFFSET..)
+            .and_then(|context| context.get(..word(8) as usize))
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Restore the memory break first, as it fails if the checkpointed
+        // memory does not fit in the memory of the process.
+        self.brk(self.mem_start().wrapping_add(ram_len))?;
+        self.stored_state
+            .map(|stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .load_context(stored_state, context)
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))?;
+
+        // Tasks queued since the process was loaded, such as the call to its
+        // entry point, do not apply to the restored process.
+        self.tasks.map(|tasks| tasks.empty());
+        self.state.set(State::Stopped(stopped_state));
+        Ok(())
+    }
 }
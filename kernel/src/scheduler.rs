@@ -5,6 +5,7 @@
 //! Interface for Tock kernel schedulers.
 
 pub mod cooperative;
+pub mod edf;
 pub mod mlfq;
 pub mod priority;
 pub mod round_robin;
@@ -0,0 +1,445 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Earliest Deadline First real-time scheduler for Tock
+//!
+//! Processes that include the real-time TBF header declare a period and a
+//! budget: every period they are released and may use up to budget
+//! microseconds of CPU time, which must be used before the end of the period
+//! (their deadline). This scheduler follows these rules:
+//!
+//! - Rule 1: Among the ready real-time processes that have budget left in their
+//!   current period, the one with the earliest deadline runs.
+//! - Rule 2: A real-time process runs with a timeslice of at most its remaining
+//!   budget, which the `SchedulerTimer` enforces. A process that exhausts its
+//!   budget while it still has work to do has overrun: the overrun is counted
+//!   and reported to the `OverrunClient`, and the process does not run again
+//!   until its next period.
+//! - Rule 3: The timeslice is also cut at the next period start of any
+//!   real-time process, so that a newly released process with an earlier
+//!   deadline preempts the running one.
+//! - Rule 4: Processes without the real-time header run in round-robin fashion
+//!   when no real-time process can run.
+//!
+//! With budget enforcement, all deadlines are met as long as the sum of
+//! `budget / period` over all real-time processes does not exceed the share of
+//! CPU time left by the kernel itself (interrupt and deferred call handling).
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::deferred_call::DeferredCall;
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::kernel::MIN_QUANTA_THRESHOLD_US;
+use crate::platform::chip::Chip;
+use crate::process::{ProcessId, ProcessSlot, StoppedExecutingReason};
+use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::utilities::cells::OptionalCell;
+
+/// Receives notifications of budget overruns.
+pub trait OverrunClient {
+    /// The process `processid` used all of its `budget_us` microseconds of CPU
+    /// time in its current period without finishing its work. It will not run
+    /// again until its next period.
+    fn overrun(&self, processid: ProcessId, budget_us: u32);
+}
+
+/// Real-time state of the process in its current period.
+struct EdfProcState<T: Ticks> {
+    /// The process the state belongs to. The state is reset when a different
+    /// process (or a restarted one) occupies the slot.
+    processid: OptionalCell<ProcessId>,
+    /// Start of the current period.
+    release: Cell<T>,
+    /// End of the current period.
+    deadline: Cell<T>,
+    /// CPU time the process can still use in the current period.
+    remaining_us: Cell<u32>,
+    /// Number of periods in which the process exhausted its budget.
+    overruns: Cell<u32>,
+}
+
+impl<T: Ticks> EdfProcState<T> {
+    fn new() -> Self {
+        EdfProcState {
+            processid: OptionalCell::empty(),
+            release: Cell::new(T::from(0)),
+            deadline: Cell::new(T::from(0)),
+            remaining_us: Cell::new(0),
+            overruns: Cell::new(0),
+        }
+    }
+
+    /// Starts the first period of a process at `now`.
+    fn start(&self, now: T, period: T, budget_us: u32) {
+        self.release.set(now);
+        self.deadline.set(now.wrapping_add(period));
+        self.remaining_us.set(budget_us);
+        self.overruns.set(0);
+    }
+
+    /// Starts the period that contains `now` if the current one ended,
+    /// skipping the periods that ended while the process was not scheduled.
+    fn update(&self, now: T, period: T, budget_us: u32) {
+        if now.within_range(self.release.get(), self.deadline.get()) {
+            return;
+        }
+        let late = now.wrapping_sub(self.deadline.get()).into_u32();
+        let skipped = late - late % period.into_u32();
+        let release = self.deadline.get().wrapping_add(T::from(skipped));
+        self.release.set(release);
+        self.deadline.set(release.wrapping_add(period));
+        self.remaining_us.set(budget_us);
+    }
+
+    /// Ticks from `now` until the deadline. Earlier deadlines have smaller
+    /// values.
+    fn until_deadline(&self, now: T) -> u32 {
+        self.deadline.get().wrapping_sub(now).into_u32()
+    }
+
+    /// Charges `execution_time_us` of CPU time to the budget of the process.
+    /// Returns whether the process overran its budget: its timeslice expired
+    /// without leaving enough budget to run again in this period. The process
+    /// is then throttled until its next period.
+    fn charge(&self, execution_time_us: u32, timeslice_expired: bool) -> bool {
+        let remaining_us = self.remaining_us.get().saturating_sub(execution_time_us);
+        self.remaining_us.set(remaining_us);
+
+        let overrun = timeslice_expired && remaining_us <= MIN_QUANTA_THRESHOLD_US;
+        if overrun {
+            self.remaining_us.set(0);
+            self.overruns.set(self.overruns.get().saturating_add(1));
+        }
+        overrun
+    }
+}
+
+/// Nodes store per-process state
+pub struct EdfProcessNode<'a, A: 'static + time::Alarm<'static>> {
+    proc: &'static ProcessSlot,
+    state: EdfProcState<A::Ticks>,
+    next: ListLink<'a, EdfProcessNode<'a, A>>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfProcessNode<'a, A> {
+    pub fn new(proc: &'static ProcessSlot) -> EdfProcessNode<'a, A> {
+        EdfProcessNode {
+            proc,
+            state: EdfProcState::new(),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Returns the `(period_us, budget_us)` of the process in this slot, if it
+    /// is a real-time process.
+    fn real_time_parameters(&self) -> Option<(u32, u32)> {
+        self.proc
+            .get()
+            .and_then(|proc| proc.get_real_time_parameters())
+            .filter(|&(period_us, budget_us)| period_us > 0 && budget_us > 0)
+    }
+
+    /// Whether the process in this slot is a real-time process that is ready
+    /// and has budget left in its current period.
+    fn can_run(&self) -> bool {
+        self.state.processid.is_some()
+            && self.state.remaining_us.get() > MIN_QUANTA_THRESHOLD_US
+            && self.proc.get().is_some_and(|proc| proc.ready())
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> ListNode<'a, EdfProcessNode<'a, A>>
+    for EdfProcessNode<'a, A>
+{
+    fn next(&'a self) -> &'a ListLink<'a, EdfProcessNode<'a, A>> {
+        &self.next
+    }
+}
+
+pub struct EdfSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    pub processes: List<'a, EdfProcessNode<'a, A>>,
+    /// The node of the process currently running.
+    running: OptionalCell<&'a EdfProcessNode<'a, A>>,
+    /// Index in `processes` of the last background process that ran.
+    last_background: Cell<usize>,
+    client: OptionalCell<&'a dyn OverrunClient>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfSched<'a, A> {
+    /// Timeslice of processes without real-time parameters.
+    pub const BACKGROUND_TIMESLICE_US: u32 = 10000;
+
+    pub fn new(alarm: &'static A) -> Self {
+        Self {
+            alarm,
+            processes: List::new(),
+            running: OptionalCell::empty(),
+            last_background: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_overrun_client(&self, client: &'a dyn OverrunClient) {
+        self.client.set(client);
+    }
+
+    /// Returns the number of periods in which the process exhausted its
+    /// budget, or `None` if it is not a real-time process.
+    pub fn overruns(&self, processid: ProcessId) -> Option<u32> {
+        self.processes
+            .iter()
+            .find(|node| node.state.processid.contains(&processid))
+            .map(|node| node.state.overruns.get())
+    }
+
+    /// Starts the current period of every real-time process, and forgets the
+    /// state of processes that are no longer running.
+    fn update_periods(&self, now: A::Ticks) {
+        for node in self.processes.iter() {
+            let Some((period_us, budget_us)) = node.real_time_parameters() else {
+                node.state.processid.clear();
+                continue;
+            };
+            let state = &node.state;
+            // Periods shorter than a tick last one tick.
+            let period = A::Ticks::from(self.alarm.ticks_from_us(period_us).into_u32().max(1));
+            let processid = node.proc.get().map(|proc| proc.processid());
+
+            if state.processid.get() != processid {
+                // The process just started: its first period starts now.
+                state.processid.insert(processid);
+                state.start(now, period, budget_us);
+            } else {
+                state.update(now, period, budget_us);
+            }
+        }
+    }
+
+    /// Returns the ready real-time process with the earliest deadline.
+    fn earliest_deadline(&self, now: A::Ticks) -> Option<&'a EdfProcessNode<'a, A>> {
+        self.processes
+            .iter()
+            .filter(|node| node.can_run())
+            .min_by_key(|node| node.state.until_deadline(now))
+    }
+
+    /// Returns the next ready process without real-time parameters, in
+    /// round-robin order.
+    fn next_background(&self) -> Option<&'a EdfProcessNode<'a, A>> {
+        let last = self.last_background.get();
+        // The first ready process after the last one that ran, or else the
+        // first ready process.
+        let mut first = None;
+        let mut next = None;
+        for (index, node) in self.processes.iter().enumerate() {
+            if node.state.processid.is_some() || !node.proc.get().is_some_and(|proc| proc.ready()) {
+                continue;
+            }
+            if index > last {
+                next = Some((index, node));
+                break;
+            }
+            first.get_or_insert((index, node));
+        }
+        next.or(first).map(|(index, node)| {
+            self.last_background.set(index);
+            node
+        })
+    }
+
+    /// Returns the time until the next period of any real-time process
+    /// starts.
+    fn us_until_next_release(&self, now: A::Ticks) -> Option<u32> {
+        self.processes
+            .iter()
+            .filter(|node| node.state.processid.is_some())
+            .map(|node| node.state.deadline.get().wrapping_sub(now))
+            .min_by_key(|ticks| ticks.into_u32())
+            .map(|ticks| self.alarm.ticks_to_us(ticks))
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EdfSched<'_, A> {
+    fn next(&self) -> SchedulingDecision {
+        let now = self.alarm.now();
+        self.update_periods(now);
+
+        let (node, timeslice) = match self.earliest_deadline(now) {
+            Some(node) => (node, node.state.remaining_us.get()),
+            None => match self.next_background() {
+                Some(node) => (node, Self::BACKGROUND_TIMESLICE_US),
+                None => {
+                    self.running.clear();
+                    return SchedulingDecision::TrySleep;
+                }
+            },
+        };
+
+        // Stop at the next release so that the scheduler can preempt this
+        // process if the released one has an earlier deadline. Timeslices the
+        // kernel would consider already expired are not cut.
+        let timeslice = match self.us_until_next_release(now) {
+            Some(until_release) if until_release > MIN_QUANTA_THRESHOLD_US => {
+                timeslice.min(until_release)
+            }
+            _ => timeslice,
+        };
+
+        self.running.set(node);
+        // `node` came from the process slot, so it holds a process.
+        let next = node.proc.get().unwrap().processid();
+        SchedulingDecision::RunProcess((next, NonZeroU32::new(timeslice)))
+    }
+
+    unsafe fn continue_process(&self, _: ProcessId, chip: &C) -> bool {
+        // In addition to checking for interrupts, also checks whether a
+        // real-time process with an earlier deadline became ready, for
+        // example through IPC with the running process.
+        !(chip.has_pending_interrupts()
+            || DeferredCall::has_tasks()
+            || self.running.map_or(false, |running| {
+                let now = self.alarm.now();
+                self.earliest_deadline(now).is_some_and(|earliest| {
+                    !core::ptr::eq(earliest, running)
+                        && (running.state.processid.is_none()
+                            || earliest.state.until_deadline(now)
+                                < running.state.until_deadline(now))
+                })
+            }))
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let Some(node) = self.running.take() else {
+            return;
+        };
+        let state = &node.state;
+        if state.processid.is_none() {
+            return;
+        }
+
+        // should never fail as we never run cooperatively
+        let execution_time_us = execution_time_us.unwrap_or(0);
+        if state.charge(
+            execution_time_us,
+            result == StoppedExecutingReason::TimesliceExpired,
+        ) {
+            if let (Some(processid), Some((_, budget_us))) =
+                (state.processid.get(), node.real_time_parameters())
+            {
+                self.client
+                    .map(|client| client.overrun(processid, budget_us));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hil::time::Ticks32;
+
+    fn state(now: u32, period: u32, budget_us: u32) -> EdfProcState<Ticks32> {
+        let state = EdfProcState::new();
+        state.start(Ticks32::from(now), Ticks32::from(period), budget_us);
+        state
+    }
+
+    #[test]
+    fn earliest_deadline_first() {
+        let now = Ticks32::from(1000);
+        let states = [
+            state(900, 500, 100),
+            state(1000, 200, 100),
+            state(0, 1300, 100),
+        ];
+        let deadlines = states.each_ref().map(|state| state.until_deadline(now));
+        assert_eq!(deadlines, [400, 200, 300]);
+        assert_eq!(
+            (0..3).min_by_key(|&i| states[i].until_deadline(now)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn deadline_ordering_across_wrap() {
+        // A deadline after the counter wraps is later than one before.
+        let now = Ticks32::from(u32::MAX - 100);
+        let before_wrap = state(u32::MAX - 150, 100, 100);
+        let after_wrap = state(u32::MAX - 100, 300, 100);
+        assert_eq!(before_wrap.until_deadline(now), 50);
+        assert_eq!(after_wrap.until_deadline(now), 300);
+        assert!(before_wrap.until_deadline(now) < after_wrap.until_deadline(now));
+    }
+
+    #[test]
+    fn periods_advance() {
+        let state = state(0, 100, 50);
+        state.charge(30, false);
+
+        // Still in the first period.
+        state.update(Ticks32::from(99), Ticks32::from(100), 50);
+        assert_eq!(state.remaining_us.get(), 20);
+
+        // The next period restores the budget.
+        state.update(Ticks32::from(100), Ticks32::from(100), 50);
+        assert_eq!(state.release.get(), Ticks32::from(100));
+        assert_eq!(state.deadline.get(), Ticks32::from(200));
+        assert_eq!(state.remaining_us.get(), 50);
+
+        // Periods that ended while the process was not scheduled are
+        // skipped.
+        state.update(Ticks32::from(543), Ticks32::from(100), 50);
+        assert_eq!(state.release.get(), Ticks32::from(500));
+        assert_eq!(state.deadline.get(), Ticks32::from(600));
+    }
+
+    #[test]
+    fn periods_advance_across_wrap() {
+        let state = state(u32::MAX - 49, 100, 50);
+        assert_eq!(state.deadline.get(), Ticks32::from(50));
+        state.update(Ticks32::from(10), Ticks32::from(100), 50);
+        assert_eq!(state.deadline.get(), Ticks32::from(50));
+        state.update(Ticks32::from(60), Ticks32::from(100), 50);
+        assert_eq!(state.release.get(), Ticks32::from(50));
+        assert_eq!(state.deadline.get(), Ticks32::from(150));
+    }
+
+    #[test]
+    fn budget_overrun() {
+        let budget_us = 10 * MIN_QUANTA_THRESHOLD_US;
+        let state = state(0, 1000, budget_us);
+
+        // Blocking before the end of the timeslice is not an overrun.
+        assert!(!state.charge(budget_us / 2, false));
+        assert_eq!(state.remaining_us.get(), budget_us / 2);
+        assert_eq!(state.overruns.get(), 0);
+
+        // Using the rest of the budget is.
+        assert!(state.charge(budget_us / 2, true));
+        assert_eq!(state.remaining_us.get(), 0);
+        assert_eq!(state.overruns.get(), 1);
+
+        // The process is throttled until the next period.
+        state.update(Ticks32::from(1000), Ticks32::from(1000), budget_us);
+        assert_eq!(state.remaining_us.get(), budget_us);
+        assert_eq!(state.overruns.get(), 1);
+    }
+
+    #[test]
+    fn timeslice_cut_before_budget_is_not_overrun() {
+        // The timeslice ends at the release of another process, with budget
+        // left.
+        let budget_us = 10 * MIN_QUANTA_THRESHOLD_US;
+        let state = state(0, 1000, budget_us);
+        assert!(!state.charge(budget_us / 2, true));
+        assert_eq!(state.overruns.get(), 0);
+
+        // A remainder too short to run is an overrun.
+        assert!(state.charge(budget_us / 2 - MIN_QUANTA_THRESHOLD_US, true));
+        assert_eq!(state.overruns.get(), 1);
+    }
+}
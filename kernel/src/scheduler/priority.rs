@@ -4,49 +4,198 @@
 
 //! Fixed Priority Scheduler for Tock
 //!
-//! This scheduler assigns priority to processes based on their order in the
-//! `PROCESSES` array, and runs the highest priority process available at any
-//! point in time. Kernel tasks (bottom half interrupt handling / deferred call
-//! handling) always take priority over userspace processes.
+//! This scheduler runs the highest priority process available at any point in
+//! time. Kernel tasks (bottom half interrupt handling / deferred call handling)
+//! always take priority over userspace processes.
+//!
+//! Priorities are numbers where lower values are higher priorities. When a
+//! process starts, its priority is, in order of precedence:
+//!
+//! 1. the priority the board assigned to its process name,
+//! 2. the priority requested in its TBF header, or
+//! 3. its index in the `PROCESSES` array.
+//!
+//! Processes with the same priority run in the order of the `PROCESSES` array.
+//! The priority can be changed at runtime through the [`PriorityControl`]
+//! trait, which requires a capability. Such a change lasts until the process
+//! restarts.
+//!
+//! If the scheduler is set as the notify client of the IPC driver, a service
+//! that was notified by a client runs with the priority of the client if it is
+//! higher than its own, until it notifies the client back. This prevents
+//! medium priority processes from delaying a high priority process that waits
+//! on a low priority service. A client lends its priority to the last service
+//! it notified, and stops lending it when it exits, faults or restarts, or
+//! when that service restarts.
 //!
 //! Notably, there is no need to enforce timeslices, as it is impossible for a
 //! process running to not be the highest priority process at any point while it
 //! is running. The only way for a process to longer be the highest priority is
-//! for an interrupt to occur, which will cause the process to stop running.
+//! for an interrupt to occur or for a system call to change priorities, which
+//! will cause the process to stop running.
+
+use core::cell::Cell;
 
+use crate::capabilities::ProcessManagementCapability;
 use crate::deferred_call::DeferredCall;
+use crate::ipc::IPCNotifyClient;
 use crate::kernel::Kernel;
 use crate::platform::chip::Chip;
+use crate::process::Process;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Interface to read and change the priorities of processes.
+pub trait PriorityControl {
+    /// Returns the priority assigned to the process, or `None` if the process
+    /// does not exist. Lower values are higher priorities.
+    fn get_priority(&self, processid: ProcessId) -> Option<u32>;
+
+    /// Changes the priority of the process until it restarts.
+    ///
+    /// Returns `INVAL` if the process does not exist.
+    fn set_priority(
+        &self,
+        processid: ProcessId,
+        priority: u32,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Priority state of the process in one slot of the `PROCESSES` array.
+pub struct ProcessPriority {
+    /// The process the state belongs to. The state is reset when a different
+    /// process (or a restarted one) occupies the slot.
+    processid: OptionalCell<ProcessId>,
+    /// Priority assigned to the process.
+    priority: Cell<u32>,
+    /// The IPC service this process waits on, which runs with the priority
+    /// of this process if it is higher.
+    waiting_on: OptionalCell<ProcessId>,
+}
+
+impl ProcessPriority {
+    pub const fn new() -> Self {
+        Self {
+            processid: OptionalCell::empty(),
+            priority: Cell::new(0),
+            waiting_on: OptionalCell::empty(),
+        }
+    }
+}
 
-/// Priority scheduler based on the order of processes in the `PROCESSES` array.
+impl Default for ProcessPriority {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Priority scheduler with per-process priorities.
 pub struct PrioritySched {
     kernel: &'static Kernel,
     running: OptionalCell<ProcessId>,
+    /// State of each slot of the `PROCESSES` array.
+    priorities: &'static [ProcessPriority],
+    /// Priorities the board assigns to processes, by process name.
+    board_priorities: &'static [(&'static str, u32)],
 }
 
 impl PrioritySched {
-    pub const fn new(kernel: &'static Kernel) -> Self {
+    pub const fn new(
+        kernel: &'static Kernel,
+        priorities: &'static [ProcessPriority],
+        board_priorities: &'static [(&'static str, u32)],
+    ) -> Self {
         Self {
             kernel,
             running: OptionalCell::empty(),
+            priorities,
+            board_priorities,
         }
     }
+
+    /// Returns the state of the process, initializing it if the process just
+    /// started.
+    fn state(&self, process: &dyn Process) -> Option<&ProcessPriority> {
+        let processid = process.processid();
+        let state = self.priorities.get(processid.index)?;
+        if !state.processid.contains(&processid) {
+            let name = process.get_process_name();
+            let priority = self
+                .board_priorities
+                .iter()
+                .find(|(board_name, _)| *board_name == name)
+                .map(|(_, priority)| *priority)
+                .or_else(|| process.get_priority())
+                .unwrap_or(processid.index as u32);
+            state.processid.set(processid);
+            state.priority.set(priority);
+            state.waiting_on.clear();
+            // The clients of the process that occupied the slot before no
+            // longer wait on it.
+            let previous =
+                |service: ProcessId| service.index == processid.index && service != processid;
+            for other in self.priorities {
+                if other.waiting_on.get().is_some_and(previous) {
+                    other.waiting_on.clear();
+                }
+            }
+        }
+        Some(state)
+    }
+
+    /// Returns the priority assigned to the process.
+    fn assigned_priority(&self, process: &dyn Process) -> u32 {
+        self.state(process)
+            .map_or(process.processid().index as u32, |state| {
+                state.priority.get()
+            })
+    }
+
+    /// Returns the priority the process runs with, which includes the
+    /// priorities of the IPC clients waiting on it.
+    fn effective_priority(&self, process: &dyn Process) -> u32 {
+        let priority = self.assigned_priority(process);
+        let service = process.processid();
+        self.kernel
+            .get_process_iter()
+            .filter(|client| self.is_waiting_on(*client, service))
+            .map(|client| self.assigned_priority(client))
+            .fold(priority, u32::min)
+    }
+
+    /// Returns whether `client` waits on `service`. A client that is no
+    /// longer running stops waiting.
+    fn is_waiting_on(&self, client: &dyn Process, service: ProcessId) -> bool {
+        let Some(state) = self.state(client) else {
+            return false;
+        };
+        if !client.is_running() {
+            state.waiting_on.clear();
+            return false;
+        }
+        state.waiting_on.contains(&service)
+    }
+
+    /// Returns the ready process with the highest priority.
+    fn highest_priority_ready(&self) -> Option<(ProcessId, u32)> {
+        self.kernel
+            .get_process_iter()
+            .filter(|proc| proc.ready())
+            .map(|proc| (proc.processid(), self.effective_priority(proc)))
+            .min_by_key(|&(_, priority)| priority)
+    }
 }
 
 impl<C: Chip> Scheduler<C> for PrioritySched {
     fn next(&self) -> SchedulingDecision {
-        // Iterates in-order through the process array, always running the
-        // first process it finds that is ready to run. This enforces the
-        // priorities of all processes.
-        let next = self
-            .kernel
-            .get_process_iter()
-            .find(|&proc| proc.ready())
-            .map(|proc| proc.processid());
+        // Always run the ready process with the highest priority. Among
+        // processes with the same priority, the first one in the process
+        // array runs.
+        let next = self.highest_priority_ready().map(|(next, _)| next);
         self.running.insert(next);
 
         next.map_or(SchedulingDecision::TrySleep, |next| {
@@ -58,16 +207,17 @@ impl<C: Chip> Scheduler<C> for PrioritySched {
         // In addition to checking for interrupts, also checks if any higher
         // priority processes have become ready. This check is necessary because
         // a system call by this process could make another process ready, if
-        // this app is communicating via IPC with a higher priority app.
+        // this app is communicating via IPC with a higher priority app, or
+        // change priorities.
         !(chip.has_pending_interrupts()
             || DeferredCall::has_tasks()
             || self
-                .kernel
-                .get_process_iter()
-                .find(|proc| proc.ready())
-                .is_some_and(|ready_proc| {
+                .highest_priority_ready()
+                .is_some_and(|(_, ready_priority)| {
                     self.running.map_or(false, |running| {
-                        ready_proc.processid().index < running.index
+                        self.kernel.process_map_or(false, running, |proc| {
+                            ready_priority < self.effective_priority(proc)
+                        })
                     })
                 }))
     }
@@ -76,3 +226,44 @@ impl<C: Chip> Scheduler<C> for PrioritySched {
         self.running.clear()
     }
 }
+
+impl PriorityControl for PrioritySched {
+    fn get_priority(&self, processid: ProcessId) -> Option<u32> {
+        self.kernel
+            .process_map_or(None, processid, |proc| Some(self.assigned_priority(proc)))
+    }
+
+    fn set_priority(
+        &self,
+        processid: ProcessId,
+        priority: u32,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        self.kernel
+            .process_map_or(Err(ErrorCode::INVAL), processid, |proc| {
+                let state = self.state(proc).ok_or(ErrorCode::INVAL)?;
+                state.priority.set(priority);
+                Ok(())
+            })
+    }
+}
+
+impl IPCNotifyClient for PrioritySched {
+    fn service_notified(&self, client: ProcessId, service: ProcessId) {
+        self.kernel.process_map_or((), client, |proc| {
+            if let Some(state) = self.state(proc) {
+                state.waiting_on.set(service);
+            }
+        });
+    }
+
+    fn client_notified(&self, service: ProcessId, client: ProcessId) {
+        self.kernel.process_map_or((), client, |proc| {
+            if let Some(state) = self.state(proc) {
+                if state.waiting_on.contains(&service) {
+                    state.waiting_on.clear();
+                }
+            }
+        });
+    }
+}
@@ -712,4 +712,29 @@ pub trait UserspaceKernelBoundary {
     /// Store architecture specific (e.g. CPU registers or status flags) data
     /// for a process. On success returns the number of elements written to out.
     fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Load architecture specific data for a process from `input`, which was
+    /// written by [`store_context()`](UserspaceKernelBoundary::store_context).
+    /// Used to restore a process from a checkpoint.
+    ///
+    /// Returns `ErrorCode::FAIL` if `input` is not a valid stored state. The
+    /// default implementation returns `ErrorCode::NOSUPPORT`.
+    fn load_context(&self, _state: &mut Self::StoredState, _input: &[u8]) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Returns the stack pointer of the process whose state is `state`, if
+    /// the architecture records it. The default implementation returns
+    /// `None`.
+    fn stack_pointer(&self, _state: &Self::StoredState) -> Option<usize> {
+        None
+    }
+
+    /// Returns the memory address that caused the last fault of the process
+    /// whose state is `state`, if the architecture records it and the fault
+    /// was caused by a memory access. The default implementation returns
+    /// `None`.
+    fn fault_address(&self, _state: &Self::StoredState) -> Option<usize> {
+        None
+    }
 }
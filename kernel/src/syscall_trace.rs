@@ -0,0 +1,235 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2025.
+
+//! Tracing of system calls and upcalls.
+//!
+//! When a board sets a [`SyscallTracer`] with `Kernel::set_syscall_tracer()`,
+//! the kernel records every system call, with its arguments and return value,
+//! and every upcall delivered to a process as a [`TraceRecord`]. The tracer
+//! timestamps and stores the records, for example in a ring buffer, until
+//! they are read, for example by the process console.
+//!
+//! Records are printed as a single line of hexadecimal fields, which
+//! `tools/debugging-and-development/syscall_trace_decoder.py` turns back into
+//! readable system calls:
+//!
+//! ```text
+//! trace <timestamp> <process id> <event> <arg0> <arg1> <arg2> <arg3> [<r0> <r1> <r2> <r3>]
+//! ```
+//!
+//! Unlike the `trace_syscalls` kernel configuration option, which prints
+//! every system call with `debug!()` as it happens, tracing can be enabled
+//! without rebuilding the kernel and does not slow the system down by
+//! printing.
+
+use core::fmt;
+
+use crate::process::ProcessId;
+use crate::syscall::{Syscall, SyscallReturn};
+use crate::utilities::arch_helpers::{encode_syscall_return_trd104, TRD104SyscallReturn};
+
+/// Event recorded in the trace.
+///
+/// System calls use the number of their class in TRD104.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceEvent {
+    #[default]
+    Yield = 0,
+    Subscribe = 1,
+    Command = 2,
+    ReadWriteAllow = 3,
+    ReadOnlyAllow = 4,
+    Memop = 5,
+    Exit = 6,
+    UserspaceReadableAllow = 7,
+    /// The kernel called an upcall function of the process, or returned the
+    /// upcall values from a `Yield-WaitFor` system call.
+    Upcall = 0x80,
+}
+
+/// A system call or upcall recorded in the trace.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Time of the event, in microseconds, as measured by the tracer. Wraps
+    /// around.
+    pub timestamp_us: u32,
+    /// Identifier of the process, as returned by `ProcessId::id()`.
+    pub process_id: u32,
+    pub event: TraceEvent,
+    /// For system calls, the arguments in registers r0 to r3. For upcalls,
+    /// the address of the function, or 0 for values returned by
+    /// `Yield-WaitFor`, and the three upcall arguments.
+    pub arguments: [u32; 4],
+    /// The value returned to the process in registers r0 to r3 as specified
+    /// in TRD104, or `None` for `yield`, `exit` and upcalls.
+    pub return_value: Option<[u32; 4]>,
+}
+
+impl TraceRecord {
+    /// Record `syscall`, issued by `process_id`, which returned
+    /// `return_value`.
+    pub fn syscall(
+        process_id: ProcessId,
+        syscall: &Syscall,
+        return_value: Option<SyscallReturn>,
+    ) -> Self {
+        let (event, arguments) = match *syscall {
+            Syscall::Yield {
+                which,
+                param_a,
+                param_b,
+            } => (TraceEvent::Yield, [which, param_a, param_b, 0]),
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                upcall_ptr,
+                appdata,
+            } => (
+                TraceEvent::Subscribe,
+                [
+                    driver_number,
+                    subdriver_number,
+                    upcall_ptr.as_ptr::<()>() as usize,
+                    appdata.as_usize(),
+                ],
+            ),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => (
+                TraceEvent::Command,
+                [driver_number, subdriver_number, arg0, arg1],
+            ),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                TraceEvent::ReadWriteAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                TraceEvent::UserspaceReadableAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                TraceEvent::ReadOnlyAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::Memop { operand, arg0 } => (TraceEvent::Memop, [operand, arg0, 0, 0]),
+            Syscall::Exit {
+                which,
+                completion_code,
+            } => (TraceEvent::Exit, [which, completion_code, 0, 0]),
+        };
+        let return_value = return_value.map(|return_value| {
+            let mut registers = [0; 4];
+            let [r0, r1, r2, r3] = &mut registers;
+            encode_syscall_return_trd104(
+                &TRD104SyscallReturn::from_syscall_return(return_value),
+                r0,
+                r1,
+                r2,
+                r3,
+            );
+            registers
+        });
+        Self {
+            timestamp_us: 0,
+            process_id: process_id.id() as u32,
+            event,
+            arguments: arguments.map(|argument| argument as u32),
+            return_value,
+        }
+    }
+
+    /// Record an upcall delivered to `process_id`. `function` is the address
+    /// of the upcall function, or `None` if the values were returned by
+    /// `Yield-WaitFor`.
+    pub fn upcall(process_id: ProcessId, function: Option<usize>, arguments: [usize; 3]) -> Self {
+        Self {
+            timestamp_us: 0,
+            process_id: process_id.id() as u32,
+            event: TraceEvent::Upcall,
+            arguments: [
+                function.unwrap_or(0) as u32,
+                arguments[0] as u32,
+                arguments[1] as u32,
+                arguments[2] as u32,
+            ],
+            return_value: None,
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a0, a1, a2, a3] = self.arguments;
+        write!(
+            f,
+            "trace {:08x} {:x} {:02x} {:08x} {:08x} {:08x} {:08x}",
+            self.timestamp_us, self.process_id, self.event as u8, a0, a1, a2, a3
+        )?;
+        if let Some([r0, r1, r2, r3]) = self.return_value {
+            write!(f, " {:08x} {:08x} {:08x} {:08x}", r0, r1, r2, r3)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores the records of the system call trace.
+pub trait SyscallTracer {
+    /// Timestamp and store `record`. Called by the kernel for every system
+    /// call and upcall, so it must be fast.
+    fn record(&self, record: TraceRecord);
+
+    /// Remove and return the oldest stored record.
+    fn take(&self) -> Option<TraceRecord>;
+
+    /// Number of stored records.
+    fn len(&self) -> usize;
+
+    /// Number of records that were discarded because the tracer was full.
+    fn dropped(&self) -> usize;
+}
+
+/// Reads the records stored by a [`SyscallTracer`] without removing them, for
+/// example to save the last system calls of a process that faulted.
+///
+/// This is separate from [`SyscallTracer`] so that boards that only print the
+/// trace do not include it.
+pub trait SyscallTraceHistory {
+    /// Copy the most recent stored records of the process whose identifier
+    /// is `process_id`, oldest first, into `records`, and return how many
+    /// were copied.
+    fn last_records(&self, process_id: u32, records: &mut [TraceRecord]) -> usize;
+}
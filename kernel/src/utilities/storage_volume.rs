@@ -31,3 +31,28 @@ macro_rules! storage_volume {
         pub static $N: [u8; $kB * 1024] = [0x00; $kB * 1024];
     };
 }
+
+/// Refers to on-chip non-volatile storage outside of the kernel image.
+///
+/// Volumes allocated with [`storage_volume!`] are part of the kernel image, so
+/// they are erased whenever a new kernel is flashed. Data that must survive
+/// kernel updates can instead be kept in a region of flash at a fixed address
+/// that is not part of the kernel or application regions. Boards reserve such
+/// a region by excluding it from the `rom` and `prog` regions of their linker
+/// script, for example:
+///
+/// ```ignore
+/// // Last 16 kB of flash, excluded from `prog` in layout.ld.
+/// let volume = unsafe { kernel::utilities::storage_volume::fixed_storage_volume(0xFC000, 16) };
+/// ```
+///
+/// # Safety
+///
+/// `address` must be the start of `kb` kB of flash that is not used by
+/// anything else, in particular not by the kernel image, processes, or other
+/// storage volumes. The region must be aligned to the flash page size.
+pub unsafe fn fixed_storage_volume(address: usize, kb: usize) -> &'static [u8] {
+    // SAFETY: the caller guarantees that the region is flash reserved for
+    // this volume, which is valid for the lifetime of the kernel.
+    unsafe { core::slice::from_raw_parts(address as *const u8, kb * 1024) }
+}
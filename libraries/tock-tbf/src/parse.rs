@@ -129,6 +129,9 @@ pub fn parse_tbf_header(
                 let mut storage_permissions_pointer: Option<&[u8]> = None;
                 let mut kernel_version: Option<types::TbfHeaderV2KernelVersion> = None;
                 let mut short_id: Option<types::TbfHeaderV2ShortId> = None;
+                let mut real_time: Option<types::TbfHeaderV2RealTime> = None;
+                let mut priority: Option<types::TbfHeaderV2Priority> = None;
+                let mut ipc_clients = None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -273,6 +276,47 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderRealTime => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2RealTime>();
+                            if tlv_header.length as usize == entry_len {
+                                real_time = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderPriority => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2Priority>();
+                            if tlv_header.length as usize == entry_len {
+                                priority = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderIpcClients => {
+                            ipc_clients = Some(
+                                remaining
+                                    .get(0..tlv_header.length as usize)
+                                    .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                    .try_into()?,
+                            );
+                        }
+
                         _ => {}
                     }
 
@@ -297,6 +341,9 @@ pub fn parse_tbf_header(
                     storage_permissions: storage_permissions_pointer,
                     kernel_version,
                     short_id,
+                    real_time,
+                    priority,
+                    ipc_clients,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
@@ -11,6 +11,9 @@ use core::mem::size_of;
 /// and modify. This simplification enables us to use fixed sized buffers.
 const NUM_STORAGE_PERMISSIONS: usize = 8;
 
+/// We only support up to a fixed number of allowed IPC clients for each app.
+const NUM_IPC_CLIENTS: usize = 8;
+
 /// Error when parsing just the beginning of the TBF header. This is only used
 /// when establishing the linked list structure of apps installed in flash.
 pub enum InitialTbfParseError {
@@ -131,6 +134,9 @@ pub enum TbfHeaderTypes {
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
     TbfHeaderShortId = 10,
+    TbfHeaderRealTime = 11,
+    TbfHeaderPriority = 12,
+    TbfHeaderIpcClients = 13,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -250,6 +256,35 @@ pub struct TbfHeaderV2ShortId {
     short_id: Option<core::num::NonZeroU32>,
 }
 
+/// The v2 real-time scheduling parameters for apps.
+///
+/// Header to specify that an app runs periodically and needs at most `budget`
+/// microseconds of CPU time every `period` microseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2RealTime {
+    period_us: u32,
+    budget_us: u32,
+}
+
+/// The v2 scheduling priority for apps.
+///
+/// Header to specify the priority of an app for priority schedulers. Lower
+/// values are higher priorities.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Priority {
+    priority: u32,
+}
+
+/// The v2 list of IPC clients allowed to connect to an app.
+///
+/// Header to restrict which processes, identified by their ShortId, may
+/// discover and notify the IPC service of the app.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2IpcClients<const L: usize> {
+    length: u16,
+    client_ids: [u32; L],
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TbfFooterV2CredentialsType {
     Reserved = 0,
@@ -259,6 +294,12 @@ pub enum TbfFooterV2CredentialsType {
     SHA384 = 4,
     SHA512 = 5,
     EcdsaNistP256 = 6,
+    /// ECDSA NIST P-384 signature of the SHA-384 hash of the binary, as the
+    /// concatenated 48-byte `r` and `s` values.
+    EcdsaNistP384 = 7,
+    /// Ed25519ph signature of the binary (RFC 8032, SHA-512 prehash and empty
+    /// context).
+    Ed25519ph = 8,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -330,6 +371,9 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
             10 => Ok(TbfHeaderTypes::TbfHeaderShortId),
+            11 => Ok(TbfHeaderTypes::TbfHeaderRealTime),
+            12 => Ok(TbfHeaderTypes::TbfHeaderPriority),
+            13 => Ok(TbfHeaderTypes::TbfHeaderIpcClients),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -588,6 +632,67 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2ShortId {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2RealTime {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2RealTime, Self::Error> {
+        Ok(TbfHeaderV2RealTime {
+            period_us: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            budget_us: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Priority {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2Priority, Self::Error> {
+        Ok(TbfHeaderV2Priority {
+            priority: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl<const L: usize> core::convert::TryFrom<&[u8]> for TbfHeaderV2IpcClients<L> {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2IpcClients<L>, Self::Error> {
+        let length = b.len() / size_of::<u32>();
+        if b.len() % size_of::<u32>() != 0 || length > L {
+            return Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfHeaderIpcClients as usize,
+            ));
+        }
+
+        let mut client_ids: [u32; L] = [0; L];
+        for (i, client_id) in client_ids.iter_mut().take(length).enumerate() {
+            let start = i * size_of::<u32>();
+            *client_id = u32::from_le_bytes(
+                b.get(start..start + size_of::<u32>())
+                    .ok_or(TbfParseError::NotEnoughFlash)?
+                    .try_into()?,
+            );
+        }
+
+        Ok(TbfHeaderV2IpcClients {
+            length: length as u16,
+            client_ids,
+        })
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -605,6 +710,8 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             4 => TbfFooterV2CredentialsType::SHA384,
             5 => TbfFooterV2CredentialsType::SHA512,
             6 => TbfFooterV2CredentialsType::EcdsaNistP256,
+            7 => TbfFooterV2CredentialsType::EcdsaNistP384,
+            8 => TbfFooterV2CredentialsType::Ed25519ph,
             _ => {
                 return Err(TbfParseError::BadTlvEntry(
                     TbfHeaderTypes::TbfFooterCredentials as usize,
@@ -619,6 +726,8 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             TbfFooterV2CredentialsType::SHA384 => 48,
             TbfFooterV2CredentialsType::SHA512 => 64,
             TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
+            TbfFooterV2CredentialsType::EcdsaNistP384 => 96,
+            TbfFooterV2CredentialsType::Ed25519ph => 64,
         };
         let data = &b
             .get(4..(length + 4))
@@ -661,6 +770,9 @@ pub struct TbfHeaderV2<'a> {
     pub(crate) storage_permissions: Option<&'a [u8]>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
     pub(crate) short_id: Option<TbfHeaderV2ShortId>,
+    pub(crate) real_time: Option<TbfHeaderV2RealTime>,
+    pub(crate) priority: Option<TbfHeaderV2Priority>,
+    pub(crate) ipc_clients: Option<TbfHeaderV2IpcClients<NUM_IPC_CLIENTS>>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -1022,4 +1134,37 @@ impl<'a> TbfHeader<'a> {
             _ => None,
         }
     }
+
+    /// Get the real-time scheduling parameters of the application as
+    /// `(period_us, budget_us)`. Returns `None` if the real-time header is not
+    /// included.
+    pub fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .real_time
+                .map(|real_time| (real_time.period_us, real_time.budget_us)),
+            _ => None,
+        }
+    }
+
+    /// Get the scheduling priority of the application. Returns `None` if the
+    /// priority header is not included.
+    pub fn get_priority(&self) -> Option<u32> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.priority.map(|priority| priority.priority),
+            _ => None,
+        }
+    }
+
+    /// Get the number of valid IPC client ShortIds and the ShortIds. Returns
+    /// `None` if the IPC clients header is not included, in which case any
+    /// process may connect to the app.
+    pub fn get_ipc_client_ids(&self) -> Option<(usize, [u32; NUM_IPC_CLIENTS])> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .ipc_clients
+                .map(|ipc_clients| (ipc_clients.length.into(), ipc_clients.client_ids)),
+            _ => None,
+        }
+    }
 }
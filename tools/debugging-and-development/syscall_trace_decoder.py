@@ -0,0 +1,164 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2025.
+
+'''
+Decode the system call trace recorded by the kernel.
+
+Boards that set a system call tracer (`Kernel::set_syscall_tracer()`) print
+each recorded system call or upcall as a line of hexadecimal fields, either
+with the `trace` command of the process console or on the debug UART:
+
+    trace <timestamp> <process id> <event> <arg0> <arg1> <arg2> <arg3> [<r0> <r1> <r2> <r3>]
+
+This script reads a console log, from files or standard input, and prints the
+trace lines as readable system calls. All other lines are ignored.
+
+Usage:
+
+    syscall_trace_decoder.py [console.log ...]
+    tockloader listen | syscall_trace_decoder.py
+'''
+
+import argparse
+import fileinput
+import re
+
+TRACE_RE = re.compile(r'trace((?: [0-9a-fA-F]+){7}|(?: [0-9a-fA-F]+){11})\s*$')
+
+UPCALL = 0x80
+
+SYSCALL_CLASSES = {
+    0: 'yield',
+    1: 'subscribe',
+    2: 'command',
+    3: 'allow-rw',
+    4: 'allow-ro',
+    5: 'memop',
+    6: 'exit',
+    7: 'allow-userspace-r',
+}
+
+YIELD_VARIANTS = {0: 'no-wait', 1: 'wait', 2: 'wait-for'}
+
+EXIT_VARIANTS = {0: 'terminate', 1: 'restart'}
+
+# Return variants, as specified in TRD104.
+RETURN_VARIANTS = {
+    0: 'Failure',
+    1: 'FailureU32',
+    2: 'FailureU32U32',
+    3: 'FailureU64',
+    128: 'Success',
+    129: 'SuccessU32',
+    130: 'SuccessU32U32',
+    131: 'SuccessU64',
+    132: 'SuccessU32U32U32',
+    133: 'SuccessU32U64',
+}
+
+# Number of values returned by each variant, after the error code.
+RETURN_VALUES = {
+    0: 0, 1: 1, 2: 2, 3: 2, 128: 0, 129: 1, 130: 2, 131: 2, 132: 3, 133: 3,
+}
+
+ERROR_CODES = {
+    1: 'FAIL',
+    2: 'BUSY',
+    3: 'ALREADY',
+    4: 'OFF',
+    5: 'RESERVE',
+    6: 'INVAL',
+    7: 'SIZE',
+    8: 'CANCEL',
+    9: 'NOMEM',
+    10: 'NOSUPPORT',
+    11: 'NODEVICE',
+    12: 'UNINSTALLED',
+    13: 'NOACK',
+}
+
+
+def decode_arguments(event, args):
+    '''Format the arguments of a system call.'''
+    if event == 0:
+        which = YIELD_VARIANTS.get(args[0], '{:#x}'.format(args[0]))
+        if args[0] == 2:
+            return '{}, driver {:#x}, subscribe {}'.format(which, args[1], args[2])
+        return which
+    if event == 1:
+        return 'driver {:#x}, subscribe {}, upcall {:#x}, appdata {:#x}'.format(*args)
+    if event == 2:
+        return 'driver {:#x}, command {}, {:#x}, {:#x}'.format(*args)
+    if event in (3, 4, 7):
+        return 'driver {:#x}, allow {}, address {:#x}, size {}'.format(*args)
+    if event == 5:
+        return 'operation {}, {:#x}'.format(args[0], args[1])
+    if event == 6:
+        which = EXIT_VARIANTS.get(args[0], '{:#x}'.format(args[0]))
+        return '{}, completion code {}'.format(which, args[1])
+    return ', '.join('{:#x}'.format(arg) for arg in args)
+
+
+def decode_return(event, ret):
+    '''Format the return value of a system call.'''
+    variant = ret[0]
+    name = RETURN_VARIANTS.get(variant)
+    if name is None:
+        return 'unknown return variant {:#x}'.format(variant)
+    values = ret[1:]
+    if variant < 128:
+        error = ERROR_CODES.get(values[0], '{:#x}'.format(values[0]))
+        values = values[1:]
+        # Failed allows and subscribes return the buffer or upcall passed in.
+        if event in (1, 3, 4, 7):
+            return '{}({}, {:#x}, {})'.format(name, error, values[0], values[1])
+        shown = [error] + ['{:#x}'.format(v) for v in values[:RETURN_VALUES[variant]]]
+        return '{}({})'.format(name, ', '.join(shown))
+    if event in (1, 3, 4, 7) and variant == 130:
+        # Successful allows and subscribes return the previous buffer or upcall.
+        return 'Success(previous {:#x}, {})'.format(values[0], values[1])
+    shown = ['{:#x}'.format(v) for v in values[:RETURN_VALUES[variant]]]
+    return '{}({})'.format(name, ', '.join(shown))
+
+
+def decode_line(line):
+    '''Decode one trace line, or return None if it is not a trace line.'''
+    match = TRACE_RE.search(line)
+    if match is None:
+        return None
+    fields = [int(field, 16) for field in match.group(1).split()]
+    timestamp, process_id, event = fields[0:3]
+    args = fields[3:7]
+    ret = fields[7:11] if len(fields) == 11 else None
+
+    prefix = '{:>12} us  [{}]'.format(timestamp, process_id)
+    if event == UPCALL:
+        if args[0] == 0:
+            return '{} yield-wait-for returned ({:#x}, {:#x}, {:#x})'.format(prefix, *args[1:])
+        return '{} upcall @{:#x}({:#x}, {:#x}, {:#x})'.format(prefix, *args)
+
+    name = SYSCALL_CLASSES.get(event, 'syscall {:#x}'.format(event))
+    decoded = '{} {}({})'.format(prefix, name, decode_arguments(event, args))
+    if ret is not None:
+        decoded += ' = ' + decode_return(event, ret)
+    return decoded
+
+
+def main():
+    parser = argparse.ArgumentParser(
+        description='Decode the system call trace recorded by the Tock kernel.')
+    parser.add_argument('files', nargs='*',
+                        help='console logs to decode, standard input if none')
+    args = parser.parse_args()
+
+    for line in fileinput.input(args.files):
+        decoded = decode_line(line)
+        if decoded is not None:
+            print(decoded)
+
+
+if __name__ == '__main__':
+    main()